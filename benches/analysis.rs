@@ -0,0 +1,107 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use v8_heap_analyzer::{
+    analysis::{dominator_tree::tree_from_immediate_dominators, duplicates::DuplicateAnalyzer},
+    graph::{
+        lengauer_tarjan::lengauer_tarjan,
+        test_graph::TestGraph,
+        v8_heap_graph::{EdgeType, NodeType, V8HeapGraph},
+    },
+    types::NodeId,
+};
+
+const NODE_COUNT: usize = 500_000;
+const DISTINCT_STRINGS: usize = 1000;
+
+/// Deterministic pseudo-random numbers, so every run measures the same graph
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % bound
+    }
+}
+
+/// A random tree under a single root, with extra edges from grandparents
+///
+/// Every node hangs off a random earlier node, which keeps the tree shallow. One in four
+/// nodes is also referenced by its grandparent, so not every parent is a dominator. Half
+/// of the nodes are strings drawn from a small set of values, so there are plenty of
+/// duplicates.
+fn synthetic_graph() -> V8HeapGraph {
+    let mut rng = Lcg(42);
+
+    // Strings: "", "Object", "child", then the string values
+    let mut strings = vec!["".to_string(), "Object".to_string(), "child".to_string()];
+    strings.extend((0..DISTINCT_STRINGS).map(|i| format!("value {}", i)));
+
+    let mut parents = vec![0; NODE_COUNT];
+    let mut children: Vec<Vec<(EdgeType, usize)>> = vec![vec![]; NODE_COUNT];
+    for i in 1..NODE_COUNT {
+        let parent = rng.next(i);
+        parents[i] = parent;
+        children[parent].push((EdgeType::Property, i));
+        if parent != 0 && rng.next(4) == 0 {
+            children[parents[parent]].push((EdgeType::Property, i));
+        }
+    }
+
+    let stride = 6;
+    let mut nodes = Vec::with_capacity(NODE_COUNT * stride);
+    let mut edges = vec![];
+    for (i, out) in children.iter().enumerate() {
+        let (typ, name, self_size) = match i {
+            0 => (NodeType::Synthetic, 0, 0),
+            _ if out.is_empty() && rng.next(2) == 0 => (
+                NodeType::String,
+                3 + rng.next(DISTINCT_STRINGS),
+                32 + rng.next(64),
+            ),
+            _ => (NodeType::Object, 1, 16 + 8 * rng.next(8)),
+        };
+        nodes.extend([
+            typ.index(),
+            name as NodeId,
+            2 * i as NodeId + 1,
+            self_size as NodeId,
+            out.len() as NodeId,
+            0,
+        ]);
+        for &(typ, to) in out {
+            edges.extend([typ.index(), 2, (to * stride) as NodeId]);
+        }
+    }
+
+    let meta = TestGraph::new().snapshot().snapshot.meta;
+    V8HeapGraph::from_parts(nodes, edges, strings, meta)
+}
+
+fn bench_analysis(c: &mut Criterion) {
+    let graph = synthetic_graph();
+
+    let mut group = c.benchmark_group("analysis");
+    group.sample_size(10);
+
+    group.bench_function("lengauer_tarjan", |b| {
+        b.iter(|| lengauer_tarjan(black_box(&graph), &[0]))
+    });
+
+    let idoms = lengauer_tarjan(&graph, &[0]);
+    group.bench_function("retained_sizes", |b| {
+        b.iter(|| tree_from_immediate_dominators(idoms.clone(), 0, black_box(&graph)))
+    });
+
+    group.bench_function("find_duplicates", |b| {
+        b.iter(|| DuplicateAnalyzer::new(black_box(&graph), Default::default()).find_duplicates())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_analysis);
+criterion_main!(benches);
@@ -1,10 +1,14 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use fixedbitset::FixedBitSet;
 
 use crate::graph::lengauer_tarjan::GraphOps;
-use crate::graph::v8_heap_graph::{Edge, EdgeId, EdgeType, V8HeapGraph};
+use crate::graph::v8_heap_graph::{Edge, EdgeId, EdgeType, NameOrIndex, V8HeapGraph};
 use crate::types::NodeId;
+use crate::utils::{Deadline, TimedOut};
+
+/// How many nodes the path search expands between looks at the clock
+const DEADLINE_CHECK_INTERVAL: usize = 1 << 16;
 
 #[derive(Clone, Debug, Default)]
 pub struct RootPath(Vec<EdgeId>);
@@ -13,54 +17,770 @@ impl RootPath {
     pub fn edges<'a>(&'a self, graph: &'a V8HeapGraph) -> impl Iterator<Item = Edge<'a>> {
         self.0.iter().map(|&e| graph.edge(e))
     }
+
+    /// The nodes along the path, not including the root
+    pub fn nodes(&self, graph: &V8HeapGraph) -> Vec<NodeId> {
+        self.edges(graph).map(|e| e.to_node()).collect()
+    }
+
+    /// Every step of the path from the root, with the nodes on both sides of it
+    pub fn hops<'a>(&'a self, graph: &'a V8HeapGraph) -> impl Iterator<Item = PathHop<'a>> {
+        self.edges(graph).map(|e| PathHop {
+            from: e.from_node(),
+            to: e.to_node(),
+            edge_type: e.typ(),
+            name_or_index: e.name_or_index(),
+        })
+    }
 }
 
+/// One edge of a `RootPath`: `from` retains `to` through it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathHop<'a> {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub edge_type: EdgeType,
+    pub name_or_index: NameOrIndex<'a>,
+}
+
+/// Remove paths that are duplicates of, or a prefix of, another path
+///
+/// Path A is considered a subset of path B if A's node sequence is a prefix of B's: B
+/// extends A further from the root. Of a set of identical paths only one is kept.
+///
+/// After sorting the node sequences, all extensions of a path directly follow it, so we
+/// only need to compare neighbors.
+pub fn filter_subset_paths(paths: Vec<RootPath>, graph: &V8HeapGraph) -> Vec<RootPath> {
+    let mut keyed: Vec<(Vec<NodeId>, RootPath)> =
+        paths.into_iter().map(|p| (p.nodes(graph), p)).collect();
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut ret = vec![];
+    let mut iter = keyed.into_iter().peekable();
+    while let Some((nodes, path)) = iter.next() {
+        let extended = iter
+            .peek()
+            .is_some_and(|(next, _)| next.starts_with(&nodes));
+        if !extended {
+            ret.push(path);
+        }
+    }
+    ret
+}
+
+/// A path that stands for all paths that only differ from it in element indices
+#[derive(Debug, Clone)]
+pub struct CollapsedPath {
+    pub path: RootPath,
+    /// The number of paths that were merged into this one, including itself
+    pub count: usize,
+    /// For every hop, whether the merged paths used different indices there
+    pub wildcards: Vec<bool>,
+}
+
+/// Merge paths that are the same except for the indices of their element edges
+///
+/// `root.cache[0].value` and `root.cache[1].value` go through different array slots (and
+/// usually different objects), but in a report they tell the same story: `root.cache[*].value`.
+/// The first path of every such group is kept, in the original order.
+pub fn collapse_element_indices(paths: Vec<RootPath>, graph: &V8HeapGraph) -> Vec<CollapsedPath> {
+    let mut ret: Vec<CollapsedPath> = vec![];
+    let mut by_shape: HashMap<Vec<(EdgeType, Option<NameOrIndex>)>, usize> = HashMap::new();
+    for path in paths {
+        let shape = path
+            .0
+            .iter()
+            .map(|&e| match graph.edge(e) {
+                e if e.typ() == EdgeType::Element => (e.typ(), None),
+                e => (e.typ(), Some(e.name_or_index())),
+            })
+            .collect();
+        match by_shape.get(&shape) {
+            Some(&i) => {
+                let collapsed = &mut ret[i];
+                let first = collapsed.path.edges(graph);
+                for ((wildcard, a), b) in collapsed
+                    .wildcards
+                    .iter_mut()
+                    .zip(first)
+                    .zip(path.edges(graph))
+                {
+                    *wildcard |= a.index() != b.index();
+                }
+                collapsed.count += 1;
+            }
+            None => {
+                by_shape.insert(shape, ret.len());
+                ret.push(CollapsedPath {
+                    wildcards: vec![false; path.0.len()],
+                    path,
+                    count: 1,
+                });
+            }
+        }
+    }
+    ret
+}
+
+/// Default cap on the number of paths returned by `RootPaths::paths_to`
+pub const MAX_PATHS_PER_NODE: usize = 100;
+
+/// All shortest retention paths from the root to every node
+///
+/// Every node remembers all edges that reach it from a node one step closer to the root,
+/// so the number of distinct paths to a node can be exponential in its depth. Paths are
+/// therefore only ever enumerated up to a limit.
 pub struct RootPaths {
+    root: NodeId,
     paths: Vec<Vec<EdgeId>>,
+    depth: Vec<u32>,
+    /// The search stopped at this depth, and may have missed nodes beyond it
+    cut_off_at: Option<u32>,
 }
 
 impl RootPaths {
-    /// Returns a list of all root paths for the given node
+    /// Number of edges on the paths from the root
+    ///
+    /// `None` if the node is unreachable, or further away than the maximum depth.
+    pub fn distance(&self, node: NodeId) -> Option<u32> {
+        Some(self.depth[node as usize]).filter(|&d| d != u32::MAX)
+    }
+
+    /// The maximum depth, if the search stopped there before it ran out of nodes
+    ///
+    /// If this is set, a node without paths may still be reachable.
+    pub fn cut_off_at(&self) -> Option<u32> {
+        self.cut_off_at
+    }
+
+    /// Returns the root paths for the given node, at most `MAX_PATHS_PER_NODE` of them
     pub fn paths_to(&self, node: NodeId, graph: &V8HeapGraph) -> Vec<RootPath> {
-        if node == 0 {
+        self.paths_to_limited(node, graph, MAX_PATHS_PER_NODE)
+    }
+
+    /// Returns at most `limit` root paths for the given node
+    ///
+    /// Unreachable nodes have no paths.
+    pub fn paths_to_limited(
+        &self,
+        node: NodeId,
+        graph: &V8HeapGraph,
+        limit: usize,
+    ) -> Vec<RootPath> {
+        if node == self.root {
             return vec![RootPath::default()];
         }
 
-        // For now, combinatorial explosion
+        // Partial paths from some node to `node`, their edges in reverse. Paths can be far
+        // deeper than the call stack, so this walks back to the root with a stack of its own.
         let mut ret: Vec<RootPath> = vec![];
-        for &segment in &self.paths[node as usize] {
-            let from_node = graph.edge(segment).from_node();
-            let mut parent_paths = self.paths_to(from_node, graph);
-            for path in &mut parent_paths {
-                path.0.push(segment);
+        let mut stack: Vec<(NodeId, Vec<EdgeId>)> = vec![(node, vec![])];
+        while let Some((head, mut suffix)) = stack.pop() {
+            if ret.len() >= limit {
+                break;
+            }
+            if head == self.root {
+                suffix.reverse();
+                ret.push(RootPath(suffix));
+                continue;
+            }
+
+            // Pushed in reverse, so that paths come out in the order of the segments. The
+            // first one reuses the partial path, which keeps a long chain linear
+            if let Some((&first, rest)) = self.paths[head as usize].split_first() {
+                for &segment in rest.iter().rev() {
+                    let mut path = suffix.clone();
+                    path.push(segment);
+                    stack.push((graph.edge(segment).from_node(), path));
+                }
+                suffix.push(first);
+                stack.push((graph.edge(first).from_node(), suffix));
             }
-            ret.append(&mut parent_paths);
         }
         ret
     }
 }
 
+/// Default for the maximum length of the paths reported by the CLI
+pub const DEFAULT_MAX_PATH_DEPTH: u32 = 20;
+
+/// Find all shortest paths from the root to every node, skipping weak and shortcut edges
 pub fn find_root_paths(graph: &V8HeapGraph, root: NodeId) -> RootPaths {
-    let mut paths: Vec<Vec<EdgeId>> = vec![vec![]; graph.node_count()];
+    find_root_paths_within(graph, root, u32::MAX)
+}
+
+/// Which edges retention paths may go through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PathEdges {
+    /// Every edge except weak and shortcut edges, shortest paths only
+    #[default]
+    All,
+    /// Only property and element edges, the ones that map to `a.b[0].c` in JavaScript
+    ///
+    /// Nodes that can't be reached that way fall back to paths through any edge, which start
+    /// from the nearest node that can be.
+    Properties,
+}
+
+impl PathEdges {
+    fn follows(&self, edge: &Edge<'_>) -> bool {
+        match self {
+            PathEdges::All => !matches!(edge.typ(), EdgeType::Weak | EdgeType::Shortcut),
+            PathEdges::Properties => matches!(edge.typ(), EdgeType::Property | EdgeType::Element),
+        }
+    }
+}
+
+/// Find all shortest paths of at most `max_depth` edges from the root
+///
+/// Paths longer than that are rarely useful in a report, and in a deep graph exploring them
+/// is expensive. Nodes that are further away get no paths at all.
+pub fn find_root_paths_within(graph: &V8HeapGraph, root: NodeId, max_depth: u32) -> RootPaths {
+    find_root_paths_with(graph, root, max_depth, PathEdges::All)
+}
+
+/// Find paths of at most `max_depth` edges from the root, only through the given edges
+///
+/// With `PathEdges::Properties`, a node that has a path through properties and elements only
+/// gets those paths, even if there is a shorter one through internal edges.
+pub fn find_root_paths_with(
+    graph: &V8HeapGraph,
+    root: NodeId,
+    max_depth: u32,
+    edges: PathEdges,
+) -> RootPaths {
+    find_root_paths_until(graph, root, max_depth, edges, Deadline::none())
+        .expect("no deadline to miss")
+}
+
+/// `find_root_paths_with`, giving up with `TimedOut` once `deadline` has passed
+pub fn find_root_paths_until(
+    graph: &V8HeapGraph,
+    root: NodeId,
+    max_depth: u32,
+    edges: PathEdges,
+    deadline: Deadline,
+) -> Result<RootPaths, TimedOut> {
+    let mut ret = RootPaths {
+        root,
+        paths: vec![vec![]; graph.node_count()],
+        depth: vec![u32::MAX; graph.node_count()],
+        cut_off_at: None,
+    };
+
+    // Root has an empty path. An empty graph doesn't have a root, and has no paths
+    if (root as usize) < graph.node_count() {
+        ret.depth[root as usize] = 0;
+    }
+    ret.extend_paths(graph, max_depth, deadline, |e| edges.follows(e))?;
+    if edges != PathEdges::All {
+        ret.extend_paths(graph, max_depth, deadline, |e| PathEdges::All.follows(e))?;
+    }
+    Ok(ret)
+}
+
+impl RootPaths {
+    /// Find shortest paths to the nodes that don't have any yet, through the edges that
+    /// `follow` accepts, continuing from every node that does
+    fn extend_paths<P>(
+        &mut self,
+        graph: &V8HeapGraph,
+        max_depth: u32,
+        deadline: Deadline,
+        follow: P,
+    ) -> Result<(), TimedOut>
+    where
+        P: Fn(&Edge<'_>) -> bool,
+    {
+        let mut expanded = 0;
+        let mut settled = FixedBitSet::with_capacity(graph.node_count());
+        let mut layers: Vec<Vec<NodeId>> = vec![];
+        for (node, &depth) in self.depth.iter().enumerate() {
+            if depth != u32::MAX {
+                settled.insert(node);
+                if layers.len() <= depth as usize {
+                    layers.resize(depth as usize + 1, vec![]);
+                }
+                layers[depth as usize].push(node as NodeId);
+            }
+        }
+
+        let mut depth = 0;
+        while (depth as usize) < layers.len() {
+            let layer = std::mem::take(&mut layers[depth as usize]);
+            if depth == max_depth {
+                // The remaining layers are all deeper than this
+                if layer.iter().any(|&n| graph.out_edges(n).next().is_some()) {
+                    self.cut_off_at = Some(max_depth);
+                }
+                break;
+            }
+
+            let next_depth = depth + 1;
+            for from_node in layer {
+                expanded += 1;
+                if expanded % DEADLINE_CHECK_INTERVAL == 0 {
+                    deadline.check()?;
+                }
+                for edge in graph.out_edges(from_node) {
+                    let to_node = edge.to_node() as usize;
+                    if !follow(&edge) || settled.contains(to_node) {
+                        continue;
+                    }
+
+                    if self.depth[to_node] == u32::MAX {
+                        self.depth[to_node] = next_depth;
+                        if layers.len() <= next_depth as usize {
+                            layers.push(vec![]);
+                        }
+                        layers[next_depth as usize].push(edge.to_node());
+                    }
+                    // Every edge from the previous layer is the last step of a shortest path
+                    if self.depth[to_node] == next_depth {
+                        self.paths[to_node].push(edge.id);
+                    }
+                }
+            }
+            depth = next_depth;
+        }
+        Ok(())
+    }
+}
+
+/// Shortest distance from the nearest GC root to every node, `u32::MAX` for unreachable nodes
+///
+/// The GC roots (see `V8HeapGraph::gc_roots`) are at distance 0. Snapshots without a
+/// `(GC roots)` node count from the snapshot root instead. Like the garbage collector, this
+/// only follows strong edges; the snapshot root's shortcut edges to the global objects don't
+/// retain anything.
+pub fn root_distances(graph: &V8HeapGraph) -> Vec<u32> {
+    match graph.gc_roots() {
+        [] => root_distances_from(graph, &[0]),
+        roots => root_distances_from(graph, roots),
+    }
+}
+
+/// The set of nodes that are reachable from the snapshot root
+pub fn reachable_from_root(graph: &V8HeapGraph) -> FixedBitSet {
+    let mut reachable = FixedBitSet::with_capacity(graph.node_count());
+    for (node, &distance) in root_distances_from(graph, &[0]).iter().enumerate() {
+        reachable.set(node, distance != u32::MAX);
+    }
+    reachable
+}
+
+/// Shortest distance from any of the given roots to every node, skipping weak and shortcut edges
+pub fn root_distances_from(graph: &V8HeapGraph, roots: &[NodeId]) -> Vec<u32> {
+    let mut depth: Vec<u32> = vec![u32::MAX; graph.node_count()];
     let mut queue = VecDeque::<NodeId>::new();
-    let mut seen = FixedBitSet::with_capacity(graph.node_count());
 
-    // Root has an empty path
-    queue.push_back(root);
-    seen.put(0);
+    for &root in roots.iter().filter(|&&r| (r as usize) < graph.node_count()) {
+        depth[root as usize] = 0;
+        queue.push_back(root);
+    }
     while let Some(from_node) = queue.pop_front() {
+        let next_depth = depth[from_node as usize] + 1;
         for edge in graph.out_edges(from_node) {
-            // Skip weak and shortcut edges
             if matches!(edge.typ(), EdgeType::Weak | EdgeType::Shortcut) {
                 continue;
             }
 
-            if !seen.put(edge.to_node() as usize) {
-                paths[edge.to_node() as usize].push(edge.id);
+            if depth[edge.to_node() as usize] == u32::MAX {
+                depth[edge.to_node() as usize] = next_depth;
                 queue.push_back(edge.to_node());
             }
         }
     }
 
-    RootPaths { paths }
+    depth
+}
+
+/// Every node that (transitively) retains `node`, with the retaining edges between them
+///
+/// This is the union of all retention paths, not just the shortest ones, as a DAG (or a
+/// graph with cycles) instead of a list: a reverse breadth-first search over strong edges.
+/// It is linear in the size of the result, where enumerating the paths can be exponential.
+/// The set includes `node` itself; edges are `(retainer, retained)` pairs, sorted.
+pub fn retainer_closure(
+    graph: &V8HeapGraph,
+    node: NodeId,
+) -> (HashSet<NodeId>, Vec<(NodeId, NodeId)>) {
+    let mut ancestors = HashSet::from([node]);
+    let mut edges = vec![];
+    let mut queue = VecDeque::from([node]);
+    while let Some(n) = queue.pop_front() {
+        for from in graph.predecessors(n) {
+            edges.push((from, n));
+            if ancestors.insert(from) {
+                queue.push_back(from);
+            }
+        }
+    }
+    edges.sort_unstable();
+    edges.dedup();
+    (ancestors, edges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{test_graph::TestGraph, v8_heap_graph::NodeType};
+
+    fn path_names(path: &RootPath, graph: &V8HeapGraph) -> Vec<String> {
+        path.edges(graph)
+            .map(|e| e.name_or_index().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_multiple_retention_paths() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 10);
+        let shared = g.node(NodeType::Object, "Shared", 10);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b)
+            .edge(a, EdgeType::Property, "x", shared)
+            .edge(b, EdgeType::Property, "y", shared)
+            // Longer paths are not reported
+            .edge(a, EdgeType::Property, "b", b)
+            // Neither are weak ones
+            .edge(root, EdgeType::Weak, "w", shared);
+        let graph = g.build();
+
+        let paths = find_root_paths(&graph, root);
+
+        let found = paths
+            .paths_to(shared, &graph)
+            .iter()
+            .map(|p| path_names(p, &graph))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![vec!["a", "x"], vec!["b", "y"]]);
+
+        assert_eq!(paths.paths_to_limited(shared, &graph, 1).len(), 1);
+        assert_eq!(paths.paths_to(b, &graph).len(), 1);
+    }
+
+    #[test]
+    fn test_hops_of_three_hop_path() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let window = g.node(NodeType::Object, "Window", 10);
+        let items = g.node(NodeType::Array, "Array", 10);
+        let item = g.node(NodeType::Object, "Item", 10);
+        g.edge(root, EdgeType::Internal, "window", window)
+            .edge(window, EdgeType::Property, "items", items)
+            .element(items, 3, item);
+        let graph = g.build();
+
+        let paths = find_root_paths(&graph, root).paths_to(item, &graph);
+        assert_eq!(paths.len(), 1);
+        let hops: Vec<PathHop> = paths[0].hops(&graph).collect();
+        assert_eq!(
+            hops,
+            vec![
+                PathHop {
+                    from: root,
+                    to: window,
+                    edge_type: EdgeType::Internal,
+                    name_or_index: NameOrIndex::Name("window"),
+                },
+                PathHop {
+                    from: window,
+                    to: items,
+                    edge_type: EdgeType::Property,
+                    name_or_index: NameOrIndex::Name("items"),
+                },
+                PathHop {
+                    from: items,
+                    to: item,
+                    edge_type: EdgeType::Element,
+                    name_or_index: NameOrIndex::Index(3),
+                },
+            ]
+        );
+        // Every hop starts where the previous one ended
+        assert!(hops.windows(2).all(|w| w[0].to == w[1].from));
+    }
+
+    #[test]
+    fn test_retainer_closure_of_diamond() {
+        //      /-> a -\
+        // root          -> target
+        //      \-> b -/
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 10);
+        let target = g.node(NodeType::Object, "Target", 10);
+        let unrelated = g.node(NodeType::Object, "Unrelated", 10);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b)
+            .edge(root, EdgeType::Property, "u", unrelated)
+            .edge(a, EdgeType::Property, "t", target)
+            .edge(b, EdgeType::Property, "t", target)
+            .edge(unrelated, EdgeType::Weak, "t", target);
+        let graph = g.build();
+
+        let (nodes, edges) = retainer_closure(&graph, target);
+        assert_eq!(nodes, HashSet::from([root, a, b, target]));
+        assert_eq!(edges, vec![(root, a), (root, b), (a, target), (b, target)]);
+        assert_eq!(
+            find_root_paths(&graph, root).paths_to(target, &graph).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_path_explosion_is_capped() {
+        // A ladder of diamonds has 2^n paths to its last node
+        let mut g = TestGraph::new();
+        let mut top = g.node(NodeType::Synthetic, "root", 0);
+        let root = top;
+        for _ in 0..20 {
+            let l = g.node(NodeType::Object, "L", 1);
+            let r = g.node(NodeType::Object, "R", 1);
+            let bottom = g.node(NodeType::Object, "B", 1);
+            g.edge(top, EdgeType::Property, "l", l)
+                .edge(top, EdgeType::Property, "r", r)
+                .edge(l, EdgeType::Property, "b", bottom)
+                .edge(r, EdgeType::Property, "b", bottom);
+            top = bottom;
+        }
+        let graph = g.build();
+
+        let paths = find_root_paths(&graph, root);
+        assert_eq!(paths.paths_to(top, &graph).len(), MAX_PATHS_PER_NODE);
+    }
+
+    #[test]
+    fn test_root_distances_on_chain() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let mut prev = root;
+        let mut chain = vec![root];
+        for _ in 0..5 {
+            let next = g.node(NodeType::Object, "Link", 8);
+            g.edge(prev, EdgeType::Property, "next", next);
+            chain.push(next);
+            prev = next;
+        }
+        // A shortcut doesn't make the end of the chain any closer
+        g.edge(root, EdgeType::Shortcut, "end", prev);
+        let orphan = g.node(NodeType::Object, "Orphan", 8);
+        let graph = g.build();
+
+        let distances = root_distances(&graph);
+
+        for (i, &node) in chain.iter().enumerate() {
+            assert_eq!(distances[node as usize], i as u32);
+        }
+        assert_eq!(distances[orphan as usize], u32::MAX);
+
+        let paths = find_root_paths(&graph, root);
+        assert_eq!(paths.distance(prev), Some(5));
+        assert_eq!(paths.distance(orphan), None);
+    }
+
+    #[test]
+    fn test_paths_on_deep_chain() {
+        // A linked list that is far deeper than a recursive traversal could handle
+        const DEPTH: NodeId = 100_000;
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let mut prev = root;
+        for _ in 0..DEPTH {
+            let next = g.node(NodeType::Object, "Link", 8);
+            g.edge(prev, EdgeType::Property, "next", next);
+            prev = next;
+        }
+        let graph = g.build();
+
+        let paths = find_root_paths(&graph, root).paths_to(prev, &graph);
+        assert_eq!(paths.len(), 1);
+        let nodes = paths[0].nodes(&graph);
+        assert_eq!(nodes.len(), DEPTH as usize);
+        assert_eq!(nodes.first(), Some(&1));
+        assert_eq!(nodes.last(), Some(&prev));
+    }
+
+    #[test]
+    fn test_root_distances_from_gc_roots() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "", 0);
+        let gc_roots = g.node(NodeType::Synthetic, "(GC roots)", 0);
+        let handles = g.node(NodeType::Synthetic, "(Global handles)", 0);
+        let held = g.node(NodeType::Object, "Held", 8);
+        let global = g.node(NodeType::Object, "Window", 8);
+        g.edge(root, EdgeType::Element, "1", gc_roots)
+            .edge(root, EdgeType::Shortcut, "window", global)
+            .edge(gc_roots, EdgeType::Element, "1", handles)
+            .edge(handles, EdgeType::Element, "1", held)
+            .edge(held, EdgeType::Property, "window", global);
+        let graph = g.build();
+
+        let distances = root_distances(&graph);
+        assert_eq!(distances[handles as usize], 0);
+        assert_eq!(distances[held as usize], 1);
+        assert_eq!(distances[global as usize], 2);
+        assert_eq!(distances[root as usize], u32::MAX);
+        assert_eq!(graph.root_distance(held), Some(1));
+
+        // Reachability is still from the snapshot root
+        assert!(reachable_from_root(&graph).contains(root as usize));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let mut chain = vec![root];
+        for _ in 0..30 {
+            let next = g.node(NodeType::Object, "Link", 8);
+            g.edge(*chain.last().unwrap(), EdgeType::Property, "next", next);
+            chain.push(next);
+        }
+        let graph = g.build();
+
+        let paths = find_root_paths_within(&graph, root, 20);
+        assert_eq!(paths.paths_to(chain[20], &graph)[0].nodes(&graph).len(), 20);
+        assert!(paths.paths_to(chain[21], &graph).is_empty());
+        assert!(paths.paths_to(chain[30], &graph).is_empty());
+        assert_eq!(paths.distance(chain[21]), None);
+        assert_eq!(paths.cut_off_at(), Some(20));
+
+        // If nothing is deeper than the limit, nothing was cut off
+        let paths = find_root_paths_within(&graph, root, 30);
+        assert_eq!(paths.paths_to(chain[30], &graph).len(), 1);
+        assert_eq!(paths.cut_off_at(), None);
+    }
+
+    #[test]
+    fn test_property_paths_are_preferred() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let app = g.node(NodeType::Object, "App", 8);
+        let cache = g.node(NodeType::Object, "Cache", 8);
+        let entry = g.node(NodeType::Object, "Entry", 8);
+        let context = g.node(NodeType::Object, "system / Context", 8);
+        let hidden = g.node(NodeType::Object, "Hidden", 8);
+        g.edge(root, EdgeType::Property, "app", app)
+            .edge(app, EdgeType::Property, "cache", cache)
+            .element(cache, 3, entry)
+            // A shorter way to the entry, through a closure context
+            .edge(root, EdgeType::Internal, "context", context)
+            .edge(context, EdgeType::Context, "entry", entry)
+            // Only reachable through internals, and from the entry
+            .edge(entry, EdgeType::Internal, "hidden", hidden);
+        let graph = g.build();
+
+        let paths = find_root_paths(&graph, root).paths_to(entry, &graph);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(path_names(&paths[0], &graph), vec!["context", "entry"]);
+
+        let paths = find_root_paths_with(&graph, root, u32::MAX, PathEdges::Properties);
+        let to_entry = paths.paths_to(entry, &graph);
+        assert_eq!(to_entry.len(), 1);
+        assert_eq!(path_names(&to_entry[0], &graph), vec!["app", "cache", "3"]);
+        assert_eq!(paths.distance(entry), Some(3));
+
+        // Falls back to other edges, continuing from the property path
+        let to_hidden = paths.paths_to(hidden, &graph);
+        assert_eq!(to_hidden.len(), 1);
+        assert_eq!(
+            path_names(&to_hidden[0], &graph),
+            vec!["app", "cache", "3", "hidden"]
+        );
+        let to_context = paths.paths_to(context, &graph);
+        assert_eq!(path_names(&to_context[0], &graph), vec!["context"]);
+    }
+
+    #[test]
+    fn test_collapse_element_indices() {
+        // root.cache[i].value -> target for 10 different entries, and root.other.holder.value
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let cache = g.node(NodeType::Array, "Array", 10);
+        let other = g.node(NodeType::Object, "Other", 10);
+        let target = g.node(NodeType::Object, "Target", 10);
+        g.edge(root, EdgeType::Property, "cache", cache).edge(
+            root,
+            EdgeType::Property,
+            "other",
+            other,
+        );
+        for i in 0..10 {
+            let entry = g.node(NodeType::Object, "Entry", 10);
+            g.element(cache, i, entry)
+                .edge(entry, EdgeType::Property, "value", target);
+        }
+        let holder = g.node(NodeType::Object, "Holder", 10);
+        g.edge(other, EdgeType::Property, "holder", holder).edge(
+            holder,
+            EdgeType::Property,
+            "value",
+            target,
+        );
+        let graph = g.build();
+
+        let paths = find_root_paths(&graph, root).paths_to(target, &graph);
+        assert_eq!(paths.len(), 11);
+
+        let collapsed = collapse_element_indices(paths, &graph);
+        assert_eq!(collapsed.len(), 2);
+        assert_eq!(
+            path_names(&collapsed[0].path, &graph),
+            ["cache", "0", "value"]
+        );
+        assert_eq!(collapsed[0].count, 10);
+        assert_eq!(collapsed[0].wildcards, [false, true, false]);
+        assert_eq!(
+            path_names(&collapsed[1].path, &graph),
+            ["other", "holder", "value"]
+        );
+        assert_eq!(collapsed[1].count, 1);
+        assert_eq!(collapsed[1].wildcards, [false, false, false]);
+    }
+
+    #[test]
+    fn test_filter_subset_paths() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 10);
+        let c = g.node(NodeType::Object, "C", 10);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(a, EdgeType::Property, "b", b)
+            .edge(root, EdgeType::Property, "c", c)
+            .edge(c, EdgeType::Property, "b", b);
+        let graph = g.build();
+
+        let paths = find_root_paths(&graph, root);
+        let to_a = paths.paths_to(a, &graph).remove(0);
+        let to_b = paths.paths_to(b, &graph);
+        let to_c = paths.paths_to(c, &graph).remove(0);
+        assert_eq!(to_b.len(), 2);
+
+        let names = |paths: Vec<RootPath>| {
+            paths
+                .iter()
+                .map(|p| path_names(p, &graph).join("."))
+                .collect::<Vec<_>>()
+        };
+
+        // Prefix: root.a is dropped in favor of root.a.b
+        let filtered = filter_subset_paths(vec![to_a.clone(), to_b[0].clone()], &graph);
+        assert_eq!(names(filtered), vec!["a.b"]);
+
+        // Equal: only one copy is kept
+        let filtered = filter_subset_paths(vec![to_a.clone(), to_a.clone()], &graph);
+        assert_eq!(names(filtered), vec!["a"]);
+
+        // Disjoint: both are kept, even though they have the same length and end node
+        let filtered = filter_subset_paths(to_b.clone(), &graph);
+        assert_eq!(names(filtered), vec!["a.b", "c.b"]);
+        let filtered = filter_subset_paths(vec![to_a, to_c], &graph);
+        assert_eq!(names(filtered), vec!["a", "c"]);
+    }
 }
@@ -0,0 +1,83 @@
+use crate::{
+    analysis::{
+        all_paths::{RootPath, RootPaths},
+        dominator_tree::DominatorTree,
+    },
+    graph::v8_heap_graph::{Node, V8HeapGraph},
+    types::NodeId,
+};
+
+/// A graph together with the analyses that most reports need: retained sizes and root paths
+///
+/// This only borrows its parts, so it is cheap to copy and pass around instead of the three
+/// separate references.
+#[derive(Clone, Copy)]
+pub struct AnalyzedGraph<'a> {
+    pub graph: &'a V8HeapGraph,
+    pub tree: &'a DominatorTree,
+    pub root_paths: &'a RootPaths,
+}
+
+impl<'a> AnalyzedGraph<'a> {
+    pub fn new(graph: &'a V8HeapGraph, tree: &'a DominatorTree, root_paths: &'a RootPaths) -> Self {
+        Self {
+            graph,
+            tree,
+            root_paths,
+        }
+    }
+
+    pub fn node(&self, id: NodeId) -> Node<'a> {
+        self.graph.node(id)
+    }
+
+    pub fn retained_size(&self, node: NodeId) -> usize {
+        self.tree.retained_size(node)
+    }
+
+    /// Shortest paths from the root, see `RootPaths::paths_to`
+    pub fn paths_to(&self, node: NodeId) -> Vec<RootPath> {
+        self.root_paths.paths_to(node, self.graph)
+    }
+
+    pub fn paths_to_limited(&self, node: NodeId, limit: usize) -> Vec<RootPath> {
+        self.root_paths.paths_to_limited(node, self.graph, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::{all_paths::find_root_paths, dominator_tree::tree_from_immediate_dominators},
+        graph::{
+            lengauer_tarjan::lengauer_tarjan,
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+    };
+
+    #[test]
+    fn test_analyzed_graph() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let holder = g.node(NodeType::Object, "Holder", 100);
+        let leaf = g.node(NodeType::Object, "Leaf", 10);
+        g.edge(root, EdgeType::Property, "holder", holder).edge(
+            holder,
+            EdgeType::Property,
+            "leaf",
+            leaf,
+        );
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        let root_paths = find_root_paths(&graph, root);
+
+        let analyzed = AnalyzedGraph::new(&graph, &tree, &root_paths);
+        assert_eq!(analyzed.retained_size(holder), 110);
+        assert_eq!(analyzed.node(leaf).name(), "Leaf");
+        let paths = analyzed.paths_to(leaf);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].nodes(&graph), vec![holder, leaf]);
+    }
+}
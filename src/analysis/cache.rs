@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use fixedbitset::FixedBitSet;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, ErrorKind},
+    path::Path,
+};
+
+use crate::{
+    analysis::dominator_tree::DominatorTree, graph::v8_heap_graph::V8HeapGraph, types::NodeId,
+};
+
+/// Bump this whenever the layout of `AnalysisCache` changes
+const CACHE_VERSION: u32 = 5;
+
+/// Identifies the snapshot (and analysis root) that a cache was computed for
+///
+/// The snapshot is identified by `V8HeapGraph::fingerprint`, which works the same for
+/// snapshots read from stdin. It is not a content hash, see there for what it covers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKey {
+    version: u32,
+    node_count: usize,
+    edge_count: usize,
+    fingerprint: u64,
+    root: NodeId,
+    /// Retained sizes depend on whether native sizes are counted
+    include_native: bool,
+    /// Nodes whose subtrees were left out of the dominator tree, see `--prune`
+    pruned: Vec<NodeId>,
+    /// Nodes the dominator tree was seeded from instead of the GC roots, see `--roots-from-type`
+    seeds: Vec<NodeId>,
+}
+
+impl CacheKey {
+    pub fn new(graph: &V8HeapGraph, root: NodeId) -> Self {
+        Self {
+            version: CACHE_VERSION,
+            node_count: graph.total_node_count(),
+            edge_count: graph.total_edge_count(),
+            fingerprint: graph.fingerprint(),
+            root,
+            include_native: graph.includes_native(),
+            pruned: vec![],
+            seeds: vec![],
+        }
+    }
+
+    /// The key for a dominator tree computed without the edges of these nodes
+    pub fn with_pruned(mut self, pruned: &[NodeId]) -> Self {
+        self.pruned = pruned.to_vec();
+        self.pruned.sort_unstable();
+        self
+    }
+
+    /// The key for a dominator tree seeded from these nodes
+    pub fn with_seeds(mut self, seeds: &[NodeId]) -> Self {
+        self.seeds = seeds.to_vec();
+        self.seeds.sort_unstable();
+        self
+    }
+}
+
+/// The expensive analysis results, saved next to a snapshot so they can be reused
+#[derive(Serialize, Deserialize)]
+pub struct AnalysisCache {
+    pub key: CacheKey,
+    pub tree: DominatorTree,
+    /// Nodes reachable from the snapshot root
+    pub reachable: FixedBitSet,
+}
+
+pub fn write_cache(path: &Path, cache: &AnalysisCache) -> Result<()> {
+    let file = File::create(path).context("Failed to create cache file")?;
+    bincode::serialize_into(BufWriter::new(file), cache).context("Failed to write cache file")
+}
+
+/// Read the cache at `path`, if it exists and was computed for the snapshot described by `key`
+///
+/// A cache that is stale or unreadable is ignored with a warning, so that it simply gets
+/// recomputed and overwritten.
+pub fn read_cache(path: &Path, key: &CacheKey) -> Result<Option<AnalysisCache>> {
+    // Read it all at once: the bit set can only be deserialized from a buffer
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read cache file"),
+    };
+
+    match bincode::deserialize::<AnalysisCache>(&bytes) {
+        Ok(cache) if cache.key == *key => Ok(Some(cache)),
+        Ok(_) => {
+            eprintln!(
+                "Cache {} is for a different snapshot, ignoring",
+                path.display()
+            );
+            Ok(None)
+        }
+        Err(e) => {
+            eprintln!("Cache {} is unreadable, ignoring ({})", path.display(), e);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::{
+            all_paths::reachable_from_root, dominator_tree::tree_from_immediate_dominators,
+        },
+        graph::{
+            lengauer_tarjan::lengauer_tarjan,
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+    };
+
+    #[test]
+    fn test_cache_round_trip() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 20);
+        let orphan = g.node(NodeType::Object, "Orphan", 30);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(a, EdgeType::Property, "b", b);
+        let graph = g.build();
+
+        let key = CacheKey::new(&graph, root);
+        let cache = AnalysisCache {
+            key: key.clone(),
+            tree: tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph),
+            reachable: reachable_from_root(&graph),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshot.cache");
+        write_cache(&path, &cache).unwrap();
+
+        let loaded = read_cache(&path, &key)
+            .unwrap()
+            .expect("cache should be valid");
+        assert_eq!(loaded.tree.root, root);
+        assert_eq!(loaded.tree.children, cache.tree.children);
+        assert_eq!(loaded.tree.retained_sizes, cache.tree.retained_sizes);
+        assert_eq!(loaded.tree.retained_size(a), 30);
+        assert!(loaded.reachable.contains(b as usize));
+        assert!(!loaded.reachable.contains(orphan as usize));
+
+        // A different snapshot doesn't get the cached results
+        let mut g = TestGraph::new();
+        let other_root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 25);
+        g.node(NodeType::Object, "Orphan", 30);
+        g.edge(other_root, EdgeType::Property, "a", a)
+            .edge(a, EdgeType::Property, "b", b);
+        let other_key = CacheKey::new(&g.build(), other_root);
+        assert!(read_cache(&path, &other_key).unwrap().is_none());
+        assert!(
+            read_cache(&dir.path().join("missing"), &key)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
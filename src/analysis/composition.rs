@@ -0,0 +1,229 @@
+use ahash::AHashMap;
+use fixedbitset::FixedBitSet;
+use serde::Serialize;
+use std::{borrow::Cow, cmp::Reverse};
+
+use crate::{
+    analysis::duplicates::DuplicateAnalyzer,
+    graph::v8_heap_graph::{NodeType, V8HeapGraph},
+    types::NodeId,
+};
+
+/// Number and total self size of all nodes of a single node type
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeStats {
+    pub type_name: String,
+    pub count: usize,
+    pub total_self_size: usize,
+    /// Share of the total heap self size, between 0 and 100
+    pub pct_of_heap: f64,
+}
+
+/// Break the heap down by node type, largest types first
+pub fn heap_composition(graph: &V8HeapGraph) -> Vec<TypeStats> {
+    composition_of(graph, graph.iter_nodes())
+}
+
+/// Break down a subset of the nodes by node type
+///
+/// Percentages are relative to the whole heap, not to the subset.
+pub fn subset_composition(graph: &V8HeapGraph, nodes: &[NodeId]) -> Vec<TypeStats> {
+    composition_of(graph, nodes.iter().copied())
+}
+
+/// Break down the nodes that are not reachable from the root by node type
+///
+/// These objects are garbage that hasn't been collected yet. `reachable` is the result of
+/// `reachable_from_root`. Percentages are relative to the whole heap, not to the
+/// unreachable part.
+pub fn unreachable_composition(graph: &V8HeapGraph, reachable: &FixedBitSet) -> Vec<TypeStats> {
+    composition_of(
+        graph,
+        graph
+            .iter_nodes()
+            .filter(|&n| !reachable.contains(n as usize)),
+    )
+}
+
+/// How much of the heap is strings, and how much of that is redundant
+#[derive(Debug, Clone, Serialize)]
+pub struct StringSummary {
+    /// Flat, concatenated and sliced strings. The snapshot doesn't tell internalized or
+    /// external strings apart from other flat strings.
+    pub categories: Vec<TypeStats>,
+    /// Strings whose content also appears in another string
+    pub duplicated_count: usize,
+    pub unique_count: usize,
+    /// Bytes that would be freed if every duplicated content was stored once
+    pub wasted: u64,
+}
+
+impl StringSummary {
+    pub fn total_count(&self) -> usize {
+        self.categories.iter().map(|c| c.count).sum()
+    }
+
+    pub fn total_self_size(&self) -> usize {
+        self.categories.iter().map(|c| c.total_self_size).sum()
+    }
+}
+
+/// Break down all strings by kind, and count how many of them are duplicates
+///
+/// Duplicates are found the same way as `DuplicateAnalyzer::find_duplicate_strings`, so a
+/// concatenated string counts as a duplicate of a flat string with the same content.
+pub fn string_summary(graph: &V8HeapGraph) -> StringSummary {
+    let strings = graph.iter_nodes().filter(|&n| {
+        matches!(
+            graph.node(n).typ(),
+            NodeType::String | NodeType::ConcatString | NodeType::SlicedString
+        )
+    });
+    let categories = composition_of(graph, strings);
+
+    let groups = DuplicateAnalyzer::new(graph, Default::default()).find_duplicate_strings();
+    let duplicated_count: usize = groups.iter().map(|g| g.count).sum();
+    let total_count: usize = categories.iter().map(|c| c.count).sum();
+
+    StringSummary {
+        categories,
+        duplicated_count,
+        unique_count: total_count - duplicated_count,
+        wasted: groups.iter().map(|g| g.total_wasted).sum(),
+    }
+}
+
+fn composition_of(graph: &V8HeapGraph, nodes: impl Iterator<Item = NodeId>) -> Vec<TypeStats> {
+    let heap_size: usize = graph.iter_nodes().map(|n| graph.self_size_for(n)).sum();
+
+    let mut by_type = AHashMap::<Cow<str>, (usize, usize)>::new();
+    for node in nodes {
+        let node = graph.node(node);
+        let entry = by_type.entry(node.typ_str()).or_default();
+        entry.0 += 1;
+        entry.1 += graph.self_size_for(node.id);
+    }
+
+    let mut stats: Vec<_> = by_type
+        .into_iter()
+        .map(|(type_name, (count, total_self_size))| TypeStats {
+            type_name: type_name.to_string(),
+            count,
+            total_self_size,
+            pct_of_heap: if heap_size > 0 {
+                total_self_size as f64 * 100.0 / heap_size as f64
+            } else {
+                0.0
+            },
+        })
+        .collect();
+    stats.sort_by(|a, b| {
+        Reverse(a.total_self_size)
+            .cmp(&Reverse(b.total_self_size))
+            .then_with(|| a.type_name.cmp(&b.type_name))
+    });
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::all_paths::reachable_from_root;
+    use crate::graph::{
+        test_graph::TestGraph,
+        v8_heap_graph::{EdgeType, NodeType},
+    };
+
+    #[test]
+    fn test_heap_composition() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 30);
+        let b = g.node(NodeType::Object, "B", 30);
+        let s = g.node(NodeType::String, "s", 20);
+        let c = g.node(NodeType::Code, "c", 20);
+        g.element(root, 0, a)
+            .element(root, 1, b)
+            .element(root, 2, s)
+            .element(root, 3, c);
+        let graph = g.build();
+
+        let stats = heap_composition(&graph);
+
+        assert_eq!(stats.len(), 4);
+        assert_eq!(stats[0].type_name, "object");
+        assert_eq!(stats[0].count, 2);
+        assert_eq!(stats[0].total_self_size, 60);
+        assert!((stats[0].pct_of_heap - 60.0).abs() < 1e-9);
+
+        let total: f64 = stats.iter().map(|s| s.pct_of_heap).sum();
+        assert!((total - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_string_summary() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let hello1 = g.node(NodeType::String, "hello", 20);
+        let hello2 = g.node(NodeType::String, "hello", 20);
+        let unique = g.node(NodeType::String, "unique", 24);
+        let world = g.node(NodeType::String, "world", 20);
+        let cons = g.node(NodeType::ConcatString, "hello world", 32);
+        let slice = g.node(NodeType::SlicedString, "ell", 16);
+        let obj = g.node(NodeType::Object, "Obj", 100);
+        g.element(root, 0, hello1)
+            .element(root, 1, hello2)
+            .element(root, 2, unique)
+            .element(root, 3, cons)
+            .element(root, 4, slice)
+            .element(root, 5, obj)
+            .edge(cons, EdgeType::Internal, "first", hello1)
+            .edge(cons, EdgeType::Internal, "second", world)
+            .edge(slice, EdgeType::Internal, "parent", hello1);
+        let graph = g.build();
+
+        let summary = string_summary(&graph);
+        let category = |name: &str| {
+            let c = summary
+                .categories
+                .iter()
+                .find(|c| c.type_name == name)
+                .unwrap();
+            (c.count, c.total_self_size)
+        };
+        assert_eq!(category("string"), (4, 84));
+        assert_eq!(category("concatenated string"), (1, 32));
+        assert_eq!(category("sliced string"), (1, 16));
+        assert_eq!(summary.total_count(), 6);
+        assert_eq!(summary.total_self_size(), 132);
+
+        // Only the two "hello"s have the same content
+        assert_eq!(summary.duplicated_count, 2);
+        assert_eq!(summary.unique_count, 4);
+        assert_eq!(summary.wasted, 20);
+    }
+
+    #[test]
+    fn test_unreachable_composition() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 30);
+        let orphan = g.node(NodeType::Object, "Orphan", 50);
+        let orphan_child = g.node(NodeType::String, "s", 20);
+        g.element(root, 0, a)
+            .edge(orphan, EdgeType::Property, "s", orphan_child)
+            // Only weakly held
+            .edge(a, EdgeType::Weak, "o", orphan);
+        let graph = g.build();
+
+        let stats = unreachable_composition(&graph, &reachable_from_root(&graph));
+
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].type_name, "object");
+        assert_eq!(stats[0].count, 1);
+        assert_eq!(stats[0].total_self_size, 50);
+        assert!((stats[0].pct_of_heap - 50.0).abs() < 1e-9);
+        assert_eq!(stats[1].type_name, "string");
+        assert_eq!(stats[1].total_self_size, 20);
+    }
+}
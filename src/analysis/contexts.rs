@@ -0,0 +1,108 @@
+use crate::{
+    graph::v8_heap_graph::{EdgeType, NodeType, V8HeapGraph},
+    types::NodeId,
+};
+
+const NATIVE_CONTEXT: &str = "system / NativeContext";
+
+/// A V8 context: a realm with its own global object and builtins
+///
+/// A page with iframes, or a Node.js process with worker threads or `vm` contexts, has one
+/// of these for every realm. Everything the realm's code keeps alive hangs off its native
+/// context.
+#[derive(Debug, Clone)]
+pub struct ContextRoot {
+    pub native_context: NodeId,
+    /// The global object (`globalThis`) of the context, if the snapshot has it
+    pub global_proxy: Option<NodeId>,
+    /// The URL that V8 appends to the name of the native context, if any
+    pub url: Option<String>,
+}
+
+/// All native contexts in the snapshot, in node order
+///
+/// The position in this list is the context number that `--context` takes.
+pub fn find_contexts(graph: &V8HeapGraph) -> Vec<ContextRoot> {
+    graph
+        .iter_nodes()
+        .filter_map(|n| {
+            let node = graph.node(n);
+            if node.typ() != NodeType::Hidden {
+                return None;
+            }
+            let url = node.name().strip_prefix(NATIVE_CONTEXT)?;
+            let url = match url.strip_prefix(" / ") {
+                Some(url) => Some(url.to_string()),
+                None if url.is_empty() => None,
+                None => return None,
+            };
+            Some(ContextRoot {
+                native_context: n,
+                global_proxy: graph.find_edge(n, EdgeType::Internal, "global_proxy_object"),
+                url,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::dominator_tree::tree_from_immediate_dominators,
+        graph::{lengauer_tarjan::lengauer_tarjan, test_graph::TestGraph},
+    };
+
+    #[test]
+    fn test_two_contexts() {
+        // A main thread and a worker, each with its own global and some data of their own
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "", 0);
+        let gc_roots = g.node(NodeType::Synthetic, "(GC roots)", 0);
+        let handles = g.node(NodeType::Synthetic, "(Global handles)", 0);
+        g.element(root, 1, gc_roots).element(gc_roots, 1, handles);
+
+        let mut contexts = vec![];
+        for (i, (name, size)) in [
+            ("system / NativeContext / file:///main.js", 1000),
+            ("system / NativeContext", 300),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let context = g.node(NodeType::Hidden, name, 100);
+            let global = g.node(NodeType::Object, "global", 50);
+            let data = g.node(NodeType::Array, "Array", size);
+            g.element(handles, i as NodeId, context)
+                .edge(root, EdgeType::Shortcut, "global", global)
+                .edge(context, EdgeType::Internal, "global_proxy_object", global)
+                .edge(global, EdgeType::Property, "data", data);
+            contexts.push((context, global));
+        }
+        // Not a native context, whatever its name says
+        let other = g.node(NodeType::String, "system / NativeContext", 10);
+        g.element(root, 2, other);
+        let graph = g.build();
+
+        let found = find_contexts(&graph);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].native_context, contexts[0].0);
+        assert_eq!(found[0].global_proxy, Some(contexts[0].1));
+        assert_eq!(found[0].url.as_deref(), Some("file:///main.js"));
+        assert_eq!(found[1].native_context, contexts[1].0);
+        assert_eq!(found[1].url, None);
+
+        // Scoped to a context, only that context's objects are analyzed
+        for (context, retained) in [
+            (found[0].native_context, 1150),
+            (found[1].native_context, 450),
+        ] {
+            let tree = tree_from_immediate_dominators(
+                lengauer_tarjan(&graph, &[context]),
+                context,
+                &graph,
+            );
+            assert_eq!(tree.retained_size(context), retained);
+        }
+    }
+}
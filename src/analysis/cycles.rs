@@ -0,0 +1,113 @@
+use ahash::AHashMap;
+use itertools::Itertools;
+use petgraph::algo::kosaraju_scc;
+use std::{borrow::Cow, cmp::Reverse};
+
+use crate::{
+    graph::{petgraph_traits::StrongEdges, v8_heap_graph::V8HeapGraph},
+    types::NodeId,
+};
+
+/// A group of objects that all (transitively) retain each other
+#[derive(Debug)]
+pub struct RetentionCycle {
+    pub nodes: Vec<NodeId>,
+    pub total_self_size: usize,
+    /// Node types of the members, with the number of members of every type
+    pub member_types: Vec<(String, usize)>,
+}
+
+/// Find all retention cycles in the graph
+///
+/// These are the non-trivial strongly connected components over non-weak edges: sets of
+/// objects that keep each other alive. Components of a single node are not reported.
+/// Results are sorted by total self size, descending.
+pub fn find_retention_cycles(graph: &V8HeapGraph) -> Vec<RetentionCycle> {
+    let mut cycles = kosaraju_scc(StrongEdges(graph))
+        .into_iter()
+        .filter(|scc| scc.len() > 1)
+        .map(|nodes| {
+            let total_self_size = nodes.iter().map(|&n| graph.self_size_for(n)).sum();
+
+            let mut member_types = AHashMap::<Cow<str>, usize>::new();
+            for &n in &nodes {
+                *member_types.entry(graph.node(n).typ_str()).or_default() += 1;
+            }
+            let member_types = member_types
+                .into_iter()
+                .map(|(typ, count)| (typ.to_string(), count))
+                .sorted_by_key(|(typ, count)| (Reverse(*count), typ.clone()))
+                .collect();
+
+            RetentionCycle {
+                nodes,
+                total_self_size,
+                member_types,
+            }
+        })
+        .collect_vec();
+
+    cycles.sort_by_key(|c| (Reverse(c.total_self_size), c.nodes[0]));
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{
+        test_graph::TestGraph,
+        v8_heap_graph::{EdgeType, NodeType},
+    };
+
+    #[test]
+    fn test_two_node_cycle() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "(GC roots)", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 20);
+        let c = g.node(NodeType::Object, "C", 30);
+        let d = g.node(NodeType::Object, "D", 40);
+        g.element(root, 0, a)
+            .edge(a, EdgeType::Property, "b", b)
+            .edge(b, EdgeType::Property, "a", a)
+            // A cycle through a weak edge doesn't retain anything
+            .edge(root, EdgeType::Property, "c", c)
+            .edge(c, EdgeType::Property, "d", d)
+            .edge(d, EdgeType::Weak, "c", c);
+        let graph = g.build();
+
+        let cycles = find_retention_cycles(&graph);
+
+        assert_eq!(cycles.len(), 1);
+        let mut nodes = cycles[0].nodes.clone();
+        nodes.sort();
+        assert_eq!(nodes, vec![a, b]);
+        assert_eq!(cycles[0].total_self_size, 30);
+        assert_eq!(cycles[0].member_types, vec![("object".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_cycle_on_deep_chain() {
+        // A linked list that is far deeper than a recursive traversal could handle, closed
+        // into one big ring
+        const DEPTH: NodeId = 100_000;
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let first = g.node(NodeType::Object, "Link", 10);
+        g.edge(root, EdgeType::Property, "first", first);
+        let mut prev = first;
+        for _ in 1..DEPTH {
+            let next = g.node(NodeType::Object, "Link", 10);
+            g.edge(prev, EdgeType::Property, "next", next);
+            prev = next;
+        }
+        g.edge(prev, EdgeType::Property, "next", first);
+        let graph = g.build();
+
+        let cycles = find_retention_cycles(&graph);
+
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].nodes.len(), DEPTH as usize);
+        assert_eq!(cycles[0].total_self_size, 10 * DEPTH as usize);
+    }
+}
@@ -0,0 +1,94 @@
+use std::cmp::Reverse;
+
+use serde::Serialize;
+
+use crate::{
+    analysis::dominator_tree::DominatorTree,
+    graph::v8_heap_graph::{NodeType, V8HeapGraph},
+    types::NodeId,
+};
+
+/// Name of the synthetic node that Blink hangs detached DOM trees off
+pub const DETACHED_DOM_TREES: &str = "(Detached DOM trees)";
+
+/// The DOM trees that are no longer in a document, but still in memory
+///
+/// In a web app these are almost always leaks: an event listener or a cache holding on to
+/// an element after it was removed from the page.
+#[derive(Debug, Clone, Serialize)]
+pub struct DetachedDomReport {
+    /// The `(Detached DOM trees)` node
+    pub group: NodeId,
+    /// Retained size of the group node, i.e. everything only reachable through detached trees
+    pub total_retained_size: usize,
+    /// The detached trees with their retained sizes, largest first
+    pub trees: Vec<(NodeId, usize)>,
+}
+
+/// Find the `(Detached DOM trees)` node and list the trees below it
+///
+/// `None` if the snapshot has no such node, for example because it was taken in Node.js.
+pub fn find_detached_dom_trees(
+    graph: &V8HeapGraph,
+    tree: &DominatorTree,
+) -> Option<DetachedDomReport> {
+    let group = graph.iter_nodes().find(|&n| {
+        let node = graph.node(n);
+        node.typ() == NodeType::Synthetic && node.name() == DETACHED_DOM_TREES
+    })?;
+
+    let mut trees: Vec<(NodeId, usize)> = graph
+        .out_neighbors(group)
+        .iter()
+        .map(|&n| (n, tree.retained_size(n)))
+        .collect();
+    trees.sort_by_key(|&(n, size)| (Reverse(size), n));
+    trees.dedup_by_key(|&mut (n, _)| n);
+
+    Some(DetachedDomReport {
+        group,
+        total_retained_size: tree.retained_size(group),
+        trees,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::dominator_tree::tree_from_immediate_dominators,
+        graph::{lengauer_tarjan::lengauer_tarjan, test_graph::TestGraph, v8_heap_graph::EdgeType},
+    };
+
+    #[test]
+    fn test_find_detached_dom_trees() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "", 0);
+        let gc_roots = g.node(NodeType::Synthetic, "(GC roots)", 0);
+        let detached = g.node(NodeType::Synthetic, DETACHED_DOM_TREES, 0);
+        let small = g.node(NodeType::Native, "Detached DOM tree / 3 entries", 0);
+        let big = g.node(NodeType::Native, "Detached DOM tree / 40 entries", 0);
+        let div = g.node(NodeType::Native, "Detached HTMLDivElement", 400);
+        let span = g.node(NodeType::Native, "Detached HTMLSpanElement", 30);
+        g.element(root, 1, gc_roots)
+            .element(root, 2, detached)
+            .element(detached, 1, small)
+            .element(detached, 2, big)
+            .edge(big, EdgeType::Internal, "div", div)
+            .edge(small, EdgeType::Internal, "span", span);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        let report = find_detached_dom_trees(&graph, &tree).unwrap();
+        assert_eq!(report.group, detached);
+        assert_eq!(report.trees, vec![(big, 400), (small, 30)]);
+        assert_eq!(report.total_retained_size, 430);
+
+        // Nothing to report in a snapshot without detached DOM
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "", 0);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        assert!(find_detached_dom_trees(&graph, &tree).is_none());
+    }
+}
@@ -1,6 +1,16 @@
-use std::collections::HashMap;
+use std::{cmp::Reverse, collections::HashMap};
 
-use crate::{graph::v8_heap_graph::V8HeapGraph, types::NodeId};
+use fixedbitset::FixedBitSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph::{
+        lengauer_tarjan::{GraphOps, lengauer_tarjan},
+        lengauer_tarjan_traits::{SeededRoots, WithoutEdgesFrom},
+        v8_heap_graph::{NodeType, V8HeapGraph},
+    },
+    types::NodeId,
+};
 
 #[derive(Debug, Default)]
 pub struct DominatorNode {
@@ -8,7 +18,10 @@ pub struct DominatorNode {
     pub children: Vec<NodeId>,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct DominatorTree {
+    /// The node the tree was computed from
+    pub root: NodeId,
     pub children: HashMap<NodeId, Vec<NodeId>>,
     pub retained_sizes: Vec<usize>,
 }
@@ -17,13 +30,75 @@ impl DominatorTree {
     pub fn retained_size(&self, node_id: NodeId) -> usize {
         self.retained_sizes[node_id as usize]
     }
+
+    /// Whether the root exists, which is only not the case for an empty graph
+    pub fn has_root(&self) -> bool {
+        (self.root as usize) < self.retained_sizes.len()
+    }
+
+    /// The immediate dominator of every node in the tree, except the root
+    pub fn parents(&self) -> HashMap<NodeId, NodeId> {
+        self.children
+            .iter()
+            .flat_map(|(&parent, children)| children.iter().map(move |&child| (child, parent)))
+            .collect()
+    }
+
+    /// All nodes that `node` dominates, directly or indirectly, not including `node` itself
+    ///
+    /// These are exactly the nodes that would be freed along with `node`. Depth-first, in
+    /// no particular order.
+    pub fn descendants(&self, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let children = |n: NodeId| self.children.get(&n).into_iter().flatten().copied();
+        let mut stack: Vec<NodeId> = children(node).collect();
+        std::iter::from_fn(move || {
+            let next = stack.pop()?;
+            stack.extend(children(next));
+            Some(next)
+        })
+    }
+
+    /// All nodes in the tree, every node after all of its children, ending with the root
+    ///
+    /// Iterative, so it works on trees of any depth. Empty if the tree has no root.
+    pub fn post_order(&self) -> Vec<NodeId> {
+        let mut order = Vec::with_capacity(self.retained_sizes.len());
+        if !self.has_root() {
+            return order;
+        }
+
+        // A node is pushed a second time, marked as done, before its children
+        let mut stack = vec![(self.root, false)];
+        while let Some((node, children_done)) = stack.pop() {
+            if children_done {
+                order.push(node);
+                continue;
+            }
+            stack.push((node, true));
+            if let Some(children) = self.children.get(&node) {
+                stack.extend(children.iter().rev().map(|&child| (child, false)));
+            }
+        }
+        order
+    }
+
+    /// All nodes except the root that retain at least `min_retained` bytes, largest first
+    pub fn nodes_retaining_at_least(&self, min_retained: usize) -> Vec<NodeId> {
+        let mut nodes: Vec<NodeId> = (0..self.retained_sizes.len() as NodeId)
+            .filter(|&n| n != self.root && self.retained_size(n) >= min_retained)
+            .collect();
+        nodes.sort_by_key(|&n| (Reverse(self.retained_size(n)), n));
+        nodes
+    }
 }
 
 pub fn tree_from_immediate_dominators(
     immediate_dominators: impl IntoIterator<Item = (NodeId, NodeId)>,
+    root: NodeId,
     graph: &V8HeapGraph,
 ) -> DominatorTree {
     let mut ret = DominatorTree {
+        root,
         children: Default::default(),
         retained_sizes: vec![0; graph.total_node_count()],
     };
@@ -33,25 +108,463 @@ pub fn tree_from_immediate_dominators(
         children.push(node_id);
     }
 
-    annotate_retained_sizes(0, &ret.children, &mut ret.retained_sizes, graph);
+    if ret.has_root() {
+        annotate_retained_sizes(&mut ret, graph);
+    }
 
     ret
 }
 
-fn annotate_retained_sizes(
+/// Add up the self sizes of every subtree, children before their parents
+///
+/// Iterative, so that very deep trees (long linked lists) don't overflow the stack.
+fn annotate_retained_sizes(tree: &mut DominatorTree, graph: &V8HeapGraph) {
+    for node in tree.post_order() {
+        let children_size: usize = tree
+            .children
+            .get(&node)
+            .into_iter()
+            .flatten()
+            .map(|&child| tree.retained_sizes[child as usize])
+            .sum();
+        tree.retained_sizes[node as usize] = graph.self_size_for(node) + children_size;
+    }
+}
+
+/// The dominator tree as if `owners` didn't hold on to anything
+///
+/// Use this when an object is expected to retain something, like a cache, to find out what
+/// *else* retains it: the objects that gain retained size compared to the normal tree are
+/// the secondary retainers. The owners themselves only retain their own size.
+pub fn tree_without_edges_from(
+    graph: &V8HeapGraph,
     root: NodeId,
-    children: &HashMap<NodeId, Vec<NodeId>>,
-    retained_sizes: &mut Vec<usize>,
+    owners: &[NodeId],
+) -> DominatorTree {
+    let view = WithoutEdgesFrom { graph, owners };
+    tree_from_immediate_dominators(lengauer_tarjan(&view, &[root]), root, graph)
+}
+
+/// The dominator tree of what `roots` keep alive, instead of the GC roots
+///
+/// The snapshot root (node 0) becomes a virtual root that only holds on to `roots`, so it is
+/// still the root of the tree and retains exactly what the seeds retain together. Use this
+/// to ask what a set of objects, like all instances of a class, keeps alive between them.
+pub fn dominator_tree_from_roots(graph: &V8HeapGraph, roots: &[NodeId]) -> DominatorTree {
+    let view = SeededRoots::new(graph, roots);
+    tree_from_immediate_dominators(lengauer_tarjan(&view, &[0]), 0, graph)
+}
+
+/// Sum the self sizes of everything a node retains, grouped by node type, largest first
+///
+/// The node itself is included, so the sizes add up to its retained size.
+pub fn dominated_type_breakdown(
+    node: NodeId,
+    tree: &DominatorTree,
     graph: &V8HeapGraph,
-) -> usize {
-    retained_sizes[root as usize] = graph.self_size_for(root);
+) -> Vec<(NodeType, usize)> {
+    let mut sizes = HashMap::<NodeType, usize>::new();
+    let mut stack = vec![node];
+    while let Some(n) = stack.pop() {
+        *sizes.entry(graph.node(n).typ()).or_default() += graph.self_size_for(n);
+        if let Some(children) = tree.children.get(&n) {
+            stack.extend(children);
+        }
+    }
+
+    let mut ret: Vec<_> = sizes.into_iter().collect();
+    ret.sort_by_key(|&(typ, size)| (Reverse(size), typ.index()));
+    ret
+}
+
+/// Nodes that retain far more than their own size, highest `retained / self` ratio first
+///
+/// These are container-style leaks: a small map or array that holds on to a huge subtree.
+/// Only nodes that retain at least `min_retained` bytes are considered, so that a tiny
+/// object with a tiny child doesn't drown out the containers that matter. Synthetic nodes
+/// are skipped, and a self size of 0 counts as 1 byte.
+pub fn nodes_by_retained_ratio(
+    tree: &DominatorTree,
+    graph: &V8HeapGraph,
+    min_retained: usize,
+) -> Vec<(NodeId, f64)> {
+    let mut ret: Vec<(NodeId, f64)> = tree
+        .nodes_retaining_at_least(min_retained)
+        .into_iter()
+        .filter(|&n| graph.node(n).typ() != NodeType::Synthetic)
+        .map(|n| {
+            let ratio = tree.retained_size(n) as f64 / graph.self_size_for(n).max(1) as f64;
+            (n, ratio)
+        })
+        .collect();
+    ret.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ret
+}
+
+/// Calculate the retained size of a single node, without building the full dominator tree
+///
+/// The retained size of a node is the size of everything that would become unreachable from
+/// the root if the node were removed. We find it by traversing the graph from the root while
+/// refusing to go through `node`; everything reachable from `node` that this traversal did
+/// not visit is retained by it.
+///
+/// This follows the same (non-weak) edges as the dominator calculation. Like in the dominator
+/// tree, nodes that are not reachable from the root retain nothing.
+pub fn retained_size_of(graph: &V8HeapGraph, root: NodeId, node: NodeId) -> usize {
+    let mut seen = FixedBitSet::with_capacity(graph.total_node_count());
+    seen.insert(node as usize);
 
-    if let Some(immediate_children) = children.get(&root) {
-        for child in immediate_children {
-            retained_sizes[root as usize] +=
-                annotate_retained_sizes(*child, children, retained_sizes, graph);
+    let mut stack = vec![];
+    if !seen.put(root as usize) {
+        stack.push(root);
+    }
+    while let Some(n) = stack.pop() {
+        for succ in graph.successors(n) {
+            if !seen.put(succ as usize) {
+                stack.push(succ);
+            }
         }
+    }
+
+    let reached = |from: NodeId| from != node && seen.contains(from as usize);
+    if node != root && !graph.predecessors(node).any(reached) {
+        return 0;
+    }
+
+    let mut retained_size = graph.self_size_for(node);
+    stack.push(node);
+    while let Some(n) = stack.pop() {
+        for succ in graph.successors(n) {
+            if !seen.put(succ as usize) {
+                retained_size += graph.self_size_for(succ);
+                stack.push(succ);
+            }
+        }
+    }
+    retained_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{
+        lengauer_tarjan::lengauer_tarjan,
+        test_graph::TestGraph,
+        v8_heap_graph::{EdgeType, NodeType},
     };
 
-    retained_sizes[root as usize]
+    #[test]
+    fn test_tree_without_edges_from() {
+        // root -> owner -> cache -> entries
+        //      \-> other ---^
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let owner = g.node(NodeType::Object, "Owner", 10);
+        let other = g.node(NodeType::Object, "Other", 10);
+        let cache = g.node(NodeType::Object, "Cache", 100);
+        let entries = g.node(NodeType::Array, "(object elements)", 1000);
+        let private = g.node(NodeType::Object, "Private", 50);
+        g.edge(root, EdgeType::Property, "owner", owner)
+            .edge(root, EdgeType::Property, "other", other)
+            .edge(owner, EdgeType::Property, "cache", cache)
+            .edge(owner, EdgeType::Property, "private", private)
+            .edge(other, EdgeType::Property, "leak", cache)
+            .edge(cache, EdgeType::Internal, "elements", entries);
+        let graph = g.build();
+
+        // Normally the cache is shared, so neither of them retains it
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        assert_eq!(tree.retained_size(owner), 60);
+        assert_eq!(tree.retained_size(other), 10);
+
+        // Without the owner's edges, the other retainer turns out to hold on to the cache
+        let tree = tree_without_edges_from(&graph, root, &[owner]);
+        assert_eq!(tree.retained_size(owner), 10);
+        assert_eq!(tree.retained_size(other), 1110);
+        assert_eq!(tree.retained_size(private), 0);
+        assert_eq!(tree.retained_size(root), 1120);
+    }
+
+    #[test]
+    fn test_pruned_subtree() {
+        // root -> modules -> loaded -> source
+        //      \-> app -------^
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let modules = g.node(NodeType::Object, "Modules", 10);
+        let app = g.node(NodeType::Object, "App", 10);
+        let loaded = g.node(NodeType::Object, "Module", 100);
+        let source = g.node(NodeType::String, "module source", 5000);
+        let cache = g.node(NodeType::Object, "ModuleCache", 800);
+        g.edge(root, EdgeType::Property, "modules", modules)
+            .edge(root, EdgeType::Property, "app", app)
+            .edge(modules, EdgeType::Property, "loaded", loaded)
+            .edge(modules, EdgeType::Internal, "cache", cache)
+            .edge(app, EdgeType::Property, "module", loaded)
+            .edge(loaded, EdgeType::Internal, "source", source);
+        let graph = g.build();
+
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        assert_eq!(tree.retained_size(modules), 810);
+        assert_eq!(tree.retained_size(app), 10);
+
+        // Pruned, the modules keep their own size, and what they share moves to a sibling
+        let tree = tree_without_edges_from(&graph, root, &[modules]);
+        assert_eq!(tree.retained_size(modules), 10);
+        assert_eq!(tree.retained_size(app), 5110);
+        assert!(!tree.children.values().flatten().any(|&n| n == cache));
+        assert_eq!(tree.retained_size(root), 5120);
+    }
+
+    #[test]
+    fn test_dominator_tree_from_roots() {
+        // root -> window -> a -> shared
+        //                   b ---^
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let window = g.node(NodeType::Object, "Window", 10);
+        let a = g.node(NodeType::Object, "Session", 20);
+        let b = g.node(NodeType::Object, "Session", 30);
+        let shared = g.node(NodeType::Object, "Store", 400);
+        let unrelated = g.node(NodeType::Object, "Other", 5000);
+        g.edge(root, EdgeType::Property, "window", window)
+            .edge(window, EdgeType::Property, "a", a)
+            .edge(window, EdgeType::Property, "b", b)
+            .edge(window, EdgeType::Property, "other", unrelated)
+            .edge(a, EdgeType::Property, "store", shared)
+            .edge(b, EdgeType::Property, "store", shared);
+        let graph = g.build();
+
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        assert_eq!(tree.retained_size(root), 5460);
+        assert_eq!(tree.retained_size(a), 20);
+
+        // Seeded from both sessions, only what they hold is in the tree, under the root
+        let tree = dominator_tree_from_roots(&graph, &[a, b]);
+        assert_eq!(tree.root, root);
+        assert_eq!(tree.retained_size(root), 450);
+        let mut children = tree.children[&root].clone();
+        children.sort_unstable();
+        assert_eq!(children, vec![a, b, shared]);
+        assert_eq!(tree.retained_size(window), 0);
+        assert_eq!(tree.retained_size(unrelated), 0);
+
+        // A single seed retains everything it reaches
+        let tree = dominator_tree_from_roots(&graph, &[a]);
+        assert_eq!(tree.retained_size(a), 420);
+
+        // Seeding the snapshot root is the same as the normal tree
+        let tree = dominator_tree_from_roots(&graph, &[root]);
+        assert_eq!(tree.retained_size(root), 5460);
+        assert_eq!(tree.retained_size(window), 5460);
+    }
+
+    #[test]
+    fn test_post_order_on_deep_chain() {
+        // A linked list that is far deeper than a recursive traversal could handle
+        const DEPTH: NodeId = 100_000;
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let mut prev = root;
+        for _ in 0..DEPTH {
+            let next = g.node(NodeType::Object, "Link", 10);
+            g.edge(prev, EdgeType::Property, "next", next);
+            prev = next;
+        }
+        let graph = g.build();
+
+        // Every link is dominated by the one before it
+        let tree = tree_from_immediate_dominators((1..=DEPTH).map(|n| (n, n - 1)), root, &graph);
+
+        let order = tree.post_order();
+        assert_eq!(order.len(), DEPTH as usize + 1);
+        assert_eq!(order.first(), Some(&DEPTH));
+        assert_eq!(order.last(), Some(&root));
+        assert_eq!(tree.retained_size(root), 10 * DEPTH as usize);
+        assert_eq!(tree.retained_size(DEPTH / 2), 10 * (DEPTH / 2 + 1) as usize);
+    }
+
+    #[test]
+    fn test_descendants() {
+        // root -> a -> b -> c
+        //           \-> d
+        // root -> e
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 10);
+        let c = g.node(NodeType::Object, "C", 10);
+        let d = g.node(NodeType::Object, "D", 10);
+        let e = g.node(NodeType::Object, "E", 10);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "e", e)
+            .edge(a, EdgeType::Property, "b", b)
+            .edge(a, EdgeType::Property, "d", d)
+            .edge(b, EdgeType::Property, "c", c);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        let mut below_a: Vec<_> = tree.descendants(a).collect();
+        below_a.sort();
+        assert_eq!(below_a, vec![b, c, d]);
+        assert_eq!(tree.descendants(root).count(), 5);
+        assert_eq!(tree.descendants(e).count(), 0);
+    }
+
+    #[test]
+    fn test_nodes_by_retained_ratio() {
+        // A tiny map holding on to a lot of entries, next to a big object holding nothing
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let map = g.node(NodeType::Object, "Map", 16);
+        let table = g.node(NodeType::Array, "(object elements)", 64);
+        let big = g.node(NodeType::Object, "Big", 50_000);
+        let tiny = g.node(NodeType::Object, "Tiny", 1);
+        let tiny_child = g.node(NodeType::Object, "TinyChild", 100);
+        g.edge(root, EdgeType::Property, "map", map)
+            .edge(root, EdgeType::Property, "big", big)
+            .edge(root, EdgeType::Property, "tiny", tiny)
+            .edge(map, EdgeType::Internal, "table", table)
+            .edge(tiny, EdgeType::Property, "child", tiny_child);
+        for i in 0..100 {
+            let entry = g.node(NodeType::Object, "Entry", 1000);
+            g.element(table, i, entry);
+        }
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        let ratios = nodes_by_retained_ratio(&tree, &graph, 10_000);
+        let nodes: Vec<_> = ratios.iter().map(|&(n, _)| n).collect();
+        assert_eq!(nodes, vec![map, table, big]);
+        assert_eq!(ratios[0].1, 100_080.0 / 16.0);
+        assert_eq!(ratios[2].1, 1.0);
+
+        // Without a threshold, tiny objects with a relatively big child show up too
+        let all = nodes_by_retained_ratio(&tree, &graph, 0);
+        assert!(all.contains(&(tiny, 101.0)));
+    }
+
+    #[test]
+    fn test_retained_size_of_matches_dominator_tree() {
+        // root -> a -> b -> d
+        //           \-> c -/
+        // root -> e -> c
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 20);
+        let c = g.node(NodeType::Object, "C", 30);
+        let d = g.node(NodeType::Object, "D", 40);
+        let e = g.node(NodeType::Object, "E", 50);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "e", e)
+            .edge(a, EdgeType::Property, "b", b)
+            .edge(a, EdgeType::Property, "c", c)
+            .edge(b, EdgeType::Property, "d", d)
+            .edge(c, EdgeType::Property, "d", d)
+            .edge(e, EdgeType::Property, "c", c)
+            .edge(e, EdgeType::Weak, "a", a);
+        let graph = g.build();
+
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        for node in graph.iter_nodes() {
+            assert_eq!(
+                retained_size_of(&graph, root, node),
+                tree.retained_size(node),
+                "retained size of node {}",
+                node
+            );
+        }
+        assert_eq!(retained_size_of(&graph, root, a), 10 + 20);
+    }
+
+    #[test]
+    fn test_dominator_tree_from_non_zero_root() {
+        // root -> cache -> a -> b
+        //              \-> c -/
+        // root -> b
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let cache = g.node(NodeType::Object, "Cache", 5);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 20);
+        let c = g.node(NodeType::Object, "C", 30);
+        g.edge(root, EdgeType::Property, "cache", cache)
+            .edge(root, EdgeType::Property, "b", b)
+            .edge(cache, EdgeType::Property, "a", a)
+            .edge(cache, EdgeType::Property, "c", c)
+            .edge(a, EdgeType::Property, "b", b)
+            .edge(c, EdgeType::Property, "b", b);
+        let graph = g.build();
+
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[cache]), cache, &graph);
+
+        // Seen from the cache, it retains everything below it
+        assert_eq!(tree.root, cache);
+        assert_eq!(tree.retained_size(cache), 5 + 10 + 20 + 30);
+        assert_eq!(tree.retained_size(root), 0);
+        assert_eq!(retained_size_of(&graph, cache, cache), 5 + 10 + 20 + 30);
+
+        // Seen from the real root, b is held from elsewhere
+        assert_eq!(retained_size_of(&graph, root, cache), 5 + 10 + 30);
+    }
+
+    #[test]
+    fn test_dominated_type_breakdown() {
+        // root -> map -> a -> s1
+        //             \-> s2
+        //             \-> arr
+        // root -> s2
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let map = g.node(NodeType::Object, "Map", 10);
+        let a = g.node(NodeType::Object, "A", 20);
+        let s1 = g.node(NodeType::String, "one", 100);
+        let s2 = g.node(NodeType::String, "two", 1000);
+        let arr = g.node(NodeType::Array, "", 50);
+        g.edge(root, EdgeType::Property, "map", map)
+            .edge(root, EdgeType::Property, "s2", s2)
+            .edge(map, EdgeType::Property, "a", a)
+            .edge(map, EdgeType::Property, "s2", s2)
+            .edge(map, EdgeType::Internal, "table", arr)
+            .edge(a, EdgeType::Property, "s1", s1);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        let breakdown = dominated_type_breakdown(map, &tree, &graph);
+
+        assert_eq!(
+            breakdown,
+            vec![
+                (NodeType::String, 100),
+                (NodeType::Array, 50),
+                (NodeType::Object, 30)
+            ]
+        );
+        assert_eq!(
+            breakdown.iter().map(|(_, size)| size).sum::<usize>(),
+            tree.retained_size(map)
+        );
+    }
+
+    #[test]
+    fn test_nodes_retaining_at_least() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let big = g.node(NodeType::Object, "Big", 100);
+        let child = g.node(NodeType::Object, "Child", 900);
+        let small = g.node(NodeType::Object, "Small", 50);
+        let medium = g.node(NodeType::Object, "Medium", 500);
+        g.edge(root, EdgeType::Property, "big", big)
+            .edge(big, EdgeType::Property, "child", child)
+            .edge(root, EdgeType::Property, "small", small)
+            .edge(root, EdgeType::Property, "medium", medium);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        assert_eq!(tree.nodes_retaining_at_least(500), vec![big, child, medium]);
+        assert_eq!(tree.nodes_retaining_at_least(901), vec![big]);
+        assert!(tree.nodes_retaining_at_least(2000).is_empty());
+    }
 }
@@ -0,0 +1,187 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use ahash::AHashMap;
+use serde::Serialize;
+
+use crate::{
+    analysis::duplicates::DuplicateGroup,
+    graph::v8_heap_graph::{EdgeType, NodeType, V8HeapGraph},
+    types::NodeId,
+};
+
+/// A kind of duplicate that has more copies, or wastes more memory, than before
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGrowth {
+    pub object_type: String,
+    pub sample_value: Option<String>,
+    /// A member of the group in the later snapshot
+    pub representative: NodeId,
+    pub count_before: usize,
+    pub count_after: usize,
+    pub wasted_before: u64,
+    pub wasted_after: u64,
+}
+
+impl DuplicateGrowth {
+    pub fn count_delta(&self) -> i64 {
+        self.count_after as i64 - self.count_before as i64
+    }
+
+    pub fn wasted_delta(&self) -> i64 {
+        self.wasted_after as i64 - self.wasted_before as i64
+    }
+}
+
+/// A hash of what a node looks like, without any node ids in it
+///
+/// The hashes in `DuplicateGroup` point at other nodes by index, so they mean nothing in a
+/// different snapshot. This only looks at the node itself and the names of its edges: strings
+/// by content, everything else by type, name, size and the edges it has (the target type, not
+/// the target). Groups with the same object type and structural hash are the same kind of
+/// duplicate in both snapshots.
+pub fn structural_hash(graph: &V8HeapGraph, node_id: NodeId) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let node = graph.node(node_id);
+    if matches!(node.typ(), NodeType::String | NodeType::ConcatString) {
+        graph.resolved_string(node_id).hash(&mut hasher);
+        return hasher.finish();
+    }
+
+    node.typ_str().hash(&mut hasher);
+    node.name().hash(&mut hasher);
+    node.self_size().hash(&mut hasher);
+
+    let mut edges: Vec<_> = graph
+        .out_edges(node_id)
+        .filter(|e| {
+            !matches!(
+                e.typ(),
+                EdgeType::Hidden | EdgeType::Weak | EdgeType::Shortcut
+            )
+        })
+        .map(|e| {
+            (
+                e.typ_str(),
+                e.name_or_index().to_string(),
+                graph.node(e.to_node()).typ_str(),
+            )
+        })
+        .collect();
+    edges.sort();
+    edges.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The duplicate groups that grew between two snapshots, largest increase in waste first
+///
+/// Groups are matched by object type and `structural_hash`. Several groups of one snapshot
+/// can match the same key (objects of the same shape that point at different nodes), so they
+/// are added up. Groups that only exist in `after` count as grown from nothing; groups that
+/// shrank or disappeared are left out.
+pub fn diff_duplicates(
+    before_graph: &V8HeapGraph,
+    before: &[DuplicateGroup],
+    after_graph: &V8HeapGraph,
+    after: &[DuplicateGroup],
+) -> Vec<DuplicateGrowth> {
+    let key = |group: &DuplicateGroup, graph: &V8HeapGraph| {
+        (
+            group.object_type.clone(),
+            structural_hash(graph, group.representative),
+        )
+    };
+
+    let mut totals_before: AHashMap<(String, u64), (usize, u64)> = AHashMap::new();
+    for group in before {
+        let total = totals_before.entry(key(group, before_graph)).or_default();
+        total.0 += group.count;
+        total.1 += group.total_wasted;
+    }
+
+    let mut by_key: AHashMap<(String, u64), DuplicateGrowth> = AHashMap::new();
+    for group in after {
+        let k = key(group, after_graph);
+        let (count_before, wasted_before) = totals_before.get(&k).copied().unwrap_or_default();
+        let growth = by_key.entry(k).or_insert_with(|| DuplicateGrowth {
+            object_type: group.object_type.clone(),
+            sample_value: group.sample_value.clone(),
+            representative: group.representative,
+            count_before,
+            count_after: 0,
+            wasted_before,
+            wasted_after: 0,
+        });
+        // Groups come in order of waste, so the first one is the most representative
+        growth.count_after += group.count;
+        growth.wasted_after += group.total_wasted;
+    }
+
+    let mut grown: Vec<DuplicateGrowth> = by_key
+        .into_values()
+        .filter(|g| g.count_delta() > 0 || g.wasted_delta() > 0)
+        .collect();
+    grown.sort_by(|a, b| {
+        Reverse(a.wasted_delta())
+            .cmp(&Reverse(b.wasted_delta()))
+            .then_with(|| Reverse(a.count_delta()).cmp(&Reverse(b.count_delta())))
+            .then_with(|| a.object_type.cmp(&b.object_type))
+            .then_with(|| a.representative.cmp(&b.representative))
+    });
+    grown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::duplicates::DuplicateAnalyzer, graph::test_graph::TestGraph};
+
+    /// A heap with `strings` copies of a string and `points` objects of the same shape
+    fn heap(strings: usize, points: usize) -> V8HeapGraph {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let mut index = 0;
+        for _ in 0..strings {
+            let s = g.node(NodeType::String, "https://example.com", 40);
+            g.element(root, index, s);
+            index += 1;
+        }
+        let zero = g.node(NodeType::Number, "heap number", 16);
+        for _ in 0..points {
+            let p = g.node(NodeType::Object, "Point", 24);
+            g.element(root, index, p)
+                .edge(p, EdgeType::Property, "x", zero)
+                .edge(p, EdgeType::Property, "y", zero);
+            index += 1;
+        }
+        let unique = g.node(NodeType::String, "only once", 1000);
+        g.element(root, index, unique);
+        g.build()
+    }
+
+    #[test]
+    fn test_diff_duplicates() {
+        let before = heap(3, 2);
+        let after = heap(3, 5);
+        let find = |graph| DuplicateAnalyzer::new(graph, Default::default()).find_duplicates();
+
+        let grown = diff_duplicates(&before, &find(&before), &after, &find(&after));
+        assert_eq!(grown.len(), 1);
+        assert_eq!(grown[0].object_type, "Point");
+        assert_eq!((grown[0].count_before, grown[0].count_after), (2, 5));
+        assert_eq!(grown[0].wasted_delta(), 72);
+
+        // Nothing grew the other way around
+        assert!(diff_duplicates(&after, &find(&after), &before, &find(&before)).is_empty());
+
+        // A kind of duplicate that didn't exist before counts as grown from zero
+        let before = heap(1, 0);
+        let grown = diff_duplicates(&before, &find(&before), &after, &find(&after));
+        assert_eq!(grown.len(), 2);
+        assert_eq!(grown[0].object_type, "Point");
+        assert_eq!(grown[0].count_before, 0);
+        assert_eq!(grown[1].object_type, "String");
+        assert_eq!(grown[1].wasted_delta(), 80);
+    }
+}
@@ -1,16 +1,54 @@
-use crate::graph::CompactGraph;
+use crate::analysis::dominator_tree::DominatorTree;
+use crate::graph::v8_heap_graph::{EdgeType, NodeType, V8HeapGraph, is_noise_node};
 use crate::types::NodeId;
-use crate::utils::escape_string;
-use crate::analysis::retained_size::{calculate_retained_sizes, RetainedSize};
-use ahash::{AHashMap, AHashSet};
+use crate::utils::{format_bytes, print_safe};
+use ahash::AHashMap;
 use serde::Serialize;
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, hash_map::DefaultHasher};
 use std::hash::{Hash, Hasher};
 
-pub struct DuplicateAnalyzer {
-    graph: CompactGraph,
-    include_hidden_classes: bool,
+/// How one kind of duplicate is found: the node types it looks at, the name of the kind, and
+/// the hash that puts identical nodes together (`None` to leave a node out)
+type DuplicateKind<'s, 'a> = (
+    &'s [NodeType],
+    &'s str,
+    fn(&DuplicateAnalyzer<'a>, NodeId) -> Option<u64>,
+);
+
+pub struct DuplicateAnalyzer<'a> {
+    graph: &'a V8HeapGraph,
+    options: DuplicateOptions,
+    /// Only consider these nodes, instead of the whole graph
+    nodes: Option<&'a [NodeId]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateOptions {
+    pub include_hidden_classes: bool,
+    /// Truncate string sample values to this many characters (`None` for the full value)
+    pub sample_max_len: Option<usize>,
+    /// Drop groups with fewer objects than this (at least 2, or they wouldn't be duplicates)
+    pub min_count: usize,
+    /// Drop groups that waste fewer bytes than this
+    pub min_wasted: u64,
+    /// Leave V8 internals out, see `is_noise_node`
+    pub ignore_system: bool,
+    /// Only keep this many groups, the ones that waste the most, see `find_top_duplicates`
+    pub max_groups: Option<usize>,
+}
+
+impl Default for DuplicateOptions {
+    fn default() -> Self {
+        Self {
+            include_hidden_classes: false,
+            sample_max_len: Some(100),
+            min_count: 2,
+            min_wasted: 0,
+            ignore_system: false,
+            max_groups: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -21,73 +59,258 @@ pub struct DuplicateGroup {
     pub size_per_object: u64,
     pub total_wasted: u64,
     pub representative: NodeId,
+    /// The `id` field of the representative, which unlike `representative` identifies it
+    /// across snapshots and in DevTools
+    pub representative_stable_id: NodeId,
+    /// All members, including the representative. Empty in JSON reports written without
+    /// node ids, see `JsonOptions`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub node_ids: Vec<NodeId>,
+    /// The `id` fields of all members, in the same order as `node_ids`. Only filled in on
+    /// request, see `member_stable_ids`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stable_ids: Vec<NodeId>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sample_value: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub owned_retained_size: Option<u64>,
+    pub retained_size: Option<u64>,
+    /// The closest node that dominates every member, see `group_common_dominator`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub common_dominator: Option<NodeId>,
+    /// The first `--tag` rule that matches the group, see `tag_groups`
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub shared_retained_size: Option<u64>,
+    pub tag: Option<String>,
+}
+
+impl DuplicateGroup {
+    /// The `id` fields of all members, which is what DevTools shows as `@123`
+    pub fn member_stable_ids(&self, graph: &V8HeapGraph) -> Vec<NodeId> {
+        self.node_ids
+            .iter()
+            .map(|&n| graph.node(n).stable_id())
+            .collect()
+    }
+}
+
+/// Duplicates of all shapes of a single constructor, added together
+#[derive(Debug, Clone, Serialize)]
+pub struct ConstructorSummary {
+    pub constructor: String,
+    /// How many duplicate groups (distinct shapes) this constructor has
+    pub group_count: usize,
+    /// Total number of objects in those groups
+    pub count: usize,
+    pub total_wasted: u64,
 }
 
-impl DuplicateAnalyzer {
-    pub fn new(graph: CompactGraph, include_hidden_classes: bool) -> Self {
+/// Name used for objects whose constructor has no name
+pub const ANONYMOUS_CONSTRUCTOR: &str = "(anonymous)";
+
+impl<'a> DuplicateAnalyzer<'a> {
+    pub fn new(graph: &'a V8HeapGraph, options: DuplicateOptions) -> Self {
         Self {
             graph,
-            include_hidden_classes,
+            options,
+            nodes: None,
         }
     }
 
+    /// Only group the given nodes, for example a sample of a graph that is too big to analyze
+    ///
+    /// Hashes still look at the whole graph, so members of a group are identical to each
+    /// other, but duplicates outside of the subset are not counted.
+    pub fn restricted_to(mut self, nodes: &'a [NodeId]) -> Self {
+        self.nodes = Some(nodes);
+        self
+    }
+
+    /// Every kind of duplicate `find_duplicates` looks for
+    fn kinds() -> [DuplicateKind<'static, 'a>; 4] {
+        [
+            (
+                &[NodeType::String, NodeType::ConcatString],
+                "String",
+                Self::hash_string_node,
+            ),
+            (&[NodeType::Object], "Object", |analyzer, node_id| {
+                Some(analyzer.hash_object(node_id))
+            }),
+            (&[NodeType::Closure], "Function", Self::hash_closure),
+            (&[NodeType::Native], "ArrayBuffer", Self::hash_array_buffer),
+        ]
+    }
+
+    /// All groups, largest waste first, or only the top `max_groups` if that is set
     pub fn find_duplicates(&self) -> Vec<DuplicateGroup> {
+        if let Some(n) = self.options.max_groups {
+            return self.find_top_duplicates(n);
+        }
+
         let mut all_groups = Vec::new();
-        
-        all_groups.extend(self.find_duplicate_strings());
-        all_groups.extend(self.find_duplicate_objects());
-        
-        // Sort by total wasted memory
-        all_groups.sort_by(|a, b| b.total_wasted.cmp(&a.total_wasted));
-        
+        for kind in Self::kinds() {
+            self.for_each_group(kind, &mut |group| all_groups.push(group));
+        }
+
+        self.sort_by_waste(&mut all_groups);
         all_groups
     }
 
-    /// Enriches duplicate groups with retained size information
-    pub fn enrich_with_retained_sizes(groups: &mut [DuplicateGroup], retained_sizes: &HashMap<NodeId, RetainedSize>) {
+    /// The `n` groups that `find_duplicates` would list first, in the same order
+    ///
+    /// Only the best `n` groups are kept while searching, so this needs much less memory
+    /// than `find_duplicates` on a heap with many small groups.
+    pub fn find_top_duplicates(&self, n: usize) -> Vec<DuplicateGroup> {
+        if n == 0 {
+            return vec![];
+        }
+
+        // The worst of the kept groups is on top, ready to make room for a better one
+        let mut heap = BinaryHeap::new();
+        for kind in Self::kinds() {
+            self.for_each_group(kind, &mut |group| {
+                heap.push(RankedGroup {
+                    key: self.waste_key(&group),
+                    group,
+                });
+                if heap.len() > n {
+                    heap.pop();
+                }
+            });
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|ranked| ranked.group)
+            .collect()
+    }
+
+    /// Sort groups by total wasted memory, in a total order so that reports can be diffed
+    ///
+    /// Ties are broken by object type, then by the stable id of the representative.
+    fn sort_by_waste(&self, groups: &mut [DuplicateGroup]) {
+        groups.sort_by_cached_key(|g| self.waste_key(g));
+    }
+
+    /// The order of `sort_by_waste`, smallest first
+    fn waste_key(&self, group: &DuplicateGroup) -> (Reverse<u64>, String, NodeId) {
+        (
+            Reverse(group.total_wasted),
+            group.object_type.clone(),
+            self.graph.node(group.representative).stable_id(),
+        )
+    }
+
+    /// Enriches duplicate groups with retained size information and their common dominator
+    pub fn enrich_with_retained_sizes(groups: &mut [DuplicateGroup], tree: &DominatorTree) {
+        let parents = tree.parents();
         for group in groups {
-            if let Some(size) = retained_sizes.get(&group.representative) {
-                group.owned_retained_size = Some(size.owned);
-                group.shared_retained_size = Some(size.shared);
-            }
+            group.retained_size = Some(tree.retained_size(group.representative) as u64);
+            group.common_dominator = group_common_dominator(group, tree, &parents);
         }
     }
 
+    /// Find strings with the same content, whether they are flat or concatenated
     pub fn find_duplicate_strings(&self) -> Vec<DuplicateGroup> {
-        self.find_duplicates_by_type(2, "String", |analyzer, node_id| {
-            analyzer.graph.node_name(node_id)
-                .map(|name| analyzer.hash_string(name))
-        })
+        self.find_duplicates_of_kind(Self::kinds()[0])
     }
 
     pub fn find_duplicate_objects(&self) -> Vec<DuplicateGroup> {
-        self.find_duplicates_by_type(3, "Object", |analyzer, node_id| {
-            Some(analyzer.hash_object(node_id))
-        })
+        self.find_duplicates_of_kind(Self::kinds()[1])
+    }
+
+    /// Find closures that were created from the same function, each with its own context
+    ///
+    /// Closures are grouped by their `shared` function info (or their `code` if that's
+    /// missing), not by structure: every instance of a function created in a loop has a
+    /// different context, but they all run the same code.
+    pub fn find_duplicate_closures(&self) -> Vec<DuplicateGroup> {
+        self.find_duplicates_of_kind(Self::kinds()[2])
+    }
+
+    /// Find `ArrayBuffer` backing stores that are probably copies of each other
+    ///
+    /// The snapshot doesn't contain the bytes of a buffer, so this is a heuristic: backing
+    /// stores are grouped by their size, the type of buffer that owns them, and how that
+    /// buffer is retained (the names of the incoming edges and the constructors of their
+    /// owners). The same image decoded twice ends up in the same group. Groups are made of
+    /// the native backing store nodes, so the waste is counted in native bytes.
+    pub fn find_duplicate_array_buffers(&self) -> Vec<DuplicateGroup> {
+        self.find_duplicates_of_kind(Self::kinds()[3])
+    }
+
+    fn find_duplicates_of_kind(&self, kind: DuplicateKind<'_, 'a>) -> Vec<DuplicateGroup> {
+        let mut groups = Vec::new();
+        self.for_each_group(kind, &mut |group| groups.push(group));
+        // Hash map order differs between runs
+        self.sort_by_waste(&mut groups);
+        groups
     }
 
-    fn find_duplicates_by_type<F>(&self, node_type: u8, type_name: &str, hash_fn: F) -> Vec<DuplicateGroup>
-    where
-        F: Fn(&Self, NodeId) -> Option<u64>,
-    {
+    /// Hash every node of this kind, and pass each group of identical nodes to `sink`
+    fn for_each_group(
+        &self,
+        (node_types, type_name, hash_fn): DuplicateKind<'_, 'a>,
+        sink: &mut dyn FnMut(DuplicateGroup),
+    ) {
         let mut hash_map: AHashMap<u64, Vec<NodeId>> = AHashMap::new();
-        
-        for node_id in 0..self.graph.node_count() as NodeId {
-            if self.graph.node_type(node_id).unwrap() == node_type {
-                if let Some(hash) = hash_fn(self, node_id) {
-                    hash_map.entry(hash).or_default().push(node_id);
-                }
+
+        let nodes: Box<dyn Iterator<Item = NodeId>> = match self.nodes {
+            Some(nodes) => Box::new(nodes.iter().copied()),
+            None => Box::new(self.graph.iter_nodes()),
+        };
+        for node_id in nodes {
+            let node = self.graph.node(node_id);
+            if node_types.contains(&node.typ())
+                && !(self.options.ignore_system && is_noise_node(&node))
+                && let Some(hash) = hash_fn(self, node_id)
+            {
+                hash_map.entry(hash).or_default().push(node_id);
             }
         }
-        
-        self.create_groups(hash_map, type_name)
+
+        self.create_groups(hash_map, type_name, sink)
+    }
+
+    fn hash_string_node(&self, node_id: NodeId) -> Option<u64> {
+        Some(self.hash_string(&self.graph.resolved_string(node_id)))
+    }
+
+    fn hash_closure(&self, node_id: NodeId) -> Option<u64> {
+        let graph = self.graph;
+        let code = graph
+            .find_edge(node_id, EdgeType::Internal, "shared")
+            .or_else(|| graph.find_edge(node_id, EdgeType::Internal, "code"))?;
+
+        let mut hasher = DefaultHasher::new();
+        code.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    fn hash_array_buffer(&self, node_id: NodeId) -> Option<u64> {
+        let graph = self.graph;
+        let owner = graph
+            .in_edges(node_id)
+            .find(|e| e.typ() == EdgeType::Internal && e.name_or_index().is_str("backing_store"))?
+            .from_node();
+
+        let mut retainers: Vec<_> = graph
+            .in_edges(owner)
+            .filter(|e| !matches!(e.typ(), EdgeType::Weak | EdgeType::Shortcut))
+            .map(|e| {
+                (
+                    e.typ_str(),
+                    e.name_or_index().to_string(),
+                    graph.node(e.from_node()).constructor_name(),
+                )
+            })
+            .collect();
+        retainers.sort();
+
+        let mut hasher = DefaultHasher::new();
+        graph.node(node_id).self_size().hash(&mut hasher);
+        graph.node(owner).constructor_name().hash(&mut hasher);
+        retainers.hash(&mut hasher);
+        Some(hasher.finish())
     }
 
     fn hash_string(&self, s: &str) -> u64 {
@@ -98,276 +321,707 @@ impl DuplicateAnalyzer {
 
     fn hash_object(&self, node_id: NodeId) -> u64 {
         let mut hasher = DefaultHasher::new();
-        
+
         // Hash object type
-        if let Some(name) = self.graph.node_name(node_id) {
-            name.hash(&mut hasher);
-        }
-        
+        self.graph.node(node_id).name().hash(&mut hasher);
+
         // Hash properties (edges)
-        let mut edges: Vec<_> = self.graph.edges(node_id).collect();
-        edges.sort_by_key(|e| e.name_or_index);
-        
+        let mut edges: Vec<_> = self.graph.out_edges(node_id).collect();
+        edges.sort_by_key(|e| e.index());
+
         for edge in edges {
-            if !self.include_hidden_classes && edge.edge_type == 4 {
+            if !self.options.include_hidden_classes && edge.typ() == EdgeType::Hidden {
                 continue; // Skip hidden edges
             }
-            
-            edge.edge_type.hash(&mut hasher);
-            edge.name_or_index.hash(&mut hasher);
-            edge.target.hash(&mut hasher);
+
+            edge.typ_str().hash(&mut hasher);
+            edge.index().hash(&mut hasher);
+            edge.to_node().hash(&mut hasher);
         }
-        
+
         hasher.finish()
     }
 
     fn calculate_total_size(&self, node_id: NodeId) -> u64 {
-        // For now, just return shallow size
-        // TODO: Implement proper retained size calculation that only counts
-        // objects uniquely owned by this object, not shared references
-        self.graph.node_size(node_id).unwrap_or(0) as u64
+        // For now, just return shallow size (with the backing store of arrays). The retained
+        // size is attached separately by `enrich_with_retained_sizes`. Duplicate buffers are
+        // reported in native bytes, whether or not those are included in the totals.
+        self.graph.node(node_id).effective_size() as u64
     }
 
-    fn calculate_size_recursive(&self, node_id: NodeId, visited: &mut AHashSet<NodeId>) -> u64 {
-        if visited.contains(&node_id) {
-            return 0; // Already counted or circular reference
+    fn get_sample_value(&self, node_id: NodeId) -> Option<String> {
+        let node = self.graph.node(node_id);
+
+        // For strings, return the string value
+        if matches!(node.typ(), NodeType::String | NodeType::ConcatString) {
+            let max_len = self.options.sample_max_len.unwrap_or(usize::MAX);
+            return Some(print_safe(&self.graph.resolved_string(node_id), max_len));
         }
-        visited.insert(node_id);
-        
-        let mut total = self.graph.node_size(node_id).unwrap_or(0) as u64;
-        
-        // Add sizes of all referenced objects
-        for edge in self.graph.edges(node_id) {
-            if !self.include_hidden_classes && edge.edge_type == 4 {
-                continue; // Skip hidden edges
-            }
-            total += self.calculate_size_recursive(edge.target, visited);
+
+        if node.typ() == NodeType::Closure {
+            return Some(format!("function {}()", node.name()));
         }
-        
-        total
-    }
 
-    fn get_sample_value(&self, node_id: NodeId) -> Option<String> {
-        let node_type = self.graph.node_type(node_id)?;
-        
-        // For strings, return the string value
-        if node_type == 2 {
-            return self.graph.node_name(node_id).map(|s| {
-                let escaped = escape_string(s);
-                if escaped.len() > 100 {
-                    // Truncate at char boundary, not byte boundary
-                    let truncated: String = escaped.chars().take(100).collect();
-                    format!("\"{}...\"", truncated)
-                } else {
-                    format!("\"{}\"", escaped)
-                }
-            });
+        if node.typ() == NodeType::Native {
+            return Some(format!("{} backing store", format_bytes(node.self_size())));
         }
-        
+
         // For objects, show structure
-        if node_type == 3 {
+        if node.typ() == NodeType::Object {
             let mut parts = Vec::new();
-            let edges: Vec<_> = self.graph.edges(node_id).take(5).collect();
-            
+            let edges: Vec<_> = self
+                .graph
+                .out_edges(node_id)
+                .filter(|e| e.typ() == EdgeType::Property)
+                .take(5)
+                .collect();
+
             for edge in edges {
-                if let Some(name) = edge.name() {
-                    if let Some(target_name) = self.graph.node_name(edge.target) {
-                        parts.push(format!("{}: {}", name, target_name));
-                    }
-                }
+                let target_name = self.graph.node(edge.to_node()).name();
+                parts.push(format!("{}: {}", edge.name_or_index(), target_name));
             }
-            
+
             if parts.is_empty() {
                 return Some("{}".to_string());
             }
-            
+
             return Some(format!("{{ {} }}", parts.join(", ")));
         }
-        
+
         None
     }
 
-    fn create_groups(&self, hash_map: AHashMap<u64, Vec<NodeId>>, type_name: &str) -> Vec<DuplicateGroup> {
-        let mut groups = Vec::new();
-        
+    fn create_groups(
+        &self,
+        hash_map: AHashMap<u64, Vec<NodeId>>,
+        type_name: &str,
+        sink: &mut dyn FnMut(DuplicateGroup),
+    ) {
+        let min_count = self.options.min_count.max(2);
         for (hash, node_ids) in hash_map {
-            if node_ids.len() > 1 {
+            if node_ids.len() >= min_count {
                 let representative = node_ids[0];
                 let size = self.calculate_total_size(representative);
                 let count = node_ids.len();
                 let total_wasted = (count - 1) as u64 * size;
-                
-                // For strings, use "String" as the type name, not the actual string value
-                let object_type = if type_name == "String" {
-                    "String".to_string()
+                if total_wasted < self.options.min_wasted {
+                    continue;
+                }
+
+                // Strings and buffers are named after their kind, not after their value or
+                // their (internal) node name
+                let object_type = if matches!(type_name, "String" | "ArrayBuffer") {
+                    type_name.to_string()
                 } else {
-                    self.graph.node_name(representative)
-                        .unwrap_or(type_name)
-                        .to_string()
+                    self.graph
+                        .node(representative)
+                        .constructor_name()
+                        .into_owned()
                 };
-                
+
                 let sample_value = self.get_sample_value(representative);
-                
-                groups.push(DuplicateGroup {
+
+                sink(DuplicateGroup {
                     hash,
                     object_type,
                     count,
                     size_per_object: size,
                     total_wasted,
                     representative,
+                    representative_stable_id: self.graph.node(representative).stable_id(),
                     node_ids,
+                    stable_ids: vec![],
                     sample_value,
-                    owned_retained_size: None,
-                    shared_retained_size: None,
+                    retained_size: None,
+                    common_dominator: None,
+                    tag: None,
                 });
             }
         }
-        
-        groups
     }
+}
+
+/// A group with its `waste_key`, ordered by that key alone
+struct RankedGroup {
+    key: (Reverse<u64>, String, NodeId),
+    group: DuplicateGroup,
+}
+
+impl PartialEq for RankedGroup {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
 
-    pub fn into_graph(self) -> CompactGraph {
-        self.graph
+impl Eq for RankedGroup {}
+
+impl PartialOrd for RankedGroup {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
+impl Ord for RankedGroup {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// The `limit` objects with the largest retained size that aren't part of any duplicate group
+///
+/// Duplicate detection can't see a single huge object, so this completes the picture.
+/// Synthetic nodes (the root and the GC root categories) are skipped, since they aren't
+/// objects.
+pub fn largest_unique_retainers(
+    groups: &[DuplicateGroup],
+    tree: &DominatorTree,
+    graph: &V8HeapGraph,
+    limit: usize,
+) -> Vec<NodeId> {
+    let duplicates: ahash::AHashSet<NodeId> = groups
+        .iter()
+        .flat_map(|g| g.node_ids.iter().copied())
+        .collect();
+
+    tree.nodes_retaining_at_least(0)
+        .into_iter()
+        .filter(|n| !duplicates.contains(n) && graph.node(*n).typ() != NodeType::Synthetic)
+        .take(limit)
+        .collect()
+}
+
+/// The lowest node in the dominator tree that dominates every member of the group
+///
+/// Freeing this node frees all duplicates at once, so it is usually the cache or collection
+/// that holds them. `None` if a member is not in the tree (it is unreachable).
+///
+/// `parents` is `tree.parents()`, which takes a pass over the whole tree: compute it once for
+/// all groups.
+pub fn group_common_dominator(
+    group: &DuplicateGroup,
+    tree: &DominatorTree,
+    parents: &HashMap<NodeId, NodeId>,
+) -> Option<NodeId> {
+    common_dominator(&group.node_ids, tree.root, parents)
+}
+
+/// Lowest common ancestor of `nodes` in the dominator tree, given as a child-to-parent map
+fn common_dominator(
+    nodes: &[NodeId],
+    root: NodeId,
+    parents: &HashMap<NodeId, NodeId>,
+) -> Option<NodeId> {
+    let ancestors = |node: NodeId| -> Option<Vec<NodeId>> {
+        // From the node up to the root, inclusive
+        let mut chain = vec![node];
+        let mut current = node;
+        while current != root {
+            current = *parents.get(&current)?;
+            chain.push(current);
+        }
+        Some(chain)
+    };
+
+    let (&first, rest) = nodes.split_first()?;
+    // The candidates, root first: the common dominator is always the last one
+    let mut common = ancestors(first)?;
+    common.reverse();
+    for &node in rest {
+        let chain = ancestors(node)?;
+        let shared = chain
+            .iter()
+            .rev()
+            .zip(&common)
+            .take_while(|(a, b)| a == b)
+            .count();
+        common.truncate(shared);
+    }
+    common.last().copied()
+}
+
+/// Sort duplicate groups so that those closest to a GC root come first
+///
+/// `distances` is the result of `root_distances`. Groups at the same distance are ordered
+/// by wasted memory. Shallow duplicates are usually the easiest to find in the code.
+pub fn sort_by_root_distance(groups: &mut [DuplicateGroup], distances: &[u32]) {
+    groups.sort_by_key(|g| {
+        (
+            distances[g.representative as usize],
+            Reverse(g.total_wasted),
+        )
+    });
+}
+
+/// Roll up shape-level duplicate groups by constructor name
+///
+/// Every distinct object shape produces its own `DuplicateGroup`; this adds up all groups
+/// that share a constructor, so that "`Widget` is duplicated 5000 times across 12 shapes"
+/// shows up as one line. Objects without a constructor name share a single
+/// `(anonymous)` bucket.
+pub fn rollup_by_constructor(groups: &[DuplicateGroup]) -> Vec<ConstructorSummary> {
+    let mut by_constructor: AHashMap<&str, ConstructorSummary> = AHashMap::new();
+
+    for group in groups {
+        let constructor = match group.object_type.trim() {
+            "" => ANONYMOUS_CONSTRUCTOR,
+            name => name,
+        };
+
+        let summary = by_constructor
+            .entry(constructor)
+            .or_insert_with(|| ConstructorSummary {
+                constructor: constructor.to_string(),
+                group_count: 0,
+                count: 0,
+                total_wasted: 0,
+            });
+        summary.group_count += 1;
+        summary.count += group.count;
+        summary.total_wasted += group.total_wasted;
+    }
+
+    let mut summaries: Vec<_> = by_constructor.into_values().collect();
+    summaries.sort_by(|a, b| {
+        Reverse(a.total_wasted)
+            .cmp(&Reverse(b.total_wasted))
+            .then_with(|| a.constructor.cmp(&b.constructor))
+    });
+    summaries
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::StringTable;
-    use std::sync::Arc;
+    use crate::{
+        analysis::{all_paths::root_distances, dominator_tree::tree_from_immediate_dominators},
+        graph::{lengauer_tarjan::lengauer_tarjan, test_graph::TestGraph},
+    };
 
     #[test]
     fn test_find_duplicate_strings() {
-        let strings = vec![
-            "".to_string(),
-            "duplicate".to_string(),
-            "unique".to_string(),
-        ];
-        let string_table = Arc::new(StringTable::new(strings));
-        
-        let mut graph = CompactGraph::new(string_table);
-        
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+
         // Add 3 string nodes: 2 duplicates, 1 unique
-        graph.node_types.extend(&[2, 2, 2]);
-        graph.node_names.extend(&[1, 1, 2]); // Two "duplicate", one "unique"
-        graph.node_ids.extend(&[1, 2, 3]);
-        graph.node_sizes.extend(&[48, 48, 32]);
-        graph.node_edge_ranges.extend(&[(0, 0), (0, 0), (0, 0)]);
-        
-        let analyzer = DuplicateAnalyzer::new(graph, false);
+        let a = g.node(NodeType::String, "duplicate", 48);
+        let b = g.node(NodeType::String, "duplicate", 48);
+        let c = g.node(NodeType::String, "unique", 32);
+        g.element(root, 0, a)
+            .element(root, 1, b)
+            .element(root, 2, c);
+        let graph = g.build();
+
+        let analyzer = DuplicateAnalyzer::new(&graph, Default::default());
         let groups = analyzer.find_duplicate_strings();
-        
+
         assert_eq!(groups.len(), 1);
         assert_eq!(groups[0].count, 2);
         assert_eq!(groups[0].object_type, "String"); // Changed: now uses "String" as caption
         assert_eq!(groups[0].total_wasted, 48);
         assert!(groups[0].sample_value.is_some());
-        assert!(groups[0].sample_value.as_ref().unwrap().contains("duplicate")); // Actual value in sample
+        assert!(
+            groups[0]
+                .sample_value
+                .as_ref()
+                .unwrap()
+                .contains("duplicate")
+        ); // Actual value in sample
+    }
+
+    #[test]
+    fn test_largest_unique_retainers() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let buffer = g.node(NodeType::Native, "ArrayBuffer", 5000);
+        let small = g.node(NodeType::Object, "Small", 10);
+        g.edge(root, EdgeType::Property, "buffer", buffer).edge(
+            root,
+            EdgeType::Property,
+            "small",
+            small,
+        );
+        for i in 0..20 {
+            let dup = g.node(NodeType::String, "the same string", 100);
+            g.element(root, i, dup);
+        }
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        let groups = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicates();
+        assert_eq!(groups.len(), 1);
+
+        // The duplicates are bigger than 'small', but are already reported
+        assert_eq!(
+            largest_unique_retainers(&groups, &tree, &graph, 10),
+            vec![buffer, small]
+        );
+        assert_eq!(
+            largest_unique_retainers(&groups, &tree, &graph, 1),
+            vec![buffer]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_array_buffers() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let image = |g: &mut TestGraph, i: NodeId, size: usize| {
+            let view = g.node(NodeType::Object, "Uint8Array", 64);
+            let buffer = g.node(NodeType::Object, "ArrayBuffer", 96);
+            let store = g.node(NodeType::Native, "system / JSArrayBufferData", size);
+            g.element(root, i, view)
+                .edge(view, EdgeType::Internal, "buffer", buffer)
+                .edge(buffer, EdgeType::Internal, "backing_store", store);
+            store
+        };
+        let a = image(&mut g, 0, 1 << 20);
+        let b = image(&mut g, 1, 1 << 20);
+        image(&mut g, 2, 1 << 19);
+        // A native node that isn't a backing store is never a duplicate buffer
+        let other = g.node(NodeType::Native, "system / JSArrayBufferData", 1 << 20);
+        g.element(root, 3, other);
+        let graph = g.build();
+
+        let groups =
+            DuplicateAnalyzer::new(&graph, Default::default()).find_duplicate_array_buffers();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].object_type, "ArrayBuffer");
+        assert_eq!(groups[0].node_ids, vec![a, b]);
+        assert_eq!(groups[0].total_wasted, 1 << 20);
+        assert_eq!(
+            groups[0].sample_value.as_deref(),
+            Some("1.0M backing store")
+        );
+    }
+
+    #[test]
+    fn test_find_top_duplicates() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/test-duplicates.heapsnapshot");
+        let graph = V8HeapGraph::from(crate::snapshot::read_v8_snapshot_file(&path).unwrap());
+        let analyzer = DuplicateAnalyzer::new(&graph, Default::default());
+
+        let all = analyzer.find_duplicates();
+        let top = analyzer.find_top_duplicates(5);
+        let summary = |groups: &[DuplicateGroup]| {
+            groups
+                .iter()
+                .map(|g| (g.representative, g.count, g.total_wasted))
+                .collect::<Vec<_>>()
+        };
+        assert!(all.len() > 5);
+        assert_eq!(summary(&top), summary(&all[..5]));
+
+        assert!(analyzer.find_top_duplicates(0).is_empty());
+        assert_eq!(analyzer.find_top_duplicates(usize::MAX).len(), all.len());
+
+        // Reports get the same groups through the options
+        let bounded = DuplicateAnalyzer::new(
+            &graph,
+            DuplicateOptions {
+                max_groups: Some(5),
+                ..Default::default()
+            },
+        )
+        .find_duplicates();
+        assert_eq!(summary(&bounded), summary(&all[..5]));
+    }
+
+    #[test]
+    fn test_closures_sharing_code() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/test-duplicates.heapsnapshot");
+        let graph = V8HeapGraph::from(crate::snapshot::read_v8_snapshot_file(&path).unwrap());
+        let groups = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicate_closures();
+
+        // Node's internal errors create the same functions for every error class
+        let to_string = groups
+            .iter()
+            .filter(|g| g.sample_value.as_deref() == Some("function toString()"))
+            .max_by_key(|g| g.count)
+            .unwrap();
+        assert_eq!(to_string.object_type, "Function");
+        assert_eq!(to_string.count, 136);
+        assert_eq!(to_string.total_wasted, 135 * 56);
+
+        let shared = |n: NodeId| graph.find_edge(n, EdgeType::Internal, "shared");
+        let first = shared(to_string.node_ids[0]);
+        assert!(first.is_some());
+        assert!(to_string.node_ids.iter().all(|&n| shared(n) == first));
+    }
+
+    #[test]
+    fn test_concat_string_matches_flat_string() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let flat = g.node(NodeType::String, "foobar", 32);
+        let foo = g.node(NodeType::String, "foo", 16);
+        let bar = g.node(NodeType::String, "bar", 16);
+        let concat = g.node(NodeType::ConcatString, "(concatenated string)", 32);
+        g.element(root, 0, flat)
+            .element(root, 1, concat)
+            .edge(concat, EdgeType::Internal, "first", foo)
+            .edge(concat, EdgeType::Internal, "second", bar);
+        let graph = g.build();
+
+        let groups = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicate_strings();
+
+        assert_eq!(groups.len(), 1);
+        let mut nodes = groups[0].node_ids.clone();
+        nodes.sort();
+        assert_eq!(nodes, vec![flat, concat]);
+        assert_eq!(groups[0].sample_value.as_deref(), Some("\"foobar\""));
     }
 
     #[test]
     fn test_shallow_size_calculation() {
-        let strings = vec![
-            "".to_string(),
-            "parent".to_string(),
-            "child".to_string(),
-            "prop".to_string(),
-        ];
-        let string_table = Arc::new(StringTable::new(strings));
-        
-        let mut graph = CompactGraph::new(string_table);
-        
+        let mut g = TestGraph::new();
+
         // Add parent object (node 0) with size 100
-        graph.node_types.push(3); // object
-        graph.node_names.push(1); // "parent"
-        graph.node_ids.push(1);
-        graph.node_sizes.push(100);
-        graph.node_edge_ranges.push((0, 1)); // 1 edge
-        
+        let parent = g.node(NodeType::Object, "parent", 100);
+
         // Add child object (node 1) with size 50
-        graph.node_types.push(3); // object
-        graph.node_names.push(2); // "child"
-        graph.node_ids.push(2);
-        graph.node_sizes.push(50);
-        graph.node_edge_ranges.push((1, 1)); // 0 edges
-        
+        let child = g.node(NodeType::Object, "child", 50);
+
         // Add edge from parent to child
-        graph.edge_types.push(2); // property
-        graph.edge_names.push(3); // "prop"
-        graph.edge_targets.push(1); // target node 1
-        
-        let analyzer = DuplicateAnalyzer::new(graph, false);
-        
+        g.edge(parent, EdgeType::Property, "prop", child);
+        let graph = g.build();
+
+        let analyzer = DuplicateAnalyzer::new(&graph, Default::default());
+
         // Calculate size of parent (should be shallow size only)
-        let size = analyzer.calculate_total_size(0);
+        let size = analyzer.calculate_total_size(parent);
         assert_eq!(size, 100, "Should return shallow size of parent object");
     }
 
     #[test]
-    fn test_multiple_retention_paths() {
-        use crate::paths::RetentionPathFinder;
-        
-        let strings = vec![
-            "".to_string(),
-            "root1".to_string(),
-            "root2".to_string(),
-            "target".to_string(),
-            "prop".to_string(),
-        ];
-        let string_table = Arc::new(StringTable::new(strings));
-        
-        let mut graph = CompactGraph::new(string_table);
-        
-        // Node 0: root1 (GC root)
-        graph.node_types.push(3);
-        graph.node_names.push(1);
-        graph.node_ids.push(1);
-        graph.node_sizes.push(100);
-        graph.node_edge_ranges.push((0, 1)); // 1 edge
-        graph.gc_roots.push(0);
-        
-        // Node 1: root2 (GC root)
-        graph.node_types.push(3);
-        graph.node_names.push(2);
-        graph.node_ids.push(2);
-        graph.node_sizes.push(100);
-        graph.node_edge_ranges.push((1, 2)); // 1 edge
-        graph.gc_roots.push(1);
-        
-        // Node 2: target (reachable from both roots)
-        graph.node_types.push(3);
-        graph.node_names.push(3);
-        graph.node_ids.push(3);
-        graph.node_sizes.push(50);
-        graph.node_edge_ranges.push((2, 2)); // 0 edges
-        
-        // Edge from root1 to target
-        graph.edge_types.push(2);
-        graph.edge_names.push(4);
-        graph.edge_targets.push(2);
-        
-        // Edge from root2 to target
-        graph.edge_types.push(2);
-        graph.edge_names.push(4);
-        graph.edge_targets.push(2);
-        
-        let finder = RetentionPathFinder::new(&graph);
-        let paths = finder.find_paths(2, 10); // Find up to 10 paths
-        
-        // Should find 2 paths (one from each root)
-        assert!(paths.len() >= 2, "Expected at least 2 retention paths, found {}", paths.len());
-        
-        // Verify both paths lead to the target
-        for path in &paths {
-            assert_eq!(*path.nodes.last().unwrap(), 2, "Path should end at target node");
-            assert!(graph.is_gc_root(path.nodes[0]), "Path should start from GC root");
+    fn test_rollup_by_constructor() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let value = g.node(NodeType::String, "value", 16);
+
+        // Two shapes of Widget, and one anonymous class
+        for i in 0..3 {
+            let w = g.node(NodeType::Object, "Widget", 40);
+            g.element(root, i, w)
+                .edge(w, EdgeType::Property, "a", value);
+        }
+        for i in 3..5 {
+            let w = g.node(NodeType::Object, "Widget", 40);
+            g.element(root, i, w)
+                .edge(w, EdgeType::Property, "b", value);
+        }
+        for i in 5..7 {
+            let w = g.node(NodeType::Object, "", 24);
+            g.element(root, i, w);
         }
-        
-        // Verify we have paths from different roots
-        let root_nodes: std::collections::HashSet<_> = paths.iter()
-            .map(|p| p.nodes[0])
+        let graph = g.build();
+
+        let groups = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicate_objects();
+        assert_eq!(groups.len(), 3);
+
+        let rollup = rollup_by_constructor(&groups);
+        assert_eq!(rollup.len(), 2);
+
+        assert_eq!(rollup[0].constructor, "Widget");
+        assert_eq!(rollup[0].group_count, 2);
+        assert_eq!(rollup[0].count, 5);
+        assert_eq!(rollup[0].total_wasted, 2 * 40 + 40);
+
+        assert_eq!(rollup[1].constructor, ANONYMOUS_CONSTRUCTOR);
+        assert_eq!(rollup[1].group_count, 1);
+        assert_eq!(rollup[1].total_wasted, 24);
+    }
+
+    #[test]
+    fn test_sample_max_len() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let long = "x".repeat(150);
+        let a = g.node(NodeType::String, &long, 160);
+        let b = g.node(NodeType::String, &long, 160);
+        g.element(root, 0, a).element(root, 1, b);
+        let graph = g.build();
+
+        let truncated = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicate_strings();
+        assert_eq!(
+            truncated[0].sample_value.as_deref(),
+            Some(format!("\"{}...\"", "x".repeat(100)).as_str())
+        );
+
+        let options = DuplicateOptions {
+            sample_max_len: None,
+            ..Default::default()
+        };
+        let full = DuplicateAnalyzer::new(&graph, options).find_duplicate_strings();
+        assert_eq!(
+            full[0].sample_value.as_deref(),
+            Some(format!("\"{}\"", long).as_str())
+        );
+    }
+
+    #[test]
+    fn test_ignore_system() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let mut index = 0;
+        for name in ["system / Context", "system / Context", "Widget", "Widget"] {
+            let node = g.node(NodeType::Object, name, 40);
+            g.element(root, index, node);
+            index += 1;
+        }
+        for _ in 0..2 {
+            let code = g.node(NodeType::Code, "(compiled code)", 100);
+            g.element(root, index, code);
+            index += 1;
+        }
+        let graph = g.build();
+
+        let all = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicate_objects();
+        assert_eq!(all.len(), 2);
+
+        let options = DuplicateOptions {
+            ignore_system: true,
+            ..Default::default()
+        };
+        let groups = DuplicateAnalyzer::new(&graph, options).find_duplicate_objects();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].object_type, "Widget");
+
+        // Only the report skips them, the graph still has every node
+        assert_eq!(graph.total_node_count(), 7);
+        let noise: Vec<_> = graph
+            .iter_nodes()
+            .filter(|&n| is_noise_node(&graph.node(n)))
             .collect();
-        assert_eq!(root_nodes.len(), 2, "Should have paths from 2 different GC roots");
+        assert_eq!(noise, vec![1, 2, 5, 6]);
+    }
+
+    #[test]
+    fn test_group_thresholds() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let mut i = 0;
+        // Three copies of a small string, two copies of a big one
+        for (value, size, copies) in [("small", 10, 3), ("big", 1000, 2)] {
+            for _ in 0..copies {
+                let s = g.node(NodeType::String, value, size);
+                g.element(root, i, s);
+                i += 1;
+            }
+        }
+        let graph = g.build();
+        let samples = |options: DuplicateOptions| -> Vec<String> {
+            DuplicateAnalyzer::new(&graph, options)
+                .find_duplicate_strings()
+                .into_iter()
+                .map(|g| g.sample_value.unwrap())
+                .collect()
+        };
+
+        assert_eq!(samples(Default::default()), vec!["\"big\"", "\"small\""]);
+        let min_count = DuplicateOptions {
+            min_count: 3,
+            ..Default::default()
+        };
+        assert_eq!(samples(min_count), vec!["\"small\""]);
+        let min_wasted = DuplicateOptions {
+            min_wasted: 100,
+            ..Default::default()
+        };
+        assert_eq!(samples(min_wasted), vec!["\"big\""]);
+    }
+
+    #[test]
+    fn test_reanalyze_borrowed_graph() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let value = g.node(NodeType::String, "value", 16);
+        let map1 = g.node(NodeType::Hidden, "system / Map", 40);
+        let map2 = g.node(NodeType::Hidden, "system / Map", 40);
+        let a = g.node(NodeType::Object, "Point", 24);
+        let b = g.node(NodeType::Object, "Point", 24);
+        g.element(root, 0, a)
+            .element(root, 1, b)
+            .edge(a, EdgeType::Property, "x", value)
+            .edge(b, EdgeType::Property, "x", value)
+            .edge(a, EdgeType::Hidden, "map", map1)
+            .edge(b, EdgeType::Hidden, "map", map2);
+        let graph = g.build();
+
+        // Both analyses borrow the same graph, so it doesn't need to be rebuilt in between
+        let objects = |include_hidden_classes| {
+            DuplicateAnalyzer::new(
+                &graph,
+                DuplicateOptions {
+                    include_hidden_classes,
+                    ..Default::default()
+                },
+            )
+            .find_duplicate_objects()
+        };
+        assert_eq!(objects(false).len(), 1);
+        assert_eq!(objects(true).len(), 0);
+        assert_eq!(objects(false)[0].node_ids, vec![a, b]);
+    }
+
+    #[test]
+    fn test_group_common_dominator() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let cache = g.node(NodeType::Object, "Cache", 50);
+        let bucket = g.node(NodeType::Array, "Bucket", 20);
+        let entry1 = g.node(NodeType::String, "entry", 32);
+        let entry2 = g.node(NodeType::String, "entry", 32);
+        let entry3 = g.node(NodeType::String, "entry", 32);
+        g.edge(root, EdgeType::Property, "cache", cache)
+            .edge(cache, EdgeType::Property, "bucket", bucket)
+            .element(bucket, 0, entry1)
+            .element(bucket, 1, entry2)
+            .element(cache, 0, entry3);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        let mut groups = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicates();
+        assert_eq!(groups.len(), 1);
+        let parents = tree.parents();
+        assert_eq!(
+            group_common_dominator(&groups[0], &tree, &parents),
+            Some(cache)
+        );
+
+        DuplicateAnalyzer::enrich_with_retained_sizes(&mut groups, &tree);
+        assert_eq!(groups[0].common_dominator, Some(cache));
+
+        // Two of them share a deeper dominator
+        groups[0].node_ids = vec![entry1, entry2];
+        assert_eq!(
+            group_common_dominator(&groups[0], &tree, &parents),
+            Some(bucket)
+        );
+    }
+
+    #[test]
+    fn test_sort_by_root_distance() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let holder = g.node(NodeType::Object, "Holder", 8);
+        let deep1 = g.node(NodeType::String, "deep", 100);
+        let deep2 = g.node(NodeType::String, "deep", 100);
+        let shallow1 = g.node(NodeType::String, "shallow", 10);
+        let shallow2 = g.node(NodeType::String, "shallow", 10);
+        g.edge(root, EdgeType::Property, "holder", holder)
+            .element(holder, 0, deep1)
+            .element(holder, 1, deep2)
+            .element(root, 0, shallow1)
+            .element(root, 1, shallow2);
+        let graph = g.build();
+
+        let mut groups = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicates();
+        assert_eq!(groups[0].total_wasted, 100);
+
+        sort_by_root_distance(&mut groups, &root_distances(&graph));
+        assert_eq!(groups[0].total_wasted, 10);
     }
 }
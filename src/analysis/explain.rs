@@ -0,0 +1,143 @@
+use crate::{
+    analysis::{
+        all_paths::{PathEdges, RootPath, find_root_paths_until},
+        dominator_tree::tree_from_immediate_dominators,
+        duplicates::{DuplicateAnalyzer, DuplicateGroup, DuplicateOptions},
+    },
+    graph::{lengauer_tarjan::lengauer_tarjan_until, v8_heap_graph::V8HeapGraph},
+    types::NodeId,
+    utils::{Deadline, TimedOut},
+};
+
+/// Everything that answers "why is this node still in memory?"
+pub struct Explanation {
+    pub node: NodeId,
+    pub retained_size: usize,
+    /// The node that has to go before this one can be collected, `None` for the root or
+    /// unreachable nodes
+    pub immediate_dominator: Option<NodeId>,
+    pub detached: bool,
+    /// Shortest path from the root through the edges that were asked for, `None` if the node
+    /// is unreachable or further away than the maximum depth
+    pub shortest_path: Option<RootPath>,
+    /// The group of duplicates this node is part of, if any
+    pub duplicate_group: Option<DuplicateGroup>,
+}
+
+/// Collect the explanation for a single node, relative to `root`
+///
+/// This runs each analysis once, but doesn't build a report for the whole heap. Duplicates
+/// are grouped with `duplicate_options`, and the path is found with `max_path_depth` and
+/// `path_edges`, as they are in the report.
+pub fn explain_node(
+    graph: &V8HeapGraph,
+    root: NodeId,
+    node: NodeId,
+    max_path_depth: u32,
+    path_edges: PathEdges,
+    duplicate_options: DuplicateOptions,
+) -> Explanation {
+    explain_node_until(
+        graph,
+        root,
+        node,
+        max_path_depth,
+        path_edges,
+        duplicate_options,
+        Deadline::none(),
+    )
+    .expect("no deadline to miss")
+}
+
+/// `explain_node`, giving up with `TimedOut` once `deadline` has passed
+pub fn explain_node_until(
+    graph: &V8HeapGraph,
+    root: NodeId,
+    node: NodeId,
+    max_path_depth: u32,
+    path_edges: PathEdges,
+    duplicate_options: DuplicateOptions,
+    deadline: Deadline,
+) -> Result<Explanation, TimedOut> {
+    let immediate_dominators = lengauer_tarjan_until(graph, &[root], deadline.phase("dominators"))?;
+    let immediate_dominator = immediate_dominators.get(&node).copied();
+    // Unreachable nodes are not in the tree, so they retain nothing
+    let tree = tree_from_immediate_dominators(immediate_dominators, root, graph);
+    let shortest_path = find_root_paths_until(
+        graph,
+        root,
+        max_path_depth,
+        path_edges,
+        deadline.phase("root paths"),
+    )?
+    .paths_to_limited(node, graph, 1)
+    .into_iter()
+    .next();
+    deadline.phase("duplicates").check()?;
+    let duplicate_group = DuplicateAnalyzer::new(graph, duplicate_options)
+        .find_duplicates()
+        .into_iter()
+        .find(|g| g.node_ids.contains(&node));
+
+    Ok(Explanation {
+        node,
+        retained_size: tree.retained_size(node),
+        immediate_dominator,
+        detached: graph.node(node).detachedness(),
+        shortest_path,
+        duplicate_group,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::{all_paths::DEFAULT_MAX_PATH_DEPTH, dominator_tree::retained_size_of},
+        graph::{
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+    };
+
+    #[test]
+    fn test_explain_unreachable_node() {
+        // root -> a;  orphan -> b <- a
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 20);
+        let orphan = g.node(NodeType::Object, "Orphan", 30);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(a, EdgeType::Property, "b", b)
+            .edge(orphan, EdgeType::Property, "b", b)
+            .edge(orphan, EdgeType::Property, "self", orphan);
+        let graph = g.build();
+
+        let explain = |node| {
+            explain_node(
+                &graph,
+                root,
+                node,
+                DEFAULT_MAX_PATH_DEPTH,
+                PathEdges::All,
+                Default::default(),
+            )
+        };
+
+        let explanation = explain(orphan);
+        assert_eq!(explanation.retained_size, 0);
+        assert!(explanation.immediate_dominator.is_none());
+        assert!(explanation.shortest_path.is_none());
+        assert_eq!(retained_size_of(&graph, root, orphan), 0);
+
+        let explanation = explain(a);
+        assert_eq!(explanation.retained_size, 10 + 20);
+        assert_eq!(explanation.immediate_dominator, Some(root));
+
+        // Paths are not looked for beyond the maximum depth
+        let explanation = explain_node(&graph, root, b, 1, PathEdges::All, Default::default());
+        assert!(explanation.shortest_path.is_none());
+        assert_eq!(explanation.immediate_dominator, Some(a));
+    }
+}
@@ -0,0 +1,232 @@
+use std::cmp::Reverse;
+
+use ahash::AHashSet;
+use serde::Serialize;
+
+use crate::{
+    analysis::dominator_tree::DominatorTree,
+    graph::v8_heap_graph::{EdgeType, NodeType, V8HeapGraph},
+    types::NodeId,
+};
+
+/// Everything about the instances of a single constructor
+#[derive(Debug, Clone, Serialize)]
+pub struct FocusReport {
+    pub constructor: String,
+    pub count: usize,
+    pub total_self_size: usize,
+    /// Sum of the retained sizes of all instances
+    ///
+    /// If instances retain each other, the inner ones are counted more than once.
+    pub total_retained_size: usize,
+    /// Number of distinct hidden classes (`map` edges) among the instances
+    pub hidden_class_count: usize,
+    /// Number of distinct sets of property names among the instances
+    pub property_set_count: usize,
+    /// How the self sizes of the instances are spread out
+    pub size_distribution: SizeDistribution,
+    /// Instances, largest retained size first
+    pub instances: Vec<NodeId>,
+}
+
+/// Percentiles and a histogram of the self sizes of a set of nodes
+///
+/// Percentiles use the nearest-rank method, so every value is the size of an actual node.
+/// All sizes are 0 if there are no nodes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SizeDistribution {
+    pub count: usize,
+    pub min: usize,
+    pub median: usize,
+    pub p90: usize,
+    pub p99: usize,
+    pub max: usize,
+    /// Power-of-two buckets that contain at least one node, smallest first
+    pub histogram: Vec<SizeBucket>,
+}
+
+/// The number of nodes with a self size in `from..to`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SizeBucket {
+    pub from: usize,
+    pub to: usize,
+    pub count: usize,
+}
+
+/// Collect all objects with the given constructor name
+///
+/// Matches on `Node::constructor_name`, so this finds the instances of a class no matter how
+/// V8 named the individual nodes.
+pub fn focus_on_constructor(
+    constructor: &str,
+    tree: &DominatorTree,
+    graph: &V8HeapGraph,
+) -> FocusReport {
+    let mut instances = instances_of(constructor, graph);
+    instances.sort_by_key(|&n| (Reverse(tree.retained_size(n)), n));
+
+    let mut hidden_classes = AHashSet::new();
+    let mut property_sets = AHashSet::new();
+    for &n in &instances {
+        if let Some(map) = graph.find_edge(n, EdgeType::Internal, "map") {
+            hidden_classes.insert(map);
+        }
+
+        let mut properties: Vec<String> = graph
+            .out_edges(n)
+            .filter(|e| e.typ() == EdgeType::Property)
+            .map(|e| e.name_or_index().to_string())
+            .collect();
+        properties.sort();
+        property_sets.insert(properties);
+    }
+
+    FocusReport {
+        constructor: constructor.to_string(),
+        count: instances.len(),
+        total_self_size: instances.iter().map(|&n| graph.self_size_for(n)).sum(),
+        total_retained_size: instances.iter().map(|&n| tree.retained_size(n)).sum(),
+        hidden_class_count: hidden_classes.len(),
+        property_set_count: property_sets.len(),
+        size_distribution: distribution_of(&instances, graph),
+        instances,
+    }
+}
+
+/// How the self sizes of all objects with the given constructor name are spread out
+///
+/// Objects are matched the same way as in `focus_on_constructor`.
+pub fn size_distribution(graph: &V8HeapGraph, type_name: &str) -> SizeDistribution {
+    distribution_of(&instances_of(type_name, graph), graph)
+}
+
+fn instances_of(constructor: &str, graph: &V8HeapGraph) -> Vec<NodeId> {
+    graph
+        .iter_nodes()
+        .filter(|&n| {
+            let node = graph.node(n);
+            matches!(node.typ(), NodeType::Object | NodeType::Closure)
+                && node.constructor_name() == constructor
+        })
+        .collect()
+}
+
+fn distribution_of(nodes: &[NodeId], graph: &V8HeapGraph) -> SizeDistribution {
+    SizeDistribution::of(nodes.iter().map(|&n| graph.self_size_for(n)).collect())
+}
+
+impl SizeDistribution {
+    /// The distribution of any set of values, not necessarily sizes
+    pub fn of(mut sizes: Vec<usize>) -> Self {
+        if sizes.is_empty() {
+            return SizeDistribution::default();
+        }
+        sizes.sort_unstable();
+
+        let percentile = |p: usize| sizes[(p * sizes.len()).div_ceil(100).max(1) - 1];
+
+        let mut histogram: Vec<SizeBucket> = vec![];
+        for &size in &sizes {
+            let from = if size == 0 { 0 } else { 1 << size.ilog2() };
+            match histogram.last_mut() {
+                Some(bucket) if bucket.from == from => bucket.count += 1,
+                _ => histogram.push(SizeBucket {
+                    from,
+                    to: if from == 0 { 1 } else { from * 2 },
+                    count: 1,
+                }),
+            }
+        }
+
+        SizeDistribution {
+            count: sizes.len(),
+            min: sizes[0],
+            median: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: sizes[sizes.len() - 1],
+            histogram,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::dominator_tree::tree_from_immediate_dominators,
+        graph::{lengauer_tarjan::lengauer_tarjan, test_graph::TestGraph},
+        snapshot::read_v8_snapshot_file,
+    };
+
+    #[test]
+    fn test_focus_on_fixture_constructor() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/object.heapsnapshot");
+        let graph = V8HeapGraph::from(read_v8_snapshot_file(&path).unwrap());
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[0]), 0, &graph);
+
+        let report = focus_on_constructor("BuiltinModule", &tree, &graph);
+
+        assert_eq!(report.count, 365);
+        assert_eq!(report.instances.len(), 365);
+        assert_eq!(report.total_self_size, 365 * 80);
+        assert_eq!(report.hidden_class_count, 1);
+        assert_eq!(report.property_set_count, 1);
+        assert!(report.total_retained_size > report.total_self_size);
+        let retained = |n: &NodeId| tree.retained_size(*n);
+        assert!(
+            report
+                .instances
+                .windows(2)
+                .all(|w| retained(&w[0]) >= retained(&w[1]))
+        );
+
+        assert_eq!(focus_on_constructor("NoSuchClass", &tree, &graph).count, 0);
+    }
+
+    #[test]
+    fn test_skewed_size_distribution() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        for i in 0..98 {
+            let small = g.node(NodeType::Object, "Foo", 40);
+            g.element(root, i, small);
+        }
+        for i in 98..100 {
+            let huge = g.node(NodeType::Object, "Foo", 2 << 20);
+            g.element(root, i, huge);
+        }
+        let other = g.node(NodeType::Object, "Bar", 1000);
+        g.element(root, 100, other);
+        let graph = g.build();
+
+        let dist = size_distribution(&graph, "Foo");
+        assert_eq!(dist.count, 100);
+        assert_eq!(dist.min, 40);
+        assert_eq!(dist.median, 40);
+        assert_eq!(dist.p90, 40);
+        assert_eq!(dist.p99, 2 << 20);
+        assert_eq!(dist.max, 2 << 20);
+        assert_eq!(
+            dist.histogram,
+            vec![
+                SizeBucket {
+                    from: 32,
+                    to: 64,
+                    count: 98
+                },
+                SizeBucket {
+                    from: 2 << 20,
+                    to: 4 << 20,
+                    count: 2
+                },
+            ]
+        );
+
+        assert_eq!(
+            size_distribution(&graph, "Nothing"),
+            SizeDistribution::default()
+        );
+    }
+}
@@ -1,5 +1,22 @@
-// pub mod duplicates;
+pub mod duplicates;
 // pub mod hidden_classes;
 // pub mod retained_size;
-pub mod dominator_tree;
 pub mod all_paths;
+pub mod analyzed;
+pub mod cache;
+pub mod composition;
+pub mod contexts;
+pub mod cycles;
+pub mod detached;
+pub mod dominator_tree;
+pub mod duplicate_diff;
+pub mod explain;
+pub mod focus;
+pub mod results;
+pub mod sample;
+pub mod sanity;
+pub mod shapes;
+pub mod sparse_arrays;
+pub mod stats;
+pub mod suspects;
+pub mod tags;
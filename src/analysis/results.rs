@@ -0,0 +1,402 @@
+use std::{
+    io::Write,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+
+use crate::{
+    analysis::{
+        all_paths::{DEFAULT_MAX_PATH_DEPTH, RootPaths, find_root_paths_within},
+        analyzed::AnalyzedGraph,
+        composition::heap_composition,
+        detached::find_detached_dom_trees,
+        dominator_tree::{DominatorTree, tree_from_immediate_dominators},
+        duplicates::{
+            DuplicateAnalyzer, DuplicateGroup, DuplicateOptions, largest_unique_retainers,
+            rollup_by_constructor,
+        },
+        sanity::SanityReport,
+    },
+    graph::{lengauer_tarjan::lengauer_tarjan, v8_heap_graph::V8HeapGraph},
+    report::{
+        format_constructor_rollup, format_detached_dom, format_duplicates, format_footer,
+        format_heap_composition, format_largest_unique_retainers, format_sanity_report,
+        json::{JsonOptions, generate_json_report, retention_paths_of, write_json_report},
+    },
+    utils::{Timings, format_bytes, format_modified},
+};
+
+/// How many objects the text report lists as largest unique retainers
+const REPORTED_UNIQUE_RETAINERS: usize = 10;
+
+/// Everything the standard report is made of, for a single graph
+///
+/// This is what the command line tool prints; library users can get the same reports with
+/// `to_text_report` and `to_json_report`.
+pub struct AnalysisResults<'a> {
+    pub graph: &'a V8HeapGraph,
+    pub tree: DominatorTree,
+    pub root_paths: RootPaths,
+    /// Duplicate groups, with retained sizes
+    pub duplicates: Vec<DuplicateGroup>,
+    /// Only available if the snapshot file was checked before it was turned into a graph
+    pub sanity: Option<SanityReport>,
+    pub timings: Timings,
+    /// Free text to tell reports apart by, see `--label`
+    pub label: Option<String>,
+    /// When the snapshot file was last written, if it was read from a file
+    pub modified: Option<SystemTime>,
+}
+
+/// Run the standard analysis on a graph, relative to the snapshot root
+///
+/// ```
+/// use std::path::Path;
+/// use v8_heap_analyzer::analysis::results::analyze_snapshot;
+/// use v8_heap_analyzer::graph::v8_heap_graph::V8HeapGraph;
+/// use v8_heap_analyzer::snapshot::read_v8_snapshot_file;
+///
+/// let snapshot = read_v8_snapshot_file(Path::new("tests/fixtures/test-duplicates.heapsnapshot"))?;
+/// let graph = V8HeapGraph::from(snapshot);
+/// let results = analyze_snapshot(&graph, &Default::default());
+///
+/// let mut report = Vec::new();
+/// results.to_text_report(&mut report)?;
+/// assert!(String::from_utf8(report)?.contains("Duplicate objects:"));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn analyze_snapshot<'a>(
+    graph: &'a V8HeapGraph,
+    options: &DuplicateOptions,
+) -> AnalysisResults<'a> {
+    let elapsed_ms = |start: Instant| start.elapsed().as_millis() as u64;
+    let started = Instant::now();
+
+    let tree = tree_from_immediate_dominators(lengauer_tarjan(graph, &[0]), 0, graph);
+    let dominators_ms = elapsed_ms(started);
+
+    let phase = Instant::now();
+    let mut duplicates = DuplicateAnalyzer::new(graph, options.clone()).find_duplicates();
+    DuplicateAnalyzer::enrich_with_retained_sizes(&mut duplicates, &tree);
+    let duplicates_ms = elapsed_ms(phase);
+
+    let phase = Instant::now();
+    let root_paths = find_root_paths_within(graph, 0, DEFAULT_MAX_PATH_DEPTH);
+    let paths_ms = elapsed_ms(phase);
+
+    AnalysisResults {
+        graph,
+        root_paths,
+        tree,
+        duplicates,
+        sanity: None,
+        timings: Timings {
+            parse_ms: 0,
+            dominators_ms,
+            duplicates_ms,
+            paths_ms,
+            total_ms: elapsed_ms(started),
+        },
+        label: None,
+        modified: None,
+    }
+}
+
+impl AnalysisResults<'_> {
+    /// The graph with its retained sizes and root paths, for the report functions
+    pub fn analyzed(&self) -> AnalyzedGraph<'_> {
+        AnalyzedGraph::new(self.graph, &self.tree, &self.root_paths)
+    }
+
+    /// The human readable summary: counts, detached DOM, heap composition, integrity and
+    /// duplicates
+    pub fn to_text_report(&self, w: &mut impl Write) -> Result<()> {
+        let graph = self.graph;
+        let mut text = String::new();
+        {
+            use std::fmt::Write;
+
+            if let Some(label) = &self.label {
+                writeln!(text, "Label:        {}", label)?;
+            }
+            if let Some(modified) = self.modified {
+                writeln!(text, "Modified:     {}", format_modified(modified))?;
+            }
+            writeln!(text, "Fingerprint:  {:016x}", graph.fingerprint())?;
+            writeln!(text, "Nodes:        {}", graph.total_node_count())?;
+            writeln!(text, "Edges:        {}", graph.total_edge_count())?;
+            writeln!(
+                text,
+                "Extra native: {}",
+                format_bytes(graph.extra_native_bytes())
+            )?;
+            if let Some(detached) = find_detached_dom_trees(graph, &self.tree) {
+                writeln!(text)?;
+                format_detached_dom(&mut text, &detached, graph)?;
+            }
+            writeln!(text)?;
+            format_heap_composition(&mut text, &heap_composition(graph))?;
+            if let Some(sanity) = &self.sanity {
+                writeln!(text)?;
+                format_sanity_report(&mut text, sanity)?;
+            }
+            writeln!(text)?;
+            format_duplicates(&mut text, &self.duplicates, graph)?;
+            writeln!(text)?;
+            format_constructor_rollup(&mut text, &rollup_by_constructor(&self.duplicates))?;
+            writeln!(text)?;
+            format_largest_unique_retainers(
+                &mut text,
+                &largest_unique_retainers(
+                    &self.duplicates,
+                    &self.tree,
+                    graph,
+                    REPORTED_UNIQUE_RETAINERS,
+                ),
+                self.analyzed(),
+            )?;
+            writeln!(text)?;
+            format_footer(&mut text, &self.timings, graph)?;
+        }
+
+        w.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    /// The machine-readable report, see `json::JsonReport`
+    pub fn to_json_report(&self, w: impl Write) -> Result<()> {
+        self.to_json_report_with(w, &JsonOptions::default())
+    }
+
+    pub fn to_json_report_with(&self, w: impl Write, options: &JsonOptions) -> Result<()> {
+        let mut report = generate_json_report(self.graph, self.duplicates.clone());
+        report.summary.timings = self.timings.clone();
+        report.summary.label = self.label.clone();
+        report.summary.modified = self
+            .modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        if options.stable_ids {
+            for group in &mut report.duplicates {
+                group.stable_ids = group.member_stable_ids(self.graph);
+            }
+        }
+        report.retention_paths = retention_paths_of(
+            self.duplicates.iter().map(|g| g.representative),
+            self.analyzed(),
+        );
+        write_json_report(report, options, w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        graph::{
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+        types::NodeId,
+    };
+
+    #[test]
+    fn test_json_timings() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::String, "same", 20);
+        let b = g.node(NodeType::String, "same", 20);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b);
+        let graph = g.build();
+
+        let mut out = vec![];
+        analyze_snapshot(&graph, &Default::default())
+            .to_json_report(&mut out)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        let timings = json["summary"]["timings"].as_object().unwrap();
+        let keys: Vec<&str> = timings.keys().map(|k| k.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "dominators_ms",
+                "duplicates_ms",
+                "parse_ms",
+                "paths_ms",
+                "total_ms"
+            ]
+        );
+        assert!(timings.values().all(|v| v.is_u64()));
+        assert!(json["summary"]["graph_mem_size"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_report_metadata() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 20);
+        g.edge(root, EdgeType::Property, "a", a);
+        let graph = g.build();
+        let fingerprint = format!("{:016x}", graph.fingerprint());
+
+        let mut results = analyze_snapshot(&graph, &Default::default());
+        let mut out = vec![];
+        results.to_json_report(&mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["summary"]["fingerprint"], fingerprint.as_str());
+        assert!(json["summary"]["label"].is_null());
+        assert!(json["summary"]["modified"].is_null());
+
+        results.label = Some("after login".to_string());
+        results.modified = Some(UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000));
+        let mut out = vec![];
+        results.to_json_report(&mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["summary"]["label"], "after login");
+        assert_eq!(json["summary"]["modified"], 1_700_000_000);
+
+        let mut out = vec![];
+        results.to_text_report(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(
+            text.starts_with("Label:        after login\nModified:     2023-11-14 22:13:20 UTC")
+        );
+        assert!(text.contains(&format!("Fingerprint:  {}\n", fingerprint)));
+    }
+
+    #[test]
+    fn test_json_retention_paths() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let cache = g.node(NodeType::Object, "Cache", 20);
+        let entries = g.node(NodeType::Array, "Array", 16);
+        let a = g.node(NodeType::String, "same", 20);
+        let b = g.node(NodeType::String, "same", 20);
+        g.edge(root, EdgeType::Property, "cache", cache)
+            .edge(cache, EdgeType::Property, "entries", entries)
+            .element(entries, 0, a)
+            .element(entries, 1, b);
+        let graph = g.build();
+
+        let results = analyze_snapshot(&graph, &Default::default());
+        let mut out = vec![];
+        results.to_json_report(&mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        let representative = json["duplicates"][0]["representative"].clone();
+        let paths = &json["retention_paths"][0];
+        assert_eq!(paths["node_id"], representative);
+        let hops = paths["paths"][0].as_array().unwrap();
+        assert_eq!(hops.len(), 3);
+        assert_eq!(
+            hops[0],
+            serde_json::json!({
+                "node_id": cache,
+                "type": "object",
+                "name": "Cache",
+                "edge_type": "property",
+                "edge_name": "cache",
+                "any_index": false,
+            })
+        );
+        assert_eq!(hops[1]["edge_name"], "entries");
+        assert_eq!(hops[2]["node_id"], representative);
+        assert_eq!(hops[2]["type"], "string");
+        assert_eq!(hops[2]["edge_type"], "element");
+
+        // The members of a group are often in the same array: their paths are merged
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let entries = g.node(NodeType::Array, "Array", 16);
+        let shared = g.node(NodeType::Object, "Config", 20);
+        g.edge(root, EdgeType::Property, "entries", entries);
+        for i in 0..5 {
+            let entry = g.node(NodeType::Object, "Entry", 20);
+            g.element(entries, i, entry)
+                .edge(entry, EdgeType::Property, "config", shared);
+        }
+        let graph = g.build();
+        let results = analyze_snapshot(&graph, &Default::default());
+        let analyzed = AnalyzedGraph::new(&graph, &results.tree, &results.root_paths);
+        let paths = retention_paths_of([shared], analyzed);
+        assert_eq!(paths[0].paths.len(), 1);
+        let any_index: Vec<bool> = paths[0].paths[0].iter().map(|h| h.any_index).collect();
+        assert_eq!(any_index, vec![false, true, false]);
+
+        // Reports without root paths leave them out
+        let report = generate_json_report(&graph, results.duplicates.clone());
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json.get("retention_paths").is_none());
+    }
+
+    #[test]
+    fn test_json_stable_ids() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::String, "same", 20);
+        let b = g.node(NodeType::String, "same", 20);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b);
+        let graph = g.build();
+        let results = analyze_snapshot(&graph, &Default::default());
+        let write = |options: JsonOptions| {
+            let mut out = vec![];
+            results.to_json_report_with(&mut out, &options).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&out).unwrap()
+        };
+
+        // Only the representative's by default
+        let json = write(JsonOptions::default());
+        let group = &json["duplicates"][0];
+        let representative = group["representative"].as_u64().unwrap() as NodeId;
+        assert_eq!(
+            group["representative_stable_id"],
+            graph.node(representative).stable_id()
+        );
+        assert!(group.get("stable_ids").is_none());
+
+        // Stable ids map one-to-one to the members, even if those are left out
+        let json = write(JsonOptions {
+            node_ids: false,
+            stable_ids: true,
+            ..Default::default()
+        });
+        let group = &json["duplicates"][0];
+        assert!(group.get("node_ids").is_none());
+        assert_eq!(
+            group["stable_ids"],
+            serde_json::json!([2 * a + 1, 2 * b + 1])
+        );
+    }
+
+    #[test]
+    fn test_tiny_snapshots() {
+        let report = |graph: &V8HeapGraph| {
+            let results = analyze_snapshot(graph, &Default::default());
+            let mut text = vec![];
+            results.to_text_report(&mut text).unwrap();
+            let mut json = vec![];
+            results.to_json_report(&mut json).unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+            (String::from_utf8(text).unwrap(), json)
+        };
+
+        // No nodes at all, so not even a root
+        let graph = TestGraph::new().build();
+        let (text, json) = report(&graph);
+        assert!(text.contains("Nodes:        0"));
+        assert_eq!(json["summary"]["node_count"], 0);
+        assert_eq!(json["duplicates"].as_array().unwrap().len(), 0);
+
+        // Only a root, without edges
+        let mut g = TestGraph::new();
+        g.node(NodeType::Synthetic, "root", 0);
+        let graph = g.build();
+        let (text, json) = report(&graph);
+        assert!(text.contains("Nodes:        1"));
+        assert_eq!(json["summary"]["edge_count"], 0);
+    }
+}
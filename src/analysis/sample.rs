@@ -0,0 +1,100 @@
+use std::cmp::Reverse;
+
+use serde::Serialize;
+
+use crate::{
+    analysis::{
+        composition::{TypeStats, subset_composition},
+        duplicates::{DuplicateAnalyzer, DuplicateGroup, DuplicateOptions},
+    },
+    graph::v8_heap_graph::V8HeapGraph,
+    types::NodeId,
+};
+
+/// Type and duplicate analysis over the largest nodes only
+///
+/// This is a quick preview for snapshots that are too big to analyze fully: there is no
+/// dominator tree, so there are no retained sizes, and duplicates outside of the sample are
+/// missed. Numbers are lower bounds.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampledReport {
+    /// Always true, so that JSON consumers can't mistake this for a full report
+    pub sampled: bool,
+    pub total_node_count: usize,
+    /// The sampled nodes, in node order
+    #[serde(skip)]
+    pub nodes: Vec<NodeId>,
+    pub sampled_node_count: usize,
+    pub composition: Vec<TypeStats>,
+    pub duplicates: Vec<DuplicateGroup>,
+}
+
+/// The `limit` nodes with the largest self size, in node order
+///
+/// Ties are broken by node id, so the sample is deterministic.
+pub fn largest_nodes(graph: &V8HeapGraph, limit: usize) -> Vec<NodeId> {
+    let mut nodes: Vec<NodeId> = graph.iter_nodes().collect();
+    let key = |&n: &NodeId| (Reverse(graph.self_size_for(n)), n);
+    if limit < nodes.len() {
+        nodes.select_nth_unstable_by_key(limit, key);
+        nodes.truncate(limit);
+    }
+    nodes.sort_unstable();
+    nodes
+}
+
+pub fn analyze_sample(
+    graph: &V8HeapGraph,
+    limit: usize,
+    options: &DuplicateOptions,
+) -> SampledReport {
+    let nodes = largest_nodes(graph, limit);
+    let duplicates = DuplicateAnalyzer::new(graph, options.clone())
+        .restricted_to(&nodes)
+        .find_duplicates();
+
+    SampledReport {
+        sampled: true,
+        total_node_count: graph.total_node_count(),
+        sampled_node_count: nodes.len(),
+        composition: subset_composition(graph, &nodes),
+        duplicates,
+        nodes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{
+        test_graph::TestGraph,
+        v8_heap_graph::{EdgeType, NodeType},
+    };
+
+    #[test]
+    fn test_analyze_sample() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let big1 = g.node(NodeType::String, "big", 1000);
+        let big2 = g.node(NodeType::String, "big", 1000);
+        let array = g.node(NodeType::Array, "Array", 500);
+        let small1 = g.node(NodeType::String, "small", 10);
+        let small2 = g.node(NodeType::String, "small", 10);
+        for (i, node) in [big1, big2, array, small1, small2].into_iter().enumerate() {
+            g.element(root, i as NodeId, node);
+        }
+        g.edge(array, EdgeType::Property, "first", big1);
+        let graph = g.build();
+
+        let report = analyze_sample(&graph, 3, &Default::default());
+        assert_eq!(report.nodes, vec![big1, big2, array]);
+        assert_eq!(report.sampled_node_count, 3);
+        assert_eq!(report.total_node_count, 6);
+
+        // Composition and duplicates only cover the sampled nodes
+        let counted: usize = report.composition.iter().map(|s| s.count).sum();
+        assert_eq!(counted, 3);
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].node_ids, vec![big1, big2]);
+    }
+}
@@ -0,0 +1,148 @@
+use fixedbitset::FixedBitSet;
+use serde::Serialize;
+
+use crate::{
+    graph::v8_heap_graph::{NodeType, V8HeapGraph},
+    snapshot::{SnapshotFile, compat::compatibility_warnings},
+};
+
+/// A quick integrity check of a snapshot, to tell a broken file apart from a strange heap
+///
+/// The counts are checked on the raw snapshot, before building a graph. Reachability needs
+/// the graph, and is filled in afterwards by `add_graph_stats`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SanityReport {
+    pub declared_node_count: usize,
+    pub actual_node_count: usize,
+    pub declared_edge_count: usize,
+    pub actual_edge_count: usize,
+    pub has_detachedness: bool,
+    pub has_trace_node_id: bool,
+    /// Number of children of the `(GC roots)` node
+    pub gc_roots: usize,
+    pub unreachable_nodes: usize,
+    /// Problems worth mentioning, which don't stop the analysis
+    pub warnings: Vec<String>,
+}
+
+/// Compare the counts declared in the snapshot header with the arrays that were actually read,
+/// and point out fields this version doesn't know about
+pub fn check_snapshot(snapshot: &SnapshotFile) -> SanityReport {
+    let meta = &snapshot.snapshot.meta;
+    let mut report = SanityReport {
+        declared_node_count: snapshot.snapshot.node_count,
+        actual_node_count: snapshot.nodes.len() / meta.node_field_count(),
+        declared_edge_count: snapshot.snapshot.edge_count,
+        actual_edge_count: snapshot.edges.len() / meta.edge_field_count(),
+        has_detachedness: meta.node_fields.iter().any(|f| f == "detachedness"),
+        has_trace_node_id: meta.node_fields.iter().any(|f| f == "trace_node_id"),
+        ..Default::default()
+    };
+
+    for (what, declared, actual) in [
+        (
+            "nodes",
+            report.declared_node_count,
+            report.actual_node_count,
+        ),
+        (
+            "edges",
+            report.declared_edge_count,
+            report.actual_edge_count,
+        ),
+    ] {
+        if declared != actual {
+            report.warnings.push(format!(
+                "Snapshot declares {} {} but contains {}; the file may be truncated",
+                declared, what, actual
+            ));
+        }
+    }
+    if !snapshot.nodes.len().is_multiple_of(meta.node_field_count()) {
+        report
+            .warnings
+            .push("Nodes array does not contain a whole number of nodes".to_string());
+    }
+    if !snapshot.edges.len().is_multiple_of(meta.edge_field_count()) {
+        report
+            .warnings
+            .push("Edges array does not contain a whole number of edges".to_string());
+    }
+    report.warnings.extend(compatibility_warnings(meta));
+
+    report
+}
+
+/// The same report for a graph read back with `V8HeapGraph::load_saved`
+///
+/// Without the raw snapshot, the counts are the ones the graph was built from, so they
+/// always agree. Problems with the snapshot itself were reported when the graph was saved.
+pub fn check_graph(graph: &V8HeapGraph) -> SanityReport {
+    SanityReport {
+        declared_node_count: graph.total_node_count(),
+        actual_node_count: graph.total_node_count(),
+        declared_edge_count: graph.total_edge_count(),
+        actual_edge_count: graph.total_edge_count(),
+        has_detachedness: graph.node_fields.detachedness_field().is_some(),
+        has_trace_node_id: graph.node_fields.field_index("trace_node_id").is_some(),
+        ..Default::default()
+    }
+}
+
+impl SanityReport {
+    /// Count GC roots and unreachable nodes, given the result of `reachable_from_root`
+    pub fn add_graph_stats(&mut self, graph: &V8HeapGraph, reachable: &FixedBitSet) {
+        // An empty graph doesn't have a root, so it has no GC roots either
+        let gc_roots = graph.iter_nodes().next().and_then(|root| {
+            graph.out_edges(root).map(|e| e.to_node()).find(|&n| {
+                let node = graph.node(n);
+                node.typ() == NodeType::Synthetic && node.name() == "(GC roots)"
+            })
+        });
+        match gc_roots {
+            Some(gc_roots) => self.gc_roots = graph.out_edges(gc_roots).count(),
+            None => self
+                .warnings
+                .push("No (GC roots) node found below the root".to_string()),
+        }
+
+        self.unreachable_nodes = graph
+            .iter_nodes()
+            .filter(|&n| !reachable.contains(n as usize))
+            .count();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::all_paths::reachable_from_root, graph::test_graph::TestGraph};
+
+    #[test]
+    fn test_truncated_node_array() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "", 0);
+        let gc_roots = g.node(NodeType::Synthetic, "(GC roots)", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        g.node(NodeType::Object, "Garbage", 10);
+        g.element(root, 0, gc_roots).element(gc_roots, 0, a);
+
+        let mut snapshot = g.snapshot();
+        snapshot.snapshot.node_count += 5;
+
+        let mut report = check_snapshot(&snapshot);
+        assert_eq!(report.declared_node_count, 9);
+        assert_eq!(report.actual_node_count, 4);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("declares 9 nodes but contains 4"));
+
+        // The graph only holds the nodes that are actually there
+        let graph = V8HeapGraph::from(snapshot);
+        assert_eq!(graph.total_node_count(), 4);
+
+        report.add_graph_stats(&graph, &reachable_from_root(&graph));
+        assert_eq!(report.gc_roots, 1);
+        assert_eq!(report.unreachable_nodes, 1);
+        assert_eq!(report.warnings.len(), 1);
+    }
+}
@@ -0,0 +1,109 @@
+use std::cmp::Reverse;
+
+use ahash::AHashMap;
+use serde::Serialize;
+
+use crate::{
+    graph::v8_heap_graph::{EdgeType, NodeType, V8HeapGraph},
+    types::NodeId,
+};
+
+/// Objects that have the same property names, whatever their values
+///
+/// Exact duplicate detection misses objects that only differ in a unique field like an
+/// `id`. Many objects with the same shape point at data that could be stored more compactly,
+/// for example as columns instead of rows.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShapeCluster {
+    /// The property names, sorted
+    pub properties: Vec<String>,
+    pub count: usize,
+    pub total_self_size: usize,
+    /// The largest object in the cluster
+    pub representative: NodeId,
+}
+
+/// Group all objects by their set of property names, largest total size first
+///
+/// `__proto__` is left out, since nearly every object has one. Objects without properties
+/// and shapes with a single object are not reported.
+pub fn cluster_by_shape(graph: &V8HeapGraph) -> Vec<ShapeCluster> {
+    let mut clusters: AHashMap<Vec<String>, ShapeCluster> = AHashMap::new();
+    for n in graph.iter_nodes() {
+        if graph.node(n).typ() != NodeType::Object {
+            continue;
+        }
+
+        let mut properties: Vec<String> = graph
+            .out_edges(n)
+            .filter(|e| e.typ() == EdgeType::Property && !e.name_or_index().is_str("__proto__"))
+            .map(|e| e.name_or_index().to_string())
+            .collect();
+        if properties.is_empty() {
+            continue;
+        }
+        properties.sort();
+        properties.dedup();
+
+        let size = graph.self_size_for(n);
+        let cluster = clusters
+            .entry(properties)
+            .or_insert_with_key(|properties| ShapeCluster {
+                properties: properties.clone(),
+                count: 0,
+                total_self_size: 0,
+                representative: n,
+            });
+        cluster.count += 1;
+        cluster.total_self_size += size;
+        if size > graph.self_size_for(cluster.representative) {
+            cluster.representative = n;
+        }
+    }
+
+    let mut clusters: Vec<ShapeCluster> = clusters.into_values().filter(|c| c.count > 1).collect();
+    clusters.sort_by(|a, b| {
+        Reverse(a.total_self_size)
+            .cmp(&Reverse(b.total_self_size))
+            .then_with(|| a.properties.cmp(&b.properties))
+    });
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::duplicates::DuplicateAnalyzer, graph::test_graph::TestGraph};
+
+    #[test]
+    fn test_cluster_by_shape() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let proto = g.node(NodeType::Object, "Object", 16);
+        for i in 0..3 {
+            let user = g.node(NodeType::Object, "User", 32);
+            let id = g.node(NodeType::Number, &format!("{}", i), 16);
+            let name = g.node(NodeType::String, &format!("user{}", i), 24);
+            g.element(root, i, user)
+                .edge(user, EdgeType::Property, "name", name)
+                .edge(user, EdgeType::Property, "id", id)
+                .edge(user, EdgeType::Property, "__proto__", proto);
+        }
+        let other = g.node(NodeType::Object, "Other", 100);
+        let value = g.node(NodeType::String, "value", 24);
+        g.element(root, 3, other)
+            .edge(other, EdgeType::Property, "id", value);
+        let graph = g.build();
+
+        // No two users are equal, so they aren't duplicates
+        let duplicates =
+            DuplicateAnalyzer::new(&graph, Default::default()).find_duplicate_objects();
+        assert!(duplicates.is_empty());
+
+        let clusters = cluster_by_shape(&graph);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].properties, vec!["id", "name"]);
+        assert_eq!(clusters[0].count, 3);
+        assert_eq!(clusters[0].total_self_size, 96);
+    }
+}
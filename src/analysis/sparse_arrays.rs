@@ -0,0 +1,100 @@
+use std::cmp::Reverse;
+
+use serde::Serialize;
+
+use crate::{graph::v8_heap_graph::V8HeapGraph, types::NodeId};
+
+/// Arrays shorter than this are never reported, however few elements they have
+pub const MIN_SPARSE_LENGTH: usize = 1024;
+
+/// An array counts as sparse if its length is this many times its number of elements
+pub const SPARSE_RATIO: usize = 8;
+
+/// An array whose highest element index is far beyond the number of elements it has
+#[derive(Debug, Clone, Serialize)]
+pub struct SparseArray {
+    pub node: NodeId,
+    /// Number of element edges
+    pub populated: usize,
+    pub max_index: NodeId,
+    /// Self size of the array plus its `elements` backing store
+    pub size: usize,
+}
+
+impl SparseArray {
+    /// The JavaScript `length`, assuming nothing was assigned after the highest index
+    pub fn length(&self) -> usize {
+        self.max_index as usize + 1
+    }
+}
+
+/// Find arrays with holes, largest first
+///
+/// A handful of elements at far apart indices (`a[0]` and `a[1000000]`) can make V8 keep a
+/// backing store sized for the whole range, or switch to a slow dictionary. Either way it is
+/// rarely what the program meant. Only array-like objects (the ones with an `elements`
+/// backing store) are considered.
+pub fn find_sparse_arrays(graph: &V8HeapGraph) -> Vec<SparseArray> {
+    let mut arrays: Vec<SparseArray> = graph
+        .iter_nodes()
+        .filter_map(|n| {
+            let node = graph.node(n);
+            if !node.is_array_like() {
+                return None;
+            }
+
+            let mut populated = 0;
+            let mut max_index = 0;
+            for edge in node.element_edges() {
+                populated += 1;
+                max_index = max_index.max(edge.name_or_index().index().unwrap_or(0));
+            }
+            let length = max_index as usize + 1;
+            if populated == 0 || length < MIN_SPARSE_LENGTH || length < populated * SPARSE_RATIO {
+                return None;
+            }
+
+            Some(SparseArray {
+                node: n,
+                populated,
+                max_index,
+                size: node.effective_size(),
+            })
+        })
+        .collect();
+    arrays.sort_by_key(|a| (Reverse(a.size), Reverse(a.max_index), a.node));
+    arrays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::read_v8_snapshot_file;
+
+    #[test]
+    fn test_sparse_array_fixture() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/test-sparse-array.heapsnapshot");
+        let graph = V8HeapGraph::from(read_v8_snapshot_file(&path).unwrap());
+
+        let arrays = find_sparse_arrays(&graph);
+
+        let global = graph
+            .iter_nodes()
+            .find(|&n| graph.get_property(n, "sparseArray").is_some())
+            .unwrap();
+        let sparse = graph.get_property(global, "sparseArray").unwrap();
+        let found = arrays.iter().find(|a| a.node == sparse).unwrap();
+        assert_eq!(found.populated, 2);
+        assert_eq!(found.max_index, 1_000_000);
+        assert_eq!(found.length(), 1_000_001);
+        assert!(found.size > graph.node(sparse).self_size());
+
+        // Arrays that are merely large are not sparse
+        assert!(
+            arrays
+                .iter()
+                .all(|a| a.length() >= a.populated * SPARSE_RATIO)
+        );
+    }
+}
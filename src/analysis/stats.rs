@@ -0,0 +1,145 @@
+use petgraph::algo::kosaraju_scc;
+use serde::Serialize;
+
+use crate::{
+    analysis::{
+        all_paths::{reachable_from_root, root_distances_from},
+        focus::SizeDistribution,
+    },
+    graph::{petgraph_traits::StrongEdges, v8_heap_graph::V8HeapGraph},
+    types::NodeId,
+};
+
+/// Number of nodes that a diameter estimate starts a search from
+pub const DIAMETER_SAMPLES: usize = 8;
+
+/// The shape of the graph as a whole, for studying heap structure rather than single leaks
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphStats {
+    pub node_count: usize,
+    pub edge_count: usize,
+    /// Number of outgoing edges per node, over all edges
+    pub out_degree: SizeDistribution,
+    /// Number of incoming edges per node, over all edges
+    pub in_degree: SizeDistribution,
+    /// Strongly connected components over strong edges, including single nodes
+    pub scc_count: usize,
+    pub largest_scc: usize,
+    /// The longest shortest path found from a few sample nodes; a lower bound of the diameter
+    pub diameter_estimate: u32,
+    /// Share of the nodes that are reachable from the root, between 0 and 1
+    pub reachable_fraction: f64,
+}
+
+/// Degree distributions, components, and an estimate of the diameter of the graph
+///
+/// The diameter is estimated with a breadth-first search from the root and from
+/// `DIAMETER_SAMPLES` nodes spread evenly over the node ids, following the same edges as the
+/// root distances. Nodes that a search can't reach don't count.
+pub fn graph_stats(graph: &V8HeapGraph) -> GraphStats {
+    let node_count = graph.total_node_count();
+
+    let out_degree = graph.iter_nodes().map(|n| graph.edge_count_for(n) as usize);
+    let mut in_degree = vec![0; node_count];
+    for &to in graph.iter_nodes().flat_map(|n| graph.out_neighbors(n)) {
+        in_degree[to as usize] += 1;
+    }
+
+    let components = kosaraju_scc(StrongEdges(graph));
+
+    let step = node_count.div_ceil(DIAMETER_SAMPLES).max(1);
+    let diameter_estimate = (0..node_count)
+        .step_by(step)
+        .map(|n| {
+            root_distances_from(graph, &[n as NodeId])
+                .into_iter()
+                .filter(|&d| d != u32::MAX)
+                .max()
+                .unwrap_or(0)
+        })
+        .max()
+        .unwrap_or(0);
+
+    let reachable = reachable_from_root(graph).count_ones(..);
+
+    GraphStats {
+        node_count,
+        edge_count: graph.total_edge_count(),
+        out_degree: SizeDistribution::of(out_degree.collect()),
+        in_degree: SizeDistribution::of(in_degree),
+        scc_count: components.len(),
+        largest_scc: components.iter().map(|c| c.len()).max().unwrap_or(0),
+        diameter_estimate,
+        reachable_fraction: if node_count > 0 {
+            reachable as f64 / node_count as f64
+        } else {
+            0.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{
+        test_graph::TestGraph,
+        v8_heap_graph::{EdgeType, NodeType},
+    };
+
+    #[test]
+    fn test_graph_stats() {
+        // root -> a <-> b -> c, and d on its own, weakly held by c
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 10);
+        let c = g.node(NodeType::Object, "C", 10);
+        let d = g.node(NodeType::Object, "D", 10);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(a, EdgeType::Property, "b", b)
+            .edge(b, EdgeType::Property, "a", a)
+            .edge(b, EdgeType::Property, "c", c)
+            .edge(c, EdgeType::Weak, "d", d);
+        let graph = g.build();
+
+        let stats = graph_stats(&graph);
+        assert_eq!(stats.node_count, 5);
+        assert_eq!(stats.edge_count, 5);
+
+        // {a, b}, and everything else on its own
+        assert_eq!(stats.scc_count, 4);
+        assert_eq!(stats.largest_scc, 2);
+
+        assert_eq!(stats.out_degree.count, 5);
+        assert_eq!(stats.out_degree.max, 2);
+        assert_eq!(stats.out_degree.min, 0);
+        assert_eq!(stats.in_degree.max, 2);
+        assert_eq!(stats.in_degree.min, 0);
+
+        // root -> a -> b -> c
+        assert_eq!(stats.diameter_estimate, 3);
+        assert_eq!(stats.reachable_fraction, 0.8);
+    }
+
+    #[test]
+    fn test_graph_stats_on_deep_chain() {
+        // A linked list that is far deeper than a recursive traversal could handle
+        const DEPTH: NodeId = 100_000;
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let mut prev = root;
+        for _ in 0..DEPTH {
+            let next = g.node(NodeType::Object, "Link", 10);
+            g.edge(prev, EdgeType::Property, "next", next);
+            prev = next;
+        }
+        let graph = g.build();
+
+        let stats = graph_stats(&graph);
+        assert_eq!(stats.node_count, DEPTH as usize + 1);
+        assert_eq!(stats.scc_count, DEPTH as usize + 1);
+        assert_eq!(stats.largest_scc, 1);
+        assert_eq!(stats.diameter_estimate, DEPTH);
+        assert_eq!(stats.reachable_fraction, 1.0);
+    }
+}
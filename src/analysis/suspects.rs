@@ -0,0 +1,191 @@
+use std::cmp::Reverse;
+
+use serde::Serialize;
+
+use crate::{
+    analysis::analyzed::AnalyzedGraph,
+    graph::v8_heap_graph::{EdgeType, NodeType, is_noise_node},
+    types::NodeId,
+};
+
+/// Only nodes that retain at least this share of the heap are considered
+pub const MIN_SUSPECT_SHARE: f64 = 0.01;
+
+/// Weight of the share of the heap a node retains, between 0 and 1
+pub const RETAINED_WEIGHT: f64 = 4.0;
+/// Weight of a node being part of a detached DOM tree
+pub const DETACHED_WEIGHT: f64 = 3.0;
+/// Weight of the number of similar siblings, 1 at `MANY_SIBLINGS` or more
+pub const SIBLINGS_WEIGHT: f64 = 1.5;
+/// Weight of having a single retention path, doubled if it goes through V8 internals
+pub const SINGLE_PATH_WEIGHT: f64 = 1.0;
+
+/// A number of siblings with the same constructor that counts as "a lot"
+const MANY_SIBLINGS: f64 = 1000.0;
+
+/// A node that is likely to be a leak, and why
+#[derive(Debug, Clone, Serialize)]
+pub struct Suspect {
+    pub node: NodeId,
+    pub score: f64,
+    pub retained_size: usize,
+    pub detached: bool,
+    /// Other nodes with the same constructor and the same immediate dominator
+    pub similar_siblings: usize,
+    /// Whether there is only one shortest path from the root
+    pub single_path: bool,
+    /// Whether that path goes through context, internal or hidden edges: the node is kept
+    /// alive by something the program doesn't reference by name, like a closure
+    pub through_internals: bool,
+}
+
+/// The nodes that are most likely leaks, highest score first
+///
+/// Every node that retains at least `MIN_SUSPECT_SHARE` of the heap gets a score that adds up
+/// these signals, each between 0 and 1 and multiplied by its weight:
+///
+/// - `RETAINED_WEIGHT`: the share of the heap it retains.
+/// - `DETACHED_WEIGHT`: whether it is a detached DOM node.
+/// - `SIBLINGS_WEIGHT`: how many siblings in the dominator tree have the same constructor, on
+///   a log scale. Many similar objects under one owner is what an ever-growing cache looks
+///   like.
+/// - `SINGLE_PATH_WEIGHT`: whether there is only one retention path, so a single forgotten
+///   reference keeps it alive. Half if that path only has properties and elements, full if it
+///   goes through closure contexts or V8 internals.
+///
+/// Only objects, closures, arrays and native (DOM) nodes can be suspects, and not if they are
+/// V8 internals (see `is_noise_node`). Strings and code only ever leak because of what holds
+/// them.
+pub fn leak_suspects(analyzed: AnalyzedGraph<'_>) -> Vec<Suspect> {
+    let graph = analyzed.graph;
+    let tree = analyzed.tree;
+    if !tree.has_root() {
+        return vec![];
+    }
+    let total = tree.retained_size(tree.root).max(1);
+    let min_retained = (total as f64 * MIN_SUSPECT_SHARE).ceil() as usize;
+    let candidates: Vec<NodeId> = tree
+        .nodes_retaining_at_least(min_retained)
+        .into_iter()
+        .filter(|&n| {
+            let node = graph.node(n);
+            matches!(
+                node.typ(),
+                NodeType::Object | NodeType::Closure | NodeType::Array | NodeType::Native
+            ) && !is_noise_node(&node)
+        })
+        .collect();
+
+    let parents = tree.parents();
+
+    let mut suspects: Vec<Suspect> = candidates
+        .into_iter()
+        .map(|n| {
+            let node = graph.node(n);
+            let retained_size = tree.retained_size(n);
+
+            let constructor = node.constructor_name();
+            let similar_siblings = parents
+                .get(&n)
+                .and_then(|parent| tree.children.get(parent))
+                .map_or(0, |siblings| {
+                    siblings
+                        .iter()
+                        .filter(|&&s| s != n && graph.node(s).constructor_name() == constructor)
+                        .count()
+                });
+
+            let paths = analyzed.paths_to_limited(n, 2);
+            let single_path = paths.len() == 1;
+            let through_internals = single_path
+                && paths[0].edges(graph).any(|e| {
+                    matches!(
+                        e.typ(),
+                        EdgeType::Context | EdgeType::Internal | EdgeType::Hidden
+                    )
+                });
+
+            let siblings_signal =
+                ((similar_siblings as f64 + 1.0).log10() / MANY_SIBLINGS.log10()).min(1.0);
+            let path_signal = match (single_path, through_internals) {
+                (false, _) => 0.0,
+                (true, false) => 0.5,
+                (true, true) => 1.0,
+            };
+            let score = RETAINED_WEIGHT * (retained_size as f64 / total as f64)
+                + DETACHED_WEIGHT * f64::from(u8::from(node.detachedness()))
+                + SIBLINGS_WEIGHT * siblings_signal
+                + SINGLE_PATH_WEIGHT * path_signal;
+
+            Suspect {
+                node: n,
+                score,
+                retained_size,
+                detached: node.detachedness(),
+                similar_siblings,
+                single_path,
+                through_internals,
+            }
+        })
+        .collect();
+
+    suspects.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| Reverse(a.retained_size).cmp(&Reverse(b.retained_size)))
+            .then_with(|| a.node.cmp(&b.node))
+    });
+    suspects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::{all_paths::find_root_paths, dominator_tree::tree_from_immediate_dominators},
+        graph::{lengauer_tarjan::lengauer_tarjan, test_graph::TestGraph},
+    };
+
+    #[test]
+    fn test_detached_retainer_ranks_first() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+
+        // The biggest retainer, but an ordinary one: reachable in two ways
+        let app = g.node(NodeType::Object, "App", 100);
+        let state = g.node(NodeType::Array, "Array", 5000);
+        g.edge(root, EdgeType::Property, "app", app)
+            .edge(root, EdgeType::Property, "application", app)
+            .edge(app, EdgeType::Property, "state", state);
+
+        // A smaller detached DOM tree that a closure still holds on to
+        let listener = g.node(NodeType::Closure, "onClick", 50);
+        let context = g.node(NodeType::Object, "system / Context", 50);
+        let div = g.node(NodeType::Native, "Detached HTMLDivElement", 100);
+        let children = g.node(NodeType::Native, "Detached HTMLUListElement", 2000);
+        g.edge(root, EdgeType::Property, "listener", listener)
+            .edge(listener, EdgeType::Context, "context", context)
+            .edge(context, EdgeType::Context, "div", div)
+            .edge(div, EdgeType::Property, "list", children)
+            .detach(div)
+            .detach(children);
+        let graph = g.build();
+
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        let root_paths = find_root_paths(&graph, root);
+        let suspects = leak_suspects(AnalyzedGraph::new(&graph, &tree, &root_paths));
+
+        assert_eq!(suspects[0].node, div);
+        assert!(suspects[0].detached);
+        assert!(suspects[0].single_path);
+        assert!(suspects[0].through_internals);
+        assert!(suspects[0].retained_size < tree.retained_size(app));
+
+        let app_suspect = suspects.iter().find(|s| s.node == app).unwrap();
+        assert!(!app_suspect.detached);
+        assert!(!app_suspect.single_path);
+        assert!(suspects.windows(2).all(|w| w[0].score >= w[1].score));
+        // The root itself and V8 internals are never suspects
+        assert!(suspects.iter().all(|s| s.node != root && s.node != context));
+    }
+}
@@ -0,0 +1,156 @@
+use std::cmp::Reverse;
+
+use ahash::AHashMap;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::analysis::duplicates::DuplicateGroup;
+
+/// Bucket for the groups that no rule matches
+pub const UNTAGGED: &str = "(untagged)";
+
+/// A user-supplied classification: groups whose type or sample value match get this tag
+#[derive(Debug, Clone)]
+pub struct TagRule {
+    pub name: String,
+    pub pattern: Regex,
+}
+
+impl TagRule {
+    /// Parse a `name=regex` rule, as given to `--tag`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, pattern) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=REGEX, got {:?}", s))?;
+        if name.is_empty() {
+            return Err(format!("tag without a name: {:?}", s));
+        }
+        let pattern = Regex::new(pattern).map_err(|e| e.to_string())?;
+        Ok(TagRule {
+            name: name.to_string(),
+            pattern,
+        })
+    }
+
+    pub fn matches(&self, group: &DuplicateGroup) -> bool {
+        self.pattern.is_match(&group.object_type)
+            || group
+                .sample_value
+                .as_deref()
+                .is_some_and(|v| self.pattern.is_match(v))
+    }
+}
+
+/// Duplicates of all groups with the same tag, added together
+#[derive(Debug, Clone, Serialize)]
+pub struct TagSummary {
+    pub tag: String,
+    pub group_count: usize,
+    pub count: usize,
+    pub total_wasted: u64,
+}
+
+/// Tag every group with the first rule that matches its object type or sample value
+///
+/// Rules are tried in the order they were given, so a specific rule should come before a
+/// more general one that overlaps it. Groups that no rule matches get no tag.
+pub fn tag_groups(groups: &mut [DuplicateGroup], rules: &[TagRule]) {
+    for group in groups {
+        group.tag = rules
+            .iter()
+            .find(|rule| rule.matches(group))
+            .map(|rule| rule.name.clone());
+    }
+}
+
+/// Add up the waste of all groups per tag, largest first
+///
+/// Untagged groups share the `(untagged)` bucket.
+pub fn rollup_by_tag(groups: &[DuplicateGroup]) -> Vec<TagSummary> {
+    let mut by_tag: AHashMap<&str, TagSummary> = AHashMap::new();
+    for group in groups {
+        let tag = group.tag.as_deref().unwrap_or(UNTAGGED);
+        let summary = by_tag.entry(tag).or_insert_with(|| TagSummary {
+            tag: tag.to_string(),
+            group_count: 0,
+            count: 0,
+            total_wasted: 0,
+        });
+        summary.group_count += 1;
+        summary.count += group.count;
+        summary.total_wasted += group.total_wasted;
+    }
+
+    let mut summaries: Vec<_> = by_tag.into_values().collect();
+    summaries.sort_by(|a, b| {
+        Reverse(a.total_wasted)
+            .cmp(&Reverse(b.total_wasted))
+            .then_with(|| a.tag.cmp(&b.tag))
+    });
+    summaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(object_type: &str, sample_value: Option<&str>, wasted: u64) -> DuplicateGroup {
+        DuplicateGroup {
+            hash: 0,
+            object_type: object_type.to_string(),
+            count: 2,
+            size_per_object: wasted,
+            total_wasted: wasted,
+            representative: 0,
+            representative_stable_id: 0,
+            node_ids: vec![],
+            stable_ids: vec![],
+            sample_value: sample_value.map(str::to_string),
+            retained_size: None,
+            common_dominator: None,
+            tag: None,
+        }
+    }
+
+    #[test]
+    fn test_first_matching_tag_wins() {
+        let rules = [
+            TagRule::parse("react=^React").unwrap(),
+            // Overlaps with the first rule for ReactElement
+            TagRule::parse("elements=Element$").unwrap(),
+        ];
+        let mut groups = vec![
+            group("ReactElement", None, 100),
+            group("ReactFiber", None, 50),
+            group("HTMLDivElement", None, 30),
+            group("String", Some("React is great"), 10),
+            group("Object", None, 5),
+        ];
+        tag_groups(&mut groups, &rules);
+
+        let tags: Vec<_> = groups.iter().map(|g| g.tag.as_deref()).collect();
+        assert_eq!(
+            tags,
+            vec![
+                Some("react"),
+                Some("react"),
+                Some("elements"),
+                Some("react"),
+                None
+            ]
+        );
+
+        let rollup = rollup_by_tag(&groups);
+        let totals: Vec<_> = rollup
+            .iter()
+            .map(|s| (s.tag.as_str(), s.group_count, s.total_wasted))
+            .collect();
+        assert_eq!(
+            totals,
+            vec![("react", 3, 160), ("elements", 1, 30), (UNTAGGED, 1, 5)]
+        );
+
+        assert!(TagRule::parse("no equals sign").is_err());
+        assert!(TagRule::parse("bad=(").is_err());
+    }
+}
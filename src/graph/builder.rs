@@ -0,0 +1,254 @@
+use anyhow::{Context, Result, ensure};
+
+use crate::{
+    graph::v8_heap_graph::{V8HeapGraph, edge_target_node},
+    snapshot::{Snapshot, SnapshotFile, SnapshotMetadata, read_v8_snapshot_from_reader},
+    types::NodeId,
+};
+
+/// Builds a `V8HeapGraph` from snapshot data that arrives in multiple chunks
+///
+/// Some tooling splits very large snapshots over multiple files. `add_nodes` and `add_edges`
+/// can be called any number of times, and `finalize` stitches everything together. This
+/// works as long as the following invariants hold:
+///
+/// - Node chunks are added in order, and every chunk contains only whole nodes.
+/// - Edge chunks are added in order, and every chunk contains only whole edges.
+/// - Edges belong to nodes in the order in which the nodes were added, according to the
+///   `edge_count` field of every node. Node and edge chunks don't need to line up.
+/// - The `to_node` field of every edge is an index into the *complete* nodes array, not
+///   into the chunk that the target node happened to be in.
+pub struct GraphBuilder {
+    meta: SnapshotMetadata,
+    strings: Vec<String>,
+    nodes: Vec<NodeId>,
+    edges: Vec<NodeId>,
+}
+
+impl GraphBuilder {
+    pub fn new(meta: SnapshotMetadata, strings: Vec<String>) -> Self {
+        Self {
+            meta,
+            strings,
+            nodes: vec![],
+            edges: vec![],
+        }
+    }
+
+    /// Start from a complete snapshot
+    pub fn from_snapshot(snapshot: SnapshotFile) -> Self {
+        Self {
+            meta: snapshot.snapshot.meta,
+            strings: snapshot.strings,
+            nodes: snapshot.nodes,
+            edges: snapshot.edges,
+        }
+    }
+
+    /// Start from the JSON text of a snapshot held in memory, without touching the filesystem
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::from_snapshot(read_v8_snapshot_from_reader(bytes)?))
+    }
+
+    /// Add a chunk of the flattened `nodes` array
+    pub fn add_nodes(&mut self, nodes: &[NodeId]) -> Result<()> {
+        let stride = self.meta.node_field_count();
+        ensure!(
+            nodes.len().is_multiple_of(stride),
+            "Node chunk of length {} does not contain whole nodes (stride {})",
+            nodes.len(),
+            stride
+        );
+        self.nodes.extend_from_slice(nodes);
+        Ok(())
+    }
+
+    /// Add a chunk of the flattened `edges` array
+    pub fn add_edges(&mut self, edges: &[NodeId]) -> Result<()> {
+        let stride = self.meta.edge_field_count();
+        ensure!(
+            edges.len().is_multiple_of(stride),
+            "Edge chunk of length {} does not contain whole edges (stride {})",
+            edges.len(),
+            stride
+        );
+        self.edges.extend_from_slice(edges);
+        Ok(())
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len() / self.meta.node_field_count()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.len() / self.meta.edge_field_count()
+    }
+
+    /// Build the graph, after checking that the nodes and edges fit together
+    ///
+    /// If the `edge_count` fields of the nodes don't add up to the number of edges, edges
+    /// would be attributed to the wrong nodes, or nodes would point past the end of the edges.
+    /// Every edge must also point at the start of a node, see `edge_target_node`.
+    pub fn finalize(self) -> Result<V8HeapGraph> {
+        let edge_count_field = self
+            .meta
+            .node_fields
+            .iter()
+            .position(|f| f == "edge_count")
+            .context("Snapshot nodes have no 'edge_count' field")?;
+        let declared_edges: usize = self
+            .nodes
+            .iter()
+            .skip(edge_count_field)
+            .step_by(self.meta.node_field_count())
+            .map(|&c| c as usize)
+            .sum();
+        ensure!(
+            declared_edges == self.edge_count(),
+            "Nodes declare {} edges in total, but {} edges were added",
+            declared_edges,
+            self.edge_count()
+        );
+
+        let to_node_field = self
+            .meta
+            .edge_fields
+            .iter()
+            .position(|f| f == "to_node")
+            .context("Snapshot edges have no 'to_node' field")?;
+        for &to_node in self
+            .edges
+            .iter()
+            .skip(to_node_field)
+            .step_by(self.meta.edge_field_count())
+        {
+            edge_target_node(
+                to_node,
+                self.meta.node_field_count() as NodeId,
+                self.node_count(),
+            )?;
+        }
+
+        let snapshot = Snapshot {
+            node_count: self.node_count(),
+            edge_count: self.edge_count(),
+            meta: self.meta,
+            trace_function_count: 0,
+            extra_native_bytes: 0,
+        };
+
+        V8HeapGraph::from_snapshot(SnapshotFile {
+            snapshot,
+            nodes: self.nodes,
+            edges: self.edges,
+            locations: vec![],
+            samples: vec![],
+            strings: self.strings,
+            trace_function_infos: vec![],
+            trace_tree: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{
+        test_graph::TestGraph,
+        v8_heap_graph::{EdgeType, NodeType},
+    };
+
+    #[test]
+    fn test_build_in_two_batches() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 20);
+        let c = g.node(NodeType::String, "C", 30);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b)
+            .edge(a, EdgeType::Property, "c", c)
+            .edge(b, EdgeType::Property, "a", a)
+            .edge(b, EdgeType::Property, "c", c)
+            .element(c, 0, root);
+
+        let single = g.build();
+
+        let snapshot = g.snapshot();
+        let mut builder = GraphBuilder::new(snapshot.snapshot.meta, snapshot.strings);
+        // Split the nodes after 'a', and the edges in the middle of 'b''s edges
+        let (nodes1, nodes2) = snapshot.nodes.split_at(2 * 6);
+        let (edges1, edges2) = snapshot.edges.split_at(4 * 3);
+        builder.add_nodes(nodes1).unwrap();
+        builder.add_edges(edges1).unwrap();
+        builder.add_nodes(nodes2).unwrap();
+        builder.add_edges(edges2).unwrap();
+        let batched = builder.finalize().unwrap();
+
+        assert_eq!(batched.total_node_count(), single.total_node_count());
+        assert_eq!(batched.total_edge_count(), single.total_edge_count());
+        for node in single.iter_nodes() {
+            assert_eq!(batched.node(node).name(), single.node(node).name());
+            assert_eq!(batched.out_neighbors(node), single.out_neighbors(node));
+            assert_eq!(
+                batched
+                    .in_edges(node)
+                    .map(|e| e.from_node())
+                    .collect::<Vec<_>>(),
+                single
+                    .in_edges(node)
+                    .map(|e| e.from_node())
+                    .collect::<Vec<_>>(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_partial_node_chunk() {
+        let snapshot = TestGraph::new().snapshot();
+        let mut builder = GraphBuilder::new(snapshot.snapshot.meta, snapshot.strings);
+
+        assert!(builder.add_nodes(&[3, 0, 1, 0]).is_err());
+        assert!(builder.add_edges(&[2, 0]).is_err());
+    }
+
+    #[test]
+    fn test_misaligned_edge_target() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        g.edge(root, EdgeType::Property, "a", a);
+        let mut snapshot = g.snapshot();
+        // Point into the middle of 'a' instead of at its first field
+        snapshot.edges[2] += 1;
+
+        let err = GraphBuilder::from_snapshot(snapshot)
+            .finalize()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Edge target 7 is not the start of a node: not a multiple of the node stride 6"
+        );
+    }
+
+    #[test]
+    fn test_mismatched_edge_counts() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(a, EdgeType::Property, "root", root);
+        let snapshot = g.snapshot();
+
+        // Drop the last edge, so that 'a' claims an edge that doesn't exist
+        let mut builder = GraphBuilder::new(snapshot.snapshot.meta, snapshot.strings);
+        builder.add_nodes(&snapshot.nodes).unwrap();
+        builder.add_edges(&snapshot.edges[..3]).unwrap();
+
+        let err = builder.finalize().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Nodes declare 2 edges in total, but 1 edges were added"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::{io::BufWriter, path::Path};
+
+use fixedbitset::FixedBitSet;
+use petgraph::visit::Bfs;
+use serde::{Deserialize, Serialize};
+
+use super::v8_heap_graph::V8HeapGraph;
+use crate::types::NodeId;
+
+/// The graph as plain JSON, the format d3 and cytoscape read most easily
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonGraph {
+    pub nodes: Vec<JsonGraphNode>,
+    pub edges: Vec<JsonGraphEdge>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonGraphNode {
+    pub id: NodeId,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    pub name: String,
+    pub self_size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonGraphEdge {
+    pub source: NodeId,
+    pub target: NodeId,
+    #[serde(rename = "type")]
+    pub edge_type: String,
+    /// The property name, or the index for elements and hidden edges
+    pub name: String,
+}
+
+/// How many nodes `--json-graph` writes by default; about as many as a browser draws smoothly
+pub const DEFAULT_JSON_GRAPH_NODES: usize = 1000;
+
+/// The nodes found by a breadth-first search from `root`, at most `max_nodes` of them
+///
+/// This is the subgraph `print_graph` walks, and a way to keep an export small enough for
+/// a browser to draw.
+pub fn bfs_subgraph(graph: &V8HeapGraph, root: NodeId, max_nodes: usize) -> FixedBitSet {
+    let mut nodes = FixedBitSet::with_capacity(graph.total_node_count());
+    let mut bfs = Bfs::new(&graph, root);
+    for n in std::iter::from_fn(|| bfs.next(&graph)).take(max_nodes) {
+        nodes.insert(n as usize);
+    }
+    nodes
+}
+
+pub fn write_json_graph_file(
+    filename: &Path,
+    graph: &V8HeapGraph,
+    filter: Option<&FixedBitSet>,
+) -> anyhow::Result<()> {
+    let f = std::fs::File::create(filename)?;
+    write_json_graph(&mut BufWriter::new(f), graph, filter)?;
+    Ok(())
+}
+
+/// Write the nodes and edges of the graph, or only of the nodes in `filter`
+///
+/// With a filter, only edges between two nodes in it are written, so every `source` and
+/// `target` refers to a node in the file.
+pub fn write_json_graph<F: std::io::Write>(
+    f: &mut F,
+    graph: &V8HeapGraph,
+    filter: Option<&FixedBitSet>,
+) -> serde_json::Result<()> {
+    let included = |n: NodeId| filter.is_none_or(|nodes| nodes.contains(n as usize));
+
+    let nodes = graph
+        .iter_nodes()
+        .filter(|&n| included(n))
+        .map(|n| {
+            let node = graph.node(n);
+            JsonGraphNode {
+                id: n,
+                node_type: node.typ_str().into_owned(),
+                name: node.name().to_string(),
+                self_size: node.self_size(),
+            }
+        })
+        .collect();
+    let edges = graph
+        .iter_edges()
+        .map(|e| graph.edge(e))
+        .filter(|e| included(e.from_node()) && included(e.to_node()))
+        .map(|e| JsonGraphEdge {
+            source: e.from_node(),
+            target: e.to_node(),
+            edge_type: e.typ_str().into_owned(),
+            name: e.name_or_index().to_string(),
+        })
+        .collect();
+
+    serde_json::to_writer(f, &JsonGraph { nodes, edges })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{
+        test_graph::TestGraph,
+        v8_heap_graph::{EdgeType, NodeType},
+    };
+
+    #[test]
+    fn test_json_graph_round_trip() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 16);
+        let b = g.node(NodeType::String, "b\"quoted\"", 24);
+        let unreachable = g.node(NodeType::Object, "Garbage", 8);
+        g.edge(root, EdgeType::Property, "a", a)
+            .element(a, 0, b)
+            .edge(unreachable, EdgeType::Property, "a", a);
+        let graph = g.build();
+
+        let mut out = vec![];
+        write_json_graph(&mut out, &graph, None).unwrap();
+        let json: JsonGraph = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json.nodes.len(), graph.total_node_count());
+        assert_eq!(json.edges.len(), graph.total_edge_count());
+        assert_eq!(json.nodes[b as usize].name, "b\"quoted\"");
+        assert_eq!(json.nodes[b as usize].node_type, "string");
+        assert_eq!(json.nodes[b as usize].self_size, 24);
+        assert_eq!(json.edges[1].source, a);
+        assert_eq!(json.edges[1].target, b);
+        assert_eq!(json.edges[1].edge_type, "element");
+        assert_eq!(json.edges[1].name, "0");
+
+        // Only what the root reaches, and the edges between those nodes
+        let reachable = bfs_subgraph(&graph, root, usize::MAX);
+        let mut out = vec![];
+        write_json_graph(&mut out, &graph, Some(&reachable)).unwrap();
+        let json: JsonGraph = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json.nodes.len(), 3);
+        assert_eq!(json.edges.len(), 2);
+        assert!(json.nodes.iter().all(|n| n.id != unreachable));
+
+        assert_eq!(bfs_subgraph(&graph, root, 2).count_ones(..), 2);
+    }
+}
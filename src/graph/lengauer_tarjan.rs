@@ -1,6 +1,12 @@
-use crate::types::NodeId;
+use crate::{
+    types::NodeId,
+    utils::{Deadline, TimedOut},
+};
 use std::collections::HashMap;
 
+/// How many nodes the DFS and the dominator loop handle between looks at the clock
+const DEADLINE_CHECK_INTERVAL: NodeId = 1 << 16;
+
 /// Lengauer-Tarjan dominator tree algorithm
 ///
 /// This implements the classic algorithm from "A Fast Algorithm for Finding Dominators
@@ -13,6 +19,11 @@ use std::collections::HashMap;
 ///
 /// Time complexity: O(E * α(V)) where α is the inverse Ackermann function (nearly linear)
 ///
+/// The result only depends on the graph, so it is the same on every run: the DFS starts at
+/// the roots in ascending order (the order in which they are passed doesn't matter), and
+/// visits successors in the order that `GraphOps::successors` returns them. Nodes that can
+/// be reached from several roots belong to the lowest of those roots.
+///
 /// # Arguments
 /// * `graph` - The graph to analyze (must implement GraphOps trait)
 /// * `roots` - The root nodes to start the dominator analysis from
@@ -20,17 +31,36 @@ use std::collections::HashMap;
 /// # Returns
 /// A HashMap mapping each node to its immediate dominator
 pub fn lengauer_tarjan<'a, G>(graph: &'a G, roots: &[NodeId]) -> HashMap<NodeId, NodeId>
+where
+    G: GraphOps<'a>,
+{
+    lengauer_tarjan_until(graph, roots, Deadline::none()).expect("no deadline to miss")
+}
+
+/// `lengauer_tarjan`, giving up with `TimedOut` once `deadline` has passed
+pub fn lengauer_tarjan_until<'a, G>(
+    graph: &'a G,
+    roots: &[NodeId],
+    deadline: Deadline,
+) -> Result<HashMap<NodeId, NodeId>, TimedOut>
 where
     G: GraphOps<'a>,
 {
     let mut lt = LengauerTarjan::new(graph.node_count());
 
-    // Run DFS from all roots
-    for &root in roots {
-        lt.dfs(graph, root, NodeId::MAX);
+    // Run DFS from all roots. An empty graph doesn't even have a root, and has no dominators
+    let mut roots: Vec<NodeId> = roots
+        .iter()
+        .copied()
+        .filter(|&r| (r as usize) < graph.node_count())
+        .collect();
+    roots.sort_unstable();
+    roots.dedup();
+    for root in roots {
+        lt.dfs(graph, root, deadline)?;
     }
 
-    lt.compute_dominators(graph)
+    lt.compute_dominators(graph, deadline)
 }
 
 /// Trait for graph operations required by the Lengauer-Tarjan algorithm
@@ -62,6 +92,9 @@ struct LengauerTarjan {
     idom: Vec<NodeId>,
     samedom: Vec<NodeId>,
     bucket: Vec<Vec<NodeId>>,
+
+    /// Scratch space for `ancestor_with_lowest_semi`, kept to avoid allocating on every call
+    compress_path: Vec<NodeId>,
 }
 
 impl LengauerTarjan {
@@ -77,27 +110,62 @@ impl LengauerTarjan {
             idom: vec![NodeId::MAX; node_count],
             samedom: vec![NodeId::MAX; node_count],
             bucket: vec![Vec::new(); node_count],
+            compress_path: Vec::new(),
         }
     }
 
-    fn dfs<'a, G: GraphOps<'a>>(&mut self, graph: &'a G, node: NodeId, p: NodeId) {
-        if self.dfnum[node as usize] != NodeId::MAX {
-            return;
+    /// Number the nodes reachable from `root` in depth-first preorder
+    ///
+    /// Iterative, with an explicit stack of successor iterators, so that long chains (linked
+    /// lists, deep DOM trees) don't overflow the stack. Nodes are numbered in the same order
+    /// as a recursive DFS would: successors in the order `GraphOps::successors` returns them.
+    fn dfs<'a, G: GraphOps<'a>>(
+        &mut self,
+        graph: &'a G,
+        root: NodeId,
+        deadline: Deadline,
+    ) -> Result<(), TimedOut> {
+        if self.dfnum[root as usize] != NodeId::MAX {
+            return Ok(());
+        }
+        self.visit(root, NodeId::MAX);
+
+        let mut stack = vec![(root, graph.successors(root))];
+        while let Some((node, successors)) = stack.last_mut() {
+            let node = *node;
+            match successors.find(|&succ| self.dfnum[succ as usize] == NodeId::MAX) {
+                Some(succ) => {
+                    if self.n.is_multiple_of(DEADLINE_CHECK_INTERVAL) {
+                        deadline.check()?;
+                    }
+                    self.visit(succ, node);
+                    stack.push((succ, graph.successors(succ)));
+                }
+                None => {
+                    stack.pop();
+                }
+            }
         }
+        Ok(())
+    }
 
+    fn visit(&mut self, node: NodeId, p: NodeId) {
         self.dfnum[node as usize] = self.n;
         self.vertex[self.n as usize] = node;
         self.parent[node as usize] = p;
         self.n += 1;
-
-        for succ in graph.successors(node) {
-            self.dfs(graph, succ, node);
-        }
     }
 
-    fn compute_dominators<'a, G: GraphOps<'a>>(mut self, graph: &'a G) -> HashMap<NodeId, NodeId> {
+    fn compute_dominators<'a, G: GraphOps<'a>>(
+        mut self,
+        graph: &'a G,
+        deadline: Deadline,
+    ) -> Result<HashMap<NodeId, NodeId>, TimedOut> {
         // Process nodes in reverse DFS order
         for i in (1..self.n).rev() {
+            if i.is_multiple_of(DEADLINE_CHECK_INTERVAL) {
+                deadline.check()?;
+            }
             let w = self.vertex[i as usize];
             let p = self.parent[w as usize];
 
@@ -132,7 +200,7 @@ impl LengauerTarjan {
             self.link(p, w);
 
             // Process bucket
-            let bucket_items: Vec<_> = self.bucket[p as usize].drain(..).collect();
+            let bucket_items = std::mem::take(&mut self.bucket[p as usize]);
             for v in bucket_items {
                 let y = self.ancestor_with_lowest_semi(v);
                 if self.semi[y as usize] == self.semi[v as usize] {
@@ -159,20 +227,38 @@ impl LengauerTarjan {
                 result.insert(node, self.idom[node as usize]);
             }
         }
-        result
+        Ok(result)
     }
 
+    /// EVAL with path compression
+    ///
+    /// Walks up to the topmost node that still has an ancestor, then compresses the path on
+    /// the way back down, so that every node on it points close to the root of its linked
+    /// forest. Iterative for the same reason as `dfs`: the paths can be as long as the graph.
     fn ancestor_with_lowest_semi(&mut self, v: NodeId) -> NodeId {
-        let a = self.ancestor[v as usize];
-        if a != NodeId::MAX && self.ancestor[a as usize] != NodeId::MAX {
-            let b = self.ancestor_with_lowest_semi(a);
-            self.ancestor[v as usize] = self.ancestor[a as usize];
+        let mut path = std::mem::take(&mut self.compress_path);
+        let mut x = v;
+        while self.ancestor[x as usize] != NodeId::MAX
+            && self.ancestor[self.ancestor[x as usize] as usize] != NodeId::MAX
+        {
+            path.push(x);
+            x = self.ancestor[x as usize];
+        }
+
+        // Every node's ancestor is compressed before the node itself
+        for &w in path.iter().rev() {
+            let a = self.ancestor[w as usize];
+            let b = self.best[a as usize];
+            self.ancestor[w as usize] = self.ancestor[a as usize];
             if self.dfnum[self.semi[b as usize] as usize]
-                < self.dfnum[self.semi[self.best[v as usize] as usize] as usize]
+                < self.dfnum[self.semi[self.best[w as usize] as usize] as usize]
             {
-                self.best[v as usize] = b;
+                self.best[w as usize] = b;
             }
         }
+
+        path.clear();
+        self.compress_path = path;
         self.best[v as usize]
     }
 
@@ -253,4 +339,57 @@ mod tests {
         assert_eq!(idom.get(&2), Some(&1)); // 1 dominates 2
         assert_eq!(idom.get(&3), Some(&1)); // 1 dominates 3
     }
+
+    #[test]
+    fn test_deterministic_with_several_roots() {
+        // Graph: 0 -> 1 -> 2
+        //        4 -> 3 -> 2
+        // Node 2 can be reached from both roots
+        let graph = TestGraph {
+            preds: vec![vec![], vec![0], vec![1, 3], vec![4], vec![]],
+            succs: vec![vec![1], vec![2], vec![], vec![2], vec![3]],
+        };
+
+        let idom = lengauer_tarjan(&graph, &[0, 4]);
+        assert_eq!(lengauer_tarjan(&graph, &[0, 4]), idom);
+        assert_eq!(lengauer_tarjan(&graph, &[4, 0]), idom);
+        assert_eq!(lengauer_tarjan(&graph, &[4, 0, 4]), idom);
+
+        assert_eq!(idom.get(&1), Some(&0));
+        assert_eq!(idom.get(&3), Some(&4));
+    }
+
+    #[test]
+    fn test_deadline() {
+        const N: NodeId = 200_000;
+        let preds = (0..N)
+            .map(|n| n.checked_sub(1).into_iter().collect())
+            .collect();
+        let succs = (0..N)
+            .map(|n| vec![n + 1].into_iter().filter(|&s| s < N).collect())
+            .collect();
+        let graph = TestGraph { preds, succs };
+
+        let passed = Deadline::after(std::time::Duration::ZERO);
+        assert!(lengauer_tarjan_until(&graph, &[0], passed).is_err());
+        let idom = lengauer_tarjan_until(&graph, &[0], Deadline::none()).unwrap();
+        assert_eq!(idom.len(), N as usize - 1);
+    }
+
+    #[test]
+    fn test_long_chain_does_not_overflow() {
+        // 0 -> 1 -> ... -> N, with a back edge from the end to the start, so that path
+        // compression has to walk the whole chain as well
+        const N: NodeId = 1_000_000;
+        let mut preds: Vec<Vec<NodeId>> = (0..=N).map(|n| vec![n.saturating_sub(1)]).collect();
+        preds[0] = vec![];
+        preds[1].push(N);
+        let mut succs: Vec<Vec<NodeId>> = (0..=N).map(|n| vec![n + 1]).collect();
+        succs[N as usize] = vec![1];
+        let graph = TestGraph { preds, succs };
+
+        let idom = lengauer_tarjan(&graph, &[0]);
+        assert_eq!(idom.len(), N as usize);
+        assert!((1..=N).all(|n| idom[&n] == n - 1));
+    }
 }
@@ -1,6 +1,11 @@
-use crate::graph::{
-    lengauer_tarjan::IterWrapper,
-    v8_heap_graph::{Edge, EdgeType},
+use fixedbitset::FixedBitSet;
+
+use crate::{
+    graph::{
+        lengauer_tarjan::{GraphOps, IterWrapper},
+        v8_heap_graph::{V8HeapGraph, is_strong},
+    },
+    types::NodeId,
 };
 
 impl<'a> super::lengauer_tarjan::GraphOps<'a> for super::v8_heap_graph::V8HeapGraph {
@@ -13,15 +18,112 @@ impl<'a> super::lengauer_tarjan::GraphOps<'a> for super::v8_heap_graph::V8HeapGr
 
     fn predecessors(&'a self, node: crate::types::NodeId) -> Self::PredIter {
         // This is only used for dominator calculations, and we want to ignore weak nodes there
-        IterWrapper::new(self.in_edges(node).filter(no_weak).map(|e| e.from_node()))
+        IterWrapper::new(
+            self.in_edges_filtered(node, is_strong)
+                .map(|e| e.from_node()),
+        )
     }
 
     fn successors(&'a self, node: crate::types::NodeId) -> Self::SuccIter {
         // This is only used for dominator calculations, and we want to ignore weak nodes there
-        IterWrapper::new(self.out_edges(node).filter(no_weak).map(|e| e.to_node()))
+        IterWrapper::new(
+            self.out_edges_filtered(node, is_strong)
+                .map(|e| e.to_node()),
+        )
+    }
+}
+
+/// The graph as if the outgoing edges of some nodes were removed
+///
+/// Dominators of this view answer "what would still be retained if these owners let go":
+/// everything that is only reachable through them drops out of the tree, and anything that
+/// is also held by someone else now shows up under that other retainer.
+pub struct WithoutEdgesFrom<'g> {
+    pub graph: &'g V8HeapGraph,
+    pub owners: &'g [NodeId],
+}
+
+impl<'a> GraphOps<'a> for WithoutEdgesFrom<'_> {
+    type PredIter = IterWrapper<'a>;
+    type SuccIter = IterWrapper<'a>;
+
+    fn node_count(&self) -> usize {
+        self.graph.total_node_count()
+    }
+
+    fn predecessors(&'a self, node: NodeId) -> Self::PredIter {
+        IterWrapper::new(
+            self.graph
+                .predecessors(node)
+                .filter(|from| !self.owners.contains(from)),
+        )
+    }
+
+    fn successors(&'a self, node: NodeId) -> Self::SuccIter {
+        if self.owners.contains(&node) {
+            return IterWrapper::new(std::iter::empty());
+        }
+        self.graph.successors(node)
     }
 }
 
-fn no_weak<'a>(e: &Edge<'a>) -> bool {
-    e.typ() != EdgeType::Weak
+/// The graph as if the snapshot root only held on to `roots`
+///
+/// Node 0 stands in for a virtual root whose only edges go to `roots`, so that their
+/// dominator tree is a single tree again and everything else keeps its node id. Objects that
+/// are only reachable from the real GC roots drop out of it. If node 0 is one of the roots,
+/// it keeps its own edges as well.
+pub struct SeededRoots<'g> {
+    graph: &'g V8HeapGraph,
+    roots: &'g [NodeId],
+    /// `roots` as a set, since there can be tens of thousands of them
+    seeded: FixedBitSet,
+    keeps_root_edges: bool,
+}
+
+impl<'g> SeededRoots<'g> {
+    pub fn new(graph: &'g V8HeapGraph, roots: &'g [NodeId]) -> Self {
+        let mut seeded = FixedBitSet::with_capacity(graph.total_node_count());
+        for &root in roots {
+            seeded.insert(root as usize);
+        }
+        SeededRoots {
+            graph,
+            roots,
+            keeps_root_edges: seeded.contains(0),
+            seeded,
+        }
+    }
+}
+
+impl<'a> GraphOps<'a> for SeededRoots<'_> {
+    type PredIter = IterWrapper<'a>;
+    type SuccIter = IterWrapper<'a>;
+
+    fn node_count(&self) -> usize {
+        self.graph.total_node_count()
+    }
+
+    fn predecessors(&'a self, node: NodeId) -> Self::PredIter {
+        let keep_root = self.keeps_root_edges;
+        let seeded = (node != 0 && self.seeded.contains(node as usize)).then_some(0);
+        IterWrapper::new(
+            self.graph
+                .predecessors(node)
+                .filter(move |&from| from != 0 || keep_root)
+                .chain(seeded),
+        )
+    }
+
+    fn successors(&'a self, node: NodeId) -> Self::SuccIter {
+        if node != 0 {
+            return self.graph.successors(node);
+        }
+        let real = self
+            .keeps_root_edges
+            .then(|| self.graph.successors(0))
+            .into_iter()
+            .flatten();
+        IterWrapper::new(self.roots.iter().copied().filter(|&r| r != 0).chain(real))
+    }
 }
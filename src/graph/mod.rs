@@ -1,6 +1,10 @@
+pub mod builder;
 pub mod gexf;
 pub mod gml;
+pub mod json_graph;
 pub mod lengauer_tarjan;
 pub mod lengauer_tarjan_traits;
 pub mod petgraph_traits;
+#[cfg(any(test, feature = "testing"))]
+pub mod test_graph;
 pub mod v8_heap_graph;
@@ -1,12 +1,21 @@
-use std::iter::Copied;
+use std::{iter::Copied, ops::Range};
 
 use fixedbitset::FixedBitSet;
 use petgraph::{
-    Directed,
-    visit::{GraphBase, GraphProp, IntoNeighbors, VisitMap, Visitable},
+    Directed, Direction,
+    visit::{
+        GraphBase, GraphProp, GraphRef, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers,
+        NodeIndexable, VisitMap, Visitable,
+    },
 };
 
-use crate::{graph::v8_heap_graph::V8HeapGraph, types::NodeId};
+use crate::{
+    graph::{
+        lengauer_tarjan::{GraphOps, IterWrapper},
+        v8_heap_graph::V8HeapGraph,
+    },
+    types::NodeId,
+};
 
 impl GraphProp for V8HeapGraph {
     #[doc = r" The kind of edges in the graph."]
@@ -30,6 +39,28 @@ impl<'a> IntoNeighbors for &'a V8HeapGraph {
     }
 }
 
+impl IntoNodeIdentifiers for &V8HeapGraph {
+    type NodeIdentifiers = Range<NodeId>;
+
+    fn node_identifiers(self) -> Self::NodeIdentifiers {
+        0..self.total_node_count() as NodeId
+    }
+}
+
+impl NodeIndexable for V8HeapGraph {
+    fn node_bound(&self) -> usize {
+        self.total_node_count()
+    }
+
+    fn to_index(&self, a: NodeId) -> usize {
+        a as usize
+    }
+
+    fn from_index(&self, i: usize) -> NodeId {
+        i as NodeId
+    }
+}
+
 impl Visitable for V8HeapGraph {
     #[doc = r" The associated map type"]
     type Map = MyFixedBitSet;
@@ -58,3 +89,69 @@ impl VisitMap<NodeId> for MyFixedBitSet {
         self.0.contains(*a as usize)
     }
 }
+
+/// A view of the graph that only follows the edges that keep objects alive (non-weak edges)
+///
+/// These are the same edges that the dominator calculation uses.
+#[derive(Clone, Copy)]
+pub struct StrongEdges<'a>(pub &'a V8HeapGraph);
+
+impl<'a> GraphBase for StrongEdges<'a> {
+    type EdgeId = NodeId;
+    type NodeId = NodeId;
+}
+
+impl<'a> GraphRef for StrongEdges<'a> {}
+
+impl<'a> IntoNeighbors for StrongEdges<'a> {
+    type Neighbors = IterWrapper<'a>;
+
+    fn neighbors(self, a: NodeId) -> Self::Neighbors {
+        self.0.successors(a)
+    }
+}
+
+impl<'a> IntoNeighborsDirected for StrongEdges<'a> {
+    type NeighborsDirected = IterWrapper<'a>;
+
+    fn neighbors_directed(self, a: NodeId, d: Direction) -> Self::NeighborsDirected {
+        match d {
+            Direction::Outgoing => self.0.successors(a),
+            Direction::Incoming => self.0.predecessors(a),
+        }
+    }
+}
+
+impl<'a> Visitable for StrongEdges<'a> {
+    type Map = MyFixedBitSet;
+
+    fn visit_map(&self) -> Self::Map {
+        self.0.visit_map()
+    }
+
+    fn reset_map(&self, map: &mut Self::Map) {
+        self.0.reset_map(map)
+    }
+}
+
+impl<'a> IntoNodeIdentifiers for StrongEdges<'a> {
+    type NodeIdentifiers = Range<NodeId>;
+
+    fn node_identifiers(self) -> Self::NodeIdentifiers {
+        self.0.node_identifiers()
+    }
+}
+
+impl<'a> NodeIndexable for StrongEdges<'a> {
+    fn node_bound(&self) -> usize {
+        self.0.node_bound()
+    }
+
+    fn to_index(&self, a: NodeId) -> usize {
+        a as usize
+    }
+
+    fn from_index(&self, i: usize) -> NodeId {
+        i as NodeId
+    }
+}
@@ -0,0 +1,180 @@
+//! Build small heap graphs by hand for unit tests
+use std::collections::HashMap;
+
+use crate::{
+    graph::v8_heap_graph::{EdgeType, NodeType, V8HeapGraph},
+    snapshot::{Snapshot, SnapshotFile, SnapshotMetadata, StringOrStrings},
+    types::NodeId,
+};
+
+pub const NODE_TYPES: [&str; 15] = [
+    "hidden",
+    "array",
+    "string",
+    "object",
+    "code",
+    "closure",
+    "regexp",
+    "number",
+    "native",
+    "synthetic",
+    "concatenated string",
+    "sliced string",
+    "symbol",
+    "bigint",
+    "object shape",
+];
+
+pub const EDGE_TYPES: [&str; 7] = [
+    "context", "element", "property", "internal", "hidden", "shortcut", "weak",
+];
+
+pub const NODE_FIELDS: [&str; 6] = [
+    "type",
+    "name",
+    "id",
+    "self_size",
+    "edge_count",
+    "detachedness",
+];
+
+struct TestNode {
+    typ: NodeId,
+    name: NodeId,
+    self_size: NodeId,
+    detached: bool,
+    edges: Vec<(NodeId, NodeId, NodeId)>,
+}
+
+/// Builder for a `V8HeapGraph`
+///
+/// Nodes get ids in the order they are added, so add the root first. Stable ids are
+/// assigned the way V8 does it: `2 * id + 1`.
+pub struct TestGraph {
+    nodes: Vec<TestNode>,
+    strings: Vec<String>,
+    string_ids: HashMap<String, NodeId>,
+}
+
+impl Default for TestGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestGraph {
+    pub fn new() -> Self {
+        let mut ret = Self {
+            nodes: vec![],
+            strings: vec![],
+            string_ids: HashMap::new(),
+        };
+        ret.intern("");
+        ret
+    }
+
+    /// Add a node, returning its id
+    pub fn node(&mut self, typ: NodeType, name: &str, self_size: usize) -> NodeId {
+        let name = self.intern(name);
+        self.nodes.push(TestNode {
+            typ: typ.index(),
+            name,
+            self_size: self_size as NodeId,
+            detached: false,
+            edges: vec![],
+        });
+        (self.nodes.len() - 1) as NodeId
+    }
+
+    /// Add a named edge
+    pub fn edge(&mut self, from: NodeId, typ: EdgeType, name: &str, to: NodeId) -> &mut Self {
+        let name = self.intern(name);
+        self.nodes[from as usize]
+            .edges
+            .push((typ.index(), name, to));
+        self
+    }
+
+    /// Add an element edge
+    pub fn element(&mut self, from: NodeId, index: NodeId, to: NodeId) -> &mut Self {
+        self.indexed(from, EdgeType::Element, index, to)
+    }
+
+    /// Add an edge whose `name_or_index` field is a number, not a string
+    pub fn indexed(&mut self, from: NodeId, typ: EdgeType, index: NodeId, to: NodeId) -> &mut Self {
+        self.nodes[from as usize]
+            .edges
+            .push((typ.index(), index, to));
+        self
+    }
+
+    pub fn detach(&mut self, node: NodeId) -> &mut Self {
+        self.nodes[node as usize].detached = true;
+        self
+    }
+
+    /// The snapshot file that describes this graph
+    pub fn snapshot(&self) -> SnapshotFile {
+        let stride = NODE_FIELDS.len() as NodeId;
+
+        let mut nodes = vec![];
+        let mut edges = vec![];
+        for (i, node) in self.nodes.iter().enumerate() {
+            nodes.extend([
+                node.typ,
+                node.name,
+                2 * i as NodeId + 1,
+                node.self_size,
+                node.edges.len() as NodeId,
+                node.detached as NodeId,
+            ]);
+            for &(typ, name_or_index, to) in &node.edges {
+                edges.extend([typ, name_or_index, to * stride]);
+            }
+        }
+
+        SnapshotFile {
+            snapshot: Snapshot {
+                meta: SnapshotMetadata {
+                    edge_fields: strings(&["type", "name_or_index", "to_node"]),
+                    edge_types: vec![StringOrStrings::Strs(strings(&EDGE_TYPES))],
+                    location_fields: vec![],
+                    node_fields: strings(&NODE_FIELDS),
+                    node_types: vec![StringOrStrings::Strs(strings(&NODE_TYPES))],
+                    sample_fields: vec![],
+                    trace_function_info_fields: vec![],
+                    trace_node_fields: vec![],
+                },
+                node_count: self.nodes.len(),
+                edge_count: edges.len() / 3,
+                trace_function_count: 0,
+                extra_native_bytes: 0,
+            },
+            nodes,
+            edges,
+            locations: vec![],
+            samples: vec![],
+            strings: self.strings.clone(),
+            trace_function_infos: vec![],
+            trace_tree: vec![],
+        }
+    }
+
+    pub fn build(&self) -> V8HeapGraph {
+        V8HeapGraph::from(self.snapshot())
+    }
+
+    fn intern(&mut self, s: &str) -> NodeId {
+        if let Some(&id) = self.string_ids.get(s) {
+            return id;
+        }
+        let id = self.strings.len() as NodeId;
+        self.strings.push(s.to_string());
+        self.string_ids.insert(s.to_string(), id);
+        id
+    }
+}
+
+fn strings(xs: &[&str]) -> Vec<String> {
+    xs.iter().map(|x| x.to_string()).collect()
+}
@@ -1,10 +1,33 @@
-use std::borrow::Cow;
-
-use crate::{snapshot::StringOrStrings, utils::print_safe};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{BufReader, BufWriter, Read},
+    path::{Path, PathBuf},
+    sync::{
+        OnceLock,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use fixedbitset::FixedBitSet;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    snapshot::{
+        StringOrStrings,
+        compat::{EDGE_FIELDS, REQUIRED_NODE_FIELDS},
+        read_v8_snapshot_file, read_v8_snapshot_from_reader,
+    },
+    utils::print_safe,
+};
 
 use super::super::{snapshot::SnapshotFile, types::NodeId};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct EdgeId(NodeId);
 
 pub type GraphPath = Vec<EdgeId>;
@@ -15,7 +38,7 @@ impl std::fmt::Display for EdgeId {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct V8HeapGraph {
     node_count: usize,
     nodes: Vec<NodeId>,
@@ -23,6 +46,8 @@ pub struct V8HeapGraph {
     strings: Vec<String>,
     pub node_types: Vec<String>,
     pub edge_types: Vec<String>,
+    /// The `NodeType` for every type index, based on the names in `node_types`
+    node_type_map: Vec<NodeType>,
     pub node_fields: NodeFields,
     pub edge_fields: EdgeFields,
 
@@ -30,18 +55,154 @@ pub struct V8HeapGraph {
     node_out_edges: Vec<NodeId>,
 
     /// For every node, what edges are incoming edges for it
-    node_in_edges: Vec<Vec<NodeId>>,
+    node_in_edges: InEdges,
+
+    /// Native memory that the snapshot reports, but doesn't attribute to any node
+    extra_native_bytes: usize,
+    /// Whether `Native` nodes count towards sizes, see `set_include_native`
+    include_native: bool,
+    /// The children of the `(GC roots)` node, sorted, see `gc_roots`
+    gc_roots: Vec<NodeId>,
+    /// Computed on first use, see `root_distance`
+    #[serde(skip)]
+    root_distances: OnceLock<Vec<u32>>,
+}
+
+/// The snapshot file a graph was built from, as recorded by `V8HeapGraph::save`
+///
+/// Rewriting the file changes its size or modification time, so a saved graph of an older
+/// version of it doesn't match either.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotSource {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl SnapshotSource {
+    pub fn of(path: &Path) -> Result<Self> {
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Self {
+            path: path.canonicalize()?,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
 }
 
+/// How deeply concatenated and sliced strings are followed by `resolved_string`
+pub const MAX_STRING_NESTING: usize = 1000;
+
+/// The longest content `resolved_string` builds before it gives up and adds `...`
+///
+/// Concatenated strings can share their parts, so a small snapshot can describe a string of
+/// many gigabytes. This is far longer than anything a report shows.
+pub const MAX_RESOLVED_STRING_LEN: usize = 16 << 20;
+
+/// Bump this whenever the layout of `V8HeapGraph` (or anything in it) changes, see `save`
+const GRAPH_FORMAT_VERSION: u32 = 2;
+
 impl V8HeapGraph {
+    /// Read a snapshot file and build its graph
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use v8_heap_analyzer::V8HeapGraph;
+    ///
+    /// let graph = V8HeapGraph::load(Path::new("tests/fixtures/object.heapsnapshot"))?;
+    /// assert!(graph.total_node_count() > 0);
+    /// assert_eq!(graph.node(0).typ_str(), "synthetic");
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// If the file holds several snapshots, the last one is used; see
+    /// `snapshot::read_v8_snapshot_file_at` to pick another one.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_snapshot(read_v8_snapshot_file(path)?)
+    }
+
+    /// Write the built graph in a compact binary format that `load_saved` reads back
+    ///
+    /// Loading that is many times faster than parsing the snapshot's JSON again, so it is
+    /// worth it when the same snapshot is analyzed over and over. `source` is the snapshot
+    /// the graph was built from, so that the file isn't mistaken for another snapshot's.
+    pub fn save(&self, path: &Path, source: &SnapshotSource) -> Result<()> {
+        let file = File::create(path).context("Failed to create graph file")?;
+        let mut writer = BufWriter::new(file);
+        bincode::serialize_into(&mut writer, &GRAPH_FORMAT_VERSION)
+            .and_then(|_| bincode::serialize_into(&mut writer, source))
+            .and_then(|_| bincode::serialize_into(&mut writer, self))
+            .context("Failed to write graph file")
+    }
+
+    /// Read a graph written by `save`, if it was built from `source`
+    ///
+    /// Fails if the file was written by a version of this crate with a different graph layout.
+    /// A graph of another snapshot, or of an older version of the same file, gives `None`.
+    pub fn load_saved(path: &Path, source: &SnapshotSource) -> Result<Option<Self>> {
+        let file = File::open(path).context("Failed to open graph file")?;
+        let mut reader = BufReader::new(file);
+        let version: u32 =
+            bincode::deserialize_from(&mut reader).context("Failed to read graph file")?;
+        ensure!(
+            version == GRAPH_FORMAT_VERSION,
+            "Graph file {} has format version {}, this version reads {}",
+            path.display(),
+            version,
+            GRAPH_FORMAT_VERSION
+        );
+        let saved_source: SnapshotSource =
+            bincode::deserialize_from(&mut reader).context("Failed to read graph file")?;
+        if saved_source != *source {
+            return Ok(None);
+        }
+        let graph = bincode::deserialize_from(reader).context("Failed to read graph file")?;
+        Ok(Some(graph))
+    }
+
+    /// Parse a snapshot from any source and build its graph, see `load`
+    ///
+    /// The reader is not buffered here; wrap it in a `BufReader` if reads are expensive.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        Self::from_snapshot(read_v8_snapshot_from_reader(reader)?)
+    }
+
+    /// A short hash that tells snapshots apart, printed as 16 hex digits
+    ///
+    /// Snapshots carry no timestamp or capture id, so this hashes what two different captures
+    /// are very unlikely to share: the node and edge counts, the total self size, and the
+    /// stable ids of the first and last node (V8 hands those out in increasing order, so they
+    /// keep growing over the life of a process). Copies of the same file always have the same
+    /// fingerprint, whether or not native sizes are included. The hash is FNV-1a, so it doesn't
+    /// change between builds.
+    pub fn fingerprint(&self) -> u64 {
+        let last = self.total_node_count().checked_sub(1);
+        let values = [
+            self.total_node_count() as u64,
+            self.total_edge_count() as u64,
+            self.iter_nodes()
+                .map(|n| self.node(n).self_size() as u64)
+                .sum(),
+            last.map_or(0, |_| self.node(0).stable_id() as u64),
+            last.map_or(0, |n| self.node(n as NodeId).stable_id() as u64),
+        ];
+
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in values.iter().flat_map(|v| v.to_le_bytes()) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+
     pub fn mem_size(&self) -> usize {
         let mut ret = 0;
         ret += self.nodes.len() * size_of::<NodeId>();
         ret += self.edges.mem_size();
         ret += self.node_out_edges.len() * size_of::<NodeId>();
-        ret += self.node_in_edges.iter().fold(0, |acc, x| {
-            acc + size_of::<Vec<NodeId>>() + x.len() * size_of::<NodeId>()
-        });
+        ret += self.node_in_edges.mem_size();
+        ret += self.gc_roots.len() * size_of::<NodeId>();
 
         ret
     }
@@ -50,6 +211,7 @@ impl V8HeapGraph {
         (0 as NodeId)..(self.total_node_count() as NodeId)
     }
 
+    /// All edges in the graph, in snapshot order (grouped by source node)
     pub fn iter_edges(&self) -> impl Iterator<Item = EdgeId> {
         ((0 as NodeId)..(self.total_edge_count() as NodeId)).map(EdgeId)
     }
@@ -59,6 +221,7 @@ impl V8HeapGraph {
         &self.nodes[start..start + self.node_fields.stride()]
     }
 
+    /// Access a single edge by id
     pub fn edge(&self, nr: EdgeId) -> Edge<'_> {
         Edge {
             edges: &self.edges,
@@ -75,14 +238,97 @@ impl V8HeapGraph {
         }
     }
 
+    /// The name of a node's type, as listed in the snapshot metadata
+    pub fn node_type_name(&self, node: NodeId) -> Option<&str> {
+        let typ = self.node_range(node)[self.node_fields.type_field()];
+        self.node_types.get(typ as usize).map(String::as_str)
+    }
+
+    /// Find a node by its stable id (the `@id` shown in DevTools)
+    pub fn find_node_by_stable_id(&self, stable_id: NodeId) -> Option<NodeId> {
+        self.iter_nodes()
+            .find(|&n| self.node_range(n)[self.node_fields.stable_id()] == stable_id)
+    }
+
     pub fn string(&self, index: NodeId) -> &str {
         &self.strings[index as usize]
     }
 
+    /// The logical content of a string node, following the parts of concatenated strings
+    ///
+    /// Snapshots don't record the offset and length of a sliced string, so a slice
+    /// resolves to the full content of its parent. Nesting deeper than
+    /// `MAX_STRING_NESTING` (which also catches cycles in a malformed snapshot) is
+    /// replaced with `...`, and so is everything after the first `MAX_RESOLVED_STRING_LEN`
+    /// bytes. Parts that occur more than once are only resolved once.
+    pub fn resolved_string(&self, node: NodeId) -> Cow<'_, str> {
+        enum Step {
+            Resolve(NodeId, usize),
+            /// All parts of the node have been added to the result, starting at this offset
+            Done(NodeId, usize),
+        }
+
+        let n = self.node(node);
+        if !matches!(n.typ(), NodeType::ConcatString | NodeType::SlicedString) {
+            return Cow::Borrowed(n.name());
+        }
+
+        let mut ret = String::new();
+        let mut resolved: HashMap<NodeId, (usize, usize)> = HashMap::new();
+        let mut stack = vec![Step::Resolve(node, 0)];
+        while let Some(step) = stack.pop() {
+            if ret.len() > MAX_RESOLVED_STRING_LEN {
+                let mut end = MAX_RESOLVED_STRING_LEN;
+                while !ret.is_char_boundary(end) {
+                    end -= 1;
+                }
+                ret.truncate(end);
+                ret.push_str("...");
+                break;
+            }
+
+            let (node, depth) = match step {
+                Step::Resolve(node, depth) => (node, depth),
+                Step::Done(node, start) => {
+                    resolved.insert(node, (start, ret.len()));
+                    continue;
+                }
+            };
+            if let Some(&(start, end)) = resolved.get(&node) {
+                ret.extend_from_within(start..end);
+                continue;
+            }
+            if depth > MAX_STRING_NESTING {
+                ret.push_str("...");
+                continue;
+            }
+            match self.node(node).typ() {
+                NodeType::ConcatString => {
+                    stack.push(Step::Done(node, ret.len()));
+                    // Push in reverse, so that 'first' is handled first
+                    for part in ["second", "first"] {
+                        if let Some(child) = self.find_edge(node, EdgeType::Internal, part) {
+                            stack.push(Step::Resolve(child, depth + 1));
+                        }
+                    }
+                }
+                NodeType::SlicedString => {
+                    stack.push(Step::Done(node, ret.len()));
+                    if let Some(parent) = self.find_edge(node, EdgeType::Internal, "parent") {
+                        stack.push(Step::Resolve(parent, depth + 1));
+                    }
+                }
+                _ => ret.push_str(self.node(node).name()),
+            }
+        }
+        Cow::Owned(ret)
+    }
+
     pub fn total_node_count(&self) -> usize {
         self.node_count
     }
 
+    /// Number of edges in the graph, which is one more than the highest `EdgeId`
     pub fn total_edge_count(&self) -> usize {
         self.edges.size()
     }
@@ -102,6 +348,30 @@ impl V8HeapGraph {
         None
     }
 
+    /// The first edge from `from` to `to`, in snapshot order
+    ///
+    /// Like `find_edge`, but by target instead of by name: this recovers the type and name of
+    /// an edge between two nodes that are known to be adjacent, such as the steps of a path.
+    /// Weak and shortcut edges count too.
+    pub fn edge_between(&self, from: NodeId, to: NodeId) -> Option<Edge<'_>> {
+        self.out_edges(from).find(|e| e.to_node() == to)
+    }
+
+    /// Named properties of a node and the nodes they point to, in snapshot order
+    ///
+    /// Only `Property` edges are included; elements, internal and context edges are not.
+    pub fn properties(&self, n: NodeId) -> impl Iterator<Item = (NameOrIndex<'_>, NodeId)> {
+        self.out_edges(n)
+            .filter(|e| e.typ() == EdgeType::Property)
+            .map(|e| (e.name_or_index(), e.to_node()))
+    }
+
+    /// The value of the named property of a node, if it has one
+    pub fn get_property(&self, n: NodeId, name: &str) -> Option<NodeId> {
+        self.find_edge(n, EdgeType::Property, name)
+    }
+
+    /// Outgoing edges of a node, in snapshot order
     pub fn out_edges(&self, n: NodeId) -> impl Iterator<Item = Edge<'_>> {
         let start = self.node_out_edges[n as usize] as usize;
         let end = start + self.edge_count_for(n) as usize;
@@ -109,17 +379,61 @@ impl V8HeapGraph {
         (start..end).map(|e| self.edge(EdgeId(e as NodeId)))
     }
 
+    /// Incoming edges of a node, ordered by edge id
     pub fn in_edges(&self, n: NodeId) -> impl Iterator<Item = Edge<'_>> {
-        self.node_in_edges[n as usize]
+        self.node_in_edges
+            .of(n)
             .iter()
             .map(|e| self.edge(EdgeId(*e)))
     }
 
+    /// Outgoing edges of a node that match `pred`, in snapshot order
+    ///
+    /// For example, `graph.out_edges_filtered(n, is_strong)` skips weak references.
+    pub fn out_edges_filtered<P>(&self, n: NodeId, pred: P) -> impl Iterator<Item = Edge<'_>>
+    where
+        P: Fn(&Edge<'_>) -> bool,
+    {
+        self.out_edges(n).filter(move |e| pred(e))
+    }
+
+    /// Incoming edges of a node that match `pred`, ordered by edge id
+    pub fn in_edges_filtered<P>(&self, n: NodeId, pred: P) -> impl Iterator<Item = Edge<'_>>
+    where
+        P: Fn(&Edge<'_>) -> bool,
+    {
+        self.in_edges(n).filter(move |e| pred(e))
+    }
+
+    /// The size a node contributes to totals like retained sizes and the heap composition
+    ///
+    /// This is the node's self size, except for `Native` nodes when native sizes are
+    /// excluded, which count as 0. Use `Node::self_size` for the size V8 reported.
     pub fn self_size_for(&self, n: NodeId) -> usize {
+        if !self.include_native && self.node(n).typ() == NodeType::Native {
+            return 0;
+        }
         self.nodes[n as usize * self.node_fields.stride() + self.node_fields.self_size_field()]
             as usize
     }
 
+    /// Count (or stop counting) the size of `Native` nodes towards totals
+    ///
+    /// Native nodes stand for memory outside the JavaScript heap, like `ArrayBuffer`
+    /// backing stores. They are included by default.
+    pub fn set_include_native(&mut self, include: bool) {
+        self.include_native = include;
+    }
+
+    pub fn includes_native(&self) -> bool {
+        self.include_native
+    }
+
+    /// Native memory that the snapshot reports, but doesn't attribute to any node
+    pub fn extra_native_bytes(&self) -> usize {
+        self.extra_native_bytes
+    }
+
     /// Returns the target nodes for all outgoing edges for the given node
     pub fn out_neighbors(&self, node: NodeId) -> &[NodeId] {
         let start = self.node_out_edges[node as usize];
@@ -129,22 +443,134 @@ impl V8HeapGraph {
 
     /// Returns the source nodes for all incoming edges for the given node
     pub fn in_neighbors(&self, node: NodeId) -> impl Iterator<Item = NodeId> {
-        self.in_edges(node).map(|e| e.to_node())
+        self.in_edges(node).map(|e| e.from_node())
+    }
+
+    /// The nodes that the garbage collector starts from, sorted and without duplicates
+    ///
+    /// These are the targets of the `(GC roots)` synthetic node below the snapshot root:
+    /// usually more synthetic nodes that group the roots by kind, such as `(Strong roots)` and
+    /// `(Global handles)`. Empty if the snapshot has no `(GC roots)` node.
+    ///
+    /// Only `root_distance` counts from these. The dominator tree and retention paths still
+    /// start at the snapshot root (node 0), which holds on to all of them; pass these to
+    /// `dominator_tree_from_roots` for a tree of only what they keep alive.
+    pub fn gc_roots(&self) -> &[NodeId] {
+        &self.gc_roots
+    }
+
+    pub fn is_gc_root(&self, node: NodeId) -> bool {
+        self.gc_roots.binary_search(&node).is_ok()
+    }
+
+    /// How many strong edges the node is away from the nearest GC root, `None` if unreachable
+    ///
+    /// The first call computes the distances of all nodes, see `root_distances`.
+    pub fn root_distance(&self, node: NodeId) -> Option<u32> {
+        let distances = self
+            .root_distances
+            .get_or_init(|| crate::analysis::all_paths::root_distances(self));
+        Some(distances[node as usize]).filter(|&d| d != u32::MAX)
+    }
+
+    fn find_gc_roots(&self) -> Vec<NodeId> {
+        let Some(root) = self.iter_nodes().next() else {
+            return vec![];
+        };
+        let gc_roots = self.out_neighbors(root).iter().copied().find(|&n| {
+            let node = self.node(n);
+            node.typ() == NodeType::Synthetic && node.name() == "(GC roots)"
+        });
+        let mut roots = gc_roots.map_or(vec![], |n| self.out_neighbors(n).to_vec());
+        roots.sort_unstable();
+        roots.dedup();
+        roots
+    }
+
+    /// Whether `to` can be reached from `from` over strong edges
+    ///
+    /// A breadth-first search that stops as soon as it finds `to`, so nearby nodes are
+    /// answered quickly. Every node is reachable from itself.
+    pub fn is_reachable(&self, from: NodeId, to: NodeId) -> bool {
+        if from == to {
+            return true;
+        }
+
+        let mut seen = FixedBitSet::with_capacity(self.node_count);
+        seen.insert(from as usize);
+        let mut queue = VecDeque::from([from]);
+        while let Some(node) = queue.pop_front() {
+            for edge in self.out_edges_filtered(node, is_strong) {
+                let next = edge.to_node();
+                if next == to {
+                    return true;
+                }
+                if !seen.put(next as usize) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        false
     }
 }
 
+#[cfg(feature = "testing")]
+impl V8HeapGraph {
+    /// Build a graph directly from the flattened snapshot arrays, without a snapshot file
+    pub fn from_parts(
+        nodes: Vec<NodeId>,
+        edges: Vec<NodeId>,
+        strings: Vec<String>,
+        meta: crate::snapshot::SnapshotMetadata,
+    ) -> Self {
+        let snapshot = crate::snapshot::Snapshot {
+            node_count: nodes.len() / meta.node_field_count(),
+            edge_count: edges.len() / meta.edge_field_count(),
+            meta,
+            trace_function_count: 0,
+            extra_native_bytes: 0,
+        };
+        V8HeapGraph::from(SnapshotFile {
+            snapshot,
+            nodes,
+            edges,
+            locations: vec![],
+            samples: vec![],
+            strings,
+            trace_function_infos: vec![],
+            trace_tree: vec![],
+        })
+    }
+}
+
+/// Panics if the snapshot is malformed; snapshots read from a file or stream should go
+/// through `V8HeapGraph::from_snapshot` instead
 impl From<SnapshotFile> for V8HeapGraph {
-    fn from(mut value: SnapshotFile) -> Self {
-        let node_count = value.snapshot.node_count;
+    fn from(value: SnapshotFile) -> Self {
+        Self::from_snapshot(value).unwrap_or_else(|e| panic!("Malformed snapshot: {:#}", e))
+    }
+}
 
-        let node_fields = NodeFields::new(value.snapshot.meta.node_fields);
-        let edge_fields = EdgeFields::new(value.snapshot.meta.edge_fields);
+impl V8HeapGraph {
+    /// Build the graph of a parsed snapshot
+    ///
+    /// Fails if the node or edge fields aren't in a layout this version reads (see
+    /// `snapshot::compat`), if an edge points to something other than a node (see
+    /// `edge_target_node`), or if the metadata doesn't list the node and edge types.
+    pub fn from_snapshot(mut value: SnapshotFile) -> Result<Self> {
+        let node_fields = NodeFields::new(value.snapshot.meta.node_fields)?;
+
+        // Trust the data over the header, which disagree if the file was truncated
+        let node_count = value.nodes.len() / node_fields.stride();
+        let edge_fields = EdgeFields::new(value.snapshot.meta.edge_fields)?;
 
         let mut edges = Edges::new(
             value.edges,
             value.snapshot.edge_count,
+            edge_fields.stride(),
             node_fields.stride() as NodeId,
-        );
+            node_count,
+        )?;
 
         let edge_counts = value
             .nodes
@@ -156,9 +582,6 @@ impl From<SnapshotFile> for V8HeapGraph {
         // node -> index of its starting 'out' edges
         let mut node_out_edges = Vec::<NodeId>::with_capacity(node_count);
 
-        // node -> ['in' edges]
-        let mut node_in_edges = vec![Vec::<NodeId>::new(); node_count];
-
         let mut current_edge: NodeId = 0;
         for (from_node, edge_count) in edge_counts.enumerate() {
             // Count the indexes of the 'out' edges
@@ -170,38 +593,54 @@ impl From<SnapshotFile> for V8HeapGraph {
                 edge_count as usize,
             ));
 
-            // For every edge, record the source node and add it to the list if incoming edges
-            for _ in 0..edge_count {
-                let to_node = edges.to1(current_edge as usize);
-                node_in_edges[to_node as usize].push(current_edge);
-
-                current_edge += 1;
-            }
+            current_edge += edge_count;
         }
 
+        // node -> ['in' edges]
+        let node_in_edges = InEdges::build(&edges.to_nodes, node_count);
+
         let StringOrStrings::Strs(node_types) =
             std::mem::take(&mut value.snapshot.meta.node_types[0])
         else {
-            panic!("Expected 'node_types[0]' to be a list of strings");
+            bail!("Expected 'node_types[0]' to be a list of strings");
         };
         let StringOrStrings::Strs(edge_types) =
             std::mem::take(&mut value.snapshot.meta.edge_types[0])
         else {
-            panic!("Expected 'edge_types[0]' to be a list of strings");
+            bail!("Expected 'edge_types[0]' to be a list of strings");
         };
 
-        V8HeapGraph {
+        // Don't assume V8's usual order of types, look them up by name
+        let node_type_map = node_types
+            .iter()
+            .enumerate()
+            .map(|(i, name)| NodeType::from_name(name).unwrap_or(NodeType::Unknown(i as NodeId)))
+            .collect();
+        edges.type_map = edge_types
+            .iter()
+            .enumerate()
+            .map(|(i, name)| EdgeType::from_name(name).unwrap_or(EdgeType::Unknown(i as NodeId)))
+            .collect();
+
+        let mut graph = V8HeapGraph {
             node_count,
             nodes: value.nodes,
             edges,
             strings: value.strings,
             node_types,
             edge_types,
+            node_type_map,
             node_out_edges,
             node_in_edges,
             node_fields,
             edge_fields,
-        }
+            extra_native_bytes: value.snapshot.extra_native_bytes,
+            include_native: true,
+            gc_roots: vec![],
+            root_distances: OnceLock::new(),
+        };
+        graph.gc_roots = graph.find_gc_roots();
+        Ok(graph)
     }
 }
 
@@ -213,12 +652,21 @@ pub struct Node<'a> {
 
 impl<'a> Node<'a> {
     pub fn typ(&self) -> NodeType {
-        self.node_range[self.graph.node_fields.type_field()].into()
+        let typ = self.node_range[self.graph.node_fields.type_field()];
+        self.graph
+            .node_type_map
+            .get(typ as usize)
+            .copied()
+            .unwrap_or(NodeType::Unknown(typ))
     }
 
-    pub fn typ_str(&self) -> &'a str {
+    /// The type name from the snapshot, or `type:<n>` if the snapshot doesn't name it
+    pub fn typ_str(&self) -> Cow<'a, str> {
         let typ_id = self.node_range[self.graph.node_fields.type_field()];
-        &self.graph.node_types[typ_id as usize]
+        match self.graph.node_types.get(typ_id as usize) {
+            Some(name) => Cow::Borrowed(name),
+            None => Cow::Owned(format!("type:{}", typ_id)),
+        }
     }
 
     pub fn name(&self) -> &'a str {
@@ -226,6 +674,26 @@ impl<'a> Node<'a> {
         self.graph.string(name_id)
     }
 
+    /// The value of a number or bigint, if V8 wrote it as the name of the node
+    ///
+    /// Depending on its version and settings, V8 names numbers either after their value or
+    /// just `heap number`. Anything longer than `MAX_NUMERIC_NAME` is not taken as a value.
+    pub fn numeric_value(&self) -> Option<&'a str> {
+        let name = self.name();
+        if name.is_empty() || name.len() > MAX_NUMERIC_NAME {
+            return None;
+        }
+        let is_value = match self.typ() {
+            NodeType::Number => name.parse::<f64>().is_ok(),
+            NodeType::BigInt => {
+                let digits = name.strip_prefix('-').unwrap_or(name);
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            }
+            _ => false,
+        };
+        is_value.then_some(name)
+    }
+
     pub fn print_safe_name(&self, max_len: usize) -> Cow<'a, str> {
         match self.typ() {
             NodeType::String => Cow::Owned(print_safe(self.name(), max_len)),
@@ -237,6 +705,11 @@ impl<'a> Node<'a> {
         self.node_range[self.graph.node_fields.stable_id()]
     }
 
+    /// Whether this is one of the graph's `gc_roots`
+    pub fn is_gc_root(&self) -> bool {
+        self.graph.is_gc_root(self.id)
+    }
+
     pub fn self_size(&self) -> usize {
         self.node_range[self.graph.node_fields.self_size_field()] as usize
     }
@@ -246,7 +719,111 @@ impl<'a> Node<'a> {
     }
 
     pub fn detachedness(&self) -> bool {
-        self.node_range[self.graph.node_fields.detachedness_field()] == 1
+        self.graph
+            .node_fields
+            .detachedness_field()
+            .is_some_and(|i| self.node_range[i] == 1)
+    }
+
+    /// The raw value of any node field by name, such as `trace_node_id`
+    ///
+    /// `None` if the snapshot doesn't have that field. Values are not interpreted: the name
+    /// is a string index and the type is a type index.
+    pub fn field(&self, name: &str) -> Option<NodeId> {
+        let index = self.graph.node_fields.field_index(name)?;
+        Some(self.node_range[index])
+    }
+
+    /// Whether this is an array, or an object that stores indexed elements like one
+    ///
+    /// V8 gives these objects an internal `elements` edge to their backing store.
+    pub fn is_array_like(&self) -> bool {
+        self.typ() == NodeType::Object
+            && self
+                .graph
+                .find_edge(self.id, EdgeType::Internal, "elements")
+                .is_some()
+    }
+
+    /// The separate node that holds the elements of this array, if it has one of its own
+    ///
+    /// V8 keeps an array's elements in a backing store behind its `elements` edge, so the
+    /// array object itself is only a few dozen bytes. Stores that other nodes hold on to as
+    /// well, like the empty store all empty arrays share, don't belong to any one array.
+    pub fn backing_store(&self) -> Option<Node<'a>> {
+        if !self.is_array_like() {
+            return None;
+        }
+        let store = self
+            .graph
+            .find_edge(self.id, EdgeType::Internal, "elements")?;
+        let owned = self
+            .graph
+            .in_edges_filtered(store, is_strong)
+            .all(|e| e.from_node() == self.id);
+        owned.then(|| self.graph.node(store))
+    }
+
+    /// The self size of this node, plus that of its backing store for arrays
+    ///
+    /// This is the size a program "sees" for an array; the self size alone makes even huge
+    /// arrays look tiny.
+    pub fn effective_size(&self) -> usize {
+        self.self_size() + self.backing_store().map_or(0, |store| store.self_size())
+    }
+
+    /// The element edges of this node, in index order as V8 wrote them
+    pub fn element_edges(&self) -> impl Iterator<Item = Edge<'a>> + 'a {
+        self.graph
+            .out_edges(self.id)
+            .filter(|e| e.typ() == EdgeType::Element)
+    }
+
+    /// The targets of the element edges of this node
+    pub fn elements(&self) -> impl Iterator<Item = NodeId> + 'a {
+        self.element_edges().map(|e| e.to_node())
+    }
+
+    /// A clean class name for this node, suitable for grouping
+    ///
+    /// - Primitives and functions get the name of their JavaScript type (`String`, `Function`).
+    /// - V8 internals lose their `system / ` prefix (`system / Context` becomes `Context`).
+    /// - Plain `Object`s are named after the constructor of their prototype, if there is one.
+    ///
+    /// Anonymous objects keep their empty name.
+    pub fn constructor_name(&self) -> Cow<'a, str> {
+        match self.typ() {
+            NodeType::String | NodeType::ConcatString | NodeType::SlicedString => {
+                return Cow::Borrowed("String");
+            }
+            NodeType::Number => return Cow::Borrowed("Number"),
+            NodeType::BigInt => return Cow::Borrowed("BigInt"),
+            NodeType::Symbol => return Cow::Borrowed("Symbol"),
+            NodeType::Closure => return Cow::Borrowed("Function"),
+            NodeType::RegExp => return Cow::Borrowed("RegExp"),
+            _ => {}
+        }
+
+        let name = self.name();
+        if let Some(stripped) = name.strip_prefix("system / ") {
+            return Cow::Borrowed(stripped);
+        }
+
+        if name == "Object"
+            && let Some(proto) = self
+                .graph
+                .find_edge(self.id, EdgeType::Property, "__proto__")
+            && let Some(ctor) = self
+                .graph
+                .find_edge(proto, EdgeType::Property, "constructor")
+        {
+            let ctor_name = self.graph.node(ctor).name();
+            if !ctor_name.is_empty() {
+                return Cow::Borrowed(ctor_name);
+            }
+        }
+
+        Cow::Borrowed(name)
     }
 }
 
@@ -268,25 +845,39 @@ impl<'a> std::fmt::Debug for Edge<'a> {
 
 impl<'a> Edge<'a> {
     pub fn typ(&self) -> EdgeType {
-        self.edges.types[self.id.0 as usize].into()
+        let typ = self.edges.types[self.id.0 as usize];
+        self.edges
+            .type_map
+            .get(typ as usize)
+            .copied()
+            .unwrap_or(EdgeType::Unknown(typ))
     }
 
-    pub fn typ_str(&self) -> &str {
-        self.typ().as_str()
+    pub fn typ_str(&self) -> Cow<'static, str> {
+        self.typ().display_name()
     }
 
+    /// The raw `name_or_index` field, without interpreting it
     pub fn index(&self) -> NodeId {
         self.edges.names[self.id.0 as usize]
     }
 
+    /// The name or the index of this edge, depending on its type
+    ///
+    /// V8 stores an index for element and hidden edges, and a string table offset for
+    /// all other known edge types.
     pub fn name_or_index(&self) -> NameOrIndex<'a> {
         let ni = self.edges.names[self.id.0 as usize];
         match self.typ() {
-            EdgeType::Element => NameOrIndex::Index(ni),
+            EdgeType::Element | EdgeType::Hidden => NameOrIndex::Index(ni),
+            EdgeType::Unknown(_) => {
+                NameOrIndex::Unsure(ni, self.strings.get(ni as usize).map_or("", String::as_str))
+            }
             _ => NameOrIndex::Name(&self.strings[ni as usize]),
         }
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn from_node(&self) -> NodeId {
         self.edges.from_nodes[self.id.0 as usize]
     }
@@ -296,10 +887,17 @@ impl<'a> Edge<'a> {
     }
 }
 
-#[derive(Debug)]
+/// How an edge is labeled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum NameOrIndex<'a> {
+    /// Property, context, internal, shortcut and weak edges
     Name(&'a str),
+    /// Element and hidden edges
     Index(NodeId),
+    /// Edges of a type this analyzer doesn't know, which could be either
+    ///
+    /// Holds the raw value, and the string it refers to if it is a valid string table
+    /// offset (empty otherwise).
     Unsure(NodeId, &'a str),
 }
 
@@ -310,6 +908,14 @@ impl<'a> NameOrIndex<'a> {
             _ => false,
         }
     }
+
+    /// The index of an element or hidden edge
+    pub fn index(&self) -> Option<NodeId> {
+        match self {
+            NameOrIndex::Index(i) => Some(*i),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> std::fmt::Display for NameOrIndex<'a> {
@@ -323,37 +929,101 @@ impl<'a> std::fmt::Display for NameOrIndex<'a> {
 }
 
 /// Edges in SoA format
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 struct Edges {
     types: Vec<NodeId>,
     names: Vec<NodeId>,
     to_nodes: Vec<NodeId>,
     pub from_nodes: Vec<NodeId>,
+    /// The `EdgeType` for every type index, based on the snapshot's `edge_types`
+    type_map: Vec<EdgeType>,
+}
+
+/// Edge predicate for `out_edges_filtered` and `in_edges_filtered`: everything but weak edges
+///
+/// Weak edges don't keep their target alive, so they are ignored for retention.
+pub fn is_strong(e: &Edge<'_>) -> bool {
+    e.typ() != EdgeType::Weak
+}
+
+/// Edge predicate for `out_edges_filtered` and `in_edges_filtered`: the structure a user
+/// sees in their own code, properties and array elements
+pub fn is_user_visible(e: &Edge<'_>) -> bool {
+    matches!(e.typ(), EdgeType::Property | EdgeType::Element)
+}
+
+/// Whether a node belongs to V8 or the embedder rather than to the program
+///
+/// That is code, hidden classes, and everything named `system / ...` or `(...)`, like
+/// `system / Context` and `(compiled code)`. Strings are never noise, whatever they contain.
+pub fn is_noise_node(node: &Node<'_>) -> bool {
+    let typ = node.typ();
+    if typ.is_internal() {
+        return true;
+    }
+    if matches!(
+        typ,
+        NodeType::String | NodeType::ConcatString | NodeType::SlicedString
+    ) {
+        return false;
+    }
+    let name = node.name();
+    name.starts_with("system /") || name.starts_with('(')
+}
+
+/// The node that the `to_node` field of an edge points to
+///
+/// The `to_node` fields in the snapshot's edges array are *indexes* into the flattened
+/// `nodes` array, not node identifiers, so they must be a multiple of the node stride. This
+/// is the one place where they are converted.
+pub fn edge_target_node(to_node: NodeId, node_stride: NodeId, node_count: usize) -> Result<NodeId> {
+    ensure!(
+        to_node.is_multiple_of(node_stride),
+        "Edge target {} is not the start of a node: not a multiple of the node stride {}",
+        to_node,
+        node_stride
+    );
+    let node = to_node / node_stride;
+    ensure!(
+        (node as usize) < node_count,
+        "Edge target {} points past the last node (node {} of {})",
+        to_node,
+        node,
+        node_count
+    );
+    Ok(node)
 }
 
 impl Edges {
-    pub fn new(snapshot_edges: Vec<NodeId>, edge_count: usize, node_stride: NodeId) -> Self {
+    /// Fails if an edge points to something other than a node, see `edge_target_node`
+    pub fn new(
+        snapshot_edges: Vec<NodeId>,
+        edge_count: usize,
+        edge_stride: usize,
+        node_stride: NodeId,
+        node_count: usize,
+    ) -> Result<Self> {
         let mut ret = Edges {
             types: Vec::with_capacity(edge_count),
             names: Vec::with_capacity(edge_count),
             to_nodes: Vec::with_capacity(edge_count),
 
-            // from_nodes gets filled later
+            // from_nodes and type_map get filled later
             from_nodes: Vec::with_capacity(edge_count),
+            type_map: vec![],
         };
 
         // All indirection for indexes out the window here :D
-        for chunk in snapshot_edges.chunks_exact(3) {
+        for chunk in snapshot_edges.chunks_exact(edge_stride) {
             ret.types.push(chunk[0]);
             ret.names.push(chunk[1]);
 
-            // The `to_node` fields in the input edges array are *indexes* into the `nodes`
-            // array, not node identifiers. Divide them all by the node stride so we don't
-            // have to do that later.
-            ret.to_nodes.push(chunk[2] / node_stride);
+            // Convert them all to node identifiers now, so we don't have to do that later
+            let to_node = edge_target_node(chunk[2], node_stride, node_count)?;
+            ret.to_nodes.push(to_node);
         }
 
-        ret
+        Ok(ret)
     }
 
     pub fn mem_size(&self) -> usize {
@@ -376,32 +1046,107 @@ impl Edges {
     }
 }
 
+/// The incoming edges of every node, in compressed sparse row format
+///
+/// The incoming edges of node `n` are `edges[offsets[n]..offsets[n + 1]]`, ordered by edge
+/// id. This takes a fraction of the memory of a `Vec` per node, and is built in parallel.
+#[derive(Debug, Serialize, Deserialize)]
+struct InEdges {
+    offsets: Vec<NodeId>,
+    edges: Vec<NodeId>,
+}
+
+impl InEdges {
+    fn build(to_nodes: &[NodeId], node_count: usize) -> Self {
+        let in_degrees: Vec<AtomicU32> = (0..node_count).map(|_| AtomicU32::new(0)).collect();
+        to_nodes.par_iter().for_each(|&to| {
+            in_degrees[to as usize].fetch_add(1, Ordering::Relaxed);
+        });
+
+        let mut offsets = Vec::with_capacity(node_count + 1);
+        let mut total: NodeId = 0;
+        offsets.push(total);
+        for degree in in_degrees {
+            total += degree.into_inner();
+            offsets.push(total);
+        }
+
+        // Scatter every edge into the next free slot of its target node
+        let cursors: Vec<AtomicU32> = offsets[..node_count]
+            .iter()
+            .map(|&o| AtomicU32::new(o))
+            .collect();
+        let slots: Vec<AtomicU32> = (0..to_nodes.len()).map(|_| AtomicU32::new(0)).collect();
+        to_nodes.par_iter().enumerate().for_each(|(edge, &to)| {
+            let slot = cursors[to as usize].fetch_add(1, Ordering::Relaxed);
+            slots[slot as usize].store(edge as NodeId, Ordering::Relaxed);
+        });
+        let mut edges: Vec<NodeId> = slots.into_par_iter().map(AtomicU32::into_inner).collect();
+
+        // The scatter doesn't preserve order within a node
+        let mut per_node = Vec::with_capacity(node_count);
+        let mut rest = edges.as_mut_slice();
+        for window in offsets.windows(2) {
+            let (head, tail) = rest.split_at_mut((window[1] - window[0]) as usize);
+            per_node.push(head);
+            rest = tail;
+        }
+        per_node
+            .into_par_iter()
+            .for_each(|edges| edges.sort_unstable());
+
+        Self { offsets, edges }
+    }
+
+    fn of(&self, node: NodeId) -> &[NodeId] {
+        let start = self.offsets[node as usize] as usize;
+        let end = self.offsets[node as usize + 1] as usize;
+        &self.edges[start..end]
+    }
+
+    fn mem_size(&self) -> usize {
+        (self.offsets.len() + self.edges.len()) * size_of::<NodeId>()
+    }
+}
+
 // For now these have static knowledge of all fields, but they validate
 // against the actual fields we're seeing.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct NodeFields {
     stride: usize,
-    trace_node_id: Option<usize>,
-    detachedness: usize,
+    /// Older snapshots don't have this field
+    detachedness: Option<usize>,
+    /// The position of every field, including the ones that don't have a getter
+    indices: HashMap<String, usize>,
 }
 
 impl NodeFields {
-    pub fn new(fields: Vec<String>) -> Self {
-        assert!(fields.len() >= 5);
-        assert!(fields[0] == "type");
-        assert!(fields[1] == "name");
-        assert!(fields[2] == "id");
-        assert!(fields[3] == "self_size");
-        assert!(fields[4] == "edge_count");
-
-        Self {
-            stride: fields.len(),
-            trace_node_id: fields.iter().position(|x| x == "trace_node_id"),
-            detachedness: fields
+    /// Fails unless the fields start with `REQUIRED_NODE_FIELDS`, in that order
+    pub fn new(fields: Vec<String>) -> Result<Self> {
+        ensure!(
+            fields
                 .iter()
-                .position(|x| x == "detachedness")
-                .expect("Did not find detachedness"),
-        }
+                .take(REQUIRED_NODE_FIELDS.len())
+                .eq(REQUIRED_NODE_FIELDS),
+            "Unsupported snapshot format: node fields [{}] don't start with [{}], see --version",
+            fields.join(", "),
+            REQUIRED_NODE_FIELDS.join(", ")
+        );
+
+        Ok(Self {
+            stride: fields.len(),
+            detachedness: fields.iter().position(|x| x == "detachedness"),
+            indices: fields
+                .into_iter()
+                .enumerate()
+                .map(|(i, f)| (f, i))
+                .collect(),
+        })
+    }
+
+    /// Position of the field with this name within a node, if the snapshot has it
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
     }
 
     pub fn edge_count(&self, nodes: &[NodeId], i: NodeId) -> NodeId {
@@ -428,7 +1173,7 @@ impl NodeFields {
         4
     }
 
-    pub fn detachedness_field(&self) -> usize {
+    pub fn detachedness_field(&self) -> Option<usize> {
         self.detachedness
     }
 
@@ -439,16 +1184,24 @@ impl NodeFields {
 
 // For now these have static knowledge of all fields, but they validate
 // against the actual fields we're seeing.
-#[derive(Debug)]
-pub struct EdgeFields {}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EdgeFields {
+    stride: usize,
+}
 
 impl EdgeFields {
-    pub fn new(fields: Vec<String>) -> Self {
-        assert!(fields.len() == 3);
-        assert!(fields[0] == "type");
-        assert!(fields[1] == "name_or_index");
-        assert!(fields[2] == "to_node");
-        Self {}
+    /// Fields after the three we know about are skipped, see `compatibility_warnings`. Fails
+    /// unless the fields start with `EDGE_FIELDS`, in that order
+    pub fn new(fields: Vec<String>) -> Result<Self> {
+        ensure!(
+            fields.iter().take(EDGE_FIELDS.len()).eq(EDGE_FIELDS),
+            "Unsupported snapshot format: edge fields [{}] don't start with [{}], see --version",
+            fields.join(", "),
+            EDGE_FIELDS.join(", ")
+        );
+        Ok(Self {
+            stride: fields.len(),
+        })
     }
 
     pub fn to_node_field(&self) -> usize {
@@ -456,32 +1209,105 @@ impl EdgeFields {
     }
 
     pub fn stride(&self) -> usize {
-        3
+        self.stride
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// The longest node name that `Node::numeric_value` considers, enough for any `f64` and for
+/// a bigint of about 200 bits
+pub const MAX_NUMERIC_NAME: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum NodeType {
-    Hidden = 0,
-    Array = 1,
-    String = 2,
-    Object = 3,
-    Code = 4,
-    Closure = 5,
-    RegExp = 6,
-    Number = 7,
-    Native = 8,
-    Synthetic = 9,
-    ConcatString = 10,
-    SlicedString = 11,
-    Symbol = 12,
-    BigInt = 13,
-    ObjectShape = 14,
-}
-
-impl From<NodeId> for NodeType {
-    fn from(value: NodeId) -> Self {
-        match value {
+    Hidden,
+    Array,
+    String,
+    Object,
+    Code,
+    Closure,
+    RegExp,
+    Number,
+    Native,
+    Synthetic,
+    ConcatString,
+    SlicedString,
+    Symbol,
+    BigInt,
+    ObjectShape,
+    /// A type this version doesn't know about, with its index in the snapshot
+    Unknown(NodeId),
+}
+
+impl NodeType {
+    /// Types that only exist for V8's own bookkeeping: hidden objects, code and shapes
+    pub fn is_internal(&self) -> bool {
+        matches!(
+            self,
+            NodeType::Hidden | NodeType::Code | NodeType::ObjectShape
+        )
+    }
+
+    /// Convert a type index from the snapshot, keeping unrecognized types as `Unknown`
+    pub fn from_index(value: NodeId) -> Self {
+        NodeType::try_from(value).unwrap_or(NodeType::Unknown(value))
+    }
+
+    /// The type index as it appears in the snapshot
+    pub fn index(&self) -> NodeId {
+        match self {
+            NodeType::Hidden => 0,
+            NodeType::Array => 1,
+            NodeType::String => 2,
+            NodeType::Object => 3,
+            NodeType::Code => 4,
+            NodeType::Closure => 5,
+            NodeType::RegExp => 6,
+            NodeType::Number => 7,
+            NodeType::Native => 8,
+            NodeType::Synthetic => 9,
+            NodeType::ConcatString => 10,
+            NodeType::SlicedString => 11,
+            NodeType::Symbol => 12,
+            NodeType::BigInt => 13,
+            NodeType::ObjectShape => 14,
+            NodeType::Unknown(value) => *value,
+        }
+    }
+    /// Look up a type by the name V8 uses in snapshot metadata
+    pub fn from_name(name: &str) -> Option<Self> {
+        (0..15)
+            .map(NodeType::from_index)
+            .find(|typ| typ.as_str() == name)
+    }
+
+    /// The name V8 uses for this type in snapshot metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeType::Hidden => "hidden",
+            NodeType::Array => "array",
+            NodeType::String => "string",
+            NodeType::Object => "object",
+            NodeType::Code => "code",
+            NodeType::Closure => "closure",
+            NodeType::RegExp => "regexp",
+            NodeType::Number => "number",
+            NodeType::Native => "native",
+            NodeType::Synthetic => "synthetic",
+            NodeType::ConcatString => "concatenated string",
+            NodeType::SlicedString => "sliced string",
+            NodeType::Symbol => "symbol",
+            NodeType::BigInt => "bigint",
+            NodeType::ObjectShape => "object shape",
+            NodeType::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl TryFrom<NodeId> for NodeType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: NodeId) -> Result<Self> {
+        Ok(match value {
             0 => NodeType::Hidden,
             1 => NodeType::Array,
             2 => NodeType::String,
@@ -497,23 +1323,59 @@ impl From<NodeId> for NodeType {
             12 => NodeType::Symbol,
             13 => NodeType::BigInt,
             14 => NodeType::ObjectShape,
-            _ => panic!("Unexpecte node type"),
-        }
+            _ => bail!("Unexpected node type: {}", value),
+        })
     }
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum EdgeType {
-    Context = 0,
-    Element = 1,
-    Property = 2,
-    Internal = 3,
-    Hidden = 4,
-    Shortcut = 5,
-    Weak = 6,
+    Context,
+    Element,
+    Property,
+    Internal,
+    Hidden,
+    Shortcut,
+    Weak,
+    /// A type this version doesn't know about, with its index in the snapshot
+    Unknown(NodeId),
 }
 
 impl EdgeType {
+    /// Convert a type index from the snapshot, keeping unrecognized types as `Unknown`
+    pub fn from_index(value: NodeId) -> Self {
+        EdgeType::try_from(value).unwrap_or(EdgeType::Unknown(value))
+    }
+
+    /// The type index as it appears in the snapshot
+    pub fn index(&self) -> NodeId {
+        match self {
+            EdgeType::Context => 0,
+            EdgeType::Element => 1,
+            EdgeType::Property => 2,
+            EdgeType::Internal => 3,
+            EdgeType::Hidden => 4,
+            EdgeType::Shortcut => 5,
+            EdgeType::Weak => 6,
+            EdgeType::Unknown(value) => *value,
+        }
+    }
+
+    /// Look up a type by the name V8 uses in snapshot metadata
+    pub fn from_name(name: &str) -> Option<Self> {
+        (0..7)
+            .map(EdgeType::from_index)
+            .find(|typ| typ.as_str() == name)
+    }
+
+    /// The type name, or `type:<n>` for types this analyzer doesn't know
+    pub fn display_name(&self) -> Cow<'static, str> {
+        match self {
+            EdgeType::Unknown(typ) => Cow::Owned(format!("type:{}", typ)),
+            typ => Cow::Borrowed(typ.as_str()),
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             EdgeType::Context => "context",
@@ -523,13 +1385,16 @@ impl EdgeType {
             EdgeType::Hidden => "hidden",
             EdgeType::Shortcut => "shortcut",
             EdgeType::Weak => "weak",
+            EdgeType::Unknown(_) => "unknown",
         }
     }
 }
 
-impl From<NodeId> for EdgeType {
-    fn from(value: NodeId) -> Self {
-        match value {
+impl TryFrom<NodeId> for EdgeType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: NodeId) -> Result<Self> {
+        Ok(match value {
             0 => EdgeType::Context,
             1 => EdgeType::Element,
             2 => EdgeType::Property,
@@ -537,7 +1402,588 @@ impl From<NodeId> for EdgeType {
             4 => EdgeType::Hidden,
             5 => EdgeType::Shortcut,
             6 => EdgeType::Weak,
-            _ => panic!("Unexpecte edge type"),
+            _ => bail!("Unexpected edge type: {}", value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{graph::test_graph::TestGraph, report::minimal_node_repr};
+
+    #[test]
+    fn test_types_are_looked_up_by_name() {
+        let mut g = TestGraph::new();
+        let obj = g.node(NodeType::Object, "Obj", 16);
+        let s = g.node(NodeType::String, "str", 16);
+        g.edge(obj, EdgeType::Property, "s", s).element(obj, 0, s);
+        let mut snapshot = g.snapshot();
+
+        // Reverse the order of all types, and renumber the nodes and edges to match
+        for types in [
+            &mut snapshot.snapshot.meta.node_types,
+            &mut snapshot.snapshot.meta.edge_types,
+        ] {
+            let StringOrStrings::Strs(names) = &mut types[0] else {
+                panic!("Expected a list of types");
+            };
+            names.reverse();
+        }
+        let node_type_count = NodeType::ObjectShape.index() + 1;
+        let edge_type_count = EdgeType::Weak.index() + 1;
+        let stride = snapshot.snapshot.meta.node_field_count();
+        for typ in snapshot.nodes.iter_mut().step_by(stride) {
+            *typ = node_type_count - 1 - *typ;
+        }
+        for typ in snapshot.edges.iter_mut().step_by(3) {
+            *typ = edge_type_count - 1 - *typ;
+        }
+        let graph = V8HeapGraph::from(snapshot);
+
+        assert_eq!(graph.node(obj).typ(), NodeType::Object);
+        assert_eq!(graph.node(s).typ(), NodeType::String);
+        assert_eq!(graph.node_type_name(s), Some("string"));
+        let edge_types = graph.out_edges(obj).map(|e| e.typ()).collect::<Vec<_>>();
+        assert_eq!(edge_types, vec![EdgeType::Property, EdgeType::Element]);
+    }
+
+    #[test]
+    fn test_malformed_snapshot_is_an_error() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        g.edge(root, EdgeType::Property, "a", a);
+        let mut snapshot = g.snapshot();
+        // Point into the middle of 'a' instead of at its first field
+        snapshot.edges[2] += 1;
+
+        let err = V8HeapGraph::from_snapshot(snapshot).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Edge target 7 is not the start of a node: not a multiple of the node stride 6"
+        );
+    }
+
+    #[test]
+    fn test_custom_node_field() {
+        let mut g = TestGraph::new();
+        let a = g.node(NodeType::Object, "A", 16);
+        let b = g.node(NodeType::Object, "B", 16);
+        g.edge(a, EdgeType::Property, "b", b);
+        let mut snapshot = g.snapshot();
+
+        // Append a field this tool doesn't know about to every node
+        let stride = snapshot.snapshot.meta.node_field_count();
+        snapshot
+            .snapshot
+            .meta
+            .node_fields
+            .push("trace_node_id".to_string());
+        snapshot.nodes = snapshot
+            .nodes
+            .chunks(stride)
+            .enumerate()
+            .flat_map(|(i, node)| node.iter().copied().chain([100 + i as NodeId]))
+            .collect();
+        // Edges point at node offsets, which move along
+        for to_node in snapshot.edges.iter_mut().skip(2).step_by(3) {
+            *to_node = *to_node / stride as NodeId * (stride as NodeId + 1);
+        }
+        let graph = V8HeapGraph::from(snapshot);
+
+        assert_eq!(graph.node(a).field("trace_node_id"), Some(100));
+        assert_eq!(graph.node(b).field("trace_node_id"), Some(101));
+        assert_eq!(graph.node(b).field("self_size"), Some(16));
+        assert_eq!(graph.node(b).field("no_such_field"), None);
+        // The known fields are still where they were
+        assert_eq!(graph.node(b).name(), "B");
+        assert_eq!(graph.out_neighbors(a), vec![b]);
+    }
+
+    #[test]
+    fn test_is_reachable() {
+        let mut g = TestGraph::new();
+        let a = g.node(NodeType::Object, "A", 16);
+        let b = g.node(NodeType::Object, "B", 16);
+        let c = g.node(NodeType::Object, "C", 16);
+        let weakly_held = g.node(NodeType::Object, "D", 16);
+        g.edge(a, EdgeType::Property, "b", b)
+            .edge(b, EdgeType::Property, "c", c)
+            .edge(c, EdgeType::Property, "a", a)
+            .edge(c, EdgeType::Weak, "d", weakly_held);
+        let graph = g.build();
+
+        assert!(graph.is_reachable(a, c));
+        assert!(graph.is_reachable(c, b));
+        assert!(graph.is_reachable(weakly_held, weakly_held));
+        assert!(!graph.is_reachable(a, weakly_held));
+        assert!(!graph.is_reachable(weakly_held, a));
+    }
+
+    #[test]
+    fn test_is_array_like() {
+        let mut g = TestGraph::new();
+        let array = g.node(NodeType::Object, "Array", 32);
+        let store = g.node(NodeType::Array, "", 64);
+        let first = g.node(NodeType::String, "first", 16);
+        let second = g.node(NodeType::String, "second", 16);
+        let plain = g.node(NodeType::Object, "Object", 32);
+        g.edge(array, EdgeType::Internal, "elements", store)
+            .element(array, 0, first)
+            .element(array, 1, second)
+            .edge(plain, EdgeType::Property, "first", first);
+        let graph = g.build();
+
+        assert!(graph.node(array).is_array_like());
+        assert_eq!(
+            graph.node(array).elements().collect::<Vec<_>>(),
+            vec![first, second]
+        );
+        assert_eq!(
+            minimal_node_repr(array, &graph),
+            "Array(96b) [ \"first\", \"second\" ]  (depth 0)"
+        );
+
+        assert!(!graph.node(plain).is_array_like());
+        assert!(!graph.node(store).is_array_like());
+        assert_eq!(graph.node(plain).elements().count(), 0);
+    }
+
+    #[test]
+    fn test_effective_size_includes_backing_store() {
+        let mut g = TestGraph::new();
+        let array = g.node(NodeType::Object, "Array", 32);
+        let store = g.node(NodeType::Array, "(object elements)", 8000);
+        let empty = g.node(NodeType::Object, "Array", 32);
+        let other_empty = g.node(NodeType::Object, "Array", 32);
+        let shared = g.node(NodeType::Array, "(object elements)", 16);
+        let watcher = g.node(NodeType::Object, "Watcher", 16);
+        g.edge(array, EdgeType::Internal, "elements", store)
+            .edge(empty, EdgeType::Internal, "elements", shared)
+            .edge(other_empty, EdgeType::Internal, "elements", shared)
+            .edge(watcher, EdgeType::Weak, "target", store);
+        let graph = g.build();
+
+        // Weak references don't make the store shared
+        assert_eq!(graph.node(array).backing_store().map(|s| s.id), Some(store));
+        assert_eq!(graph.node(array).effective_size(), 8032);
+        assert_eq!(graph.node(store).effective_size(), 8000);
+
+        // A shared store belongs to neither array
+        assert!(graph.node(empty).backing_store().is_none());
+        assert_eq!(graph.node(empty).effective_size(), 32);
+        assert_eq!(graph.node(watcher).effective_size(), 16);
+    }
+
+    #[test]
+    fn test_save_and_load_saved() {
+        let graph =
+            V8HeapGraph::load(Path::new("tests/fixtures/test-duplicates.heapsnapshot")).unwrap();
+
+        let source =
+            SnapshotSource::of(Path::new("tests/fixtures/test-duplicates.heapsnapshot")).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshot.graph");
+        graph.save(&path, &source).unwrap();
+        let loaded = V8HeapGraph::load_saved(&path, &source).unwrap().unwrap();
+
+        assert_eq!(loaded.total_node_count(), graph.total_node_count());
+        assert_eq!(loaded.total_edge_count(), graph.total_edge_count());
+        assert_eq!(loaded.fingerprint(), graph.fingerprint());
+        assert_eq!(loaded.strings, graph.strings);
+        assert!(graph.iter_nodes().all(|n| {
+            loaded.node_range(n) == graph.node_range(n)
+                && loaded.node(n).name() == graph.node(n).name()
+                && loaded
+                    .in_edges(n)
+                    .map(|e| e.index())
+                    .eq(graph.in_edges(n).map(|e| e.index()))
+        }));
+        assert!(graph.iter_edges().all(|e| {
+            let (a, b) = (loaded.edge(e), graph.edge(e));
+            a.from_node() == b.from_node()
+                && a.to_node() == b.to_node()
+                && a.typ() == b.typ()
+                && a.name_or_index().to_string() == b.name_or_index().to_string()
+        }));
+
+        // The graph of another snapshot, or of an older version of this one, isn't used
+        let other = SnapshotSource::of(Path::new("tests/fixtures/object.heapsnapshot")).unwrap();
+        assert!(V8HeapGraph::load_saved(&path, &other).unwrap().is_none());
+        let rewritten = SnapshotSource {
+            size: source.size + 1,
+            ..source.clone()
+        };
+        assert!(
+            V8HeapGraph::load_saved(&path, &rewritten)
+                .unwrap()
+                .is_none()
+        );
+
+        // A file from a build with another layout is refused rather than misread
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[..4].copy_from_slice(&(GRAPH_FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let err = V8HeapGraph::load_saved(&path, &source).unwrap_err();
+        assert!(err.to_string().contains("format version"));
+    }
+
+    #[test]
+    fn test_constructor_name() {
+        let mut g = TestGraph::new();
+        let context = g.node(NodeType::Object, "system / Context", 0);
+        let closure = g.node(NodeType::Closure, "handleClick", 0);
+        let sliced = g.node(NodeType::SlicedString, "abc", 0);
+        let widget = g.node(NodeType::Object, "Widget", 0);
+        let anonymous = g.node(NodeType::Object, "", 0);
+
+        // An object literal created by a class-like constructor function
+        let ctor = g.node(NodeType::Closure, "LegacyWidget", 0);
+        let proto = g.node(NodeType::Object, "Object", 0);
+        let instance = g.node(NodeType::Object, "Object", 0);
+        g.edge(proto, EdgeType::Property, "constructor", ctor).edge(
+            instance,
+            EdgeType::Property,
+            "__proto__",
+            proto,
+        );
+        let plain = g.node(NodeType::Object, "Object", 0);
+        let graph = g.build();
+
+        let name = |n| graph.node(n).constructor_name().into_owned();
+        assert_eq!(name(context), "Context");
+        assert_eq!(name(closure), "Function");
+        assert_eq!(name(sliced), "String");
+        assert_eq!(name(widget), "Widget");
+        assert_eq!(name(anonymous), "");
+        assert_eq!(name(instance), "LegacyWidget");
+        assert_eq!(name(plain), "Object");
+    }
+
+    #[test]
+    fn test_in_edges_match_naive_construction() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/object.heapsnapshot");
+        let graph = V8HeapGraph::from(crate::snapshot::read_v8_snapshot_file(&path).unwrap());
+
+        let mut naive = vec![Vec::<NodeId>::new(); graph.total_node_count()];
+        for edge in graph.iter_edges() {
+            naive[graph.edge(edge).to_node() as usize].push(edge.0);
+        }
+
+        for node in graph.iter_nodes() {
+            assert_eq!(graph.node_in_edges.of(node), naive[node as usize]);
+        }
+    }
+
+    #[test]
+    fn test_edge_between() {
+        let mut g = TestGraph::new();
+        let a = g.node(NodeType::Object, "A", 16);
+        let b = g.node(NodeType::Object, "B", 16);
+        let c = g.node(NodeType::Object, "C", 16);
+        g.edge(a, EdgeType::Internal, "map", c)
+            .edge(a, EdgeType::Property, "first", b)
+            .edge(a, EdgeType::Property, "second", b)
+            .element(b, 4, c);
+        let graph = g.build();
+
+        // The first of several edges to the same node
+        let edge = graph.edge_between(a, b).unwrap();
+        assert_eq!(edge.typ(), EdgeType::Property);
+        assert!(edge.name_or_index().is_str("first"));
+        assert_eq!(edge.from_node(), a);
+
+        let edge = graph.edge_between(b, c).unwrap();
+        assert_eq!(edge.typ(), EdgeType::Element);
+        assert_eq!(edge.name_or_index().index(), Some(4));
+
+        // Edges have a direction
+        assert!(graph.edge_between(b, a).is_none());
+        assert!(graph.edge_between(c, c).is_none());
+    }
+
+    #[test]
+    fn test_numeric_values() {
+        let mut g = TestGraph::new();
+        let pi = g.node(NodeType::Number, "3.14", 16);
+        let negative = g.node(NodeType::Number, "-42", 16);
+        let heap_number = g.node(NodeType::Number, "heap number", 16);
+        let garbage = g.node(NodeType::Number, &"9".repeat(MAX_NUMERIC_NAME + 1), 16);
+        let big = g.node(NodeType::BigInt, "-123456789012345678901234567890", 32);
+        let unnamed_big = g.node(NodeType::BigInt, "bigint", 32);
+        let minus = g.node(NodeType::BigInt, "-", 32);
+        let graph = g.build();
+
+        assert_eq!(graph.node(pi).numeric_value(), Some("3.14"));
+        // The first node is the root, the others are unreachable
+        assert_eq!(minimal_node_repr(pi, &graph), "3.14  (depth 0)");
+        assert_eq!(minimal_node_repr(negative, &graph), "-42");
+        assert_eq!(graph.node(heap_number).numeric_value(), None);
+        assert_eq!(minimal_node_repr(heap_number, &graph), "<a number>");
+        assert_eq!(minimal_node_repr(garbage, &graph), "<a number>");
+
+        assert_eq!(
+            minimal_node_repr(big, &graph),
+            "-123456789012345678901234567890n"
+        );
+        assert_eq!(minimal_node_repr(unnamed_big, &graph), "<a bigint>");
+        assert_eq!(minimal_node_repr(minus, &graph), "<a bigint>");
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fixture = |name: &str| {
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(name)
+        };
+        let load = |path: &Path| V8HeapGraph::load(path).unwrap().fingerprint();
+
+        let original = fixture("object.heapsnapshot");
+        let copy = tempfile::NamedTempFile::new().unwrap();
+        std::fs::copy(&original, copy.path()).unwrap();
+        assert_eq!(load(&original), load(copy.path()));
+
+        assert_ne!(
+            load(&original),
+            load(&fixture("test-duplicates.heapsnapshot"))
+        );
+        assert_ne!(TestGraph::new().build().fingerprint(), load(&original));
+
+        // Only the snapshot counts, not how sizes are added up
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let native = g.node(NodeType::Native, "HTMLDivElement", 100);
+        g.edge(root, EdgeType::Property, "div", native);
+        let mut graph = g.build();
+        let fingerprint = graph.fingerprint();
+        graph.set_include_native(!graph.includes_native());
+        assert_eq!(graph.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_gc_roots_of_fixture() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/object.heapsnapshot");
+        let graph = V8HeapGraph::from(crate::snapshot::read_v8_snapshot_file(&path).unwrap());
+
+        let roots = graph.gc_roots();
+        assert!(!roots.is_empty());
+        assert!(roots.windows(2).all(|w| w[0] < w[1]));
+        let names: Vec<_> = roots.iter().map(|&n| graph.node(n).name()).collect();
+        assert!(names.contains(&"(Global handles)"));
+        assert!(names.contains(&"(Strong roots)"));
+        for &root in roots {
+            assert!(graph.node(root).is_gc_root());
+            assert!(
+                graph
+                    .in_neighbors(root)
+                    .any(|n| graph.node(n).name() == "(GC roots)")
+            );
+        }
+        // Neither the snapshot root nor the (GC roots) node itself
+        assert!(!graph.is_gc_root(0));
+        assert!(!graph.is_gc_root(1));
+
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 16);
+        g.edge(root, EdgeType::Property, "a", a);
+        assert!(g.build().gc_roots().is_empty());
+    }
+
+    #[test]
+    fn test_name_or_index() {
+        let mut g = TestGraph::new();
+        let from = g.node(NodeType::Object, "from", 0);
+        let to = g.node(NodeType::Object, "to", 0);
+        g.edge(from, EdgeType::Context, "ctx", to)
+            .element(from, 7, to)
+            .edge(from, EdgeType::Property, "prop", to)
+            .edge(from, EdgeType::Internal, "map", to)
+            .indexed(from, EdgeType::Hidden, 3, to)
+            .edge(from, EdgeType::Shortcut, "short", to)
+            .edge(from, EdgeType::Weak, "weak", to)
+            .edge(from, EdgeType::Unknown(42), "other", to);
+        let graph = g.build();
+
+        let labels = graph
+            .out_edges(from)
+            .map(|e| e.name_or_index())
+            .collect::<Vec<_>>();
+        let other = graph.out_edges(from).last().map(|e| e.index()).unwrap();
+        assert_eq!(
+            labels,
+            vec![
+                NameOrIndex::Name("ctx"),
+                NameOrIndex::Index(7),
+                NameOrIndex::Name("prop"),
+                NameOrIndex::Name("map"),
+                NameOrIndex::Index(3),
+                NameOrIndex::Name("short"),
+                NameOrIndex::Name("weak"),
+                NameOrIndex::Unsure(other, "other"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolved_string() {
+        let mut g = TestGraph::new();
+        let hello = g.node(NodeType::String, "Hello", 0);
+        let comma = g.node(NodeType::String, ", ", 0);
+        let world = g.node(NodeType::String, "world", 0);
+        let inner = g.node(NodeType::ConcatString, "(concatenated string)", 0);
+        let outer = g.node(NodeType::ConcatString, "(concatenated string)", 0);
+        let slice = g.node(NodeType::SlicedString, "(sliced string)", 0);
+        g.edge(inner, EdgeType::Internal, "first", hello)
+            .edge(inner, EdgeType::Internal, "second", comma)
+            .edge(outer, EdgeType::Internal, "first", inner)
+            .edge(outer, EdgeType::Internal, "second", world)
+            .edge(slice, EdgeType::Internal, "parent", outer);
+
+        // A malformed snapshot where a string contains itself
+        let cyclic = g.node(NodeType::ConcatString, "(concatenated string)", 0);
+        g.edge(cyclic, EdgeType::Internal, "first", hello).edge(
+            cyclic,
+            EdgeType::Internal,
+            "second",
+            cyclic,
+        );
+        let graph = g.build();
+
+        assert_eq!(graph.resolved_string(hello), "Hello");
+        assert_eq!(graph.resolved_string(outer), "Hello, world");
+        assert_eq!(graph.resolved_string(slice), "Hello, world");
+
+        let resolved = graph.resolved_string(cyclic);
+        assert!(resolved.starts_with("HelloHello"));
+        assert!(resolved.ends_with("..."));
+    }
+
+    #[test]
+    fn test_resolved_string_with_shared_parts() {
+        // Every level is its previous level twice: 2^60 copies of "ab", from 61 nodes
+        let mut g = TestGraph::new();
+        let leaf = g.node(NodeType::String, "ab", 0);
+        let mut levels = vec![leaf];
+        for _ in 0..60 {
+            let prev = *levels.last().unwrap();
+            let next = g.node(NodeType::ConcatString, "(concatenated string)", 0);
+            g.edge(next, EdgeType::Internal, "first", prev).edge(
+                next,
+                EdgeType::Internal,
+                "second",
+                prev,
+            );
+            levels.push(next);
         }
+        let graph = g.build();
+
+        assert_eq!(graph.resolved_string(levels[3]), "ab".repeat(8));
+
+        let resolved = graph.resolved_string(*levels.last().unwrap());
+        assert_eq!(resolved.len(), MAX_RESOLVED_STRING_LEN + 3);
+        assert!(resolved.starts_with("abab"));
+        assert!(resolved.ends_with("..."));
+    }
+
+    #[test]
+    fn test_unknown_types() {
+        assert!(NodeType::try_from(99).is_err());
+        assert!(EdgeType::try_from(99).is_err());
+        assert_eq!(NodeType::try_from(3).unwrap(), NodeType::Object);
+
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let future = g.node(NodeType::Unknown(42), "Future", 8);
+        g.edge(root, EdgeType::Unknown(9), "link", future);
+        let graph = g.build();
+
+        let node = graph.node(future);
+        assert_eq!(node.typ(), NodeType::Unknown(42));
+        assert_eq!(node.typ_str(), "type:42");
+        assert_eq!(
+            minimal_node_repr(future, &graph),
+            "type:42:Future  (depth 1)"
+        );
+
+        let edge = graph.out_edges(root).next().unwrap();
+        assert_eq!(edge.typ(), EdgeType::Unknown(9));
+        assert_eq!(edge.typ_str(), "type:9");
+        assert_eq!(
+            edge.name_or_index().to_string(),
+            format!("{}/link", edge.index())
+        );
+    }
+
+    #[test]
+    fn test_properties() {
+        let mut g = TestGraph::new();
+        let obj = g.node(NodeType::Object, "User", 32);
+        let name = g.node(NodeType::String, "alice", 16);
+        let password = g.node(NodeType::String, "hunter2", 16);
+        let map = g.node(NodeType::Hidden, "system / Map", 40);
+        g.edge(obj, EdgeType::Property, "name", name)
+            .edge(obj, EdgeType::Internal, "map", map)
+            .element(obj, 0, name)
+            .edge(obj, EdgeType::Property, "password", password);
+        let graph = g.build();
+
+        let props: Vec<_> = graph
+            .properties(obj)
+            .map(|(name, to)| (name.to_string(), to))
+            .collect();
+        assert_eq!(
+            props,
+            vec![
+                ("name".to_string(), name),
+                ("password".to_string(), password)
+            ]
+        );
+
+        assert_eq!(graph.get_property(obj, "password"), Some(password));
+        assert_eq!(graph.get_property(obj, "map"), None);
+        assert_eq!(graph.get_property(obj, "email"), None);
+    }
+
+    #[test]
+    fn test_filtered_edges() {
+        let mut g = TestGraph::new();
+        let obj = g.node(NodeType::Object, "Holder", 32);
+        let value = g.node(NodeType::String, "value", 16);
+        let map = g.node(NodeType::Hidden, "system / Map", 40);
+        let other = g.node(NodeType::Object, "Other", 16);
+        g.edge(obj, EdgeType::Property, "a", value)
+            .edge(obj, EdgeType::Internal, "map", map)
+            .edge(obj, EdgeType::Weak, "w", value)
+            .edge(obj, EdgeType::Property, "b", other)
+            .edge(other, EdgeType::Property, "v", value);
+        let graph = g.build();
+
+        let names = |edges: Vec<Edge<'_>>| {
+            edges
+                .iter()
+                .map(|e| e.name_or_index().to_string())
+                .collect::<Vec<_>>()
+        };
+        let is_property = |e: &Edge<'_>| e.typ() == EdgeType::Property;
+
+        assert_eq!(
+            names(graph.out_edges_filtered(obj, is_property).collect()),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            names(graph.out_edges_filtered(obj, is_strong).collect()),
+            vec!["a", "map", "b"]
+        );
+        assert_eq!(
+            names(graph.in_edges_filtered(value, is_property).collect()),
+            vec!["a", "v"]
+        );
+        assert_eq!(graph.in_edges_filtered(map, is_user_visible).count(), 0);
     }
 }
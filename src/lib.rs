@@ -1 +1,14 @@
 #![allow(dead_code)]
+pub mod analysis;
+pub mod graph;
+pub mod paths;
+pub mod report;
+pub mod snapshot;
+pub mod types;
+pub mod utils;
+
+pub use graph::v8_heap_graph::V8HeapGraph;
+
+pub use analysis::all_paths::{
+    MAX_PATHS_PER_NODE, RootPath, RootPaths, filter_subset_paths, find_root_paths, root_distances,
+};
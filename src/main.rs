@@ -1,46 +1,215 @@
-#![allow(dead_code)]
-mod analysis;
-mod graph;
-mod paths;
-mod report;
-mod snapshot;
-mod types;
-mod utils;
-
-use anyhow::Result;
-use clap::Parser;
-use std::path::PathBuf;
-
-use crate::analysis::all_paths::find_root_paths;
-use crate::analysis::dominator_tree::tree_from_immediate_dominators;
+use anyhow::{Context, Result};
+use clap::{CommandFactory, Parser, Subcommand};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use v8_heap_analyzer::analysis::all_paths::{
+    DEFAULT_MAX_PATH_DEPTH, PathEdges, find_root_paths_until, find_root_paths_with,
+    reachable_from_root, root_distances,
+};
+use v8_heap_analyzer::analysis::cache::{AnalysisCache, CacheKey, read_cache, write_cache};
+use v8_heap_analyzer::analysis::composition::{
+    heap_composition, string_summary, unreachable_composition,
+};
+use v8_heap_analyzer::analysis::contexts::find_contexts;
+use v8_heap_analyzer::analysis::cycles::find_retention_cycles;
+use v8_heap_analyzer::analysis::detached::find_detached_dom_trees;
+use v8_heap_analyzer::analysis::dominator_tree::{
+    nodes_by_retained_ratio, retained_size_of, tree_from_immediate_dominators,
+};
+use v8_heap_analyzer::analysis::duplicate_diff::diff_duplicates;
+use v8_heap_analyzer::analysis::duplicates::{
+    DuplicateAnalyzer, DuplicateOptions, largest_unique_retainers, rollup_by_constructor,
+    sort_by_root_distance,
+};
+use v8_heap_analyzer::analysis::explain::explain_node_until;
+use v8_heap_analyzer::analysis::focus::focus_on_constructor;
+use v8_heap_analyzer::analysis::results::AnalysisResults;
+use v8_heap_analyzer::analysis::sample::analyze_sample;
+use v8_heap_analyzer::analysis::sanity::{check_graph, check_snapshot};
+use v8_heap_analyzer::analysis::shapes::cluster_by_shape;
+use v8_heap_analyzer::analysis::sparse_arrays::find_sparse_arrays;
+use v8_heap_analyzer::analysis::stats::graph_stats;
+use v8_heap_analyzer::analysis::suspects::leak_suspects;
+use v8_heap_analyzer::analysis::tags::{TagRule, rollup_by_tag, tag_groups};
 // Import the shared analysis functions
-use crate::graph::v8_heap_graph::V8HeapGraph;
-use crate::report::{explore_graph, print_dominator_tree, print_graph};
-use crate::snapshot::read_v8_snapshot_file;
-use crate::types::NodeId;
-use crate::utils::format_bytes;
-use crate::utils::start_timer;
+use v8_heap_analyzer::graph::gexf::write_gexf;
+use v8_heap_analyzer::graph::gml::write_gml;
+use v8_heap_analyzer::graph::json_graph::{
+    DEFAULT_JSON_GRAPH_NODES, bfs_subgraph, write_json_graph_file,
+};
+use v8_heap_analyzer::graph::lengauer_tarjan::lengauer_tarjan_until;
+use v8_heap_analyzer::graph::lengauer_tarjan_traits::{SeededRoots, WithoutEdgesFrom};
+use v8_heap_analyzer::graph::v8_heap_graph::{SnapshotSource, V8HeapGraph, is_noise_node};
+use v8_heap_analyzer::report::dot::{DEFAULT_DOT_NODES, write_top_retainers_dot};
+use v8_heap_analyzer::report::folded::write_type_folded;
+use v8_heap_analyzer::report::json::JsonOptions;
+use v8_heap_analyzer::report::{
+    OutputFormat, PathFormat, explore_graph, print_constructor_rollup, print_contexts,
+    print_detached_dom, print_dominator_tree, print_duplicate_growth, print_duplicates,
+    print_explanation, print_focus, print_footer, print_graph, print_heap_composition,
+    print_inspect, print_large_retainers, print_largest_unique_retainers, print_leak_suspects,
+    print_retained_ratios, print_retention_cycles, print_sampled_report, print_sanity_report,
+    print_shape_clusters, print_sparse_arrays, print_string_summary, print_tag_rollup,
+    print_unreachable,
+};
+use v8_heap_analyzer::snapshot::compat::version_info;
+use v8_heap_analyzer::snapshot::recover::recover_v8_snapshot;
+use v8_heap_analyzer::snapshot::{
+    SnapshotFile, read_v8_snapshot_file_at, read_v8_snapshots_from_reader, select_snapshot,
+};
+use v8_heap_analyzer::types::NodeId;
+use v8_heap_analyzer::utils::{Deadline, TIMEOUT_EXIT_CODE, TimedOut, Timings, start_timer};
+use v8_heap_analyzer::utils::{format_bytes, format_modified, parse_size};
 
 #[derive(Parser)]
 #[command(name = "v8-heap-analyzer")]
+#[command(version, long_version = version_info())]
 #[command(about = "Analyze V8 heap snapshots for duplicates and memory issues")]
 struct Cli {
-    /// Input heap snapshot file
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input heap snapshot file, or `-` to read it from stdin
     #[arg(short, long)]
     input: PathBuf,
 
-    /// Output report file (defaults to stdout)
+    /// Which snapshot to analyze if the input contains several (defaults to the last one)
+    #[arg(long)]
+    snapshot_index: Option<usize>,
+
+    /// Analyze as much as can be read of a snapshot that was cut off, e.g. by a crash
+    #[arg(long, default_value = "false", conflicts_with = "snapshot_index")]
+    recover: bool,
+
+    /// Save the parsed graph to this file, and read it from there instead of parsing the
+    /// snapshot again as long as it was saved for the same, unchanged snapshot file
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["snapshot_index", "recover"])]
+    graph_cache: Option<PathBuf>,
+
+    /// Output report file (defaults to stdout). Its extension picks the format, unless
+    /// `--format` is given. A text report written to a file is the summary that `--output-dir`
+    /// writes as `report.txt`.
     #[arg(short, long)]
     output: Option<PathBuf>,
 
-    /// Output format (text or json)
-    #[arg(short, long, default_value = "text")]
-    format: String,
+    /// Also write `report.txt`, `report.json` and `top-retainers.dot` to this directory
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Write the dominator tree as folded stacks of node types to this file, for
+    /// `inferno-flamegraph`
+    #[arg(long, value_name = "FILE")]
+    type_flamegraph: Option<PathBuf>,
+
+    /// Write the graph around the root as plain JSON nodes and edges to this file, for d3 or
+    /// cytoscape
+    #[arg(long, value_name = "FILE")]
+    json_graph: Option<PathBuf>,
+
+    /// How many nodes `--json-graph` writes, found breadth-first from the root
+    #[arg(long, default_value_t = DEFAULT_JSON_GRAPH_NODES)]
+    json_graph_nodes: usize,
+
+    /// Output format (defaults to the extension of `--output`, or text). `dot`, `gexf` and
+    /// `gml` write the graph instead of a report
+    #[arg(short, long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Indent the JSON report; `--json-pretty=false` writes it on a single line
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    json_pretty: bool,
+
+    /// Leave the member lists out of the duplicate groups in the JSON report
+    #[arg(long, default_value = "false")]
+    omit_node_ids: bool,
+
+    /// List the stable ids (`@123` in DevTools) of every member of the duplicate groups in
+    /// the JSON report, not just of the representative
+    #[arg(long, default_value = "false")]
+    stable_ids: bool,
+
+    /// Write the same JSON report every time for the same snapshot, for comparing against a
+    /// golden file: the generation time and timings are 0, and the file's modification time
+    /// is left out
+    #[arg(long, default_value = "false")]
+    reproducible: bool,
+
+    /// Analyze retention relative to the node with this id, instead of the snapshot root
+    #[arg(long)]
+    root: Option<NodeId>,
+
+    /// Analyze retention within the V8 context with this number (see `--contexts`), instead
+    /// of the whole snapshot
+    #[arg(long, value_name = "N", conflicts_with = "root")]
+    context: Option<usize>,
+
+    /// Print the V8 contexts in the snapshot (main thread, workers, iframes, `vm` contexts)
+    #[arg(long, default_value = "false")]
+    contexts: bool,
+
+    /// Save dominators to this file, or reuse them if it was written for the same snapshot
+    #[arg(long)]
+    cache: Option<PathBuf>,
+
+    /// Syntax for retention paths
+    #[arg(long, value_enum, default_value_t = PathFormat::Compact)]
+    path_format: PathFormat,
+
+    /// Don't look for retention paths longer than this
+    #[arg(long, default_value_t = DEFAULT_MAX_PATH_DEPTH)]
+    max_path_depth: u32,
+
+    /// Which edges retention paths go through. `properties` shows the `a.b.c` chain where
+    /// there is one, even if a path through V8 internals is shorter
+    #[arg(long, value_enum, default_value_t = PathEdges::All)]
+    path_edges: PathEdges,
+
+    /// Count the size of native objects (like `ArrayBuffer` contents) in retained sizes and
+    /// the heap composition (the default); `--include-native=false` leaves them out
+    #[arg(
+        long,
+        default_value_t = true,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set
+    )]
+    include_native: bool,
 
     /// Include hidden classes in duplicate detection
     #[arg(long, default_value = "false")]
     include_hidden_classes: bool,
 
+    /// Show duplicate strings in full, instead of truncating them
+    #[arg(long, default_value = "false")]
+    full_samples: bool,
+
+    /// Only report duplicate groups with at least this many objects
+    #[arg(long, default_value_t = 2)]
+    min_count: usize,
+
+    /// Only report duplicate groups that waste at least this much memory (e.g. `1M`)
+    #[arg(long, value_parser = parse_size, default_value = "0")]
+    min_wasted: usize,
+
+    /// Only keep the N duplicate groups that waste the most memory. Finding them takes far
+    /// less memory on a heap with many small groups, but the rollups, the JSON report and the
+    /// explorer then only cover those groups
+    #[arg(long, value_name = "N")]
+    max_groups: Option<usize>,
+
+    /// Leave V8 internals (code, hidden classes, `system / ...` and `(...)` objects) out of
+    /// the duplicates and the top retainers
+    #[arg(long, default_value = "false")]
+    ignore_system: bool,
+
+    /// Sort duplicates by how close they are to a GC root, instead of by wasted memory
+    #[arg(long, default_value = "false")]
+    sort_by_distance: bool,
+
     /// Print the graph
     #[arg(short, long, default_value = "false")]
     print: bool,
@@ -49,52 +218,639 @@ struct Cli {
     #[arg(short, long, default_value = "false")]
     tree: bool,
 
+    /// Print duplicate objects
+    #[arg(short, long, default_value = "false")]
+    duplicates: bool,
+
+    /// Tag duplicate groups whose type or sample value matches a regex, and add up the waste
+    /// per tag (e.g. `--tag react='^React'`). Can be repeated; the first matching rule wins.
+    #[arg(long, value_name = "NAME=REGEX", value_parser = TagRule::parse)]
+    tag: Vec<TagRule>,
+
+    /// Print all objects that retain at least this much memory (e.g. `10M`)
+    #[arg(long, value_parser = parse_size)]
+    min_retained: Option<usize>,
+
+    /// Print the objects that retain the most relative to their own size, among those that
+    /// retain at least this much memory (e.g. `1M`)
+    #[arg(long, value_parser = parse_size)]
+    retained_ratio: Option<usize>,
+
+    /// Print statistics and the largest instances of the objects with this constructor
+    #[arg(long)]
+    focus: Option<String>,
+
+    /// Print how much memory goes to each kind of string, and how much of it is duplicated
+    #[arg(long, default_value = "false")]
+    strings: bool,
+
+    /// Print the most common object shapes (sets of property names), to find objects that
+    /// are structurally the same but hold different values
+    #[arg(long, default_value = "false")]
+    shapes: bool,
+
+    /// Print arrays whose highest index is far beyond the number of elements they hold
+    #[arg(long, default_value = "false")]
+    sparse_arrays: bool,
+
+    /// Print groups of objects that retain each other
+    #[arg(short, long, default_value = "false")]
+    cycles: bool,
+
+    /// Print objects that are not reachable from the root
+    #[arg(short, long, default_value = "false")]
+    unreachable: bool,
+
+    /// Rank the objects that are most likely to be leaks, and say why
+    #[arg(long, default_value = "false")]
+    suspects: bool,
+
+    /// Only analyze the N largest nodes, for a quick approximate preview of a huge snapshot
+    ///
+    /// Skips the dominator tree; only the heap composition and duplicates are reported.
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["root", "context", "prune", "roots_from_type"]
+    )]
+    limit_nodes: Option<usize>,
+
+    /// Only print statistics about the structure of the graph (degrees, components, diameter)
+    /// as JSON
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with_all = ["limit_nodes", "root", "context", "prune", "roots_from_type"]
+    )]
+    stats: bool,
+
     /// Explore the dominator tree interactively
     #[arg(short, long, default_value = "false")]
     explore: bool,
+
+    /// Give up if the analysis takes longer than this many seconds, keeping the output so far
+    /// and exiting with code 124. The explorer is not limited.
+    #[arg(long, value_name = "SECS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Leave what the node with this id retains out of the dominator tree, so that one big,
+    /// well-understood subtree doesn't hide everything else. Can be repeated
+    #[arg(long, value_name = "ID")]
+    prune: Vec<NodeId>,
+
+    /// Compute dominators as if every object with this constructor name were a GC root, and
+    /// nothing else was, to see what those objects keep alive between them
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["root", "context", "prune"])]
+    roots_from_type: Option<String>,
+
+    /// A name for this snapshot to show at the top of the report, for telling many apart
+    #[arg(long)]
+    label: Option<String>,
+}
+
+impl Cli {
+    /// Reject the combinations clap can't express: options that replace the whole analysis
+    /// don't run subcommands either, and subcommands work on the full graph, so using both
+    /// would silently ignore one of them
+    fn validate(&self) -> Result<(), clap::Error> {
+        let format = self.output_format();
+        if format.is_graph() {
+            let modes = [
+                ("--limit-nodes", self.limit_nodes.is_some()),
+                ("--stats", self.stats),
+                ("a subcommand", self.command.is_some()),
+            ];
+            if let Some((mode, _)) = modes.into_iter().find(|&(_, used)| used) {
+                return Err(Self::command().error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    format!("the {:?} format can't be used with {}", format, mode),
+                ));
+            }
+        }
+
+        let Some(command) = &self.command else {
+            return Ok(());
+        };
+        let name = match command {
+            Command::Inspect { .. } => "inspect",
+            Command::Explain { .. } => "explain",
+            Command::CompareDuplicates { .. } => "compare-duplicates",
+        };
+        let modes = [
+            ("--limit-nodes", self.limit_nodes.is_some()),
+            ("--stats", self.stats),
+            ("--prune", !self.prune.is_empty()),
+            ("--roots-from-type", self.roots_from_type.is_some()),
+        ];
+        match modes.into_iter().find(|&(_, used)| used) {
+            Some((flag, _)) => Err(Self::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("{} can't be used with the {} subcommand", flag, name),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The options asking for sections of the printed report that a text report written to
+    /// `--output` doesn't have
+    fn sections_left_out_of_file(&self) -> Vec<&'static str> {
+        let sections = [
+            ("--suspects", self.suspects),
+            ("--contexts", self.contexts),
+            ("--print", self.print),
+            ("--tree", self.tree),
+            ("--min-retained", self.min_retained.is_some()),
+            ("--retained-ratio", self.retained_ratio.is_some()),
+            ("--duplicates", self.duplicates),
+            ("--focus", self.focus.is_some()),
+            ("--strings", self.strings),
+            ("--shapes", self.shapes),
+            ("--sparse-arrays", self.sparse_arrays),
+            ("--cycles", self.cycles),
+            ("--unreachable", self.unreachable),
+            ("--explore", self.explore),
+        ];
+        sections
+            .into_iter()
+            .filter(|&(_, used)| used)
+            .map(|(flag, _)| flag)
+            .collect()
+    }
+
+    /// `--format` if given, otherwise whatever the `--output` file name suggests
+    fn output_format(&self) -> OutputFormat {
+        self.format
+            .or_else(|| self.output.as_deref().and_then(OutputFormat::from_path))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a single node in detail, the same way the explorer's inspector shows it
+    Inspect {
+        /// Stable id of the node (the `@id` shown in Chrome DevTools)
+        #[arg(short, long)]
+        node: NodeId,
+    },
+    /// Explain why a single node is still in memory
+    Explain {
+        /// Stable id of the node (the `@id` shown in Chrome DevTools)
+        #[arg(short, long)]
+        node: NodeId,
+    },
+    /// Report the duplicate groups that grew since an earlier snapshot of the same program
+    CompareDuplicates {
+        /// The earlier snapshot; the input is the later one
+        #[arg(short, long)]
+        before: PathBuf,
+    },
 }
 
 fn main() -> Result<()> {
+    match run() {
+        Err(e) if e.is::<TimedOut>() => {
+            // Keep what was printed so far, but tell CI apart from any other failure
+            let _ = std::io::stdout().flush();
+            eprintln!();
+            eprintln!("Error: {}", e);
+            std::process::exit(TIMEOUT_EXIT_CODE);
+        }
+        result => result,
+    }
+}
+
+fn run() -> Result<()> {
     let args = Cli::parse();
+    args.validate().unwrap_or_else(|e| e.exit());
+    if let (None, Some(path)) = (args.format, &args.output)
+        && path.extension().is_some()
+        && OutputFormat::from_path(path).is_none()
+    {
+        eprintln!(
+            "Warning: no format for the extension of {}, writing a text report",
+            path.display()
+        );
+    }
+    // Statistics are always JSON, so keep the text header out of them
+    let text = args.output_format() == OutputFormat::Text && !args.stats;
 
-    println!("V8 Heap Analyzer v0.1.0");
-    println!();
+    if text {
+        println!("V8 Heap Analyzer v{}", env!("CARGO_PKG_VERSION"));
+        println!();
+        if let Some(label) = &args.label {
+            println!("Label:        {}", label);
+        }
+        if let Some(modified) = modified_time(&args.input) {
+            println!("Modified:     {}", format_modified(modified));
+        }
+    }
+
+    let deadline = args.timeout.map_or_else(Deadline::none, Deadline::after);
+    let started = Instant::now();
+    let mut timings = Timings::default();
 
     // Full serde
-    let _t = start_timer(format!("Loading {}", args.input.display()));
-    let snap = read_v8_snapshot_file(&args.input)?;
-    let graph = V8HeapGraph::from(snap);
-    std::mem::drop(_t);
+    let graph_source = match &args.graph_cache {
+        Some(_) if is_stdin(&args.input) => {
+            anyhow::bail!("--graph-cache needs a snapshot file, not stdin")
+        }
+        Some(_) => Some(SnapshotSource::of(&args.input)?),
+        None => None,
+    };
+    let cached_graph = match (&args.graph_cache, &graph_source) {
+        (Some(path), Some(source)) if path.exists() => {
+            let _t = start_timer(format!("Loading {}", path.display()));
+            let loaded = V8HeapGraph::load_saved(path, source);
+            timings.parse_ms = _t.finish_ms();
+            usable_graph_cache(path, loaded)
+        }
+        _ => None,
+    };
+    let (mut graph, mut sanity) = if let Some(graph) = cached_graph {
+        let sanity = check_graph(&graph);
+        (graph, sanity)
+    } else {
+        let _t = start_timer(format!("Loading {}", args.input.display()));
+        let snap = if args.recover {
+            recover_snapshot(&args.input)?
+        } else {
+            load_snapshot(&args.input, args.snapshot_index)?
+        };
+        let sanity = check_snapshot(&snap);
+        let graph = V8HeapGraph::from_snapshot(snap)?;
+        timings.parse_ms = _t.finish_ms();
+        if let (Some(path), Some(source)) = (&args.graph_cache, &graph_source) {
+            let _t = start_timer(format!("Writing {}", path.display()));
+            let saved = graph.save(path, source);
+            drop(_t);
+            // The analysis doesn't need the cache, so this isn't worth failing over
+            if let Err(e) = saved {
+                eprintln!("Warning: {:#}", e);
+            }
+        }
+        (graph, sanity)
+    };
+    graph.set_include_native(args.include_native);
+    // Parsing can't be interrupted, but there's no point in starting on the analysis
+    deadline.phase("parsing").check()?;
 
-    println!("Nodes:       {}", graph.total_node_count());
-    println!("Edges:       {}", graph.total_edge_count());
-    println!("Memory used: {}", format_bytes(graph.mem_size()));
+    if text {
+        println!("Fingerprint:  {:016x}", graph.fingerprint());
+        println!("Nodes:        {}", graph.total_node_count());
+        println!("Edges:        {}", graph.total_edge_count());
+        println!("Extra native: {}", format_bytes(graph.extra_native_bytes()));
+        println!("Memory used:  {}", format_bytes(graph.mem_size()));
+    }
 
-    let root: NodeId = 0;
-    let _t = start_timer("Calculating dominators".into());
-    let lt = graph::lengauer_tarjan::lengauer_tarjan(&graph, &[root]);
-    std::mem::drop(_t);
+    // Only needed to pick the root or to list them
+    let contexts = if args.context.is_some() || args.contexts {
+        find_contexts(&graph)
+    } else {
+        vec![]
+    };
+    let root: NodeId = match (args.root, args.context) {
+        (Some(stable_id), _) => graph
+            .find_node_by_stable_id(stable_id)
+            .with_context(|| format!("No node with id @{}", stable_id))?,
+        (None, Some(n)) => {
+            contexts
+                .get(n)
+                .with_context(|| format!("No context {}, there are {}", n, contexts.len()))?
+                .native_context
+        }
+        (None, None) => 0,
+    };
+    let pruned = args
+        .prune
+        .iter()
+        .map(|&stable_id| {
+            graph
+                .find_node_by_stable_id(stable_id)
+                .with_context(|| format!("No node with id @{} to prune", stable_id))
+        })
+        .collect::<Result<Vec<NodeId>>>()?;
+    let seeds: Vec<NodeId> = match &args.roots_from_type {
+        Some(constructor) => {
+            let seeds: Vec<NodeId> = graph
+                .iter_nodes()
+                .filter(|&n| graph.node(n).constructor_name() == constructor.as_str())
+                .collect();
+            if seeds.is_empty() {
+                anyhow::bail!(
+                    "No objects with constructor {} to use as roots",
+                    constructor
+                );
+            }
+            seeds
+        }
+        None => vec![],
+    };
 
-    let _t = start_timer("Converting dominators to tree".into());
-    let tree = tree_from_immediate_dominators(lt, &graph);
-    std::mem::drop(_t);
+    if let Some(limit) = args.limit_nodes {
+        return sample(&graph, limit, &args);
+    }
+
+    if args.stats {
+        return stats(&graph, &args);
+    }
+
+    if matches!(args.output_format(), OutputFormat::Gexf | OutputFormat::Gml) {
+        return export_graph(&graph, &args);
+    }
+
+    match args.command {
+        Some(Command::Inspect { node }) => return inspect(&graph, root, node, &args),
+        Some(Command::Explain { node }) => {
+            return explain(&graph, root, node, &args, deadline);
+        }
+        Some(Command::CompareDuplicates { ref before }) => {
+            return compare_duplicates(&graph, before, &args);
+        }
+        None => {}
+    }
 
+    if text {
+        println!();
+        print_heap_composition(&heap_composition(&graph));
+    }
+
+    let key = CacheKey::new(&graph, root)
+        .with_pruned(&pruned)
+        .with_seeds(&seeds);
+    let cached = match &args.cache {
+        Some(path) => read_cache(path, &key)?,
+        None => None,
+    };
+    let AnalysisCache {
+        tree, reachable, ..
+    } = match cached {
+        Some(cache) => {
+            eprintln!("Using cached dominators");
+            cache
+        }
+        None => {
+            let _t = start_timer("Calculating dominators".into());
+            let lt = if !seeds.is_empty() {
+                let view = SeededRoots::new(&graph, &seeds);
+                lengauer_tarjan_until(&view, &[root], deadline.phase("dominators"))?
+            } else if pruned.is_empty() {
+                lengauer_tarjan_until(&graph, &[root], deadline.phase("dominators"))?
+            } else {
+                let view = WithoutEdgesFrom {
+                    graph: &graph,
+                    owners: &pruned,
+                };
+                lengauer_tarjan_until(&view, &[root], deadline.phase("dominators"))?
+            };
+            timings.dominators_ms += _t.finish_ms();
+
+            let _t = start_timer("Converting dominators to tree".into());
+            let tree = tree_from_immediate_dominators(lt, root, &graph);
+            timings.dominators_ms += _t.finish_ms();
+
+            let _t = start_timer("Finding reachable objects".into());
+            let reachable = reachable_from_root(&graph);
+            timings.dominators_ms += _t.finish_ms();
+
+            let cache = AnalysisCache {
+                key,
+                tree,
+                reachable,
+            };
+            if let Some(path) = &args.cache {
+                let _t = start_timer(format!("Writing {}", path.display()));
+                write_cache(path, &cache)?;
+            }
+            cache
+        }
+    };
+
+    sanity.add_graph_stats(&graph, &reachable);
+    for warning in &sanity.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    // Duplicates and root paths are shared by several outputs, so compute them at most once
+    let groups = if args.duplicates || args.explore || args.output_dir.is_some() || !text {
+        let _t = start_timer("Finding duplicates".into());
+        let mut groups = DuplicateAnalyzer::new(&graph, duplicate_options(&args)).find_duplicates();
+        DuplicateAnalyzer::enrich_with_retained_sizes(&mut groups, &tree);
+        tag_groups(&mut groups, &args.tag);
+        if args.sort_by_distance {
+            sort_by_root_distance(&mut groups, &root_distances(&graph));
+        }
+        timings.duplicates_ms = _t.finish_ms();
+        groups
+    } else {
+        vec![]
+    };
+
+    deadline.phase("duplicates").check()?;
     let _t = start_timer("Finding root paths".into());
-    let root_paths = find_root_paths(&graph, root);
-    std::mem::drop(_t);
+    let root_paths = find_root_paths_until(
+        &graph,
+        root,
+        args.max_path_depth,
+        args.path_edges,
+        deadline.phase("root paths"),
+    )?;
+    timings.paths_ms = _t.finish_ms();
+    timings.total_ms = started.elapsed().as_millis() as u64;
+
+    let results = AnalysisResults {
+        graph: &graph,
+        tree,
+        root_paths,
+        duplicates: groups,
+        sanity: Some(sanity.clone()),
+        timings,
+        label: args.label.clone(),
+        modified: modified_time(&args.input),
+    };
+
+    if let Some(dir) = &args.output_dir {
+        let _t = start_timer(format!("Writing reports to {}", dir.display()));
+        write_output_dir(dir, &results, &args)?;
+    }
+
+    if let Some(path) = &args.type_flamegraph {
+        let _t = start_timer(format!("Writing {}", path.display()));
+        write_type_folded(BufWriter::new(File::create(path)?), &results.tree, &graph)?;
+    }
+
+    if let Some(path) = &args.json_graph {
+        let _t = start_timer(format!("Writing {}", path.display()));
+        let nodes = bfs_subgraph(&graph, root, args.json_graph_nodes);
+        write_json_graph_file(path, &graph, Some(&nodes))?;
+    }
+
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match args.output_format() {
+        OutputFormat::Json => {
+            return results.to_json_report_with(output, &json_options(&args));
+        }
+        OutputFormat::Dot => {
+            return write_top_retainers_dot(output, &results.tree, &graph, DEFAULT_DOT_NODES);
+        }
+        OutputFormat::Gexf | OutputFormat::Gml => unreachable!("exported right after loading"),
+        OutputFormat::Text if args.output.is_some() => {
+            let left_out = args.sections_left_out_of_file();
+            if !left_out.is_empty() {
+                eprintln!(
+                    "Warning: text reports written to a file only have the summary, leaving out {}",
+                    left_out.join(", ")
+                );
+            }
+            return results.to_text_report(&mut { output });
+        }
+        OutputFormat::Text => {}
+    }
+
+    let AnalysisResults {
+        tree,
+        duplicates: groups,
+        timings,
+        ..
+    } = &results;
+    let analyzed = results.analyzed();
+
+    println!();
+    print_sanity_report(&sanity);
+
+    if let Some(detached) = find_detached_dom_trees(&graph, tree) {
+        println!();
+        print_detached_dom(&detached, &graph);
+    }
+
+    if args.suspects {
+        let _t = start_timer("Ranking leak suspects".into());
+        let suspects = leak_suspects(analyzed);
+        std::mem::drop(_t);
+        println!();
+        print_leak_suspects(&suspects, analyzed);
+    }
+
+    if args.contexts {
+        println!();
+        print_contexts(&contexts, analyzed);
+    }
 
     if args.print {
         println!();
-        print_graph(&graph, &root_paths, &tree);
+        print_graph(analyzed, args.path_format);
     }
 
     if args.tree {
         println!();
-        print_dominator_tree(&tree, &graph);
+        print_dominator_tree(tree, &graph);
+    }
+
+    if let Some(min_retained) = args.min_retained {
+        println!();
+        print_large_retainers(
+            &without_noise(tree.nodes_retaining_at_least(min_retained), &graph, &args),
+            analyzed,
+        );
     }
 
+    if let Some(min_retained) = args.retained_ratio {
+        println!();
+        let mut ratios = nodes_by_retained_ratio(tree, &graph, min_retained);
+        if args.ignore_system {
+            ratios.retain(|&(n, _)| !is_noise_node(&graph.node(n)));
+        }
+        print_retained_ratios(&ratios, analyzed);
+    }
+
+    if args.duplicates {
+        println!();
+        print_duplicates(groups, &graph);
+        println!();
+        print_constructor_rollup(&rollup_by_constructor(groups));
+        if !args.tag.is_empty() {
+            println!();
+            print_tag_rollup(&rollup_by_tag(groups));
+        }
+        println!();
+        let limit = if args.ignore_system { usize::MAX } else { 10 };
+        let mut unique = without_noise(
+            largest_unique_retainers(groups, tree, &graph, limit),
+            &graph,
+            &args,
+        );
+        unique.truncate(10);
+        print_largest_unique_retainers(&unique, analyzed);
+    }
+
+    if let Some(constructor) = &args.focus {
+        let _t = start_timer(format!("Finding instances of {}", constructor));
+        let report = focus_on_constructor(constructor, tree, &graph);
+        std::mem::drop(_t);
+
+        println!();
+        print_focus(&report, analyzed);
+    }
+
+    if args.strings {
+        let _t = start_timer("Summarizing strings".into());
+        let summary = string_summary(&graph);
+        std::mem::drop(_t);
+
+        println!();
+        print_string_summary(&summary);
+    }
+
+    if args.shapes {
+        let _t = start_timer("Clustering objects by shape".into());
+        let clusters = cluster_by_shape(&graph);
+        std::mem::drop(_t);
+
+        println!();
+        print_shape_clusters(&clusters, &graph);
+    }
+
+    if args.sparse_arrays {
+        let _t = start_timer("Finding sparse arrays".into());
+        let sparse_arrays = find_sparse_arrays(&graph);
+        std::mem::drop(_t);
+
+        println!();
+        print_sparse_arrays(&sparse_arrays, &graph);
+    }
+
+    if args.cycles {
+        let _t = start_timer("Finding retention cycles".into());
+        let cycles = find_retention_cycles(&graph);
+        std::mem::drop(_t);
+
+        println!();
+        print_retention_cycles(&cycles, &graph);
+    }
+
+    if args.unreachable {
+        let _t = start_timer("Finding unreachable objects".into());
+        let stats = unreachable_composition(&graph, &reachable);
+        std::mem::drop(_t);
+
+        println!();
+        print_unreachable(&stats);
+    }
+
+    println!();
+    print_footer(timings, &graph);
+
     if args.explore {
-        explore_graph(&tree, &root_paths, &graph)?;
+        explore_graph(
+            analyzed,
+            args.path_format,
+            duplicate_options(&args),
+            groups,
+            &std::env::current_dir()?,
+        )?;
     }
 
     /*
@@ -122,3 +878,381 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid number of seconds: {:?}", s))
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// The graph read from the graph cache at `path`, if it can be used
+///
+/// Like the dominator cache, a graph cache that is stale or unreadable is ignored with a
+/// warning, so that the snapshot simply gets parsed again and the cache overwritten.
+fn usable_graph_cache(path: &Path, loaded: Result<Option<V8HeapGraph>>) -> Option<V8HeapGraph> {
+    match loaded {
+        Ok(Some(graph)) => Some(graph),
+        Ok(None) => {
+            eprintln!(
+                "Graph cache {} is for a different snapshot, ignoring",
+                path.display()
+            );
+            None
+        }
+        Err(e) => {
+            eprintln!(
+                "Graph cache {} is unreadable, ignoring ({:#})",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// When the file was last modified, if the platform knows
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    if is_stdin(path) {
+        return None;
+    }
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+/// Read a snapshot from a file, or from stdin if the path is `-`
+///
+/// Stdin is read in a single pass, so a snapshot can be piped straight from a DevTools
+/// protocol client without a temporary file.
+fn load_snapshot(path: &Path, index: Option<usize>) -> Result<SnapshotFile> {
+    if is_stdin(path) {
+        let stdin = std::io::stdin().lock();
+        return select_snapshot(read_v8_snapshots_from_reader(BufReader::new(stdin))?, index);
+    }
+    read_v8_snapshot_file_at(path, index)
+}
+
+/// Read a possibly truncated snapshot, warning about whatever couldn't be recovered
+fn recover_snapshot(path: &Path) -> Result<SnapshotFile> {
+    let mut bytes = vec![];
+    if is_stdin(path) {
+        std::io::stdin().lock().read_to_end(&mut bytes)?;
+    } else {
+        bytes = std::fs::read(path).context("Failed to open snapshot file")?;
+    }
+    let (snapshot, recovery) = recover_v8_snapshot(&bytes)?;
+    if recovery.truncated {
+        eprintln!("Warning: the snapshot is truncated; {}", recovery.summary());
+    }
+    Ok(snapshot)
+}
+
+fn json_options(args: &Cli) -> JsonOptions {
+    JsonOptions {
+        pretty: args.json_pretty,
+        node_ids: !args.omit_node_ids,
+        stable_ids: args.stable_ids,
+        reproducible: args.reproducible,
+    }
+}
+
+fn duplicate_options(args: &Cli) -> DuplicateOptions {
+    DuplicateOptions {
+        include_hidden_classes: args.include_hidden_classes,
+        sample_max_len: if args.full_samples { None } else { Some(100) },
+        min_count: args.min_count,
+        min_wasted: args.min_wasted as u64,
+        ignore_system: args.ignore_system,
+        max_groups: args.max_groups,
+    }
+}
+
+/// Drop V8 internals from a list of nodes to report, if the user asked for that
+fn without_noise(
+    nodes: impl IntoIterator<Item = NodeId>,
+    graph: &V8HeapGraph,
+    args: &Cli,
+) -> Vec<NodeId> {
+    nodes
+        .into_iter()
+        .filter(|&n| !args.ignore_system || !is_noise_node(&graph.node(n)))
+        .collect()
+}
+
+/// Write the text summary, the JSON report and a DOT of the largest retainers to `dir`
+fn write_output_dir(dir: &Path, results: &AnalysisResults, args: &Cli) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    results.to_text_report(&mut BufWriter::new(File::create(dir.join("report.txt"))?))?;
+    results.to_json_report_with(
+        BufWriter::new(File::create(dir.join("report.json"))?),
+        &json_options(args),
+    )?;
+    write_top_retainers_dot(
+        BufWriter::new(File::create(dir.join("top-retainers.dot"))?),
+        &results.tree,
+        results.graph,
+        DEFAULT_DOT_NODES,
+    )?;
+
+    Ok(())
+}
+
+/// Write the graph statistics as JSON, whatever the output format
+fn stats(graph: &V8HeapGraph, args: &Cli) -> Result<()> {
+    let _t = start_timer("Calculating graph statistics".into());
+    let stats = graph_stats(graph);
+    std::mem::drop(_t);
+
+    match &args.output {
+        Some(path) => serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &stats)?,
+        None => serde_json::to_writer_pretty(std::io::stdout().lock(), &stats)?,
+    }
+    Ok(())
+}
+
+/// Write the whole graph as GEXF or GML, which doesn't need any of the analysis
+fn export_graph(graph: &V8HeapGraph, args: &Cli) -> Result<()> {
+    let _t = start_timer("Exporting the graph".into());
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match args.output_format() {
+        OutputFormat::Gexf => write_gexf(&mut output, graph)?,
+        OutputFormat::Gml => write_gml(&mut output, graph)?,
+        format => unreachable!("{:?} is not a whole-graph format", format),
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Report on the largest nodes only, skipping everything that needs the dominator tree
+fn sample(graph: &V8HeapGraph, limit: usize, args: &Cli) -> Result<()> {
+    let _t = start_timer(format!("Analyzing the {} largest nodes", limit));
+    let report = analyze_sample(graph, limit, &duplicate_options(args));
+    std::mem::drop(_t);
+
+    if args.output_format() == OutputFormat::Text {
+        println!();
+        print_sampled_report(&report, graph);
+        return Ok(());
+    }
+
+    match &args.output {
+        Some(path) => serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &report)?,
+        None => serde_json::to_writer_pretty(std::io::stdout().lock(), &report)?,
+    }
+    Ok(())
+}
+
+/// Print a single node, only computing what is needed for that node
+fn inspect(graph: &V8HeapGraph, root: NodeId, stable_id: NodeId, args: &Cli) -> Result<()> {
+    let Some(node) = graph.find_node_by_stable_id(stable_id) else {
+        anyhow::bail!("No node with id @{}", stable_id);
+    };
+
+    let _t = start_timer("Calculating retained size".into());
+    let retained_size = retained_size_of(graph, root, node);
+    std::mem::drop(_t);
+
+    let _t = start_timer("Finding root paths".into());
+    let root_paths = find_root_paths_with(graph, root, args.max_path_depth, args.path_edges);
+    std::mem::drop(_t);
+
+    println!();
+    print_inspect(node, retained_size, &root_paths, graph, args.path_format);
+
+    Ok(())
+}
+
+/// Print why a single node is retained, only computing what is needed for that node
+fn explain(
+    graph: &V8HeapGraph,
+    root: NodeId,
+    stable_id: NodeId,
+    args: &Cli,
+    deadline: Deadline,
+) -> Result<()> {
+    let Some(node) = graph.find_node_by_stable_id(stable_id) else {
+        anyhow::bail!("No node with id @{}", stable_id);
+    };
+
+    let _t = start_timer("Explaining node".into());
+    let explanation = explain_node_until(
+        graph,
+        root,
+        node,
+        args.max_path_depth,
+        args.path_edges,
+        duplicate_options(args),
+        deadline,
+    )?;
+    std::mem::drop(_t);
+
+    println!();
+    print_explanation(&explanation, graph, args.path_format);
+
+    Ok(())
+}
+
+/// Find duplicates in both snapshots, and report the groups that grew
+fn compare_duplicates(graph: &V8HeapGraph, before: &Path, args: &Cli) -> Result<()> {
+    let _t = start_timer(format!("Loading {}", before.display()));
+    let mut before_graph = V8HeapGraph::from_snapshot(load_snapshot(before, None)?)?;
+    before_graph.set_include_native(args.include_native);
+    std::mem::drop(_t);
+
+    let _t = start_timer("Finding duplicates".into());
+    // Every earlier group, so that a group of the top ones isn't mistaken for a new one
+    let before_options = DuplicateOptions {
+        max_groups: None,
+        ..duplicate_options(args)
+    };
+    let before_groups = DuplicateAnalyzer::new(&before_graph, before_options).find_duplicates();
+    let after_groups = DuplicateAnalyzer::new(graph, duplicate_options(args)).find_duplicates();
+    std::mem::drop(_t);
+
+    let _t = start_timer("Comparing duplicates".into());
+    let grown = diff_duplicates(&before_graph, &before_groups, graph, &after_groups);
+    std::mem::drop(_t);
+
+    if args.output_format() == OutputFormat::Text {
+        println!();
+        print_duplicate_growth(&grown, graph);
+        return Ok(());
+    }
+
+    match &args.output {
+        Some(path) => serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &grown)?,
+        None => serde_json::to_writer_pretty(std::io::stdout().lock(), &grown)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+
+    /// Parse and validate the arguments as `main` does, after an input file
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(
+            ["v8-heap-analyzer", "-i", "heap.heapsnapshot"]
+                .iter()
+                .chain(args),
+        )
+        .and_then(|cli| cli.validate().map(|_| cli))
+    }
+
+    #[test]
+    fn test_limit_nodes_conflicts() {
+        assert!(parse(&["--limit-nodes", "100"]).is_ok());
+        assert!(parse(&["inspect", "--node", "5"]).is_ok());
+        assert!(parse(&["--limit-nodes", "100", "--prune", "5"]).is_err());
+        assert!(parse(&["--limit-nodes", "100", "--roots-from-type", "Foo"]).is_err());
+        assert!(parse(&["--limit-nodes", "100", "inspect", "--node", "5"]).is_err());
+    }
+
+    #[test]
+    fn test_stats_conflicts() {
+        assert!(parse(&["--stats"]).is_ok());
+        assert!(parse(&["--stats", "--limit-nodes", "100"]).is_err());
+        assert!(parse(&["--stats", "--prune", "5"]).is_err());
+        assert!(parse(&["--stats", "explain", "--node", "5"]).is_err());
+    }
+
+    #[test]
+    fn test_prune_conflicts_with_subcommands() {
+        assert!(parse(&["--prune", "5"]).is_ok());
+        assert!(parse(&["--prune", "5", "inspect", "--node", "7"]).is_err());
+        assert!(parse(&["--prune", "5", "explain", "--node", "7"]).is_err());
+    }
+
+    #[test]
+    fn test_roots_from_type_conflicts_with_subcommands() {
+        assert!(parse(&["--roots-from-type", "Foo"]).is_ok());
+        assert!(parse(&["--roots-from-type", "Foo", "inspect", "--node", "7"]).is_err());
+        assert!(parse(&["--roots-from-type", "Foo", "explain", "--node", "7"]).is_err());
+    }
+
+    #[test]
+    fn test_include_native_default() {
+        // Same as `V8HeapGraph`, so reports don't change unless asked to
+        assert!(parse(&[]).unwrap().include_native);
+        assert!(!parse(&["--include-native=false"]).unwrap().include_native);
+        assert!(parse(&["--include-native"]).unwrap().include_native);
+        assert!(
+            parse(&["--include-native", "inspect", "--node", "5"])
+                .unwrap()
+                .include_native
+        );
+    }
+
+    #[test]
+    fn test_output_format_from_extension() {
+        let format = |args: &[&str]| {
+            let cli = Cli::parse_from(
+                ["v8-heap-analyzer", "-i", "heap.heapsnapshot"]
+                    .iter()
+                    .chain(args),
+            );
+            cli.output_format()
+        };
+
+        assert_eq!(format(&[]), OutputFormat::Text);
+        assert_eq!(format(&["-o", "report.json"]), OutputFormat::Json);
+        assert_eq!(format(&["-o", "REPORT.JSON"]), OutputFormat::Json);
+        assert_eq!(format(&["-o", "report.txt"]), OutputFormat::Text);
+        assert_eq!(format(&["-o", "report.dot"]), OutputFormat::Dot);
+        assert_eq!(format(&["-o", "report.gexf"]), OutputFormat::Gexf);
+        assert_eq!(format(&["-o", "report.gml"]), OutputFormat::Gml);
+        assert_eq!(format(&["-o", "report.csv"]), OutputFormat::Text);
+        assert_eq!(format(&["-o", "report"]), OutputFormat::Text);
+
+        // An explicit format always wins
+        assert_eq!(
+            format(&["-o", "report.json", "-f", "text"]),
+            OutputFormat::Text
+        );
+        assert_eq!(
+            format(&["-o", "report.txt", "-f", "json"]),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            format(&["-o", "report.dot", "-f", "json"]),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            format(&["-o", "report.gml", "-f", "gexf"]),
+            OutputFormat::Gexf
+        );
+    }
+
+    #[test]
+    fn test_sections_left_out_of_file() {
+        let left_out = |args: &[&str]| parse(args).unwrap().sections_left_out_of_file();
+        assert!(left_out(&["-o", "report.txt"]).is_empty());
+        assert_eq!(
+            left_out(&["-o", "report.txt", "--cycles", "--focus", "Foo"]),
+            vec!["--focus", "--cycles"]
+        );
+    }
+
+    #[test]
+    fn test_graph_format_conflicts() {
+        assert!(parse(&["-o", "graph.gexf"]).is_ok());
+        assert!(parse(&["-f", "dot", "--stats"]).is_err());
+        assert!(parse(&["-o", "graph.gml", "--limit-nodes", "100"]).is_err());
+        assert!(parse(&["-o", "graph.dot", "explain", "--node", "5"]).is_err());
+        // Subcommands still write JSON when asked to
+        assert!(parse(&["-f", "json", "explain", "--node", "5"]).is_ok());
+    }
+}
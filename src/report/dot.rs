@@ -0,0 +1,118 @@
+use std::{cmp::Reverse, collections::BinaryHeap, io::Write};
+
+use anyhow::Result;
+
+use crate::{
+    analysis::dominator_tree::DominatorTree,
+    graph::v8_heap_graph::V8HeapGraph,
+    types::NodeId,
+    utils::{escape_string, format_bytes},
+};
+
+use super::minimal_node_repr;
+
+/// How many nodes `top-retainers.dot` shows, not counting the root
+pub const DEFAULT_DOT_NODES: usize = 50;
+
+/// Node descriptions are cut off after this many characters, to keep the boxes readable
+const MAX_LABEL_LEN: usize = 60;
+
+/// Write the part of the dominator tree that retains the most memory as a Graphviz digraph
+///
+/// Starting from the root, the dominator tree is expanded one node at a time, always picking
+/// the largest retainer seen so far, until `limit` nodes have been added. The result is a
+/// connected subtree; every edge points from a node to one of the nodes it dominates.
+pub fn write_top_retainers_dot<W: Write>(
+    mut w: W,
+    tree: &DominatorTree,
+    graph: &V8HeapGraph,
+    limit: usize,
+) -> Result<()> {
+    writeln!(w, "digraph retainers {{")?;
+    writeln!(w, "  node [shape=box, fontname=monospace];")?;
+    if !tree.has_root() {
+        writeln!(w, "}}")?;
+        return Ok(());
+    }
+    write_dot_node(&mut w, tree.root, tree, graph)?;
+
+    let mut frontier: BinaryHeap<(usize, Reverse<NodeId>, NodeId)> = BinaryHeap::new();
+    let push_children = |frontier: &mut BinaryHeap<_>, parent: NodeId| {
+        for &child in tree.children.get(&parent).into_iter().flatten() {
+            frontier.push((tree.retained_size(child), Reverse(child), parent));
+        }
+    };
+    push_children(&mut frontier, tree.root);
+
+    let mut added = 0;
+    while added < limit
+        && let Some((_, Reverse(node), parent)) = frontier.pop()
+    {
+        write_dot_node(&mut w, node, tree, graph)?;
+        writeln!(w, "  n{} -> n{};", parent, node)?;
+        push_children(&mut frontier, node);
+        added += 1;
+    }
+
+    writeln!(w, "}}")?;
+    Ok(())
+}
+
+fn write_dot_node<W: Write>(
+    w: &mut W,
+    node: NodeId,
+    tree: &DominatorTree,
+    graph: &V8HeapGraph,
+) -> Result<()> {
+    let mut repr = minimal_node_repr(node, graph);
+    if let Some((cut, _)) = repr.char_indices().nth(MAX_LABEL_LEN) {
+        repr.truncate(cut);
+        repr.push_str("...");
+    }
+    let label = format!(
+        "@{} {}\nretains {}",
+        graph.node(node).stable_id(),
+        repr,
+        format_bytes(tree.retained_size(node)),
+    );
+    writeln!(w, "  n{} [label=\"{}\"];", node, escape_string(&label))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::dominator_tree::tree_from_immediate_dominators,
+        graph::{
+            lengauer_tarjan::lengauer_tarjan,
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+    };
+
+    #[test]
+    fn test_top_retainers_dot() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let big = g.node(NodeType::Object, "Big", 100);
+        let inner = g.node(NodeType::String, "inner \"quoted\"", 50);
+        let small = g.node(NodeType::Object, "Small", 10);
+        g.edge(root, EdgeType::Property, "big", big)
+            .edge(big, EdgeType::Property, "inner", inner)
+            .edge(root, EdgeType::Property, "small", small);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        let mut out = Vec::new();
+        write_top_retainers_dot(&mut out, &tree, &graph, 2).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+
+        assert!(dot.starts_with("digraph retainers {"));
+        assert!(dot.contains(&format!("n{} -> n{};", root, big)));
+        assert!(dot.contains(&format!("n{} -> n{};", big, inner)));
+        assert!(dot.contains(r#"\"inner \\\"quoted\\\"\""#));
+        // Only the two largest retainers are expanded
+        assert!(!dot.contains(&format!("-> n{};", small)));
+    }
+}
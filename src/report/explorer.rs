@@ -15,21 +15,28 @@ use ratatui::{
     text::{Line, Span},
     widgets::{Block, List, ListItem, Padding, Paragraph, Wrap},
 };
-use std::io;
-use std::{
-    collections::{HashMap, HashSet},
-    fmt::Write,
-};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write as _};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
-    analysis::{all_paths::RootPaths, dominator_tree::DominatorTree},
-    graph::v8_heap_graph::{NodeType, V8HeapGraph},
-    report::{detailed_node_repr, format_retention_paths, minimal_node_repr},
+    analysis::{
+        analyzed::AnalyzedGraph,
+        dominator_tree::DominatorTree,
+        duplicates::{DuplicateAnalyzer, DuplicateGroup, DuplicateOptions},
+    },
+    graph::v8_heap_graph::V8HeapGraph,
+    report::{PathFormat, detailed_node_repr, hidden_in_tree, inspector_text, minimal_node_repr},
     types::NodeId,
     utils::format_bytes,
 };
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 enum UiTreeId {
     /// This is an artificial group node
     Group(usize),
@@ -44,17 +51,42 @@ impl Default for UiTreeId {
     }
 }
 
+#[derive(Clone, Default)]
+enum UiChildren {
+    /// Children are built the first time the node is expanded
+    #[default]
+    Unloaded,
+    Loaded(Vec<UiTreeNode>),
+}
+
 #[derive(Clone, Default)]
 struct UiTreeNode {
     id: UiTreeId,
     label: String,
     retained_size: usize,
-    children: Vec<UiTreeNode>,
+    /// Whether the node has children, even if they haven't been loaded yet
+    expandable: bool,
+    children: UiChildren,
+}
+
+impl UiTreeNode {
+    fn loaded_children(&self) -> &[UiTreeNode] {
+        match &self.children {
+            UiChildren::Loaded(children) => children,
+            UiChildren::Unloaded => &[],
+        }
+    }
+}
+
+struct FlatUiTreeNode {
+    /// Child indexes leading from the root to this node
+    path: Vec<usize>,
 }
 
-struct FlatUiTreeNode<'a> {
-    node: &'a UiTreeNode,
-    depth: usize,
+impl FlatUiTreeNode {
+    fn depth(&self) -> usize {
+        self.path.len()
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
@@ -69,29 +101,97 @@ struct ExplorerState<'a> {
     pub inspector_scroll_offset: u16,
     pub height: usize,
     pub expanded: HashSet<UiTreeId>,
-    pub flat_list: Vec<FlatUiTreeNode<'a>>,
-    pub root: &'a UiTreeNode,
+    pub flat_list: Vec<FlatUiTreeNode>,
+    pub root: UiTreeNode,
     pub info_open: bool,
     pub focus: Focus,
+    /// Message shown in the footer until the next key press
+    pub status: Option<String>,
+    analyzed: AnalyzedGraph<'a>,
+    group_counter: usize,
+    duplicate_options: DuplicateOptions,
+    duplicate_groups: Vec<DuplicateGroup>,
+    /// Index into `duplicate_groups` for every node that has duplicates
+    duplicate_of: HashMap<NodeId, usize>,
 }
 
 impl<'a> ExplorerState<'a> {
-    pub fn new(root: &'a UiTreeNode) -> Self {
-        let mut expanded = HashSet::<UiTreeId>::new();
-        expanded.insert(UiTreeId::Heap(0)); // Root starts expanded
+    /// `duplicate_groups` are the groups found with `duplicate_options`
+    pub fn new(
+        analyzed: AnalyzedGraph<'a>,
+        duplicate_options: DuplicateOptions,
+        duplicate_groups: &[DuplicateGroup],
+    ) -> Self {
+        let root = heap_ui_node(analyzed.tree.root, analyzed.tree, analyzed.graph);
 
-        let flat_list = flatten_tree(root, &expanded);
+        let mut expanded = HashSet::<UiTreeId>::new();
+        expanded.insert(root.id); // Root starts expanded
 
-        ExplorerState {
+        let mut state = ExplorerState {
             selected: 0,
             tree_scroll_offset: 0,
             inspector_scroll_offset: 0,
             height: 0,
             expanded,
-            flat_list,
+            flat_list: vec![],
             root,
             info_open: false,
             focus: Focus::Tree,
+            status: None,
+            analyzed,
+            group_counter: 0,
+            duplicate_options,
+            duplicate_groups: vec![],
+            duplicate_of: HashMap::new(),
+        };
+        state.load_children(&[]);
+        state.update_flat_list();
+        state.set_duplicate_groups(duplicate_groups.to_vec());
+        state
+    }
+
+    /// Group duplicates again with the current options
+    ///
+    /// The graph and dominator tree don't depend on the options, so only the grouping is redone.
+    pub fn reanalyze_duplicates(&mut self) {
+        self.set_duplicate_groups(
+            DuplicateAnalyzer::new(self.analyzed.graph, self.duplicate_options.clone())
+                .find_duplicates(),
+        );
+    }
+
+    fn set_duplicate_groups(&mut self, groups: Vec<DuplicateGroup>) {
+        self.duplicate_groups = groups;
+        self.duplicate_of = self
+            .duplicate_groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, group)| group.node_ids.iter().map(move |&node| (node, i)))
+            .collect();
+    }
+
+    pub fn toggle_hidden_classes(&mut self) {
+        self.duplicate_options.include_hidden_classes =
+            !self.duplicate_options.include_hidden_classes;
+        self.reanalyze_duplicates();
+        self.status = Some(format!(
+            "Hidden classes {}: {} duplicate groups",
+            if self.duplicate_options.include_hidden_classes {
+                "included"
+            } else {
+                "ignored"
+            },
+            self.duplicate_groups.len()
+        ));
+    }
+
+    fn duplicate_group(&self, id: UiTreeId) -> Option<&DuplicateGroup> {
+        match id {
+            UiTreeId::Heap(node) => self
+                .duplicate_of
+                .get(&node)
+                .map(|&i| &self.duplicate_groups[i]),
+            UiTreeId::Group(_) => None,
         }
     }
 
@@ -108,8 +208,33 @@ impl<'a> ExplorerState<'a> {
         }
     }
 
+    /// Adapt to a new window height, keeping the selection on screen
+    pub fn resize(&mut self, height: usize) {
+        self.height = height;
+        self.clamp_scroll();
+    }
+
+    /// Bring `selected` and `tree_scroll_offset` back within the list and the window
+    ///
+    /// The window or the list may have shrunk since they were last set.
+    fn clamp_scroll(&mut self) {
+        self.selected = self.selected.min(self.flat_list.len().saturating_sub(1));
+        // Don't leave empty rows at the bottom if the list could fill them
+        let max_offset = self.flat_list.len().saturating_sub(self.height);
+        self.tree_scroll_offset = self.tree_scroll_offset.min(max_offset).min(self.selected);
+        if self.selected >= self.tree_scroll_offset + self.height {
+            self.tree_scroll_offset = (self.selected + 1).saturating_sub(self.height.max(1));
+        }
+    }
+
+    /// The rows of the flattened tree that fit in the window
+    pub fn visible_range(&self) -> Range<usize> {
+        let end = (self.tree_scroll_offset + self.height).min(self.flat_list.len());
+        self.tree_scroll_offset.min(end)..end
+    }
+
     pub fn selected_node(&self) -> &UiTreeNode {
-        self.flat_list[self.selected].node
+        self.node_at(&self.flat_list[self.selected].path)
     }
 
     pub fn move_selection(&mut self, delta: isize) {
@@ -122,19 +247,19 @@ impl<'a> ExplorerState<'a> {
 
     pub fn toggle_selected(&mut self) {
         let node_id = self.selected_id();
-        if !self.selected_node().children.is_empty() {
-            if self.expanded.contains(&node_id) {
-                self.expanded.remove(&node_id);
-            } else {
-                self.expanded.insert(node_id);
-            }
+        if self.expanded.contains(&node_id) {
+            self.expanded.remove(&node_id);
             self.update_flat_list();
+        } else {
+            self.expand_selected();
         }
     }
 
     pub fn expand_selected(&mut self) {
         let node_id = self.selected_id();
-        if !self.selected_node().children.is_empty() && !self.expanded.contains(&node_id) {
+        let path = self.flat_list[self.selected].path.clone();
+        self.load_children(&path);
+        if !self.node_at(&path).loaded_children().is_empty() && !self.expanded.contains(&node_id) {
             self.expanded.insert(node_id);
             self.update_flat_list();
         }
@@ -147,11 +272,11 @@ impl<'a> ExplorerState<'a> {
             self.update_flat_list();
         } else {
             // Find parent and collapse it
-            let current_depth = self.flat_list[self.selected].depth;
+            let current_depth = self.flat_list[self.selected].depth();
             if current_depth > 0 {
                 for i in (0..self.selected).rev() {
-                    if self.flat_list[i].depth < current_depth {
-                        let parent_id = self.flat_list[i].node.id;
+                    if self.flat_list[i].depth() < current_depth {
+                        let parent_id = self.node_at(&self.flat_list[i].path).id;
                         if self.expanded.contains(&parent_id) {
                             self.expanded.remove(&parent_id);
                             self.update_flat_list();
@@ -168,16 +293,90 @@ impl<'a> ExplorerState<'a> {
         self.selected_node().id
     }
 
+    fn node_at(&self, path: &[usize]) -> &UiTreeNode {
+        path.iter()
+            .fold(&self.root, |node, &i| &node.loaded_children()[i])
+    }
+
+    fn node_at_mut(&mut self, path: &[usize]) -> &mut UiTreeNode {
+        let mut node = &mut self.root;
+        for &i in path {
+            let UiChildren::Loaded(children) = &mut node.children else {
+                panic!("Path goes through a node whose children are not loaded");
+            };
+            node = &mut children[i];
+        }
+        node
+    }
+
+    /// Build the children of the node at the given path, if that hasn't happened yet
+    fn load_children(&mut self, path: &[usize]) {
+        let AnalyzedGraph { tree, graph, .. } = self.analyzed;
+        let mut group_counter = self.group_counter;
+
+        let node = self.node_at_mut(path);
+        if let (UiChildren::Unloaded, UiTreeId::Heap(node_id)) = (&node.children, node.id) {
+            let mut children = build_ui_children(node_id, tree, graph);
+            group_ui_children(&mut children, &mut group_counter);
+            node.children = UiChildren::Loaded(children);
+        }
+
+        self.group_counter = group_counter;
+    }
+
     fn update_flat_list(&mut self) {
-        self.flat_list = flatten_tree(self.root, &self.expanded);
+        self.flat_list = flatten_tree(&self.root, &self.expanded);
+    }
+
+    /// Write the subtree below the selected node to a new file in `dir`
+    fn export_selected(&self, dir: &Path) -> io::Result<PathBuf> {
+        let node = self.selected_node();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let name = match node.id {
+            UiTreeId::Heap(node_id) => {
+                format!("subtree-{}", self.analyzed.node(node_id).stable_id())
+            }
+            UiTreeId::Group(group) => format!("subtree-group-{}", group),
+        };
+
+        let (path, mut file) = create_new_file(dir, &format!("{}-{}", name, timestamp))?;
+        file.write_all(subtree_text(node, self.analyzed.tree, self.analyzed.graph).as_bytes())?;
+        Ok(path)
     }
 }
 
+/// Create `<stem>.txt` in `dir`, or `<stem>-1.txt`, `<stem>-2.txt` and so on if it exists
+///
+/// Exports are never overwritten, even if two of them are made within the same second.
+fn create_new_file(dir: &Path, stem: &str) -> io::Result<(PathBuf, File)> {
+    for attempt in 0.. {
+        let path = match attempt {
+            0 => dir.join(format!("{}.txt", stem)),
+            n => dir.join(format!("{}-{}.txt", stem, n)),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of file names")
+}
+
 pub fn explore_graph(
-    tree: &DominatorTree,
-    root_paths: &RootPaths,
-    graph: &V8HeapGraph,
+    analyzed: AnalyzedGraph<'_>,
+    path_format: PathFormat,
+    duplicate_options: DuplicateOptions,
+    duplicate_groups: &[DuplicateGroup],
+    export_dir: &Path,
 ) -> Result<()> {
+    anyhow::ensure!(
+        analyzed.tree.has_root(),
+        "Nothing to explore: the graph is empty"
+    );
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
@@ -185,13 +384,11 @@ pub fn explore_graph(
     let mut terminal = Terminal::new(backend)?;
 
     // Shared state between draw and poll
-    let mut root = build_ui_tree(0, tree, graph);
-    find_groups_in_ui_tree(&mut root);
-    let mut state = ExplorerState::new(&root);
+    let mut state = ExplorerState::new(analyzed, duplicate_options, duplicate_groups);
 
     loop {
-        draw(&mut terminal, &mut state, root_paths, graph)?;
-        let action = handle_input(&mut state)?;
+        draw(&mut terminal, &mut state, path_format)?;
+        let action = handle_input(&mut state, export_dir)?;
 
         if matches!(action, AppAction::Quit) {
             break;
@@ -206,14 +403,13 @@ pub fn explore_graph(
 fn draw<T: Backend>(
     terminal: &mut Terminal<T>,
     state: &mut ExplorerState,
-    root_paths: &RootPaths,
-    graph: &V8HeapGraph,
+    path_format: PathFormat,
 ) -> Result<()>
 where
     T::Error: Send + Sync + 'static,
 {
     terminal.draw(|frame: &mut Frame<'_>| {
-        state.height = frame.area().height.saturating_sub(5) as usize;
+        state.resize(frame.area().height.saturating_sub(5) as usize);
 
         let v_chunks = Layout::default()
             .constraints([Constraint::Min(0), Constraint::Length(3)])
@@ -234,32 +430,39 @@ where
             .split(v_chunks[0]);
 
         // We need to virtualize this tree, otherwise it's too big
-        let tree_slice =
-            (state.tree_scroll_offset)..(state.tree_scroll_offset + state.height).min(state.flat_list.len());
+        let tree_slice = state.visible_range();
 
         let items: Vec<ListItem> = state.flat_list[tree_slice]
             .iter()
-            .map(|node| {
-                let prefix = "  ".repeat(node.depth);
-                let expand_marker = match state.expanded.contains(&node.node.id) {
-                    _ if node.node.children.is_empty() => "  ",
+            .map(|flat| {
+                let node = state.node_at(&flat.path);
+                let prefix = "  ".repeat(flat.depth());
+                let expand_marker = match state.expanded.contains(&node.id) {
+                    _ if !node.expandable => "  ",
                     true => "▼ ",
                     false => "▶ ",
                 };
 
-                ListItem::new(Line::from(vec![
+                let mut spans = vec![
                     Span::raw(prefix),
                     Span::raw(expand_marker),
                     Span::styled(
-                        format!("{:>7}  ", format_bytes(node.node.retained_size)),
+                        format!("{:>7}  ", format_bytes(node.retained_size)),
                         Style::default().fg(Color::Yellow),
                     ),
-                    if matches!(node.node.id, UiTreeId::Heap(_)) {
-                        Span::raw(&node.node.label)
+                    if matches!(node.id, UiTreeId::Heap(_)) {
+                        Span::raw(&node.label)
                     } else {
-                        Span::styled(&node.node.label, Style::default().fg(Color::Green))
+                        Span::styled(&node.label, Style::default().fg(Color::Green))
                     },
-                ]))
+                ];
+                if let Some(group) = state.duplicate_group(node.id) {
+                    spans.push(Span::styled(
+                        format!("  (1 of {} duplicates)", group.count),
+                        Style::default().fg(Color::Magenta),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
             })
             .collect();
 
@@ -290,7 +493,7 @@ where
 
         if state.info_open {
             frame.render_widget(
-                render_inspector(state.selected_node(), root_paths, graph)
+                render_inspector(state.selected_node(), state.analyzed, path_format)
                 .scroll((0, state.inspector_scroll_offset))
                 .block(
                 {
@@ -308,9 +511,9 @@ where
         }
 
         frame.render_widget(
-            Paragraph::new(
-                "←/↓/↑/→ h/j/k/l: Navigate | Enter/Space: Toggle | i: Inspector | <Tab>: move focus | q: Quit",
-            )
+            Paragraph::new(state.status.as_deref().unwrap_or(
+                "←/↓/↑/→ h/j/k/l: Navigate | Enter/Space: Toggle | i: Inspector | e: Export | H: Hidden classes | <Tab>: move focus | q: Quit",
+            ))
             .block(
                 Block::bordered()
                     .merge_borders(MergeStrategy::Exact)
@@ -324,18 +527,19 @@ where
 
 fn render_inspector<'a>(
     ui_tree_node: &'a UiTreeNode,
-    root_paths: &'a RootPaths,
-    graph: &'a V8HeapGraph,
+    analyzed: AnalyzedGraph<'a>,
+    path_format: PathFormat,
 ) -> Paragraph<'a> {
     match &ui_tree_node.id {
         UiTreeId::Group(_) => Paragraph::new(ui_tree_node.label.clone()),
-        UiTreeId::Heap(node_id) => {
-            let mut s = detailed_node_repr(*node_id, graph);
-            let _ = write!(&mut s, "\n\nPath(s):\n");
-            let _ = format_retention_paths(&mut s, *node_id, root_paths, graph);
-
-            Paragraph::new(s).wrap(Wrap::default())
-        }
+        UiTreeId::Heap(node_id) => Paragraph::new(inspector_text(
+            *node_id,
+            analyzed.root_paths,
+            Some(analyzed.tree),
+            analyzed.graph,
+            path_format,
+        ))
+        .wrap(Wrap::default()),
     }
 }
 
@@ -344,11 +548,13 @@ enum AppAction {
     Continue,
 }
 
-fn handle_input(state: &mut ExplorerState) -> Result<AppAction> {
+fn handle_input(state: &mut ExplorerState, export_dir: &Path) -> Result<AppAction> {
     if event::poll(std::time::Duration::from_millis(1000))?
         && let Event::Key(key) = event::read()?
         && key.kind == KeyEventKind::Press
     {
+        state.status = None;
+
         if state.focus == Focus::Tree {
             match key.code {
                 KeyCode::Char('g') => state.move_selection(isize::MIN),
@@ -394,6 +600,13 @@ fn handle_input(state: &mut ExplorerState) -> Result<AppAction> {
 
         match key.code {
             KeyCode::Char('q') => return Ok(AppAction::Quit),
+            KeyCode::Char('e') => {
+                state.status = Some(match state.export_selected(export_dir) {
+                    Ok(path) => format!("Exported to {}", path.display()),
+                    Err(e) => format!("Export failed: {}", e),
+                });
+            }
+            KeyCode::Char('H') => state.toggle_hidden_classes(),
             KeyCode::Char('i') => {
                 state.info_open = !state.info_open;
                 if !state.info_open && state.focus == Focus::Inspector {
@@ -413,122 +626,331 @@ fn handle_input(state: &mut ExplorerState) -> Result<AppAction> {
     Ok(AppAction::Continue)
 }
 
-/// Build a UI tree from the given graph and node
-fn build_ui_tree(node_id: NodeId, tree: &DominatorTree, graph: &V8HeapGraph) -> UiTreeNode {
-    build_ui_tree_rec(node_id, tree, graph)
+/// The UI node for a heap node, with its children still unloaded
+fn heap_ui_node(node_id: NodeId, tree: &DominatorTree, graph: &V8HeapGraph) -> UiTreeNode {
+    UiTreeNode {
+        id: UiTreeId::Heap(node_id),
+        label: minimal_node_repr(node_id, graph),
+        retained_size: tree.retained_size(node_id),
+        expandable: visible_children(node_id, tree, graph).next().is_some(),
+        children: UiChildren::Unloaded,
+    }
 }
 
-fn build_ui_tree_rec(node_id: NodeId, tree: &DominatorTree, graph: &V8HeapGraph) -> UiTreeNode {
-    let node = graph.node(node_id);
-    let retained_size = tree.retained_size(node_id);
-    let label = minimal_node_repr(node.id, graph);
-
-    let mut children = if let Some(child_ids) = tree.children.get(&node_id) {
-        child_ids
-            .iter()
-            .filter(|&&n| {
-                !matches!(
-                    graph.node(n).typ(),
-                    NodeType::Hidden
-                        | NodeType::ObjectShape
-                        | NodeType::ConcatString
-                        | NodeType::SlicedString
-                        | NodeType::Code
-                        | NodeType::Array
-                )
-            })
-            .map(|&child| build_ui_tree_rec(child, tree, graph))
-            .collect()
-    } else {
-        vec![]
-    };
+/// Dominator tree children of a node, without the internal noise
+fn visible_children<'a>(
+    node_id: NodeId,
+    tree: &'a DominatorTree,
+    graph: &'a V8HeapGraph,
+) -> impl Iterator<Item = NodeId> + 'a {
+    tree.children
+        .get(&node_id)
+        .into_iter()
+        .flatten()
+        .copied()
+        .filter(|&n| !hidden_in_tree(graph.node(n).typ()))
+}
 
+/// Build one level of the UI tree below the given node
+fn build_ui_children(
+    node_id: NodeId,
+    tree: &DominatorTree,
+    graph: &V8HeapGraph,
+) -> Vec<UiTreeNode> {
+    let mut children = visible_children(node_id, tree, graph)
+        .map(|child| heap_ui_node(child, tree, graph))
+        .collect_vec();
     children.sort_by_key(|n| -(n.retained_size as isize));
-
-    UiTreeNode {
-        id: UiTreeId::Heap(node_id),
-        label,
-        retained_size,
-        children,
-    }
+    children
 }
 
-/// Find and insert groups into this tree
+/// Insert groups into a list of siblings
 ///
 /// We group nodes if they occur at the same level in the dominator tree and have the same minimal rendering (label)
-fn find_groups_in_ui_tree(tree: &mut UiTreeNode) {
-    let mut ctr = 0;
-    find_groups_in_ui_tree_rec(tree, &mut ctr);
-}
+fn group_ui_children(children: &mut Vec<UiTreeNode>, group_counter: &mut usize) {
+    let mut labels: HashMap<String, Vec<usize>> = Default::default();
+    for (i, child) in children.iter().enumerate() {
+        labels.entry(child.label.clone()).or_default().push(i);
+    }
 
-fn find_groups_in_ui_tree_rec(tree: &mut UiTreeNode, group_counter: &mut usize) {
-    // Only for heap nodes
-    if matches!(tree.id, UiTreeId::Heap(_)) {
-        let mut labels: HashMap<String, Vec<usize>> = Default::default();
-        for (i, child) in tree.children.iter().enumerate() {
-            labels.entry(child.label.clone()).or_default().push(i);
-        }
+    if labels.iter().any(|(_, ixes)| ixes.len() > 1) {
+        let mut old_children = std::mem::take(children);
 
-        if labels.iter().any(|(_, ixes)| ixes.len() > 1) {
-            let mut old_children = std::mem::take(&mut tree.children);
+        // We have duplicates. The easiest way to deal with this is to rebuild the entire "children" list for this tree node.
+        *children = labels
+            .into_values()
+            .map(|indexes| {
+                if indexes.len() == 1 {
+                    std::mem::take(&mut old_children[indexes[0]])
+                } else {
+                    let retained_size =
+                        indexes.iter().map(|&i| old_children[i].retained_size).sum();
+                    let members = indexes
+                        .iter()
+                        .map(|&i| std::mem::take(&mut old_children[i]))
+                        .collect_vec();
+
+                    let ret = UiTreeNode {
+                        id: UiTreeId::Group(*group_counter),
+                        label: format!(
+                            "<Group> {} instances of {}",
+                            indexes.len(),
+                            members[0].label
+                        ),
+                        retained_size,
+                        expandable: true,
+                        children: UiChildren::Loaded(members),
+                    };
+                    *group_counter += 1;
+                    ret
+                }
+            })
+            .collect_vec();
 
-            // We have duplicates. The easiest way to deal with this is to rebuild the entire "children" list for this tree node.
-            tree.children = labels
-                .into_values()
-                .map(|indexes| {
-                    if indexes.len() == 1 {
-                        std::mem::take(&mut old_children[indexes[0]])
-                    } else {
-                        let retained_size =
-                            indexes.iter().map(|&i| old_children[i].retained_size).sum();
-                        let children = indexes
-                            .iter()
-                            .map(|&i| std::mem::take(&mut old_children[i]))
-                            .collect_vec();
-
-                        let ret = UiTreeNode {
-                            id: UiTreeId::Group(*group_counter),
-                            label: format!(
-                                "<Group> {} instances of {}",
-                                indexes.len(),
-                                children[0].label
-                            ),
-                            retained_size,
-                            children,
-                        };
-                        *group_counter += 1;
-                        ret
-                    }
-                })
-                .collect_vec();
+        children.sort_by_key(|n| -(n.retained_size as isize));
+    }
+}
 
-            tree.children.sort_by_key(|n| -(n.retained_size as isize));
+/// Stop exporting a subtree after this many nodes
+const MAX_EXPORTED_NODES: usize = 10_000;
+
+/// Render the dominator subtree below a UI node as indented text
+///
+/// Uses the same children as the tree view, but goes all the way down, regardless of what has
+/// been loaded or expanded. A group exports the subtrees of all its members.
+fn subtree_text(node: &UiTreeNode, tree: &DominatorTree, graph: &V8HeapGraph) -> String {
+    let mut stack = match node.id {
+        UiTreeId::Heap(node_id) => vec![(node_id, 0)],
+        UiTreeId::Group(_) => node
+            .loaded_children()
+            .iter()
+            .rev()
+            .filter_map(|member| match member.id {
+                UiTreeId::Heap(node_id) => Some((node_id, 0)),
+                UiTreeId::Group(_) => None,
+            })
+            .collect(),
+    };
+
+    let mut ret = String::new();
+    let mut exported = 0;
+    while let Some((node_id, depth)) = stack.pop() {
+        if exported == MAX_EXPORTED_NODES {
+            let _ = writeln!(&mut ret, "... stopped after {} nodes", MAX_EXPORTED_NODES);
+            break;
+        }
+        exported += 1;
+
+        let indent = "  ".repeat(depth);
+        let _ = writeln!(
+            &mut ret,
+            "{}{} @{}",
+            indent,
+            format_bytes(tree.retained_size(node_id)),
+            graph.node(node_id).stable_id()
+        );
+        for line in detailed_node_repr(node_id, graph).lines() {
+            let _ = writeln!(&mut ret, "{}  {}", indent, line);
         }
-    }
 
-    for child in &mut tree.children {
-        find_groups_in_ui_tree_rec(child, group_counter);
+        let children = visible_children(node_id, tree, graph)
+            .sorted_by_key(|&n| (tree.retained_size(n), Reverse(n)));
+        stack.extend(children.map(|child| (child, depth + 1)));
     }
+    ret
 }
 
-/// Flattens the tree out to a list of renderable records, based on the expanded nodes.
-fn flatten_tree<'a>(node: &'a UiTreeNode, expanded: &HashSet<UiTreeId>) -> Vec<FlatUiTreeNode<'a>> {
+/// Flattens the loaded tree out to a list of renderable records, based on the expanded nodes.
+fn flatten_tree(node: &UiTreeNode, expanded: &HashSet<UiTreeId>) -> Vec<FlatUiTreeNode> {
     let mut result = vec![];
-    flatten_recursive(node, expanded, &mut result, 0);
+    flatten_recursive(node, expanded, &mut result, &mut vec![]);
     result
 }
 
-fn flatten_recursive<'a>(
-    node: &'a UiTreeNode,
+fn flatten_recursive(
+    node: &UiTreeNode,
     expanded: &HashSet<UiTreeId>,
-    result: &mut Vec<FlatUiTreeNode<'a>>,
-    depth: usize,
+    result: &mut Vec<FlatUiTreeNode>,
+    path: &mut Vec<usize>,
 ) {
-    result.push(FlatUiTreeNode { node, depth });
+    result.push(FlatUiTreeNode { path: path.clone() });
 
     if expanded.contains(&node.id) {
-        for child in &node.children {
-            flatten_recursive(child, expanded, result, depth + 1);
+        for (i, child) in node.loaded_children().iter().enumerate() {
+            path.push(i);
+            flatten_recursive(child, expanded, result, path);
+            path.pop();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::{all_paths::find_root_paths, dominator_tree::tree_from_immediate_dominators},
+        graph::{
+            lengauer_tarjan::lengauer_tarjan,
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+    };
+
+    #[test]
+    fn test_create_new_file_does_not_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (first, _) = create_new_file(dir.path(), "subtree-group-3-100").unwrap();
+        let (second, _) = create_new_file(dir.path(), "subtree-group-3-100").unwrap();
+        assert_eq!(first, dir.path().join("subtree-group-3-100.txt"));
+        assert_eq!(second, dir.path().join("subtree-group-3-100-1.txt"));
+    }
+
+    fn new_state(analyzed: AnalyzedGraph<'_>) -> ExplorerState<'_> {
+        let groups = DuplicateAnalyzer::new(analyzed.graph, Default::default()).find_duplicates();
+        ExplorerState::new(analyzed, Default::default(), &groups)
+    }
+
+    #[test]
+    fn test_children_load_on_expand() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let holder = g.node(NodeType::Object, "Holder", 100);
+        let leaf = g.node(NodeType::Object, "Leaf", 10);
+        let twin1 = g.node(NodeType::Object, "Twin", 5);
+        let twin2 = g.node(NodeType::Object, "Twin", 5);
+        g.edge(root, EdgeType::Property, "holder", holder)
+            .edge(holder, EdgeType::Property, "leaf", leaf)
+            .edge(root, EdgeType::Property, "t1", twin1)
+            .edge(root, EdgeType::Property, "t2", twin2);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        let root_paths = find_root_paths(&graph, root);
+
+        let mut state = new_state(AnalyzedGraph::new(&graph, &tree, &root_paths));
+
+        // The root's children are loaded and grouped, but nothing below them is
+        let children = state.root.loaded_children();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0].id, UiTreeId::Heap(holder));
+        assert!(children[0].expandable);
+        assert!(matches!(children[0].children, UiChildren::Unloaded));
+        assert!(matches!(children[1].id, UiTreeId::Group(_)));
+        assert_eq!(children[1].loaded_children().len(), 2);
+        assert_eq!(state.flat_list.len(), 3);
+
+        state.set_selection(1);
+        state.expand_selected();
+        assert_eq!(
+            state.root.loaded_children()[0].loaded_children()[0].id,
+            UiTreeId::Heap(leaf)
+        );
+        assert_eq!(state.flat_list.len(), 4);
+
+        state.collapse_selected();
+        assert_eq!(state.flat_list.len(), 3);
+    }
+
+    #[test]
+    fn test_resize_clamps_scrolling() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        for i in 0..30 {
+            let child = g.node(NodeType::Object, &format!("Child{}", i), 10 + i as usize);
+            g.element(root, i, child);
+        }
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        let root_paths = find_root_paths(&graph, root);
+        let mut state = new_state(AnalyzedGraph::new(&graph, &tree, &root_paths));
+        assert_eq!(state.flat_list.len(), 31);
+
+        // Selecting near the end of a tall window, then shrinking it
+        state.resize(20);
+        state.set_selection(25);
+        state.resize(3);
+        assert_eq!(state.tree_scroll_offset, 23);
+        assert_eq!(state.visible_range(), 23..26);
+
+        // An offset past the end of the list, as left behind by a collapse
+        state.tree_scroll_offset = 100;
+        state.selected = 50;
+        state.resize(10);
+        assert_eq!(state.selected, 30);
+        assert_eq!(state.tree_scroll_offset, 21);
+        assert_eq!(state.visible_range(), 21..31);
+
+        // A window too small to show anything
+        state.resize(0);
+        assert!(state.visible_range().is_empty());
+        assert!(state.tree_scroll_offset <= state.selected);
+    }
+
+    #[test]
+    fn test_toggle_hidden_classes() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let map1 = g.node(NodeType::Hidden, "system / Map", 40);
+        let map2 = g.node(NodeType::Hidden, "system / Map", 40);
+        let a = g.node(NodeType::Object, "Point", 24);
+        let b = g.node(NodeType::Object, "Point", 24);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b)
+            .edge(a, EdgeType::Hidden, "map", map1)
+            .edge(b, EdgeType::Hidden, "map", map2);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        let root_paths = find_root_paths(&graph, root);
+
+        let mut state = new_state(AnalyzedGraph::new(&graph, &tree, &root_paths));
+        assert_eq!(state.duplicate_group(UiTreeId::Heap(a)).unwrap().count, 2);
+
+        state.toggle_hidden_classes();
+        assert!(state.duplicate_group(UiTreeId::Heap(a)).is_none());
+
+        state.toggle_hidden_classes();
+        assert!(state.duplicate_group(UiTreeId::Heap(b)).is_some());
+    }
+
+    #[test]
+    fn test_subtree_text() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let holder = g.node(NodeType::Object, "Holder", 100);
+        let big = g.node(NodeType::String, "big", 50);
+        let small = g.node(NodeType::String, "small", 10);
+        let twin1 = g.node(NodeType::Object, "Twin", 5);
+        let twin2 = g.node(NodeType::Object, "Twin", 5);
+        g.edge(root, EdgeType::Property, "holder", holder)
+            .edge(holder, EdgeType::Property, "small", small)
+            .edge(holder, EdgeType::Property, "big", big)
+            .edge(root, EdgeType::Property, "t1", twin1)
+            .edge(root, EdgeType::Property, "t2", twin2);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+        let root_paths = find_root_paths(&graph, root);
+        let state = new_state(AnalyzedGraph::new(&graph, &tree, &root_paths));
+
+        let holder_node = &state.root.loaded_children()[0];
+        let text = subtree_text(holder_node, &tree, &graph);
+        let headers = text
+            .lines()
+            .filter(|l| l.trim_start().contains(" @"))
+            .collect_vec();
+        assert_eq!(
+            headers,
+            vec![
+                format!("160b @{}", graph.node(holder).stable_id()),
+                format!("  50b @{}", graph.node(big).stable_id()),
+                format!("  10b @{}", graph.node(small).stable_id()),
+            ]
+        );
+        assert!(text.contains("    \"big\""));
+
+        // A group exports all of its members
+        let group = &state.root.loaded_children()[1];
+        assert!(matches!(group.id, UiTreeId::Group(_)));
+        let text = subtree_text(group, &tree, &graph);
+        assert_eq!(text.lines().filter(|l| l.contains("5b @")).count(), 2);
+    }
+}
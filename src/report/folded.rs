@@ -0,0 +1,87 @@
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::Result;
+
+use crate::{
+    analysis::dominator_tree::DominatorTree, graph::v8_heap_graph::V8HeapGraph, types::NodeId,
+};
+
+/// Write the dominator tree as folded stacks of node types, for `inferno-flamegraph`
+///
+/// Every node contributes its self size to the stack of types from the root down to it, so
+/// all instances with the same chain of types end up in one frame: `object;array;string 1234`
+/// means 1234 bytes of strings are held by arrays that are held by objects. The root itself
+/// is left out of the stacks. Lines are sorted, and stacks without any bytes are skipped.
+pub fn write_type_folded<W: Write>(
+    mut w: W,
+    tree: &DominatorTree,
+    graph: &V8HeapGraph,
+) -> Result<()> {
+    let mut totals: BTreeMap<String, usize> = BTreeMap::new();
+    if tree.has_root() {
+        // Iterative, since dominator trees can be deeper than the call stack
+        let mut stack: Vec<String> = vec![];
+        let mut todo: Vec<(NodeId, usize)> = children(tree.root, tree).map(|c| (c, 0)).collect();
+        while let Some((node, depth)) = todo.pop() {
+            stack.truncate(depth);
+            stack.push(graph.node(node).typ_str().into_owned());
+
+            let size = graph.self_size_for(node);
+            if size > 0 {
+                *totals.entry(stack.join(";")).or_default() += size;
+            }
+            todo.extend(children(node, tree).map(|c| (c, depth + 1)));
+        }
+    }
+
+    for (stack, size) in totals {
+        writeln!(w, "{} {}", stack, size)?;
+    }
+    Ok(())
+}
+
+fn children(node: NodeId, tree: &DominatorTree) -> impl Iterator<Item = NodeId> + '_ {
+    tree.children.get(&node).into_iter().flatten().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::dominator_tree::tree_from_immediate_dominators,
+        graph::{
+            lengauer_tarjan::lengauer_tarjan,
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+    };
+
+    #[test]
+    fn test_type_folded() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 20);
+        let array_a = g.node(NodeType::Array, "Array", 8);
+        let array_b = g.node(NodeType::Array, "Array", 8);
+        let s1 = g.node(NodeType::String, "one", 32);
+        let s2 = g.node(NodeType::String, "two", 16);
+        let s3 = g.node(NodeType::String, "three", 4);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b)
+            .edge(a, EdgeType::Property, "items", array_a)
+            .edge(b, EdgeType::Property, "items", array_b)
+            .element(array_a, 0, s1)
+            .element(array_b, 0, s2)
+            .edge(a, EdgeType::Property, "name", s3);
+        let graph = g.build();
+        let tree = tree_from_immediate_dominators(lengauer_tarjan(&graph, &[root]), root, &graph);
+
+        let mut out = Vec::new();
+        write_type_folded(&mut out, &tree, &graph).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "object 30\nobject;array 16\nobject;array;string 48\nobject;string 4\n"
+        );
+    }
+}
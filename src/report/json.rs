@@ -0,0 +1,323 @@
+use std::{
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    analysis::{
+        all_paths::{CollapsedPath, collapse_element_indices},
+        analyzed::AnalyzedGraph,
+        composition::{TypeStats, heap_composition},
+        duplicates::DuplicateGroup,
+    },
+    graph::v8_heap_graph::V8HeapGraph,
+    report::REPORTED_PATHS,
+    types::NodeId,
+    utils::Timings,
+};
+
+/// Version of the JSON report layout
+///
+/// Bump this whenever a field is renamed, removed or changes meaning, so consumers can tell
+/// which layout they are reading. Adding fields doesn't require a bump.
+///
+/// - 2: retention paths that only differ in element indices are merged, as in the text report
+/// - 3: `node_ids` is left out of duplicate groups in reports written without node ids
+pub const FORMAT_VERSION: u32 = 3;
+
+/// The machine-readable report, as written by `--format json`
+#[derive(Debug, Serialize)]
+pub struct JsonReport {
+    pub format_version: u32,
+    /// When the report was generated, in seconds since the Unix epoch
+    pub generated_at: u64,
+    pub summary: Summary,
+    pub heap_composition: Vec<TypeStats>,
+    pub duplicates: Vec<DuplicateGroup>,
+    /// Shortest paths from the root to the representative of every duplicate group. Only
+    /// reports made from `AnalysisResults` have these.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub retention_paths: Vec<RetentionPaths>,
+}
+
+/// The retention paths of a single node
+#[derive(Debug, Serialize)]
+pub struct RetentionPaths {
+    pub node_id: NodeId,
+    pub paths: Vec<Vec<PathHopJson>>,
+}
+
+/// One step of a retention path: the edge that was followed, and the node it leads to
+#[derive(Debug, Serialize)]
+pub struct PathHopJson {
+    pub node_id: NodeId,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    pub name: String,
+    pub edge_type: String,
+    /// The property name, or the index for elements and hidden edges
+    pub edge_name: String,
+    /// Whether this path stands for several that went through different indices here; the
+    /// text report shows this as `[*]`
+    pub any_index: bool,
+}
+
+impl PathHopJson {
+    /// The hops of a path from the root, which itself is not included
+    pub fn resolve(collapsed: &CollapsedPath, graph: &V8HeapGraph) -> Vec<PathHopJson> {
+        collapsed
+            .path
+            .hops(graph)
+            .zip(&collapsed.wildcards)
+            .map(|(hop, &any_index)| {
+                let node = graph.node(hop.to);
+                PathHopJson {
+                    node_id: hop.to,
+                    node_type: node.typ_str().into_owned(),
+                    name: node.name().to_string(),
+                    edge_type: hop.edge_type.display_name().into_owned(),
+                    edge_name: hop.name_or_index.to_string(),
+                    any_index,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Up to `REPORTED_PATHS` resolved retention paths for each node, the same ones the text
+/// report shows: paths that only differ in element indices are merged into one
+pub fn retention_paths_of(
+    nodes: impl IntoIterator<Item = NodeId>,
+    analyzed: AnalyzedGraph<'_>,
+) -> Vec<RetentionPaths> {
+    nodes
+        .into_iter()
+        .map(|node| RetentionPaths {
+            node_id: node,
+            paths: collapse_element_indices(analyzed.paths_to(node), analyzed.graph)
+                .iter()
+                .take(REPORTED_PATHS)
+                .map(|path| PathHopJson::resolve(path, analyzed.graph))
+                .collect(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    /// `V8HeapGraph::fingerprint` as 16 hex digits, the same for copies of the same snapshot
+    pub fingerprint: String,
+    /// See `--label`; only reports made from `AnalysisResults` have this
+    pub label: Option<String>,
+    /// When the snapshot file was last written, in seconds since the Unix epoch; only reports
+    /// made from `AnalysisResults` of a snapshot file have this
+    pub modified: Option<u64>,
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub total_self_size: usize,
+    /// Native memory the snapshot doesn't attribute to any node, not part of `total_self_size`
+    pub extra_native_bytes: usize,
+    pub duplicate_group_count: usize,
+    pub total_wasted: u64,
+    /// Memory used by the analyzer's graph, see `V8HeapGraph::mem_size`
+    pub graph_mem_size: usize,
+    /// All 0 unless the report was made from `AnalysisResults`
+    pub timings: Timings,
+}
+
+pub fn generate_json_report(graph: &V8HeapGraph, duplicates: Vec<DuplicateGroup>) -> JsonReport {
+    JsonReport {
+        format_version: FORMAT_VERSION,
+        generated_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        summary: Summary {
+            fingerprint: format!("{:016x}", graph.fingerprint()),
+            label: None,
+            modified: None,
+            node_count: graph.total_node_count(),
+            edge_count: graph.total_edge_count(),
+            total_self_size: graph.iter_nodes().map(|n| graph.self_size_for(n)).sum(),
+            extra_native_bytes: graph.extra_native_bytes(),
+            duplicate_group_count: duplicates.len(),
+            total_wasted: duplicates.iter().map(|g| g.total_wasted).sum(),
+            graph_mem_size: graph.mem_size(),
+            timings: Timings::default(),
+        },
+        heap_composition: heap_composition(graph),
+        duplicates,
+        retention_paths: vec![],
+    }
+}
+
+/// How the JSON report is written
+#[derive(Debug, Clone)]
+pub struct JsonOptions {
+    /// Indented over many lines, instead of on a single line
+    pub pretty: bool,
+    /// List every member of a duplicate group; without these, groups only have their `count`
+    /// and `representative`, which makes reports of big heaps much smaller
+    pub node_ids: bool,
+    /// List the stable ids of every member of a duplicate group, see
+    /// `DuplicateGroup::member_stable_ids`. Groups always have the representative's.
+    pub stable_ids: bool,
+    /// Leave out everything that changes between runs on the same snapshot, so that the
+    /// report is byte for byte the same every time: `generated_at` is 0, the timings are all 0
+    /// and `modified` is left out
+    pub reproducible: bool,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self {
+            pretty: true,
+            node_ids: true,
+            stable_ids: false,
+            reproducible: false,
+        }
+    }
+}
+
+/// Write the report with the given options, dropping the node ids first if asked to
+pub fn write_json_report(
+    mut report: JsonReport,
+    options: &JsonOptions,
+    writer: impl Write,
+) -> Result<()> {
+    if !options.node_ids {
+        for group in &mut report.duplicates {
+            group.node_ids = vec![];
+        }
+    }
+    if options.reproducible {
+        report.generated_at = 0;
+        report.summary.modified = None;
+        report.summary.timings = Timings::default();
+    }
+
+    if options.pretty {
+        serde_json::to_writer_pretty(writer, &report)?;
+    } else {
+        serde_json::to_writer(writer, &report)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::duplicates::DuplicateAnalyzer,
+        graph::{
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+    };
+
+    #[test]
+    fn test_format_version() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::String, "same", 20);
+        let b = g.node(NodeType::String, "same", 20);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b);
+        let graph = g.build();
+        let groups = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicates();
+
+        let mut out = vec![];
+        write_json_report(
+            generate_json_report(&graph, groups),
+            &JsonOptions::default(),
+            &mut out,
+        )
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        // Changing this is a breaking change for consumers
+        assert_eq!(FORMAT_VERSION, 3);
+        assert_eq!(json["format_version"], 3);
+        assert!(json["generated_at"].as_u64().unwrap() > 0);
+        assert_eq!(json["summary"]["node_count"], 3);
+        assert_eq!(json["summary"]["duplicate_group_count"], 1);
+        assert_eq!(json["summary"]["total_wasted"], 20);
+        assert_eq!(json["heap_composition"][0]["type_name"], "string");
+        assert_eq!(json["duplicates"][0]["count"], 2);
+    }
+
+    #[test]
+    fn test_compact_json() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        for i in 0..50 {
+            let s = g.node(NodeType::String, "same", 20);
+            g.element(root, i, s);
+        }
+        let graph = g.build();
+        let write = |options: JsonOptions| {
+            let groups = DuplicateAnalyzer::new(&graph, Default::default()).find_duplicates();
+            let mut out = vec![];
+            write_json_report(generate_json_report(&graph, groups), &options, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let pretty = write(JsonOptions::default());
+        let compact = write(JsonOptions {
+            pretty: false,
+            ..Default::default()
+        });
+        let without_ids = write(JsonOptions {
+            pretty: false,
+            node_ids: false,
+            ..Default::default()
+        });
+        assert!(compact.len() < pretty.len());
+        assert!(!compact.contains('\n'));
+        assert!(without_ids.len() < compact.len());
+
+        // The same report either way
+        let value = |s: &str| serde_json::from_str::<serde_json::Value>(s).unwrap();
+        assert_eq!(value(&pretty)["duplicates"], value(&compact)["duplicates"]);
+        let group = &value(&without_ids)["duplicates"][0];
+        assert_eq!(group["count"], 50);
+        assert!(group["representative"].is_u64());
+        assert!(group.get("node_ids").is_none());
+
+        let reproducible = value(&write(JsonOptions {
+            reproducible: true,
+            ..Default::default()
+        }));
+        assert_eq!(reproducible["generated_at"], 0);
+        assert_eq!(reproducible["summary"]["timings"]["total_ms"], 0);
+        assert_eq!(reproducible["duplicates"], value(&pretty)["duplicates"]);
+    }
+
+    #[test]
+    fn test_native_sizes() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let buffer = g.node(NodeType::Object, "ArrayBuffer", 100);
+        let store = g.node(NodeType::Native, "system / JSArrayBufferData", 4000);
+        g.edge(root, EdgeType::Property, "buffer", buffer).edge(
+            buffer,
+            EdgeType::Internal,
+            "backing_store",
+            store,
+        );
+        let mut snapshot = g.snapshot();
+        snapshot.snapshot.extra_native_bytes = 12345;
+        let mut graph = V8HeapGraph::from(snapshot);
+
+        let summary = generate_json_report(&graph, vec![]).summary;
+        assert_eq!(summary.extra_native_bytes, 12345);
+        assert_eq!(summary.total_self_size, 4100);
+
+        graph.set_include_native(false);
+        let summary = generate_json_report(&graph, vec![]).summary;
+        assert_eq!(summary.extra_native_bytes, 12345);
+        assert_eq!(summary.total_self_size, 100);
+    }
+}
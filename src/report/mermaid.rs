@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use ahash::AHashSet;
+
+use crate::{analysis::all_paths::RootPath, graph::v8_heap_graph::V8HeapGraph, types::NodeId};
+
+use super::{devtools_edge_name, minimal_node_repr};
+
+/// Node descriptions are cut off after this many characters, to keep the diagram readable
+const MAX_LABEL_LEN: usize = 40;
+
+/// Render retention paths as a Mermaid flowchart, for pasting into Markdown
+///
+/// Every node gets the id `n<NodeId>`, since Mermaid ids can't contain arbitrary
+/// characters; the description goes into a quoted label instead. Nodes and hops that are
+/// shared between paths are only emitted once.
+pub fn retention_paths_to_mermaid(paths: &[RootPath], graph: &V8HeapGraph) -> String {
+    let mut out = String::from("graph TD\n");
+    let mut declared: AHashSet<NodeId> = AHashSet::new();
+    let mut hops = AHashSet::new();
+
+    let mut declare = |out: &mut String, node: NodeId| {
+        if declared.insert(node) {
+            let mut repr = minimal_node_repr(node, graph);
+            if let Some((cut, _)) = repr.char_indices().nth(MAX_LABEL_LEN) {
+                repr.truncate(cut);
+                repr.push_str("...");
+            }
+            let label = format!("@{} {}", graph.node(node).stable_id(), repr);
+            let _ = writeln!(out, "    n{}[\"{}\"]", node, mermaid_escape(&label));
+        }
+    };
+
+    for path in paths {
+        for edge in path.edges(graph) {
+            if !hops.insert(edge.id) {
+                continue;
+            }
+            declare(&mut out, edge.from_node());
+            declare(&mut out, edge.to_node());
+            let _ = writeln!(
+                out,
+                "    n{} -->|\"{}\"| n{}",
+                edge.from_node(),
+                mermaid_escape(&devtools_edge_name(&edge)),
+                edge.to_node()
+            );
+        }
+    }
+    out
+}
+
+/// Make a string safe to put between double quotes in a Mermaid label
+///
+/// Mermaid doesn't support backslash escapes, only HTML-like entity codes.
+fn mermaid_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '#' => out.push_str("#35;"),
+            '"' => out.push_str("#quot;"),
+            '<' => out.push_str("#lt;"),
+            '>' => out.push_str("#gt;"),
+            '|' => out.push_str("#124;"),
+            '\n' | '\r' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::all_paths::find_root_paths,
+        graph::{
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType},
+        },
+    };
+
+    #[test]
+    fn test_retention_paths_to_mermaid() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "(GC roots)", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 10);
+        let target = g.node(NodeType::String, "say \"hi\" <b>", 10);
+        g.edge(root, EdgeType::Property, "a", a)
+            .edge(root, EdgeType::Property, "b", b)
+            .edge(a, EdgeType::Property, "x", target)
+            .edge(b, EdgeType::Property, "y", target);
+        let graph = g.build();
+        let paths = find_root_paths(&graph, root).paths_to_limited(target, &graph, 5);
+        assert_eq!(paths.len(), 2);
+
+        let mermaid = retention_paths_to_mermaid(&paths, &graph);
+        assert!(mermaid.starts_with("graph TD\n"));
+
+        let hop_count: usize = paths.iter().map(|p| p.edges(&graph).count()).sum();
+        assert_eq!(mermaid.matches("-->").count(), hop_count);
+        assert!(mermaid.contains(&format!("n{} -->|\"x\"| n{}", a, target)));
+        assert!(mermaid.contains(&format!("n{} -->|\"y\"| n{}", b, target)));
+
+        // The shared target is declared once, with its label made safe
+        assert_eq!(mermaid.matches(&format!("n{}[", target)).count(), 1);
+        assert!(mermaid.contains("#lt;b#gt;"));
+        assert!(!mermaid.contains("\"hi"));
+    }
+}
@@ -4,18 +4,102 @@ use itertools::Itertools;
 use petgraph::visit::Bfs;
 
 use crate::{
-    analysis::{all_paths::RootPaths, dominator_tree::DominatorTree},
+    analysis::{
+        all_paths::{RootPath, RootPaths, collapse_element_indices},
+        analyzed::AnalyzedGraph,
+        composition::{StringSummary, TypeStats},
+        contexts::ContextRoot,
+        cycles::RetentionCycle,
+        detached::DetachedDomReport,
+        dominator_tree::{DominatorTree, dominated_type_breakdown},
+        duplicate_diff::DuplicateGrowth,
+        duplicates::{ConstructorSummary, DuplicateGroup},
+        explain::Explanation,
+        focus::FocusReport,
+        sample::SampledReport,
+        sanity::SanityReport,
+        shapes::ShapeCluster,
+        sparse_arrays::SparseArray,
+        suspects::Suspect,
+        tags::TagSummary,
+    },
     graph::v8_heap_graph::{Edge, EdgeType, Node, NodeType, V8HeapGraph},
     types::NodeId,
-    utils::{format_bytes, print_safe},
+    utils::{ByteUnit, Timings, format_bytes, format_bytes_signed, print_safe},
 };
 
+pub mod dot;
 pub mod explorer;
+pub mod folded;
+pub mod json;
+pub mod mermaid;
 
 pub use explorer::explore_graph;
 
-pub fn print_graph(graph: &V8HeapGraph, root_paths: &RootPaths, dom_tree: &DominatorTree) {
-    let mut bfs = Bfs::new(&graph, 0);
+/// How the report is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable sections
+    #[default]
+    Text,
+    /// A single JSON document, see `json::JsonReport`
+    Json,
+    /// The largest retainers as a Graphviz digraph, see `dot::write_top_retainers_dot`
+    Dot,
+    /// The whole graph as GEXF, for Gephi
+    Gexf,
+    /// The whole graph as GML
+    Gml,
+}
+
+impl OutputFormat {
+    /// The format that an output file name asks for, if its extension is one we write
+    ///
+    /// There is no CSV or HTML writer, so those extensions (like any other) don't pick a
+    /// format.
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(OutputFormat::Json),
+            "txt" => Some(OutputFormat::Text),
+            "dot" | "gv" => Some(OutputFormat::Dot),
+            "gexf" => Some(OutputFormat::Gexf),
+            "gml" => Some(OutputFormat::Gml),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a drawing of the graph rather than a report of the analysis
+    pub fn is_graph(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Dot | OutputFormat::Gexf | OutputFormat::Gml
+        )
+    }
+}
+
+/// Syntax for retention paths
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PathFormat {
+    /// One line per path, like `.foo[3](internal/bar)`
+    #[default]
+    Compact,
+    /// One line per hop, like the Retainers panel in Chrome DevTools
+    Devtools,
+    /// A Mermaid flowchart, for pasting into Markdown
+    Mermaid,
+}
+
+pub fn print_graph(analyzed: AnalyzedGraph<'_>, path_format: PathFormat) {
+    let AnalyzedGraph {
+        graph,
+        tree,
+        root_paths,
+    } = analyzed;
+    if !tree.has_root() {
+        return;
+    }
+    let mut bfs = Bfs::new(&graph, tree.root);
     while let Some(nx) = bfs.next(&graph) {
         let node = graph.node(nx);
 
@@ -26,13 +110,13 @@ pub fn print_graph(graph: &V8HeapGraph, root_paths: &RootPaths, dom_tree: &Domin
             node.print_safe_name(40),
             node.stable_id(),
             node.self_size(),
-            dom_tree.retained_size(nx)
+            tree.retained_size(nx)
         );
 
         println!("    {}", minimal_node_repr(node.id, graph));
 
         let mut s = String::new();
-        let _ = format_retention_paths(&mut s, node.id, root_paths, graph);
+        let _ = format_paths(&mut s, node.id, root_paths, graph, path_format);
         print!("{}", s);
 
         for edge in graph.out_edges(nx) {
@@ -48,7 +132,124 @@ pub fn print_graph(graph: &V8HeapGraph, root_paths: &RootPaths, dom_tree: &Domin
     }
 }
 
+pub fn print_sanity_report(report: &SanityReport) {
+    let mut s = String::new();
+    let _ = format_sanity_report(&mut s, report);
+    print!("{}", s);
+}
+
+pub fn format_sanity_report<F: std::fmt::Write>(
+    f: &mut F,
+    report: &SanityReport,
+) -> std::fmt::Result {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    writeln!(f, "Snapshot integrity:")?;
+    writeln!(
+        f,
+        "  Nodes:          {} declared, {} found",
+        report.declared_node_count, report.actual_node_count
+    )?;
+    writeln!(
+        f,
+        "  Edges:          {} declared, {} found",
+        report.declared_edge_count, report.actual_edge_count
+    )?;
+    writeln!(f, "  GC roots:       {}", report.gc_roots)?;
+    writeln!(f, "  Unreachable:    {} nodes", report.unreachable_nodes)?;
+    writeln!(f, "  Detachedness:   {}", yes_no(report.has_detachedness))?;
+    writeln!(f, "  Trace node ids: {}", yes_no(report.has_trace_node_id))
+}
+
+pub fn print_heap_composition(stats: &[TypeStats]) {
+    let mut s = String::new();
+    let _ = format_heap_composition(&mut s, stats);
+    print!("{}", s);
+}
+
+pub fn format_heap_composition<F: std::fmt::Write>(
+    f: &mut F,
+    stats: &[TypeStats],
+) -> std::fmt::Result {
+    writeln!(f, "Heap composition:")?;
+    for stat in stats {
+        writeln!(
+            f,
+            "{:>8}  {:>5.1}%  {:>10} x {}",
+            format_bytes(stat.total_self_size),
+            stat.pct_of_heap,
+            stat.count,
+            stat.type_name,
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_string_summary(summary: &StringSummary) {
+    let mut s = String::new();
+    let _ = format_string_summary(&mut s, summary);
+    print!("{}", s);
+}
+
+pub fn format_string_summary<F: std::fmt::Write>(
+    f: &mut F,
+    summary: &StringSummary,
+) -> std::fmt::Result {
+    writeln!(
+        f,
+        "Strings: {} in {} strings",
+        format_bytes(summary.total_self_size()),
+        summary.total_count()
+    )?;
+    for stat in &summary.categories {
+        writeln!(
+            f,
+            "{:>8}  {:>5.1}%  {:>10} x {}",
+            format_bytes(stat.total_self_size),
+            stat.pct_of_heap,
+            stat.count,
+            stat.type_name,
+        )?;
+    }
+    writeln!(
+        f,
+        "  {} duplicated, {} unique; {} wasted on duplicates",
+        summary.duplicated_count,
+        summary.unique_count,
+        format_bytes(summary.wasted as usize),
+    )
+}
+
+pub fn print_unreachable(stats: &[TypeStats]) {
+    let count: usize = stats.iter().map(|s| s.count).sum();
+    let size: usize = stats.iter().map(|s| s.total_self_size).sum();
+    let pct: f64 = stats.iter().map(|s| s.pct_of_heap).sum();
+
+    println!(
+        "Unreachable objects: {} objects, {} ({:.1}% of heap)",
+        count,
+        format_bytes(size),
+        pct
+    );
+    for stat in stats {
+        println!(
+            "{:>8}  {:>5.1}%  {:>10} x {}",
+            format_bytes(stat.total_self_size),
+            stat.pct_of_heap,
+            stat.count,
+            stat.type_name,
+        );
+    }
+}
+
+/// A node on a single line, followed by its distance from the GC roots if it is reachable
 pub fn minimal_node_repr(node: NodeId, graph: &V8HeapGraph) -> String {
+    match graph.root_distance(node) {
+        Some(distance) => format!("{}  (depth {})", node_repr(node, graph), distance),
+        None => node_repr(node, graph),
+    }
+}
+
+fn node_repr(node: NodeId, graph: &V8HeapGraph) -> String {
     let node = graph.node(node);
 
     match node.typ() {
@@ -62,40 +263,34 @@ pub fn minimal_node_repr(node: NodeId, graph: &V8HeapGraph) -> String {
                 .find_edge(node.id, EdgeType::Internal, "second")
                 .expect("ConcatString must have second");
 
-            format!(
-                "{} + {}",
-                minimal_node_repr(first, graph),
-                minimal_node_repr(second, graph)
-            )
+            format!("{} + {}", node_repr(first, graph), node_repr(second, graph))
         }
         NodeType::SlicedString => {
             let parent = graph
                 .find_edge(node.id, EdgeType::Internal, "parent")
                 .expect("SlicedString must have parent");
 
-            format!("<slice of {}>", minimal_node_repr(parent, graph))
+            format!("<slice of {}>", node_repr(parent, graph))
         }
-        NodeType::Number => "<a number>".to_string(),
-        NodeType::BigInt => "<a bigint>".to_string(),
+        NodeType::Number => node
+            .numeric_value()
+            .map_or_else(|| "<a number>".to_string(), str::to_string),
+        NodeType::BigInt => node
+            .numeric_value()
+            .map_or_else(|| "<a bigint>".to_string(), |v| format!("{}n", v)),
         NodeType::Closure => format!("function {}()", node.name()),
         NodeType::Symbol => match graph.find_edge(node.id, EdgeType::Internal, "name") {
-            Some(name) => format!("symbol {}", minimal_node_repr(name, graph)),
+            Some(name) => format!("symbol {}", node_repr(name, graph)),
             None => "unnamed symbol".to_string(),
         },
         NodeType::Object => {
-            if graph
-                .find_edge(node.id, EdgeType::Internal, "elements")
-                .is_some()
-            {
+            if node.is_array_like() {
                 // It's an array or array-like, format like an array
                 return format!(
-                    "{} [ {} ]",
+                    "{}({}) [ {} ]",
                     node.name(),
-                    graph
-                        .out_edges(node.id)
-                        .filter(|e| e.typ() == EdgeType::Element)
-                        .map(|e| minimal_node_repr(e.to_node(), graph))
-                        .join(", ")
+                    format_bytes(node.effective_size()),
+                    node.elements().map(|el| node_repr(el, graph)).join(", ")
                 );
             }
 
@@ -147,8 +342,12 @@ pub fn detailed_node_repr(node: NodeId, graph: &V8HeapGraph) -> String {
 
             format!("<slice of {}>", minimal_node_repr(parent, graph))
         }
-        NodeType::Number => "<a number>".to_string(),
-        NodeType::BigInt => "<a bigint>".to_string(),
+        NodeType::Number => node
+            .numeric_value()
+            .map_or_else(|| "<a number>".to_string(), str::to_string),
+        NodeType::BigInt => node
+            .numeric_value()
+            .map_or_else(|| "<a bigint>".to_string(), |v| format!("{}n", v)),
         NodeType::Closure => {
             let _ = writeln!(&mut ret, "function {}()\n", node.name());
             print_edges(&mut ret, node.id, graph);
@@ -159,14 +358,10 @@ pub fn detailed_node_repr(node: NodeId, graph: &V8HeapGraph) -> String {
             None => "unnamed symbol".to_string(),
         },
         NodeType::Object => {
-            if graph
-                .find_edge(node.id, EdgeType::Internal, "elements")
-                .is_some()
-            {
-                let elements = graph
-                    .out_edges(node.id)
-                    .filter(|e| e.typ() == EdgeType::Element)
-                    .map(|e| minimal_node_repr(e.to_node(), graph))
+            if node.is_array_like() {
+                let elements = node
+                    .elements()
+                    .map(|el| minimal_node_repr(el, graph))
                     .collect_vec();
 
                 let _ = writeln!(&mut ret, "{} ({} elements)\n", node.name(), elements.len());
@@ -207,21 +402,314 @@ pub fn print_edges<F: std::fmt::Write>(f: &mut F, node: NodeId, graph: &V8HeapGr
     }
 }
 
+/// The text shown for a single node in the explorer and by `inspect`
+///
+/// The breakdown of dominated types is only shown if a dominator tree is available.
+pub fn inspector_text(
+    node: NodeId,
+    root_paths: &RootPaths,
+    tree: Option<&DominatorTree>,
+    graph: &V8HeapGraph,
+    path_format: PathFormat,
+) -> String {
+    let mut s = detailed_node_repr(node, graph);
+    let distance = match (root_paths.distance(node), root_paths.cut_off_at()) {
+        (Some(d), _) => d.to_string(),
+        (None, Some(max_depth)) => format!("more than {}, or unreachable", max_depth),
+        (None, None) => "unreachable".to_string(),
+    };
+    let _ = write!(&mut s, "\n\nDistance from root: {}", distance);
+    if let Some(tree) = tree {
+        let _ = write!(&mut s, "\n\n");
+        let _ = format_dominated_types(&mut s, node, tree, graph);
+    }
+    let _ = write!(&mut s, "\n\nPath(s):\n");
+    let _ = format_paths(&mut s, node, root_paths, graph, path_format);
+    let _ = writeln!(&mut s);
+    let _ = format_in_edges(&mut s, node, graph);
+    s
+}
+
+/// How many node types to list in the breakdown of dominated types
+const REPORTED_DOMINATED_TYPES: usize = 5;
+
+pub fn format_dominated_types<F: std::fmt::Write>(
+    f: &mut F,
+    node: NodeId,
+    tree: &DominatorTree,
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    let retained_size = tree.retained_size(node);
+    write!(f, "Retains {}", format_bytes(retained_size))?;
+    if retained_size == 0 {
+        return Ok(());
+    }
+
+    let breakdown = dominated_type_breakdown(node, tree, graph);
+    write!(
+        f,
+        ": {}",
+        breakdown
+            .iter()
+            .take(REPORTED_DOMINATED_TYPES)
+            .map(|(typ, size)| format!(
+                "{:.0}% {}",
+                *size as f64 * 100.0 / retained_size as f64,
+                typ.as_str()
+            ))
+            .join(", ")
+    )?;
+    if breakdown.len() > REPORTED_DOMINATED_TYPES {
+        write!(f, ", ...")?;
+    }
+    Ok(())
+}
+
+/// How many incoming edges to list per node
+const REPORTED_IN_EDGES: usize = 10;
+
+/// Count the incoming edges of a node by edge type, most common first
+pub fn in_edge_summary(node: NodeId, graph: &V8HeapGraph) -> Vec<(String, usize)> {
+    graph
+        .in_edges(node)
+        .map(|e| e.typ_str())
+        .counts()
+        .into_iter()
+        .map(|(typ, count)| (typ.into_owned(), count))
+        .sorted_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)))
+        .collect()
+}
+
+pub fn format_in_edges<F: std::fmt::Write>(
+    f: &mut F,
+    node: NodeId,
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    let summary = in_edge_summary(node, graph);
+    if summary.is_empty() {
+        return writeln!(f, "Retained by: nothing");
+    }
+
+    writeln!(
+        f,
+        "Retained by: {}",
+        summary
+            .iter()
+            .map(|(typ, count)| format!(
+                "{} {} edge{}",
+                count,
+                typ,
+                if *count == 1 { "" } else { "s" }
+            ))
+            .join(", ")
+    )?;
+
+    let total: usize = summary.iter().map(|(_, count)| count).sum();
+    for edge in graph.in_edges(node).take(REPORTED_IN_EDGES) {
+        let from = graph.node(edge.from_node());
+        write!(
+            f,
+            "  @{} {} ",
+            from.stable_id(),
+            minimal_node_repr(from.id, graph)
+        )?;
+        fmt_edge(f, &edge)?;
+        writeln!(f)?;
+    }
+    if total > REPORTED_IN_EDGES {
+        writeln!(f, "  +{} more", total - REPORTED_IN_EDGES)?;
+    }
+    Ok(())
+}
+
+/// The self size, with the backing store of arrays added in
+fn self_size_line(node: &Node<'_>) -> String {
+    match node.backing_store() {
+        Some(store) => format!(
+            "{} ({} + {} backing store)",
+            format_bytes(node.effective_size()),
+            format_bytes(node.self_size()),
+            format_bytes(store.self_size())
+        ),
+        None => format_bytes(node.self_size()),
+    }
+}
+
+pub fn print_inspect(
+    node_id: NodeId,
+    retained_size: usize,
+    root_paths: &RootPaths,
+    graph: &V8HeapGraph,
+    path_format: PathFormat,
+) {
+    let node = graph.node(node_id);
+
+    println!("Node:          @{} ({})", node.stable_id(), node.typ_str());
+    println!("Self size:     {}", self_size_line(&node));
+    println!("Retained size: {}", format_bytes(retained_size));
+    println!();
+    println!(
+        "{}",
+        inspector_text(node_id, root_paths, None, graph, path_format)
+    );
+}
+
+pub fn print_explanation(explanation: &Explanation, graph: &V8HeapGraph, path_format: PathFormat) {
+    let node = graph.node(explanation.node);
+    let node_line = |n: NodeId| {
+        format!(
+            "@{} {}",
+            graph.node(n).stable_id(),
+            minimal_node_repr(n, graph)
+        )
+    };
+
+    println!("Node:          {}", node_line(node.id));
+    println!("Self size:     {}", self_size_line(&node));
+    println!("Retained size: {}", format_bytes(explanation.retained_size));
+    println!(
+        "Dominated by:  {}",
+        explanation
+            .immediate_dominator
+            .map_or_else(|| "nothing".to_string(), node_line)
+    );
+    println!(
+        "Detached:      {}",
+        if explanation.detached { "yes" } else { "no" }
+    );
+    match &explanation.duplicate_group {
+        Some(group) => println!(
+            "Duplicates:    one of {} identical {} objects, wasting {}",
+            group.count,
+            group.object_type,
+            format_bytes(group.total_wasted as usize)
+        ),
+        None => println!("Duplicates:    none"),
+    }
+
+    println!();
+    let Some(path) = &explanation.shortest_path else {
+        println!("Not reachable from the root, so it will be collected");
+        return;
+    };
+    println!("Shortest retention path:");
+    let mut s = String::new();
+    let _ = match path_format {
+        PathFormat::Compact => path
+            .edges(graph)
+            .try_for_each(|edge| fmt_edge(&mut s, &edge)),
+        PathFormat::Devtools => format_devtools_path(&mut s, path, graph),
+        PathFormat::Mermaid => write!(
+            s,
+            "{}",
+            mermaid::retention_paths_to_mermaid(std::slice::from_ref(path), graph)
+        ),
+    };
+    println!("{}", s);
+}
+
+/// How many retention paths to show per node
+pub(crate) const REPORTED_PATHS: usize = 5;
+
+/// One line per path, as a chain of edges from the root
+///
+/// Paths that only differ in array indices are shown once, with `[*]` for the indices that
+/// differ and the number of paths at the end.
 pub fn format_retention_paths<F: std::fmt::Write>(
     f: &mut F,
     node: NodeId,
     paths: &RootPaths,
     graph: &V8HeapGraph,
 ) -> std::fmt::Result {
-    for path in paths.paths_to(node, graph) {
-        for edge in path.edges(graph) {
-            fmt_edge(f, &edge)?;
+    let collapsed = collapse_element_indices(paths.paths_to(node, graph), graph);
+    for path in collapsed.iter().take(REPORTED_PATHS) {
+        for (edge, &wildcard) in path.path.edges(graph).zip(&path.wildcards) {
+            if wildcard {
+                write!(f, "[*]")?;
+            } else {
+                fmt_edge(f, &edge)?;
+            }
+        }
+        if path.count > 1 {
+            write!(f, "  ({} paths)", path.count)?;
         }
         writeln!(f)?;
     }
     Ok(())
 }
 
+pub fn format_paths<F: std::fmt::Write>(
+    f: &mut F,
+    node: NodeId,
+    paths: &RootPaths,
+    graph: &V8HeapGraph,
+    path_format: PathFormat,
+) -> std::fmt::Result {
+    match path_format {
+        PathFormat::Compact => format_retention_paths(f, node, paths, graph),
+        PathFormat::Devtools => format_retention_paths_devtools(f, node, paths, graph),
+        PathFormat::Mermaid => f.write_str(&mermaid::retention_paths_to_mermaid(
+            &paths.paths_to_limited(node, graph, REPORTED_PATHS),
+            graph,
+        )),
+    }
+}
+
+/// Format retention paths the way the Retainers panel in Chrome DevTools shows them
+///
+/// Every path is shown from the node outwards: each line is a retainer of the line above
+/// it, as `<edge> in <retainer> @<id>`. Edge names follow the DevTools conventions:
+/// `[0]` for elements, `->name` for context variables, `{name}` for internal and hidden
+/// edges and `[[name]]` for weak edges.
+pub fn format_retention_paths_devtools<F: std::fmt::Write>(
+    f: &mut F,
+    node: NodeId,
+    paths: &RootPaths,
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    for (i, path) in paths
+        .paths_to_limited(node, graph, REPORTED_PATHS)
+        .iter()
+        .enumerate()
+    {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        format_devtools_path(f, path, graph)?;
+    }
+    Ok(())
+}
+
+/// A single path, one hop per line, starting at the node and ending at the root
+fn format_devtools_path<F: std::fmt::Write>(
+    f: &mut F,
+    path: &RootPath,
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    for (depth, edge) in path.edges(graph).collect_vec().iter().rev().enumerate() {
+        let retainer = graph.node(edge.from_node());
+        writeln!(
+            f,
+            "{}{} in {} @{}",
+            "  ".repeat(depth),
+            devtools_edge_name(edge),
+            retainer.print_safe_name(40),
+            retainer.stable_id()
+        )?;
+    }
+    Ok(())
+}
+
+fn devtools_edge_name(edge: &Edge<'_>) -> String {
+    match edge.typ() {
+        EdgeType::Element => format!("[{}]", edge.index()),
+        EdgeType::Context => format!("->{}", edge.name_or_index()),
+        EdgeType::Internal | EdgeType::Hidden => format!("{{{}}}", edge.name_or_index()),
+        EdgeType::Weak => format!("[[{}]]", edge.name_or_index()),
+        _ => edge.name_or_index().to_string(),
+    }
+}
+
 fn fmt_edge<F: std::fmt::Write>(f: &mut F, edge: &Edge<'_>) -> std::fmt::Result {
     match edge.typ() {
         EdgeType::Property => write!(f, ".{}", edge.name_or_index()),
@@ -231,11 +719,14 @@ fn fmt_edge<F: std::fmt::Write>(f: &mut F, edge: &Edge<'_>) -> std::fmt::Result
         EdgeType::Shortcut => write!(f, "(shortcut/{})", edge.name_or_index()),
         EdgeType::Weak => write!(f, "(weak/{})", edge.name_or_index()),
         EdgeType::Hidden => write!(f, "(hidden/{})", edge.name_or_index()),
+        EdgeType::Unknown(_) => write!(f, "({}/{})", edge.typ_str(), edge.name_or_index()),
     }
 }
 
 pub fn print_dominator_tree(tree: &DominatorTree, graph: &V8HeapGraph) {
-    print_dominator_node(0, tree, graph, 0);
+    if tree.has_root() {
+        print_dominator_node(tree.root, tree, graph, 0);
+    }
 }
 
 fn print_dominator_node(node_id: NodeId, tree: &DominatorTree, graph: &V8HeapGraph, depth: usize) {
@@ -255,17 +746,7 @@ fn print_dominator_node(node_id: NodeId, tree: &DominatorTree, graph: &V8HeapGra
         children.sort_by_key(|node| -(tree.retained_sizes[*node as usize] as i64));
 
         // Some nodes we're going to hide
-        children.retain(|node| {
-            !matches!(
-                graph.node(*node).typ(),
-                NodeType::Hidden
-                    | NodeType::ObjectShape
-                    | NodeType::ConcatString
-                    | NodeType::SlicedString
-                    | NodeType::Code
-                    | NodeType::Array
-            )
-        });
+        children.retain(|node| !hidden_in_tree(graph.node(*node).typ()));
 
         for child in &children[0..20.min(children.len())] {
             print_dominator_node(*child, tree, graph, depth + 1);
@@ -273,9 +754,564 @@ fn print_dominator_node(node_id: NodeId, tree: &DominatorTree, graph: &V8HeapGra
     }
 }
 
+/// Node types that the dominator tree views leave out: V8 internals, and the parts of
+/// strings and arrays that are already counted in their owner
+pub fn hidden_in_tree(typ: NodeType) -> bool {
+    typ.is_internal()
+        || matches!(
+            typ,
+            NodeType::ConcatString | NodeType::SlicedString | NodeType::Array
+        )
+}
+
+pub fn print_footer(timings: &Timings, graph: &V8HeapGraph) {
+    let mut s = String::new();
+    let _ = format_footer(&mut s, timings, graph);
+    print!("{}", s);
+}
+
+/// How long the analysis took and how much memory the graph needed
+pub fn format_footer<F: std::fmt::Write>(
+    f: &mut F,
+    timings: &Timings,
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    writeln!(
+        f,
+        "Analysis took {}ms (parse {}ms, dominators {}ms, duplicates {}ms, paths {}ms)",
+        timings.total_ms,
+        timings.parse_ms,
+        timings.dominators_ms,
+        timings.duplicates_ms,
+        timings.paths_ms
+    )?;
+    writeln!(f, "Graph memory: {}", format_bytes(graph.mem_size()))
+}
+
+pub fn print_detached_dom(report: &DetachedDomReport, graph: &V8HeapGraph) {
+    let mut s = String::new();
+    let _ = format_detached_dom(&mut s, report, graph);
+    print!("{}", s);
+}
+
+/// The detached DOM trees, which in a web app are the most likely leaks
+pub fn format_detached_dom<F: std::fmt::Write>(
+    f: &mut F,
+    report: &DetachedDomReport,
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    writeln!(
+        f,
+        "Detached DOM trees: {} in {} tree(s)",
+        format_bytes(report.total_retained_size),
+        report.trees.len()
+    )?;
+    for &(tree, retained_size) in report.trees.iter().take(20) {
+        writeln!(
+            f,
+            "{:>8}  @{}  {}",
+            format_bytes(retained_size),
+            graph.node(tree).stable_id(),
+            minimal_node_repr(tree, graph),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_sparse_arrays(arrays: &[SparseArray], graph: &V8HeapGraph) {
+    let mut s = String::new();
+    let _ = format_sparse_arrays(&mut s, arrays, graph);
+    print!("{}", s);
+}
+
+/// Arrays with holes, with how many of their elements are actually there
+pub fn format_sparse_arrays<F: std::fmt::Write>(
+    f: &mut F,
+    arrays: &[SparseArray],
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    writeln!(f, "Sparse arrays: {}", arrays.len())?;
+    for array in arrays.iter().take(20) {
+        let node = graph.node(array.node);
+        writeln!(
+            f,
+            "{:>8}  @{}  {} with {} of {} elements populated",
+            format_bytes(array.size),
+            node.stable_id(),
+            node.print_safe_name(30),
+            array.populated,
+            array.length(),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_leak_suspects(suspects: &[Suspect], analyzed: AnalyzedGraph<'_>) {
+    let mut s = String::new();
+    let _ = format_leak_suspects(&mut s, suspects, analyzed);
+    print!("{}", s);
+}
+
+/// The top suspects with their score, the signals that count for them, and their first path
+pub fn format_leak_suspects<F: std::fmt::Write>(
+    f: &mut F,
+    suspects: &[Suspect],
+    analyzed: AnalyzedGraph<'_>,
+) -> std::fmt::Result {
+    let graph = analyzed.graph;
+    writeln!(f, "Leak suspects:")?;
+    if suspects.is_empty() {
+        writeln!(f, "  (none)")?;
+    }
+    for suspect in suspects.iter().take(10) {
+        let mut reasons = vec![];
+        if suspect.detached {
+            reasons.push("detached".to_string());
+        }
+        if suspect.similar_siblings > 0 {
+            reasons.push(format!("{} similar siblings", suspect.similar_siblings));
+        }
+        match (suspect.single_path, suspect.through_internals) {
+            (true, true) => reasons.push("single path through internals".to_string()),
+            (true, false) => reasons.push("single path".to_string()),
+            _ => {}
+        }
+        writeln!(
+            f,
+            "{:>5.2}  {:>8}  @{}  {}{}",
+            suspect.score,
+            format_bytes(suspect.retained_size),
+            graph.node(suspect.node).stable_id(),
+            minimal_node_repr(suspect.node, graph),
+            if reasons.is_empty() {
+                String::new()
+            } else {
+                format!("  ({})", reasons.join(", "))
+            },
+        )?;
+        if let Some(path) = analyzed.paths_to_limited(suspect.node, 1).first() {
+            write!(f, "                 ")?;
+            for edge in path.edges(graph) {
+                fmt_edge(f, &edge)?;
+            }
+            writeln!(f)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn print_duplicates(groups: &[DuplicateGroup], graph: &V8HeapGraph) {
+    let mut s = String::new();
+    let _ = format_duplicates(&mut s, groups, graph);
+    print!("{}", s);
+}
+
+pub fn format_duplicates<F: std::fmt::Write>(
+    f: &mut F,
+    groups: &[DuplicateGroup],
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    writeln!(f, "Duplicate objects:")?;
+    for group in groups.iter().take(20) {
+        writeln!(
+            f,
+            "{:>8}  {} x {} ({} each)  e.g. @{}  {}",
+            format_bytes(group.total_wasted as usize),
+            group.count,
+            group.object_type,
+            format_bytes(group.size_per_object as usize),
+            graph.node(group.representative).stable_id(),
+            group.sample_value.as_deref().unwrap_or_default(),
+        )?;
+        // The root and GC root categories dominate everything, so they don't say much
+        if let Some(dominator) = group.common_dominator
+            && graph.node(dominator).typ() != NodeType::Synthetic
+        {
+            writeln!(
+                f,
+                "          all {} dominated by {} @{}",
+                group.count,
+                minimal_node_repr(dominator, graph),
+                graph.node(dominator).stable_id(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn print_duplicate_growth(grown: &[DuplicateGrowth], graph: &V8HeapGraph) {
+    let mut s = String::new();
+    let _ = format_duplicate_growth(&mut s, grown, graph);
+    print!("{}", s);
+}
+
+/// The result of `diff_duplicates`; node ids refer to the later snapshot
+pub fn format_duplicate_growth<F: std::fmt::Write>(
+    f: &mut F,
+    grown: &[DuplicateGrowth],
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    writeln!(f, "Duplicates that grew:")?;
+    if grown.is_empty() {
+        writeln!(f, "  (none)")?;
+    }
+    for growth in grown.iter().take(20) {
+        writeln!(
+            f,
+            "{:>8}  {} x {} (was {})  e.g. @{}  {}",
+            format_bytes_signed(growth.wasted_delta(), ByteUnit::Binary),
+            growth.count_after,
+            growth.object_type,
+            growth.count_before,
+            graph.node(growth.representative).stable_id(),
+            growth.sample_value.as_deref().unwrap_or_default(),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_sampled_report(report: &SampledReport, graph: &V8HeapGraph) {
+    println!(
+        "Sampled: the {} largest of {} nodes, without retained sizes. Results are approximate.",
+        report.sampled_node_count, report.total_node_count
+    );
+    println!();
+    print_heap_composition(&report.composition);
+    println!();
+    print_duplicates(&report.duplicates, graph);
+}
+
+pub fn print_constructor_rollup(summaries: &[ConstructorSummary]) {
+    let mut s = String::new();
+    let _ = format_constructor_rollup(&mut s, summaries);
+    print!("{}", s);
+}
+
+pub fn format_constructor_rollup<F: std::fmt::Write>(
+    f: &mut F,
+    summaries: &[ConstructorSummary],
+) -> std::fmt::Result {
+    writeln!(f, "Duplicates by constructor:")?;
+    for summary in summaries.iter().take(20) {
+        writeln!(
+            f,
+            "{:>8}  {}: {} duplicates across {} shape(s)",
+            format_bytes(summary.total_wasted as usize),
+            summary.constructor,
+            summary.count,
+            summary.group_count,
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_tag_rollup(summaries: &[TagSummary]) {
+    let mut s = String::new();
+    let _ = format_tag_rollup(&mut s, summaries);
+    print!("{}", s);
+}
+
+pub fn format_tag_rollup<F: std::fmt::Write>(
+    f: &mut F,
+    summaries: &[TagSummary],
+) -> std::fmt::Result {
+    writeln!(f, "Duplicates by tag:")?;
+    for summary in summaries {
+        writeln!(
+            f,
+            "{:>8}  {}: {} duplicates in {} group(s)",
+            format_bytes(summary.total_wasted as usize),
+            summary.tag,
+            summary.count,
+            summary.group_count,
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_shape_clusters(clusters: &[ShapeCluster], graph: &V8HeapGraph) {
+    let mut s = String::new();
+    let _ = format_shape_clusters(&mut s, clusters, graph);
+    print!("{}", s);
+}
+
+pub fn format_shape_clusters<F: std::fmt::Write>(
+    f: &mut F,
+    clusters: &[ShapeCluster],
+    graph: &V8HeapGraph,
+) -> std::fmt::Result {
+    writeln!(f, "Objects by shape:")?;
+    for cluster in clusters.iter().take(20) {
+        writeln!(
+            f,
+            "{:>8}  {} objects with shape {{{}}}  e.g. @{}",
+            format_bytes(cluster.total_self_size),
+            cluster.count,
+            cluster.properties.join(", "),
+            graph.node(cluster.representative).stable_id(),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_contexts(contexts: &[ContextRoot], analyzed: AnalyzedGraph<'_>) {
+    let mut s = String::new();
+    let _ = format_contexts(&mut s, contexts, analyzed);
+    print!("{}", s);
+}
+
+/// One line per context, numbered the way `--context` takes them
+pub fn format_contexts<F: std::fmt::Write>(
+    f: &mut F,
+    contexts: &[ContextRoot],
+    analyzed: AnalyzedGraph<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "Contexts:")?;
+    for (i, context) in contexts.iter().enumerate() {
+        write!(
+            f,
+            "{:>4}  {:>8}  @{}  {}",
+            i,
+            format_bytes(analyzed.retained_size(context.native_context)),
+            analyzed.node(context.native_context).stable_id(),
+            context.url.as_deref().unwrap_or("(no URL)"),
+        )?;
+        if let Some(global) = context.global_proxy {
+            write!(f, "  global @{}", analyzed.node(global).stable_id())?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+pub fn print_large_retainers(nodes: &[NodeId], analyzed: AnalyzedGraph<'_>) {
+    println!("Objects by retained size:");
+    print_retainers(nodes, analyzed);
+}
+
+pub fn format_largest_unique_retainers<F: std::fmt::Write>(
+    f: &mut F,
+    nodes: &[NodeId],
+    analyzed: AnalyzedGraph<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "Largest retained objects (not duplicated):")?;
+    format_retainers(f, nodes, analyzed)
+}
+
+pub fn print_largest_unique_retainers(nodes: &[NodeId], analyzed: AnalyzedGraph<'_>) {
+    let mut s = String::new();
+    let _ = format_largest_unique_retainers(&mut s, nodes, analyzed);
+    print!("{}", s);
+}
+
+pub fn format_retained_ratios<F: std::fmt::Write>(
+    f: &mut F,
+    ratios: &[(NodeId, f64)],
+    analyzed: AnalyzedGraph<'_>,
+) -> std::fmt::Result {
+    let graph = analyzed.graph;
+    writeln!(f, "Objects by retained size relative to self size:")?;
+    for &(node, ratio) in ratios.iter().take(20) {
+        writeln!(
+            f,
+            "{:>7.0}x  {:>8}  @{}  {}",
+            ratio,
+            format_bytes(analyzed.retained_size(node)),
+            graph.node(node).stable_id(),
+            minimal_node_repr(node, graph),
+        )?;
+        format_first_path(f, "                    ", node, analyzed)?;
+    }
+    Ok(())
+}
+
+pub fn print_retained_ratios(ratios: &[(NodeId, f64)], analyzed: AnalyzedGraph<'_>) {
+    let mut s = String::new();
+    let _ = format_retained_ratios(&mut s, ratios, analyzed);
+    print!("{}", s);
+}
+
+/// Width of the longest bar in the size histogram
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// How many instances to show when focusing on a constructor
+const REPORTED_FOCUS_INSTANCES: usize = 5;
+
+pub fn print_focus(report: &FocusReport, analyzed: AnalyzedGraph<'_>) {
+    println!("Instances of {}:", report.constructor);
+    println!("  Count:          {}", report.count);
+    println!("  Self size:      {}", format_bytes(report.total_self_size));
+    println!(
+        "  Retained size:  {}",
+        format_bytes(report.total_retained_size)
+    );
+    println!("  Hidden classes: {}", report.hidden_class_count);
+    println!("  Property sets:  {}", report.property_set_count);
+    if report.instances.is_empty() {
+        return;
+    }
+
+    let dist = &report.size_distribution;
+    println!(
+        "  Self sizes:     min {}, median {}, p90 {}, p99 {}, max {}",
+        format_bytes(dist.min),
+        format_bytes(dist.median),
+        format_bytes(dist.p90),
+        format_bytes(dist.p99),
+        format_bytes(dist.max),
+    );
+
+    println!();
+    println!("Self size histogram:");
+    let largest_bucket = dist.histogram.iter().map(|b| b.count).max().unwrap_or(1);
+    for bucket in &dist.histogram {
+        println!(
+            "{:>8} - {:<8} {:>8}  {}",
+            format_bytes(bucket.from),
+            format_bytes(bucket.to),
+            bucket.count,
+            "#".repeat(
+                bucket
+                    .count
+                    .div_ceil(largest_bucket.div_ceil(HISTOGRAM_WIDTH))
+            ),
+        );
+    }
+
+    println!();
+    println!("Largest instances:");
+    print_retainers(
+        &report.instances[..report.instances.len().min(REPORTED_FOCUS_INSTANCES)],
+        analyzed,
+    );
+}
+
+fn print_retainers(nodes: &[NodeId], analyzed: AnalyzedGraph<'_>) {
+    let mut s = String::new();
+    let _ = format_retainers(&mut s, nodes, analyzed);
+    print!("{}", s);
+}
+
+/// One line per node with its retained size, and a second line with its first retention path
+fn format_retainers<F: std::fmt::Write>(
+    f: &mut F,
+    nodes: &[NodeId],
+    analyzed: AnalyzedGraph<'_>,
+) -> std::fmt::Result {
+    let graph = analyzed.graph;
+    for &node in nodes {
+        writeln!(
+            f,
+            "{:>8}  @{}  {}",
+            format_bytes(analyzed.retained_size(node)),
+            graph.node(node).stable_id(),
+            minimal_node_repr(node, graph),
+        )?;
+        format_first_path(f, "          ", node, analyzed)?;
+    }
+    Ok(())
+}
+
+/// The first retention path of a node on a line of its own, for under a line describing it
+fn format_first_path<F: std::fmt::Write>(
+    f: &mut F,
+    indent: &str,
+    node: NodeId,
+    analyzed: AnalyzedGraph<'_>,
+) -> std::fmt::Result {
+    write!(f, "{}", indent)?;
+    if let Some(path) = analyzed.paths_to_limited(node, 1).first() {
+        for edge in path.edges(analyzed.graph) {
+            fmt_edge(f, &edge)?;
+        }
+    }
+    writeln!(f)
+}
+
+pub fn print_retention_cycles(cycles: &[RetentionCycle], graph: &V8HeapGraph) {
+    println!("Retention cycles:");
+    for cycle in cycles.iter().take(20) {
+        println!(
+            "{:>8}  {} objects ({})  e.g. @{}  {}",
+            format_bytes(cycle.total_self_size),
+            cycle.nodes.len(),
+            cycle
+                .member_types
+                .iter()
+                .map(|(typ, count)| format!("{} {}", count, typ))
+                .join(", "),
+            graph.node(cycle.nodes[0]).stable_id(),
+            minimal_node_repr(cycle.nodes[0], graph),
+        );
+    }
+}
+
 fn show_node(node: Node<'_>) -> String {
     node.graph
         .out_edges(node.id)
         .map(|e| format!("{} {} {}", e.typ_str(), e.name_or_index(), e.to_node()))
         .join(", ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::all_paths::find_root_paths, graph::test_graph::TestGraph};
+
+    #[test]
+    fn test_devtools_path_format() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "(GC roots)", 0);
+        let app = g.node(NodeType::Object, "App", 10);
+        let ctx = g.node(NodeType::Object, "system / Context", 10);
+        let target = g.node(NodeType::Object, "Target", 10);
+        g.edge(root, EdgeType::Property, "app", app)
+            .element(app, 2, ctx)
+            .edge(ctx, EdgeType::Context, "cache", target);
+        let graph = g.build();
+        let paths = find_root_paths(&graph, root);
+
+        let mut s = String::new();
+        format_retention_paths_devtools(&mut s, target, &paths, &graph).unwrap();
+        assert_eq!(
+            s,
+            "->cache in system / Context @5\n  [2] in App @3\n    app in (GC roots) @1\n"
+        );
+
+        let mut s = String::new();
+        format_retention_paths(&mut s, target, &paths, &graph).unwrap();
+        assert_eq!(s, ".app[2](context/cache)\n");
+    }
+
+    #[test]
+    fn test_in_edge_summary() {
+        let mut g = TestGraph::new();
+        let root = g.node(NodeType::Synthetic, "root", 0);
+        let a = g.node(NodeType::Object, "A", 10);
+        let b = g.node(NodeType::Object, "B", 10);
+        let ctx = g.node(NodeType::Object, "system / Context", 10);
+        let target = g.node(NodeType::Object, "Target", 10);
+        g.edge(root, EdgeType::Property, "t", target)
+            .edge(a, EdgeType::Property, "t", target)
+            .edge(b, EdgeType::Property, "t", target)
+            .edge(ctx, EdgeType::Context, "t", target)
+            .edge(a, EdgeType::Weak, "w", target)
+            .edge(b, EdgeType::Weak, "w", target);
+        let graph = g.build();
+
+        assert_eq!(
+            in_edge_summary(target, &graph),
+            vec![
+                ("property".to_string(), 3),
+                ("weak".to_string(), 2),
+                ("context".to_string(), 1)
+            ]
+        );
+        assert!(in_edge_summary(root, &graph).is_empty());
+
+        let mut s = String::new();
+        format_in_edges(&mut s, target, &graph).unwrap();
+        assert!(s.starts_with("Retained by: 3 property edges, 2 weak edges, 1 context edge\n"));
+        assert_eq!(s.lines().count(), 1 + 6);
+    }
+}
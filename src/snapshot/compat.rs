@@ -0,0 +1,171 @@
+use std::sync::OnceLock;
+
+use crate::snapshot::SnapshotMetadata;
+
+/// Node fields every snapshot has, always in this order and always first
+pub const REQUIRED_NODE_FIELDS: [&str; 5] = ["type", "name", "id", "self_size", "edge_count"];
+
+/// Node fields that only some V8 versions write
+///
+/// `trace_node_id` links nodes to allocation traces. `detachedness` was added in 2021 to mark
+/// DOM nodes that are no longer in a document; older snapshots simply have no detached nodes.
+pub const OPTIONAL_NODE_FIELDS: [&str; 2] = ["trace_node_id", "detachedness"];
+
+/// Edge fields, which haven't changed in any V8 version so far
+pub const EDGE_FIELDS: [&str; 3] = ["type", "name_or_index", "to_node"];
+
+/// The revisions of the snapshot format this build has been tested against, oldest first,
+/// with their node layouts
+///
+/// Snapshots don't carry a format version, so a revision is recognized by its fields.
+pub const SUPPORTED_NODE_LAYOUTS: [(&str, &[&str]); 2] = [
+    (
+        "V8 before 2021",
+        &[
+            "type",
+            "name",
+            "id",
+            "self_size",
+            "edge_count",
+            "trace_node_id",
+        ],
+    ),
+    (
+        "V8 since 2021, with detachedness",
+        &[
+            "type",
+            "name",
+            "id",
+            "self_size",
+            "edge_count",
+            "trace_node_id",
+            "detachedness",
+        ],
+    ),
+];
+
+/// The version with the snapshot format revisions it supports, for `--version`
+pub fn version_info() -> &'static str {
+    static INFO: OnceLock<String> = OnceLock::new();
+    INFO.get_or_init(|| {
+        let mut info = env!("CARGO_PKG_VERSION").to_string();
+        info.push_str("\nSupported snapshot formats:");
+        for (revision, layout) in SUPPORTED_NODE_LAYOUTS {
+            info.push_str(&format!(
+                "\n  {}: node_fields [{}], edge_fields [{}]",
+                revision,
+                layout.join(", "),
+                EDGE_FIELDS.join(", ")
+            ));
+        }
+        info.push_str("\nOther fields are tolerated but ignored.");
+        info
+    })
+}
+
+/// Everything in the snapshot's layout that this build doesn't fully understand
+///
+/// Unknown fields don't stop the analysis: nodes and edges are read by position, and fields
+/// we don't know about are skipped. But they usually mean the snapshot comes from a newer V8
+/// than this build knows, so the results are worth a second look.
+pub fn compatibility_warnings(meta: &SnapshotMetadata) -> Vec<String> {
+    let mut warnings = vec![];
+
+    let unknown_node_fields: Vec<&str> = meta
+        .node_fields
+        .iter()
+        .map(String::as_str)
+        .filter(|f| !REQUIRED_NODE_FIELDS.contains(f) && !OPTIONAL_NODE_FIELDS.contains(f))
+        .collect();
+    if !unknown_node_fields.is_empty() {
+        warnings.push(format!(
+            "The snapshot has node fields this version doesn't recognize: {}",
+            unknown_node_fields.join(", ")
+        ));
+    }
+
+    let unknown_edge_fields: Vec<&str> = meta
+        .edge_fields
+        .iter()
+        .map(String::as_str)
+        .filter(|f| !EDGE_FIELDS.contains(f))
+        .collect();
+    if !unknown_edge_fields.is_empty() {
+        warnings.push(format!(
+            "The snapshot has edge fields this version doesn't recognize: {}",
+            unknown_edge_fields.join(", ")
+        ));
+    }
+
+    if !meta.node_fields.iter().any(|f| f == "detachedness") {
+        warnings.push(
+            "The snapshot has no detachedness field, so detached DOM nodes can't be recognized"
+                .to_string(),
+        );
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        graph::{
+            test_graph::TestGraph,
+            v8_heap_graph::{EdgeType, NodeType, V8HeapGraph},
+        },
+        types::NodeId,
+    };
+
+    #[test]
+    fn test_unknown_node_field_warns() {
+        let mut g = TestGraph::new();
+        let a = g.node(NodeType::Object, "A", 16);
+        let b = g.node(NodeType::Object, "B", 16);
+        g.edge(a, EdgeType::Property, "b", b);
+        let mut snapshot = g.snapshot();
+        assert_eq!(
+            compatibility_warnings(&snapshot.snapshot.meta),
+            Vec::<String>::new()
+        );
+
+        // A field from some future V8, after all the ones we know
+        let stride = snapshot.snapshot.meta.node_field_count();
+        snapshot
+            .snapshot
+            .meta
+            .node_fields
+            .push("retainer_hint".to_string());
+        snapshot.nodes = snapshot
+            .nodes
+            .chunks(stride)
+            .flat_map(|node| node.iter().copied().chain([7]))
+            .collect();
+        for to_node in snapshot.edges.iter_mut().skip(2).step_by(3) {
+            *to_node = *to_node / stride as NodeId * (stride as NodeId + 1);
+        }
+
+        let warnings = compatibility_warnings(&snapshot.snapshot.meta);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("retainer_hint"));
+
+        let graph = V8HeapGraph::from(snapshot);
+        assert_eq!(graph.out_neighbors(a), vec![b]);
+        assert_eq!(graph.node(b).self_size(), 16);
+    }
+
+    #[test]
+    fn test_reordered_required_field_is_an_error() {
+        let mut g = TestGraph::new();
+        g.node(NodeType::Object, "A", 16);
+        let mut snapshot = g.snapshot();
+        snapshot.snapshot.meta.node_fields.swap(2, 3);
+        assert!(V8HeapGraph::from_snapshot(snapshot).is_err());
+
+        let mut snapshot = TestGraph::new().snapshot();
+        snapshot.snapshot.meta.edge_fields[2] = "target".to_string();
+        let err = V8HeapGraph::from_snapshot(snapshot).unwrap_err();
+        assert!(err.to_string().contains("to_node"), "{}", err);
+    }
+}
@@ -0,0 +1,130 @@
+use ahash::AHashMap;
+use anyhow::{Context, Result, ensure};
+use serde::Serialize;
+
+use crate::{snapshot::SnapshotFile, types::NodeId};
+
+/// Where in the source code an object (usually a closure) was defined
+///
+/// Lines and columns are 0-based, as V8 reports them. Scripts are only known by their id;
+/// the snapshot doesn't contain their URLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SourceLocation {
+    pub script_id: NodeId,
+    pub line: NodeId,
+    pub column: NodeId,
+}
+
+/// The `locations` table of a snapshot, indexed by node
+///
+/// Must be built from the `SnapshotFile`, since the graph doesn't keep the table.
+#[derive(Debug, Default)]
+pub struct SourceLocations {
+    by_node: AHashMap<NodeId, SourceLocation>,
+}
+
+impl SourceLocations {
+    /// Read the locations table, using `location_fields` to find the columns
+    ///
+    /// Snapshots without locations give an empty table.
+    pub fn from_snapshot(snapshot: &SnapshotFile) -> Result<Self> {
+        let meta = &snapshot.snapshot.meta;
+        if snapshot.locations.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let field = |name: &str| {
+            meta.location_fields
+                .iter()
+                .position(|f| f == name)
+                .with_context(|| format!("Snapshot locations have no '{}' field", name))
+        };
+        let object_index = field("object_index")?;
+        let script_id = field("script_id")?;
+        let line = field("line")?;
+        let column = field("column")?;
+
+        let stride = meta.location_fields.len();
+        ensure!(
+            snapshot.locations.len().is_multiple_of(stride),
+            "Locations table of length {} does not contain whole locations (stride {})",
+            snapshot.locations.len(),
+            stride
+        );
+
+        let node_stride = meta.node_field_count() as NodeId;
+        let mut by_node = AHashMap::with_capacity(snapshot.locations.len() / stride);
+        for location in snapshot.locations.chunks_exact(stride) {
+            // Like edge targets, object indexes point into the flattened nodes array
+            let index = location[object_index];
+            ensure!(
+                index.is_multiple_of(node_stride),
+                "Location object index {} is not a multiple of the node stride {}",
+                index,
+                node_stride
+            );
+            by_node.insert(
+                index / node_stride,
+                SourceLocation {
+                    script_id: location[script_id],
+                    line: location[line],
+                    column: location[column],
+                },
+            );
+        }
+
+        Ok(Self { by_node })
+    }
+
+    pub fn source_location(&self, node: NodeId) -> Option<SourceLocation> {
+        self.by_node.get(&node).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_node.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_node.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{test_graph::TestGraph, v8_heap_graph::NodeType};
+
+    #[test]
+    fn test_source_location() {
+        let mut g = TestGraph::new();
+        g.node(NodeType::Synthetic, "root", 0);
+        let closure = g.node(NodeType::Closure, "handler", 32);
+        let mut snapshot = g.snapshot();
+        let meta = &mut snapshot.snapshot.meta;
+        meta.location_fields = ["object_index", "script_id", "line", "column"]
+            .map(String::from)
+            .to_vec();
+        let node_stride = meta.node_field_count() as NodeId;
+
+        assert!(
+            SourceLocations::from_snapshot(&snapshot)
+                .unwrap()
+                .is_empty()
+        );
+
+        snapshot.locations = vec![closure * node_stride, 42, 11, 4];
+        let locations = SourceLocations::from_snapshot(&snapshot).unwrap();
+        assert_eq!(
+            locations.source_location(closure),
+            Some(SourceLocation {
+                script_id: 42,
+                line: 11,
+                column: 4
+            })
+        );
+        assert_eq!(locations.source_location(0), None);
+
+        snapshot.locations = vec![closure * node_stride + 1, 42, 11, 4];
+        assert!(SourceLocations::from_snapshot(&snapshot).is_err());
+    }
+}
@@ -1,10 +1,21 @@
-use anyhow::{Context, Result};
-use std::{fs::File, io::BufReader, path::Path};
+use anyhow::{Context, Result, ensure};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
 
-use serde::Deserialize;
+use serde::{
+    Deserialize, Deserializer,
+    de::{IgnoredAny, MapAccess, SeqAccess, Visitor},
+};
 
 use crate::types::NodeId;
 
+pub mod compat;
+pub mod locations;
+pub mod recover;
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum StringOrStrings {
@@ -46,6 +57,8 @@ pub struct Snapshot {
     pub node_count: usize,
     pub edge_count: usize,
     pub trace_function_count: usize,
+    /// Only written by newer V8 versions (Node 22 and later)
+    #[serde(default)]
     pub extra_native_bytes: usize,
 }
 
@@ -54,19 +67,403 @@ pub struct SnapshotFile {
     pub snapshot: Snapshot,
     pub nodes: Vec<NodeId>,
     pub edges: Vec<NodeId>,
+    #[serde(default, deserialize_with = "deserialize_lenient_locations")]
     pub locations: Vec<NodeId>,
     pub samples: Vec<serde_json::Value>, // ?
+    #[serde(deserialize_with = "deserialize_lossy_strings")]
     pub strings: Vec<String>,
     pub trace_function_infos: Vec<serde_json::Value>, // ?
     pub trace_tree: Vec<serde_json::Value>,           // ?
 }
 
 pub fn read_v8_snapshot_file(path: &Path) -> Result<SnapshotFile> {
+    read_v8_snapshot_file_at(path, None)
+}
+
+/// Read one snapshot from a file that may contain several
+///
+/// `index` picks a snapshot from a file holding a top-level array of snapshots; `None` picks
+/// the last one. A file with a single snapshot (this includes `.heaptimeline` recordings,
+/// which are ordinary snapshots with a filled in `samples` table) counts as a sequence of one.
+pub fn read_v8_snapshot_file_at(path: &Path, index: Option<usize>) -> Result<SnapshotFile> {
     let file = File::open(path).context("Failed to open snapshot file")?;
-    let reader = BufReader::new(file);
+    select_snapshot(read_v8_snapshots_from_reader(BufReader::new(file))?, index)
+}
+
+/// Parse a snapshot from any source, such as a network stream or an in-memory buffer
+///
+/// The reader is not buffered here; wrap it in a `BufReader` if reads are expensive. If the
+/// source contains a sequence of snapshots, the last one is returned.
+pub fn read_v8_snapshot_from_reader<R: Read>(reader: R) -> Result<SnapshotFile> {
+    select_snapshot(read_v8_snapshots_from_reader(reader)?, None)
+}
+
+/// Parse either a single snapshot or a JSON array of snapshots
+///
+/// The two are told apart by the first non-whitespace character, so the snapshots themselves
+/// are still parsed straight from the reader. All snapshots of a sequence are kept in memory.
+pub fn read_v8_snapshots_from_reader<R: Read>(mut reader: R) -> Result<Vec<SnapshotFile>> {
+    let mut first = [0u8];
+    loop {
+        reader
+            .read_exact(&mut first)
+            .context("Failed to parse snapshot JSON")?;
+        if !first[0].is_ascii_whitespace() {
+            break;
+        }
+    }
+    let reader = std::io::Cursor::new(first).chain(reader);
+
+    if first[0] == b'[' {
+        serde_json::from_reader(reader).context("Failed to parse snapshot sequence JSON")
+    } else {
+        let snapshot: SnapshotFile =
+            serde_json::from_reader(reader).context("Failed to parse snapshot JSON")?;
+        Ok(vec![snapshot])
+    }
+}
+
+/// Pick the snapshot at `index` from a sequence, or the last one if `index` is `None`
+pub fn select_snapshot(
+    mut snapshots: Vec<SnapshotFile>,
+    index: Option<usize>,
+) -> Result<SnapshotFile> {
+    let count = snapshots.len();
+    ensure!(count > 0, "The file does not contain any snapshots");
+    let index = index.unwrap_or(count - 1);
+    ensure!(
+        index < count,
+        "Snapshot index {} is out of range: the file contains {} snapshot{}",
+        index,
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    Ok(snapshots.swap_remove(index))
+}
+
+/// Deserialize the string table, tolerating strings that are not valid Unicode
+///
+/// V8 strings are UTF-16 and can contain lone surrogates (`"\ud800"`), which serde_json
+/// refuses to decode into a `String`. We read every string as bytes instead, and replace
+/// anything that doesn't decode as UTF-8 with U+FFFD.
+fn deserialize_lossy_strings<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let strings = Vec::<LossyString>::deserialize(deserializer)?;
+    Ok(strings.into_iter().map(|s| s.0).collect())
+}
+
+/// Deserialize the locations table, giving up on it instead of on the whole snapshot
+///
+/// Locations are optional decoration, and their layout has changed between V8 versions. If
+/// any entry is not a plain unsigned integer we can't trust the arity anymore, so we drop the
+/// table entirely and the snapshot loads as if it had no locations.
+fn deserialize_lenient_locations<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<NodeId>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = Vec::<LenientNumber>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|n| n.0)
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default())
+}
+
+/// A number that fits in a `NodeId`, or `None` for any other JSON value
+struct LenientNumber(Option<NodeId>);
+
+impl<'de> Deserialize<'de> for LenientNumber {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(LenientNumberVisitor)
+    }
+}
+
+struct LenientNumberVisitor;
+
+impl<'de> Visitor<'de> for LenientNumberVisitor {
+    type Value = LenientNumber;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "any JSON value")
+    }
+
+    fn visit_u64<E>(self, v: u64) -> std::result::Result<Self::Value, E> {
+        Ok(LenientNumber(NodeId::try_from(v).ok()))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> std::result::Result<Self::Value, E> {
+        Ok(LenientNumber(NodeId::try_from(v).ok()))
+    }
+
+    fn visit_f64<E>(self, _: f64) -> std::result::Result<Self::Value, E> {
+        Ok(LenientNumber(None))
+    }
+
+    fn visit_bool<E>(self, _: bool) -> std::result::Result<Self::Value, E> {
+        Ok(LenientNumber(None))
+    }
+
+    fn visit_str<E>(self, _: &str) -> std::result::Result<Self::Value, E> {
+        Ok(LenientNumber(None))
+    }
+
+    fn visit_unit<E>(self) -> std::result::Result<Self::Value, E> {
+        Ok(LenientNumber(None))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(LenientNumber(None))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+        Ok(LenientNumber(None))
+    }
+}
 
-    let snapshot: SnapshotFile =
-        serde_json::from_reader(reader).context("Failed to parse snapshot JSON")?;
+struct LossyString(String);
 
-    Ok(snapshot)
+impl<'de> Deserialize<'de> for LossyString {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(LossyStringVisitor)
+    }
+}
+
+struct LossyStringVisitor;
+
+impl<'de> Visitor<'de> for LossyStringVisitor {
+    type Value = LossyString;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a string")
+    }
+
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E> {
+        Ok(LossyString(v.to_string()))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<Self::Value, E> {
+        Ok(LossyString(String::from_utf8_lossy(v).into_owned()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<Self::Value, E> {
+        Ok(LossyString(match String::from_utf8(v) {
+            Ok(s) => s,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_lone_surrogates_in_strings() {
+        let json = r#"{
+            "snapshot": {
+                "meta": {
+                    "node_fields": ["type", "name", "id", "self_size", "edge_count", "detachedness"],
+                    "node_types": [["hidden", "array", "string"], "string", "number", "number", "number", "number"],
+                    "edge_fields": ["type", "name_or_index", "to_node"],
+                    "edge_types": [["context", "element", "property"], "string_or_number", "node"],
+                    "location_fields": [],
+                    "sample_fields": [],
+                    "trace_function_info_fields": [],
+                    "trace_node_fields": []
+                },
+                "node_count": 1,
+                "edge_count": 0,
+                "trace_function_count": 0,
+                "extra_native_bytes": 0
+            },
+            "nodes": [2, 1, 1, 16, 0, 0],
+            "edges": [],
+            "locations": [],
+            "samples": [],
+            "strings": ["", "lone \ud800 surrogate", "valid \ud83d\ude00 pair"],
+            "trace_function_infos": [],
+            "trace_tree": []
+        }"#;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(json.as_bytes()).unwrap();
+
+        let snapshot = read_v8_snapshot_file(file.path()).unwrap();
+
+        assert_eq!(snapshot.strings.len(), 3);
+        assert!(snapshot.strings[1].starts_with("lone "));
+        assert!(snapshot.strings[1].ends_with(" surrogate"));
+        assert!(snapshot.strings[1].contains('\u{FFFD}'));
+        assert_eq!(snapshot.strings[2], "valid \u{1F600} pair");
+    }
+
+    fn snapshot_with_locations(locations: &str) -> String {
+        format!(
+            r#"{{
+            "snapshot": {{
+                "meta": {{
+                    "node_fields": ["type", "name", "id", "self_size", "edge_count"],
+                    "node_types": [["hidden", "closure", "string"], "string", "number", "number", "number"],
+                    "edge_fields": ["type", "name_or_index", "to_node"],
+                    "edge_types": [["context", "element", "property"], "string_or_number", "node"],
+                    "location_fields": ["object_index", "script_id", "line", "column"],
+                    "sample_fields": ["timestamp_us", "last_assigned_id"],
+                    "trace_function_info_fields": [],
+                    "trace_node_fields": []
+                }},
+                "node_count": 2,
+                "edge_count": 0,
+                "trace_function_count": 0,
+                "extra_native_bytes": 0
+            }},
+            "nodes": [1, 1, 1, 32, 0, 1, 2, 3, 32, 0],
+            "edges": [],
+            "locations": {locations},
+            "samples": [0, 1, 51218, 3],
+            "strings": ["", "foo", "bar"],
+            "trace_function_infos": [],
+            "trace_tree": []
+        }}"#
+        )
+    }
+
+    #[test]
+    fn test_lenient_locations() {
+        let parse = |locations: &str| {
+            let json = snapshot_with_locations(locations);
+            read_v8_snapshot_from_reader(std::io::Cursor::new(json.into_bytes())).unwrap()
+        };
+
+        // One entry per closure: object index, script id, line, column
+        let snapshot = parse("[0, 97, 12, 4, 5, 97, 20, 17]");
+        assert_eq!(snapshot.locations, vec![0, 97, 12, 4, 5, 97, 20, 17]);
+
+        // Entries we can't interpret drop the table, not the snapshot
+        let snapshot = parse("[0, 97, -1, 4.5, null, \"x\"]");
+        assert!(snapshot.locations.is_empty());
+        assert_eq!(snapshot.nodes.len(), 10);
+
+        // No locations section at all
+        let json = snapshot_with_locations("[]").replace("\"locations\": [],", "");
+        let snapshot =
+            read_v8_snapshot_from_reader(std::io::Cursor::new(json.into_bytes())).unwrap();
+        assert!(snapshot.locations.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_sequence() {
+        let first = snapshot_with_locations("[]");
+        let second = first.replace(r#""foo", "bar""#, r#""foo", "baz""#);
+        let json = format!("\n  [{}, {}]", first, second);
+        let pick = |index| {
+            select_snapshot(
+                read_v8_snapshots_from_reader(std::io::Cursor::new(json.as_bytes())).unwrap(),
+                index,
+            )
+        };
+
+        assert_eq!(pick(Some(0)).unwrap().strings[2], "bar");
+        assert_eq!(pick(Some(1)).unwrap().strings[2], "baz");
+        // The last snapshot is the default
+        assert_eq!(pick(None).unwrap().strings[2], "baz");
+        assert_eq!(
+            pick(Some(2)).unwrap_err().to_string(),
+            "Snapshot index 2 is out of range: the file contains 2 snapshots"
+        );
+
+        // A single snapshot is a sequence of one
+        let single = read_v8_snapshots_from_reader(std::io::Cursor::new(first.as_bytes()));
+        assert_eq!(single.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_read_from_cursor() {
+        let json = r#"{
+            "snapshot": {
+                "meta": {
+                    "node_fields": ["type", "name", "id", "self_size", "edge_count"],
+                    "node_types": [["hidden", "array", "string"], "string", "number", "number", "number"],
+                    "edge_fields": ["type", "name_or_index", "to_node"],
+                    "edge_types": [["context", "element", "property"], "string_or_number", "node"],
+                    "location_fields": [],
+                    "sample_fields": [],
+                    "trace_function_info_fields": [],
+                    "trace_node_fields": []
+                },
+                "node_count": 2,
+                "edge_count": 1,
+                "trace_function_count": 0,
+                "extra_native_bytes": 0
+            },
+            "nodes": [1, 1, 1, 16, 1, 2, 2, 3, 24, 0],
+            "edges": [2, 2, 5],
+            "locations": [],
+            "samples": [],
+            "strings": ["", "root", "str"],
+            "trace_function_infos": [],
+            "trace_tree": []
+        }"#;
+
+        let cursor = std::io::Cursor::new(json.as_bytes().to_vec());
+        let snapshot = read_v8_snapshot_from_reader(cursor).unwrap();
+
+        assert_eq!(snapshot.snapshot.node_count, 2);
+        assert_eq!(snapshot.nodes.len(), 10);
+        assert_eq!(snapshot.edges, vec![2, 2, 5]);
+        assert_eq!(snapshot.strings[2], "str");
+
+        assert!(read_v8_snapshot_from_reader(std::io::Cursor::new(b"{".to_vec())).is_err());
+    }
+
+    /// Counts the bytes that go through it
+    struct CountingReader<R> {
+        inner: R,
+        bytes_read: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.bytes_read += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_snapshot_is_parsed_in_one_pass() {
+        let bytes = std::fs::read("tests/fixtures/object.heapsnapshot").unwrap();
+        let mut reader = CountingReader {
+            inner: bytes.as_slice(),
+            bytes_read: 0,
+        };
+
+        // Metadata, strings, nodes and edges all come out of a single read of the input
+        let snapshot = read_v8_snapshot_from_reader(&mut reader).unwrap();
+        assert_eq!(reader.bytes_read, bytes.len());
+        assert!(!snapshot.strings.is_empty());
+        assert_eq!(
+            snapshot.nodes.len(),
+            snapshot.snapshot.node_count * snapshot.snapshot.meta.node_field_count()
+        );
+        assert_eq!(snapshot.edges.len(), snapshot.snapshot.edge_count * 3);
+    }
 }
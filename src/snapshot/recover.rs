@@ -0,0 +1,390 @@
+use anyhow::{Context, Result, bail, ensure};
+use serde::Deserialize;
+
+use crate::{
+    snapshot::{LossyString, Snapshot, SnapshotFile, StringOrStrings},
+    types::NodeId,
+};
+
+/// Placeholder for names whose strings were cut off the end of the file
+pub const LOST_STRING: &str = "(lost)";
+
+/// How much of a damaged snapshot could be read
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recovery {
+    /// Whether the file ended before the snapshot was complete
+    pub truncated: bool,
+    pub nodes: usize,
+    /// The number of nodes the header announces
+    pub expected_nodes: usize,
+    pub edges: usize,
+    pub expected_edges: usize,
+    /// Strings that were read; names referring past these are `LOST_STRING`
+    pub strings: usize,
+    pub lost_strings: usize,
+}
+
+impl Recovery {
+    pub fn summary(&self) -> String {
+        format!(
+            "recovered {} of {} nodes, {} of {} edges and {} strings ({} lost)",
+            self.nodes,
+            self.expected_nodes,
+            self.edges,
+            self.expected_edges,
+            self.strings,
+            self.lost_strings
+        )
+    }
+}
+
+/// Read as much as possible of a snapshot that may have been cut off, e.g. by a crash
+///
+/// A complete snapshot is parsed as usual. Otherwise the top-level fields are read one at a
+/// time, keeping every complete number of `nodes` and `edges` and every complete entry of
+/// `strings` up to the point where the file ends. The pieces are then made consistent:
+///
+/// - Edges past the end of the data, or pointing at nodes that were lost, are dropped, and the
+///   `edge_count` of their node is lowered to match.
+/// - The string table comes last in the file, so it is usually gone entirely. Names that refer
+///   to missing strings read as `LOST_STRING`.
+///
+/// The `snapshot` header must be complete. Locations, samples and traces are not recovered.
+/// Only single snapshots are supported, not sequences.
+pub fn recover_v8_snapshot(bytes: &[u8]) -> Result<(SnapshotFile, Recovery)> {
+    if let Ok(snapshot) = serde_json::from_slice::<SnapshotFile>(bytes) {
+        let recovery = Recovery {
+            truncated: false,
+            nodes: snapshot.nodes.len() / snapshot.snapshot.meta.node_field_count(),
+            expected_nodes: snapshot.snapshot.node_count,
+            edges: snapshot.edges.len() / snapshot.snapshot.meta.edge_field_count(),
+            expected_edges: snapshot.snapshot.edge_count,
+            strings: snapshot.strings.len(),
+            lost_strings: 0,
+        };
+        return Ok((snapshot, recovery));
+    }
+
+    let mut scanner = Scanner { bytes, pos: 0 };
+    scanner.skip_whitespace();
+    match scanner.peek() {
+        Some(b'{') => scanner.pos += 1,
+        Some(b'[') => bail!("Can only recover a single snapshot, not a sequence of snapshots"),
+        _ => bail!("Not a heap snapshot: expected a JSON object"),
+    }
+
+    let mut header: Option<Snapshot> = None;
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    let mut strings = vec![];
+    let mut truncated = true;
+    loop {
+        scanner.skip_whitespace();
+        match scanner.peek() {
+            None => break,
+            Some(b'}') => {
+                truncated = false;
+                break;
+            }
+            Some(b',') => {
+                scanner.pos += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some(key_end) = scanner.value_end(scanner.pos) else {
+            break;
+        };
+        let key: String = serde_json::from_slice(&bytes[scanner.pos..key_end])
+            .context("Failed to parse snapshot JSON")?;
+        scanner.pos = key_end;
+        scanner.skip_whitespace();
+        if scanner.peek() != Some(b':') {
+            break;
+        }
+        scanner.pos += 1;
+        scanner.skip_whitespace();
+
+        let start = scanner.pos;
+        let end = scanner.value_end(start);
+        let value = &bytes[start..end.unwrap_or(bytes.len())];
+        match (key.as_str(), end) {
+            ("snapshot", Some(_)) => {
+                header =
+                    Some(serde_json::from_slice(value).context("Failed to parse snapshot header")?);
+            }
+            ("nodes", _) => nodes = complete_numbers(value)?,
+            ("edges", _) => edges = complete_numbers(value)?,
+            ("strings", _) => strings = complete_strings(value)?,
+            _ => {}
+        }
+        match end {
+            Some(end) => scanner.pos = end,
+            None => break,
+        }
+    }
+
+    let header = header.context("The snapshot header is missing or incomplete")?;
+    let mut recovery = Recovery {
+        truncated,
+        expected_nodes: header.node_count,
+        expected_edges: header.edge_count,
+        ..Default::default()
+    };
+    let snapshot = repair(header, nodes, edges, strings, &mut recovery)?;
+    Ok((snapshot, recovery))
+}
+
+/// Make the recovered arrays fit together, see `recover_v8_snapshot`
+fn repair(
+    mut header: Snapshot,
+    mut nodes: Vec<NodeId>,
+    edges: Vec<NodeId>,
+    mut strings: Vec<String>,
+    recovery: &mut Recovery,
+) -> Result<SnapshotFile> {
+    let meta = &header.meta;
+    let field = |fields: &[String], name: &str| {
+        fields
+            .iter()
+            .position(|f| f == name)
+            .with_context(|| format!("Snapshot has no '{}' field", name))
+    };
+    let node_stride = meta.node_field_count();
+    let edge_stride = meta.edge_field_count();
+    ensure!(
+        node_stride > 0 && edge_stride > 0,
+        "Snapshot has no node or edge fields"
+    );
+    let name_field = field(&meta.node_fields, "name")?;
+    let edge_count_field = field(&meta.node_fields, "edge_count")?;
+    let type_field = field(&meta.edge_fields, "type")?;
+    let name_or_index_field = field(&meta.edge_fields, "name_or_index")?;
+    let to_node_field = field(&meta.edge_fields, "to_node")?;
+    let edge_types = match meta.edge_types.first() {
+        Some(StringOrStrings::Strs(types)) => types.clone(),
+        _ => vec![],
+    };
+
+    nodes.truncate(nodes.len() - nodes.len() % node_stride);
+    let node_count = nodes.len() / node_stride;
+
+    let mut kept = Vec::with_capacity(edges.len());
+    let mut available = edges.chunks_exact(edge_stride);
+    for node in nodes.chunks_exact_mut(node_stride) {
+        let mut count = 0;
+        for edge in available.by_ref().take(node[edge_count_field] as usize) {
+            let to_node = edge[to_node_field] as usize;
+            if to_node.is_multiple_of(node_stride) && to_node / node_stride < node_count {
+                kept.extend_from_slice(edge);
+                count += 1;
+            }
+        }
+        node[edge_count_field] = count;
+    }
+
+    // Element and hidden edges have an index instead of a name
+    let max_string = nodes
+        .chunks_exact(node_stride)
+        .map(|node| node[name_field])
+        .chain(
+            kept.chunks_exact(edge_stride)
+                .filter(|edge| {
+                    !matches!(
+                        edge_types
+                            .get(edge[type_field] as usize)
+                            .map(String::as_str),
+                        Some("element" | "hidden")
+                    )
+                })
+                .map(|edge| edge[name_or_index_field]),
+        )
+        .max();
+    recovery.strings = strings.len();
+    if let Some(max_string) = max_string {
+        let needed = max_string as usize + 1;
+        if needed > strings.len() {
+            recovery.lost_strings = needed - strings.len();
+            strings.resize(needed, LOST_STRING.to_string());
+        }
+    }
+
+    recovery.nodes = node_count;
+    recovery.edges = kept.len() / edge_stride;
+    header.node_count = recovery.nodes;
+    header.edge_count = recovery.edges;
+    Ok(SnapshotFile {
+        snapshot: header,
+        nodes,
+        edges: kept,
+        locations: vec![],
+        samples: vec![],
+        strings,
+        trace_function_infos: vec![],
+        trace_tree: vec![],
+    })
+}
+
+/// The numbers of an array that may be cut off, leaving out a last number that may be partial
+fn complete_numbers(value: &[u8]) -> Result<Vec<NodeId>> {
+    let Some(inner) = value.strip_prefix(b"[") else {
+        bail!("Expected an array of numbers");
+    };
+    let complete = value.ends_with(b"]");
+    let inner = if complete {
+        &inner[..inner.len() - 1]
+    } else {
+        // Only numbers followed by a comma are known to be whole
+        match inner.iter().rposition(|&b| b == b',') {
+            Some(last_comma) => &inner[..last_comma],
+            None => &[],
+        }
+    };
+    inner
+        .split(|&b| b == b',')
+        .map(|n| n.trim_ascii())
+        .filter(|n| !n.is_empty())
+        .map(|n| {
+            std::str::from_utf8(n)
+                .ok()
+                .and_then(|n| n.parse().ok())
+                .with_context(|| format!("Not a number: {}", String::from_utf8_lossy(n)))
+        })
+        .collect()
+}
+
+/// The complete strings of an array that may be cut off
+fn complete_strings(value: &[u8]) -> Result<Vec<String>> {
+    let mut scanner = Scanner {
+        bytes: value,
+        pos: 1,
+    };
+    let mut strings = vec![];
+    loop {
+        scanner.skip_whitespace();
+        match scanner.peek() {
+            Some(b',') => scanner.pos += 1,
+            Some(b'"') => {
+                let Some(end) = scanner.value_end(scanner.pos) else {
+                    break;
+                };
+                let s = LossyString::deserialize(&mut serde_json::Deserializer::from_slice(
+                    &value[scanner.pos..end],
+                ))
+                .context("Failed to parse snapshot strings")?;
+                strings.push(s.0);
+                scanner.pos = end;
+            }
+            _ => break,
+        }
+    }
+    Ok(strings)
+}
+
+/// Finds the extent of JSON values without parsing them
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// The end of the value that starts at `start`, or `None` if the input ends first
+    fn value_end(&self, start: usize) -> Option<usize> {
+        let bytes = self.bytes;
+        match bytes.get(start)? {
+            b'"' => {
+                let mut i = start + 1;
+                loop {
+                    match bytes.get(i)? {
+                        b'\\' => i += 2,
+                        b'"' => return Some(i + 1),
+                        _ => i += 1,
+                    }
+                }
+            }
+            b'[' | b'{' => {
+                let mut depth = 0;
+                let mut i = start;
+                loop {
+                    match bytes.get(i)? {
+                        b'"' => {
+                            i = self.value_end(i)?;
+                            continue;
+                        }
+                        b'[' | b'{' => depth += 1,
+                        b']' | b'}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                return Some(i + 1);
+                            }
+                        }
+                        _ => {}
+                    }
+                    i += 1;
+                }
+            }
+            _ => {
+                // A number, `true`, `false` or `null`, which may be cut short at the very end
+                let len = bytes[start..]
+                    .iter()
+                    .position(|&b| matches!(b, b',' | b']' | b'}') || b.is_ascii_whitespace())?;
+                Some(start + len)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::v8_heap_graph::V8HeapGraph;
+
+    fn fixture() -> Vec<u8> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/object.heapsnapshot");
+        std::fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn test_recover_truncated_in_edges() {
+        let bytes = fixture();
+        let edges_start = bytes.windows(8).position(|w| w == b"\"edges\":").unwrap();
+        // Somewhere in the middle of the edges, most likely inside a number
+        let truncated = &bytes[..edges_start + 100_003];
+        assert!(serde_json::from_slice::<SnapshotFile>(truncated).is_err());
+
+        let (snapshot, recovery) = recover_v8_snapshot(truncated).unwrap();
+        assert!(recovery.truncated);
+        assert_eq!(recovery.nodes, recovery.expected_nodes);
+        assert!(recovery.edges > 0);
+        assert!(recovery.edges < recovery.expected_edges);
+        assert_eq!(recovery.strings, 0);
+        assert!(recovery.lost_strings > 0);
+
+        let graph = V8HeapGraph::from(snapshot);
+        assert_eq!(graph.total_node_count(), recovery.nodes);
+        assert_eq!(graph.total_edge_count(), recovery.edges);
+        assert_eq!(graph.node(0).name(), LOST_STRING);
+        assert!(!graph.out_neighbors(0).is_empty());
+    }
+
+    #[test]
+    fn test_recover_complete_snapshot() {
+        let (snapshot, recovery) = recover_v8_snapshot(&fixture()).unwrap();
+        assert!(!recovery.truncated);
+        assert_eq!(recovery.lost_strings, 0);
+        assert_eq!(recovery.edges, recovery.expected_edges);
+        assert_eq!(snapshot.strings.len(), recovery.strings);
+    }
+}
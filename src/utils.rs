@@ -1,11 +1,13 @@
+use serde::Serialize;
 use std::io::Write;
 use std::io::stdout;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub fn print_safe<'a>(name: &'a str, max_len: usize) -> String {
+/// Quotes and escapes a string for display, truncating it to `max_len` characters
+pub fn print_safe(name: &str, max_len: usize) -> String {
     let mut s = String::new();
     s.push('"');
-    if name.len() > max_len {
+    if name.chars().nth(max_len).is_some() {
         s += &escape_string_chars(name.chars().take(max_len));
         s += "...";
     } else {
@@ -29,49 +31,127 @@ pub fn escape_string_chars(s: impl IntoIterator<Item = char>) -> String {
             '\t' => vec!['\\', 't'],
             '\\' => vec!['\\', '\\'],
             '"' => vec!['\\', '"'],
-            c if c < ' ' || c > '~' => vec!['?'],
+            c if !(' '..='~').contains(&c) => vec!['?'],
             c => vec![c],
         })
         .collect()
 }
 
+/// Whether `k`/`M`/`G` are powers of 1024 or of 1000
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteUnit {
+    #[default]
+    Binary,
+    /// SI units, where 1M = 1,000,000 bytes
+    Decimal,
+}
+
 /// Formats bytes into human-readable format
 pub fn format_bytes(bytes: usize) -> String {
-    const KB: usize = 1024;
-    const MB: usize = KB * 1024;
-    const GB: usize = MB * 1024;
-
-    if bytes >= GB {
-        format!("{:.1}G", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.1}M", bytes as f64 / MB as f64)
-    } else if bytes >= KB {
-        format!("{:.1}k", bytes as f64 / KB as f64)
+    format_bytes_signed(bytes as i64, ByteUnit::Binary)
+}
+
+/// Formats a byte count that may be negative, such as the difference between two sizes
+pub fn format_bytes_signed(bytes: i64, unit: ByteUnit) -> String {
+    let k: u64 = match unit {
+        ByteUnit::Binary => 1024,
+        ByteUnit::Decimal => 1000,
+    };
+    let sign = if bytes < 0 { "-" } else { "" };
+    let abs = bytes.unsigned_abs();
+
+    if abs >= k * k * k {
+        format!("{}{:.1}G", sign, abs as f64 / (k * k * k) as f64)
+    } else if abs >= k * k {
+        format!("{}{:.1}M", sign, abs as f64 / (k * k) as f64)
+    } else if abs >= k {
+        format!("{}{:.1}k", sign, abs as f64 / k as f64)
     } else {
-        format!("{}b", bytes)
+        format!("{}{}b", sign, abs)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Parses a human-friendly size like `512`, `64k` or `1.5M` into bytes
+///
+/// Units are binary, like in `format_bytes`, and a trailing `b`/`B` is allowed.
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    let number = lower.strip_suffix('b').unwrap_or(&lower);
+    let (number, multiplier) = match number.chars().last() {
+        Some('k') => (&number[..number.len() - 1], 1024),
+        Some('m') => (&number[..number.len() - 1], 1024 * 1024),
+        Some('g') => (&number[..number.len() - 1], 1024 * 1024 * 1024),
+        _ => (number, 1),
+    };
 
-    #[test]
-    fn test_escape_string() {
-        assert_eq!(escape_string("hello\nworld"), "hello\\nworld");
-        assert_eq!(escape_string("tab\there"), "tab\\there");
-        assert_eq!(escape_string("quote\"test"), "quote\\\"test");
-        assert_eq!(escape_string("backslash\\test"), "backslash\\\\test");
-        assert_eq!(escape_string("normal text"), "normal text");
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size: {:?}", s))?;
+    if value.is_nan() || value < 0.0 {
+        return Err(format!("invalid size: {:?}", s));
     }
+    Ok((value * multiplier as f64) as usize)
+}
 
-    #[test]
-    fn test_format_bytes() {
-        assert_eq!(format_bytes(500), "500b");
-        assert_eq!(format_bytes(1024), "1.00k");
-        assert_eq!(format_bytes(1536), "1.50k");
-        assert_eq!(format_bytes(1048576), "1.00M");
-        assert_eq!(format_bytes(1073741824), "1.00G");
+/// Formats seconds since the Unix epoch as a UTC date and time, like `2024-03-01 14:05:09 UTC`
+pub fn format_timestamp(secs: u64) -> String {
+    // Days to a civil date, after Howard Hinnant's `civil_from_days`
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Formats a duration in its two largest units, like `3d 4h` or `12m 5s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let units = [(86400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+    let Some(first) = units.iter().position(|&(size, _)| secs >= size) else {
+        return "0s".to_string();
+    };
+    units[first..]
+        .iter()
+        .take(2)
+        .enumerate()
+        .map(|(i, &(size, unit))| {
+            let value = if i == 0 {
+                secs / size
+            } else {
+                secs % units[first].0 / size
+            };
+            format!("{}{}", value, unit)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats a file modification time, with how long ago that was if it isn't in the future
+pub fn format_modified(modified: SystemTime) -> String {
+    let secs = modified
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    match SystemTime::now().duration_since(modified) {
+        Ok(age) => format!("{} ({} ago)", format_timestamp(secs), format_duration(age)),
+        Err(_) => format_timestamp(secs),
     }
 }
 
@@ -89,9 +169,196 @@ pub fn start_timer(name: String) -> Timer {
     }
 }
 
+impl Timer {
+    /// Stop the timer, returning how long it ran in milliseconds
+    pub fn finish_ms(self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
+
 impl Drop for Timer {
     fn drop(&mut self) {
         let duration = Instant::now() - self.start;
         eprintln!("Done ({:?})", duration);
     }
 }
+
+/// Exit code of an analysis stopped by `--timeout`, the same as that of `timeout(1)`
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// A point in time after which long computations give up, see `--timeout`
+///
+/// The dominator and path computations check it every so often, and return `TimedOut`
+/// through the normal error path once it has passed. This is for CI, where a pathological
+/// snapshot shouldn't hang the pipeline.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Deadline {
+    /// When it passes, and the timeout it was set with
+    limit: Option<(Instant, Duration)>,
+    /// What the computation checking it is busy with, for the message of `TimedOut`
+    phase: Option<&'static str>,
+}
+
+impl Deadline {
+    /// A deadline that never passes
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            limit: Some((Instant::now() + timeout, timeout)),
+            phase: None,
+        }
+    }
+
+    /// The same deadline, for a computation that is busy with `phase`
+    pub fn phase(self, phase: &'static str) -> Self {
+        Self {
+            phase: Some(phase),
+            ..self
+        }
+    }
+
+    pub fn check(&self) -> Result<(), TimedOut> {
+        match self.limit {
+            Some((at, timeout)) if Instant::now() >= at => Err(TimedOut {
+                timeout,
+                phase: self.phase,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The error of a computation that ran past its `Deadline`
+#[derive(Debug, Clone)]
+pub struct TimedOut {
+    pub timeout: Duration,
+    /// What the computation was busy with, see `Deadline::phase`
+    pub phase: Option<&'static str>,
+}
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the analysis did not finish within {:?}", self.timeout)?;
+        if let Some(phase) = &self.phase {
+            write!(f, " (still busy with: {})", phase)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// How long the phases of an analysis took, in milliseconds
+///
+/// Phases that didn't run (or were read from a cache) count as 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Timings {
+    pub parse_ms: u64,
+    pub dominators_ms: u64,
+    pub duplicates_ms: u64,
+    pub paths_ms: u64,
+    /// Everything, including the phases that aren't listed separately
+    pub total_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        assert!(Deadline::none().check().is_ok());
+        assert!(Deadline::after(Duration::from_secs(3600)).check().is_ok());
+
+        let err = Deadline::after(Duration::ZERO).check().unwrap_err();
+        assert_eq!(err.timeout, Duration::ZERO);
+        assert_eq!(err.to_string(), "the analysis did not finish within 0ns");
+
+        // Copies for different phases don't affect each other
+        let deadline = Deadline::after(Duration::ZERO);
+        let dominators = deadline.phase("dominators");
+        let paths = deadline.phase("root paths");
+        assert_eq!(
+            dominators.check().unwrap_err().to_string(),
+            "the analysis did not finish within 0ns (still busy with: dominators)"
+        );
+        assert_eq!(paths.check().unwrap_err().phase, Some("root paths"));
+        assert_eq!(deadline.check().unwrap_err().phase, None);
+    }
+
+    #[test]
+    fn test_escape_string() {
+        assert_eq!(escape_string("hello\nworld"), "hello\\nworld");
+        assert_eq!(escape_string("tab\there"), "tab\\there");
+        assert_eq!(escape_string("quote\"test"), "quote\\\"test");
+        assert_eq!(escape_string("backslash\\test"), "backslash\\\\test");
+        assert_eq!(escape_string("normal text"), "normal text");
+    }
+
+    #[test]
+    fn test_print_safe_truncates_on_chars() {
+        // 100 characters, 200 bytes
+        let s = "é".repeat(100);
+        assert_eq!(print_safe(&s, 100), format!("\"{}\"", "?".repeat(100)));
+        assert_eq!(print_safe(&s, 99), format!("\"{}...\"", "?".repeat(99)));
+        assert_eq!(print_safe("short", 100), "\"short\"");
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(500), "500b");
+        assert_eq!(format_bytes(1024), "1.0k");
+        assert_eq!(format_bytes(1536), "1.5k");
+        assert_eq!(format_bytes(1048576), "1.0M");
+        assert_eq!(format_bytes(1073741824), "1.0G");
+    }
+
+    #[test]
+    fn test_format_bytes_signed() {
+        assert_eq!(format_bytes_signed(-500, ByteUnit::Binary), "-500b");
+        assert_eq!(format_bytes_signed(-1536, ByteUnit::Binary), "-1.5k");
+        assert_eq!(format_bytes_signed(-1048576, ByteUnit::Binary), "-1.0M");
+        assert_eq!(format_bytes_signed(0, ByteUnit::Binary), "0b");
+        assert_eq!(
+            format_bytes_signed(i64::MIN, ByteUnit::Binary),
+            "-8589934592.0G"
+        );
+
+        assert_eq!(format_bytes_signed(1000, ByteUnit::Decimal), "1.0k");
+        assert_eq!(format_bytes_signed(1024, ByteUnit::Decimal), "1.0k");
+        assert_eq!(format_bytes_signed(1500000, ByteUnit::Decimal), "1.5M");
+        assert_eq!(format_bytes_signed(-2000000000, ByteUnit::Decimal), "-2.0G");
+        assert_eq!(format_bytes_signed(999, ByteUnit::Decimal), "999b");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64k"), Ok(64 * 1024));
+        assert_eq!(parse_size("10M"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1.5MB"), Ok(1536 * 1024));
+        assert_eq!(parse_size("2g"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("100b"), Ok(100));
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("-1k").is_err());
+        assert!(parse_size("").is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp_and_duration() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_709_301_909), "2024-03-01 14:05:09 UTC");
+
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(725)), "12m 5s");
+        assert_eq!(
+            format_duration(Duration::from_secs(3 * 86400 + 4 * 3600 + 9)),
+            "3d 4h"
+        );
+    }
+}
@@ -1,5 +1,10 @@
 use std::path::PathBuf;
 
+use v8_heap_analyzer::analysis::all_paths::{DEFAULT_MAX_PATH_DEPTH, PathEdges};
+use v8_heap_analyzer::analysis::explain::explain_node;
+use v8_heap_analyzer::graph::v8_heap_graph::{EdgeType, V8HeapGraph};
+use v8_heap_analyzer::snapshot::read_v8_snapshot_file;
+
 // Import the analyze function from main
 
 #[test]
@@ -10,3 +15,120 @@ fn test_find_string_duplicates_in_snapshot() {
         panic!("Test snapshot not found. Run: node tests/generate-string-duplicates.js");
     }
 }
+
+#[test]
+fn test_explain_fixture_object() {
+    let snapshot = read_v8_snapshot_file(&PathBuf::from("tests/fixtures/object.heapsnapshot"))
+        .expect("Failed to read snapshot");
+    let graph = V8HeapGraph::from(snapshot);
+
+    // `globalThis.someObject` from tests/scripts/object.js
+    let some_object = graph
+        .iter_edges()
+        .map(|e| graph.edge(e))
+        .find(|e| e.typ() == EdgeType::Property && e.name_or_index().is_str("someObject"))
+        .expect("someObject not found")
+        .to_node();
+
+    let explanation = explain_node(
+        &graph,
+        0,
+        some_object,
+        DEFAULT_MAX_PATH_DEPTH,
+        PathEdges::All,
+        Default::default(),
+    );
+
+    // It holds on to its `foo` string
+    assert!(explanation.retained_size > graph.node(some_object).self_size());
+    assert!(explanation.immediate_dominator.is_some());
+    assert!(!explanation.detached);
+    assert!(explanation.duplicate_group.is_none());
+    let path = explanation.shortest_path.expect("someObject is reachable");
+    let last_edge = path.edges(&graph).last().unwrap();
+    assert!(last_edge.name_or_index().is_str("someObject"));
+}
+
+#[test]
+fn test_output_dir_writes_all_reports() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("reports");
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_v8-heap-analyzer"))
+        .args(["-i", "tests/fixtures/object.heapsnapshot", "--output-dir"])
+        .arg(&out)
+        .stdout(std::process::Stdio::null())
+        .status()
+        .expect("Failed to run v8-heap-analyzer");
+    assert!(status.success());
+
+    for name in ["report.txt", "report.json", "top-retainers.dot"] {
+        let len = std::fs::metadata(out.join(name))
+            .unwrap_or_else(|_| panic!("{} was not written", name))
+            .len();
+        assert!(len > 0, "{} is empty", name);
+    }
+}
+
+#[test]
+fn test_json_report_is_reproducible() {
+    let run = || {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_v8-heap-analyzer"))
+            .args([
+                "-i",
+                "tests/fixtures/test-duplicates.heapsnapshot",
+                "--format",
+                "json",
+                "--reproducible",
+            ])
+            .stderr(std::process::Stdio::null())
+            .output()
+            .expect("Failed to run v8-heap-analyzer");
+        assert!(output.status.success());
+        output.stdout
+    };
+
+    let first = run();
+    assert!(first == run(), "JSON reports of the same snapshot differ");
+    let report: serde_json::Value = serde_json::from_slice(&first).unwrap();
+    assert_eq!(report["generated_at"], 0);
+    assert!(report["summary"]["modified"].is_null());
+}
+
+#[test]
+fn test_read_snapshot_from_stdin() {
+    let fixture = std::fs::File::open("tests/fixtures/object.heapsnapshot").unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_v8-heap-analyzer"))
+        .args(["-i", "-", "--format", "json"])
+        .stdin(fixture)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .expect("Failed to run v8-heap-analyzer");
+    assert!(output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let graph = V8HeapGraph::from(
+        read_v8_snapshot_file(&PathBuf::from("tests/fixtures/object.heapsnapshot")).unwrap(),
+    );
+    assert_eq!(
+        report["summary"]["node_count"].as_u64(),
+        Some(graph.total_node_count() as u64)
+    );
+}
+
+#[test]
+fn test_timeout_stops_the_analysis() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_v8-heap-analyzer"))
+        .args([
+            "-i",
+            "tests/fixtures/object.heapsnapshot",
+            "--timeout",
+            "0.001",
+        ])
+        .output()
+        .expect("Failed to run v8-heap-analyzer");
+
+    assert_eq!(output.status.code(), Some(124));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("did not finish within"), "{}", stderr);
+}
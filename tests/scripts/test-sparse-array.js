@@ -0,0 +1,4 @@
+// An array with only two elements, at the very start and very far away
+globalThis.sparseArray = [];
+globalThis.sparseArray[0] = { first: true };
+globalThis.sparseArray[1_000_000] = { last: true };
@@ -1,3 +1,4 @@
+const fs = require('fs');
 const path = require('path');
 const v8 = require('v8');
 
@@ -14,3 +15,11 @@ require(`./scripts/${script}.js`);
 // Generate snapshot
 const snapshotPath = path.join(__dirname, 'fixtures', `${script}.heapsnapshot`);
 v8.writeHeapSnapshot(snapshotPath);
+
+// Newer Node versions write every node and edge on a line of its own. Rewrite the snapshot
+// with one line per section, the layout of the other fixtures, to keep them small.
+const snapshot = JSON.parse(fs.readFileSync(snapshotPath, 'utf8'));
+const sections = Object.entries(snapshot).map(
+  ([key, value]) => `${JSON.stringify(key)}:${JSON.stringify(value)}`,
+);
+fs.writeFileSync(snapshotPath, `{${sections.join(',\n')}}`);